@@ -0,0 +1,976 @@
+//! Syntax tree produced by the parser
+
+use std::fmt;
+
+use crate::diagnostic::json_string;
+use crate::lexer::{Span, Token};
+
+// Binary operators usable in expressions
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    And,
+    Or,
+}
+
+impl BinaryOperator {
+    // Operator for the arithmetic tokens, if `token` is one
+    pub(crate) fn from_token(token: &Token) -> Option<Self> {
+        match token {
+            Token::Plus => Some(BinaryOperator::Add),
+            Token::Minus => Some(BinaryOperator::Sub),
+            Token::Star => Some(BinaryOperator::Mul),
+            Token::Slash => Some(BinaryOperator::Div),
+            Token::Percent => Some(BinaryOperator::Mod),
+            Token::EqEq => Some(BinaryOperator::Eq),
+            Token::NotEq => Some(BinaryOperator::Ne),
+            Token::Lt => Some(BinaryOperator::Lt),
+            Token::Gt => Some(BinaryOperator::Gt),
+            Token::Le => Some(BinaryOperator::Le),
+            Token::Ge => Some(BinaryOperator::Ge),
+            Token::Keyword(k) if *k == "and" => Some(BinaryOperator::And),
+            Token::Keyword(k) if *k == "or" => Some(BinaryOperator::Or),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn c_symbol(self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Sub => "-",
+            BinaryOperator::Mul => "*",
+            BinaryOperator::Div => "/",
+            BinaryOperator::Mod => "%",
+            BinaryOperator::Eq => "==",
+            BinaryOperator::Ne => "!=",
+            BinaryOperator::Lt => "<",
+            BinaryOperator::Gt => ">",
+            BinaryOperator::Le => "<=",
+            BinaryOperator::Ge => ">=",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Or => "||",
+        }
+    }
+}
+
+// Operators are displayed with their BP spelling
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BinaryOperator::And => write!(f, "and"),
+            BinaryOperator::Or => write!(f, "or"),
+            op => write!(f, "{}", op.c_symbol()),
+        }
+    }
+}
+
+// Prefix operators usable in expressions
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOperator {
+    Neg,
+    Not,
+}
+
+impl UnaryOperator {
+    pub(crate) fn c_symbol(self) -> &'static str {
+        match self {
+            UnaryOperator::Neg => "-",
+            UnaryOperator::Not => "!",
+        }
+    }
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnaryOperator::Neg => write!(f, "-"),
+            UnaryOperator::Not => write!(f, "not"),
+        }
+    }
+}
+
+// A field of a struct definition. Fields without a type annotation hold
+// ints, like function parameters.
+#[derive(Debug, Clone)]
+pub struct FieldDef {
+    pub name: String,
+    pub ty: Option<String>,
+    pub span: Span,
+}
+
+// A parameter of a function definition. Parameters without a type
+// annotation hold ints.
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub ty: Option<String>,
+    pub span: Span,
+}
+
+// An arm of a `match`: the variant it handles and the block run for it
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub variant: String,
+    pub body: Vec<ASTNode>,
+    pub span: Span,
+}
+
+// Define the AST
+#[derive(Debug, Clone)]
+pub enum ASTNode {
+    // `ty` is the written type annotation, if any. Only an annotated
+    // declaration may leave out the value.
+    VariableDeclaration {
+        name: String,
+        mutable: bool,
+        ty: Option<String>,
+        value: Option<Box<ASTNode>>,
+        span: Span,
+    },
+    Assignment {
+        name: String,
+        value: Box<ASTNode>,
+        span: Span,
+    },
+    // `name[index] = value;`
+    IndexAssignment {
+        name: String,
+        index: Box<ASTNode>,
+        value: Box<ASTNode>,
+        span: Span,
+    },
+    // `name.field = value;`
+    FieldAssignment {
+        name: String,
+        field: String,
+        value: Box<ASTNode>,
+        span: Span,
+    },
+    StringLiteral(String),
+    NumberLiteral(i32),
+    // An int literal too large for an int
+    LongLiteral(i64),
+    FloatLiteral(f64),
+    BoolLiteral(bool),
+    Identifier(String, Span),
+    // `[a, b, c]`, only valid as the value of a declaration
+    ArrayLiteral(Vec<ASTNode>, Span),
+    // `name[index]`
+    Index {
+        name: String,
+        index: Box<ASTNode>,
+        span: Span,
+    },
+    // `Name { field = value, ... }`, only valid as the value of a declaration
+    StructInit {
+        name: String,
+        fields: Vec<(String, ASTNode)>,
+        span: Span,
+    },
+    // `name.field`
+    FieldAccess {
+        name: String,
+        field: String,
+        span: Span,
+    },
+    BinaryOp {
+        op: BinaryOperator,
+        lhs: Box<ASTNode>,
+        rhs: Box<ASTNode>,
+        span: Span,
+    },
+    UnaryOp {
+        op: UnaryOperator,
+        operand: Box<ASTNode>,
+        span: Span,
+    },
+    Show(Vec<ASTNode>, Span),
+    If {
+        cond: Box<ASTNode>,
+        then_block: Vec<ASTNode>,
+        else_block: Option<Vec<ASTNode>>,
+        span: Span,
+    },
+    For {
+        var: String,
+        start: Box<ASTNode>,
+        end: Box<ASTNode>,
+        body: Vec<ASTNode>,
+        span: Span,
+    },
+    // A braced block on its own; its declarations end with it
+    Block(Vec<ASTNode>),
+    Break(Span),
+    Continue(Span),
+    Function {
+        name: String,
+        params: Vec<Parameter>,
+        body: Vec<ASTNode>,
+        span: Span,
+    },
+    Call {
+        name: String,
+        args: Vec<ASTNode>,
+        span: Span,
+    },
+    // `extern fn name(params): type;` declares a C library function, called
+    // like a defined one. It gives an int unless `result` names a type.
+    ExternFunction {
+        name: String,
+        params: Vec<Parameter>,
+        result: Option<String>,
+        span: Span,
+    },
+    // `struct Name { field; field: type; }`
+    StructDef {
+        name: String,
+        fields: Vec<FieldDef>,
+        span: Span,
+    },
+    // `enum Name { A, B, C }`
+    EnumDef {
+        name: String,
+        variants: Vec<String>,
+        span: Span,
+    },
+    // `match value { A { ... } B { ... } else { ... } }` on an enum value
+    Match {
+        value: Box<ASTNode>,
+        arms: Vec<MatchArm>,
+        else_block: Option<Vec<ASTNode>>,
+        span: Span,
+    },
+    Give {
+        value: Box<ASTNode>,
+        span: Span,
+    },
+    Exit {
+        value: Box<ASTNode>,
+        span: Span,
+    },
+    // `assert cond;` or `assert cond, "message";` stops the program when
+    // `cond` is false
+    Assert {
+        cond: Box<ASTNode>,
+        message: Option<String>,
+        span: Span,
+    },
+    // `input name;` reads a line from stdin into a mutable int or string
+    Input {
+        name: String,
+        span: Span,
+    },
+    // `craw "code";` writes `code` as a line of the generated C. Only the C
+    // target accepts it.
+    CRaw {
+        code: String,
+        span: Span,
+    },
+    // `cinclude "header";` at the top level includes a C header
+    CInclude {
+        header: String,
+        span: Span,
+    },
+    // `link "name";` at the top level links the C library `name`, as
+    // `-lname`
+    Link {
+        library: String,
+        span: Span,
+    },
+    // `use "other.bp";` at the top level brings in the definitions of
+    // another file, found relative to this one
+    Use {
+        path: String,
+        span: Span,
+    },
+}
+
+impl ASTNode {
+    // Where the node starts in the source, for the nodes that record it
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ASTNode::VariableDeclaration { span, .. }
+            | ASTNode::Assignment { span, .. }
+            | ASTNode::IndexAssignment { span, .. }
+            | ASTNode::FieldAssignment { span, .. }
+            | ASTNode::Index { span, .. }
+            | ASTNode::StructInit { span, .. }
+            | ASTNode::FieldAccess { span, .. }
+            | ASTNode::BinaryOp { span, .. }
+            | ASTNode::UnaryOp { span, .. }
+            | ASTNode::If { span, .. }
+            | ASTNode::For { span, .. }
+            | ASTNode::Function { span, .. }
+            | ASTNode::ExternFunction { span, .. }
+            | ASTNode::Call { span, .. }
+            | ASTNode::StructDef { span, .. }
+            | ASTNode::EnumDef { span, .. }
+            | ASTNode::Match { span, .. }
+            | ASTNode::Give { span, .. }
+            | ASTNode::Exit { span, .. }
+            | ASTNode::Assert { span, .. }
+            | ASTNode::Input { span, .. }
+            | ASTNode::CRaw { span, .. }
+            | ASTNode::CInclude { span, .. }
+            | ASTNode::Link { span, .. }
+            | ASTNode::Use { span, .. } => Some(*span),
+            ASTNode::Identifier(_, span)
+            | ASTNode::ArrayLiteral(_, span)
+            | ASTNode::Show(_, span)
+            | ASTNode::Break(span)
+            | ASTNode::Continue(span) => Some(*span),
+            ASTNode::StringLiteral(_)
+            | ASTNode::NumberLiteral(_)
+            | ASTNode::LongLiteral(_)
+            | ASTNode::FloatLiteral(_)
+            | ASTNode::BoolLiteral(_)
+            | ASTNode::Block(_) => None,
+        }
+    }
+}
+
+// Render a program as an indented tree, one node per line, for `--emit-ast`.
+// Statements with positions show them as `@line:col`.
+pub fn ast_tree(nodes: &[ASTNode]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        write_tree(node, 0, &mut out);
+    }
+    out
+}
+
+fn write_tree(node: &ASTNode, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    let label = |out: &mut String, text: &str| {
+        out.push_str(&format!("{}  {}\n", indent, text));
+    };
+    let at = |span: &Span| format!("@{}:{}", span.line, span.col);
+    match node {
+        ASTNode::VariableDeclaration {
+            name,
+            mutable,
+            ty,
+            value,
+            span,
+        } => {
+            let keyword = if *mutable { "m" } else { "c" };
+            let annotation = ty.as_ref().map_or(String::new(), |ty| format!(": {}", ty));
+            out.push_str(&format!(
+                "{}VariableDeclaration {} {}{} {}\n",
+                indent,
+                keyword,
+                name,
+                annotation,
+                at(span)
+            ));
+            if let Some(value) = value {
+                write_tree(value, depth + 1, out);
+            }
+        }
+        ASTNode::Assignment { name, value, span } => {
+            out.push_str(&format!("{}Assignment {} {}\n", indent, name, at(span)));
+            write_tree(value, depth + 1, out);
+        }
+        ASTNode::IndexAssignment {
+            name,
+            index,
+            value,
+            span,
+        } => {
+            out.push_str(&format!(
+                "{}IndexAssignment {} {}\n",
+                indent,
+                name,
+                at(span)
+            ));
+            write_tree(index, depth + 1, out);
+            write_tree(value, depth + 1, out);
+        }
+        ASTNode::FieldAssignment {
+            name,
+            field,
+            value,
+            span,
+        } => {
+            out.push_str(&format!(
+                "{}FieldAssignment {}.{} {}\n",
+                indent,
+                name,
+                field,
+                at(span)
+            ));
+            write_tree(value, depth + 1, out);
+        }
+        ASTNode::StringLiteral(s) => out.push_str(&format!("{}String {:?}\n", indent, s)),
+        ASTNode::NumberLiteral(num) => out.push_str(&format!("{}Number {}\n", indent, num)),
+        ASTNode::LongLiteral(num) => out.push_str(&format!("{}Long {}\n", indent, num)),
+        ASTNode::FloatLiteral(num) => out.push_str(&format!("{}Float {:?}\n", indent, num)),
+        ASTNode::BoolLiteral(b) => out.push_str(&format!("{}Bool {}\n", indent, b)),
+        ASTNode::Identifier(name, _) => out.push_str(&format!("{}Identifier {}\n", indent, name)),
+        ASTNode::ArrayLiteral(elements, _) => {
+            out.push_str(&format!("{}Array\n", indent));
+            for element in elements {
+                write_tree(element, depth + 1, out);
+            }
+        }
+        ASTNode::Index { name, index, .. } => {
+            out.push_str(&format!("{}Index {}\n", indent, name));
+            write_tree(index, depth + 1, out);
+        }
+        ASTNode::StructInit { name, fields, .. } => {
+            out.push_str(&format!("{}StructInit {}\n", indent, name));
+            for (field, value) in fields {
+                label(out, &format!("{} =", field));
+                write_tree(value, depth + 2, out);
+            }
+        }
+        ASTNode::FieldAccess { name, field, .. } => {
+            out.push_str(&format!("{}FieldAccess {}.{}\n", indent, name, field));
+        }
+        ASTNode::BinaryOp { op, lhs, rhs, .. } => {
+            out.push_str(&format!("{}BinaryOp {}\n", indent, op));
+            write_tree(lhs, depth + 1, out);
+            write_tree(rhs, depth + 1, out);
+        }
+        ASTNode::UnaryOp { op, operand, .. } => {
+            out.push_str(&format!("{}UnaryOp {}\n", indent, op));
+            write_tree(operand, depth + 1, out);
+        }
+        ASTNode::Show(values, span) => {
+            out.push_str(&format!("{}Show {}\n", indent, at(span)));
+            for value in values {
+                write_tree(value, depth + 1, out);
+            }
+        }
+        ASTNode::If {
+            cond,
+            then_block,
+            else_block,
+            span,
+        } => {
+            out.push_str(&format!("{}If {}\n", indent, at(span)));
+            write_tree(cond, depth + 1, out);
+            label(out, "then");
+            for node in then_block {
+                write_tree(node, depth + 2, out);
+            }
+            if let Some(else_block) = else_block {
+                label(out, "else");
+                for node in else_block {
+                    write_tree(node, depth + 2, out);
+                }
+            }
+        }
+        ASTNode::For {
+            var,
+            start,
+            end,
+            body,
+            span,
+        } => {
+            out.push_str(&format!("{}For {} {}\n", indent, var, at(span)));
+            write_tree(start, depth + 1, out);
+            write_tree(end, depth + 1, out);
+            label(out, "body");
+            for node in body {
+                write_tree(node, depth + 2, out);
+            }
+        }
+        ASTNode::Block(nodes) => {
+            out.push_str(&format!("{}Block\n", indent));
+            for node in nodes {
+                write_tree(node, depth + 1, out);
+            }
+        }
+        ASTNode::Break(span) => out.push_str(&format!("{}Break {}\n", indent, at(span))),
+        ASTNode::Continue(span) => out.push_str(&format!("{}Continue {}\n", indent, at(span))),
+        ASTNode::Function {
+            name,
+            params,
+            body,
+            span,
+        } => {
+            let params: Vec<String> = params
+                .iter()
+                .map(|param| match &param.ty {
+                    Some(ty) => format!("{}: {}", param.name, ty),
+                    None => param.name.clone(),
+                })
+                .collect();
+            out.push_str(&format!(
+                "{}Function {}({}) {}\n",
+                indent,
+                name,
+                params.join(", "),
+                at(span)
+            ));
+            for node in body {
+                write_tree(node, depth + 1, out);
+            }
+        }
+        ASTNode::ExternFunction {
+            name,
+            params,
+            result,
+            span,
+        } => {
+            let params: Vec<String> = params
+                .iter()
+                .map(|param| match &param.ty {
+                    Some(ty) => format!("{}: {}", param.name, ty),
+                    None => param.name.clone(),
+                })
+                .collect();
+            let result = result
+                .as_ref()
+                .map_or(String::new(), |ty| format!(": {}", ty));
+            out.push_str(&format!(
+                "{}ExternFunction {}({}){} {}\n",
+                indent,
+                name,
+                params.join(", "),
+                result,
+                at(span)
+            ));
+        }
+        ASTNode::Call { name, args, .. } => {
+            out.push_str(&format!("{}Call {}\n", indent, name));
+            for arg in args {
+                write_tree(arg, depth + 1, out);
+            }
+        }
+        ASTNode::StructDef { name, fields, span } => {
+            out.push_str(&format!("{}StructDef {} {}\n", indent, name, at(span)));
+            for field in fields {
+                match &field.ty {
+                    Some(ty) => label(out, &format!("field {}: {}", field.name, ty)),
+                    None => label(out, &format!("field {}", field.name)),
+                }
+            }
+        }
+        ASTNode::EnumDef {
+            name,
+            variants,
+            span,
+        } => {
+            out.push_str(&format!(
+                "{}EnumDef {} {{ {} }} {}\n",
+                indent,
+                name,
+                variants.join(", "),
+                at(span)
+            ));
+        }
+        ASTNode::Match {
+            value,
+            arms,
+            else_block,
+            span,
+        } => {
+            out.push_str(&format!("{}Match {}\n", indent, at(span)));
+            write_tree(value, depth + 1, out);
+            for arm in arms {
+                label(out, &arm.variant);
+                for node in &arm.body {
+                    write_tree(node, depth + 2, out);
+                }
+            }
+            if let Some(else_block) = else_block {
+                label(out, "else");
+                for node in else_block {
+                    write_tree(node, depth + 2, out);
+                }
+            }
+        }
+        ASTNode::Give { value, span } => {
+            out.push_str(&format!("{}Give {}\n", indent, at(span)));
+            write_tree(value, depth + 1, out);
+        }
+        ASTNode::Input { name, span } => {
+            out.push_str(&format!("{}Input {} {}\n", indent, name, at(span)));
+        }
+        ASTNode::CRaw { code, span } => {
+            out.push_str(&format!("{}CRaw {:?} {}\n", indent, code, at(span)));
+        }
+        ASTNode::CInclude { header, span } => {
+            out.push_str(&format!("{}CInclude {:?} {}\n", indent, header, at(span)));
+        }
+        ASTNode::Link { library, span } => {
+            out.push_str(&format!("{}Link {:?} {}\n", indent, library, at(span)));
+        }
+        ASTNode::Use { path, span } => {
+            out.push_str(&format!("{}Use {:?} {}\n", indent, path, at(span)));
+        }
+        ASTNode::Exit { value, span } => {
+            out.push_str(&format!("{}Exit {}\n", indent, at(span)));
+            write_tree(value, depth + 1, out);
+        }
+        ASTNode::Assert {
+            cond,
+            message,
+            span,
+        } => {
+            out.push_str(&format!("{}Assert {}\n", indent, at(span)));
+            write_tree(cond, depth + 1, out);
+            if let Some(message) = message {
+                label(out, &format!("message: {:?}", message));
+            }
+        }
+    }
+}
+
+// Version of the JSON `ast_json` writes. Bumped whenever a kind or field is
+// renamed or removed; adding one keeps it.
+pub const AST_SCHEMA_VERSION: u32 = 1;
+
+// Render a program as one JSON document, for `--emit-ast=json`:
+// `{"schema_version":1,"nodes":[...]}`. Every node is an object with its
+// `kind`, named as in `ast_tree`, its `span` (`{"line","col","len"}`, or
+// `null` for literals and blocks) and the fields of that kind. The README
+// lists them.
+pub fn ast_json(nodes: &[ASTNode]) -> String {
+    format!(
+        "{{\"schema_version\":{},\"nodes\":{}}}",
+        AST_SCHEMA_VERSION,
+        json_nodes(nodes)
+    )
+}
+
+fn json_span(span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!(
+            "{{\"line\":{},\"col\":{},\"len\":{}}}",
+            span.line, span.col, span.len
+        ),
+        None => "null".to_string(),
+    }
+}
+
+fn json_nodes(nodes: &[ASTNode]) -> String {
+    let nodes: Vec<String> = nodes.iter().map(json_node).collect();
+    format!("[{}]", nodes.join(","))
+}
+
+fn json_optional(text: Option<&str>) -> String {
+    text.map_or("null".to_string(), json_string)
+}
+
+// Parameters and struct fields alike: a name, an optional type and a span
+fn json_typed(name: &str, ty: &Option<String>, span: Span) -> String {
+    format!(
+        "{{\"name\":{},\"type\":{},\"span\":{}}}",
+        json_string(name),
+        json_optional(ty.as_deref()),
+        json_span(Some(span))
+    )
+}
+
+fn json_node(node: &ASTNode) -> String {
+    let (kind, fields): (&str, Vec<(&str, String)>) = match node {
+        ASTNode::VariableDeclaration {
+            name,
+            mutable,
+            ty,
+            value,
+            ..
+        } => (
+            "VariableDeclaration",
+            vec![
+                ("name", json_string(name)),
+                ("mutable", mutable.to_string()),
+                ("type", json_optional(ty.as_deref())),
+                (
+                    "value",
+                    value.as_deref().map_or("null".to_string(), json_node),
+                ),
+            ],
+        ),
+        ASTNode::Assignment { name, value, .. } => (
+            "Assignment",
+            vec![("name", json_string(name)), ("value", json_node(value))],
+        ),
+        ASTNode::IndexAssignment {
+            name, index, value, ..
+        } => (
+            "IndexAssignment",
+            vec![
+                ("name", json_string(name)),
+                ("index", json_node(index)),
+                ("value", json_node(value)),
+            ],
+        ),
+        ASTNode::FieldAssignment {
+            name, field, value, ..
+        } => (
+            "FieldAssignment",
+            vec![
+                ("name", json_string(name)),
+                ("field", json_string(field)),
+                ("value", json_node(value)),
+            ],
+        ),
+        ASTNode::StringLiteral(s) => ("String", vec![("value", json_string(s))]),
+        ASTNode::NumberLiteral(num) => ("Number", vec![("value", num.to_string())]),
+        ASTNode::LongLiteral(num) => ("Long", vec![("value", num.to_string())]),
+        // JSON has no infinities or NaN, which only the optimizer makes
+        ASTNode::FloatLiteral(num) => {
+            let value = if num.is_finite() {
+                format!("{:?}", num)
+            } else {
+                "null".to_string()
+            };
+            ("Float", vec![("value", value)])
+        }
+        ASTNode::BoolLiteral(b) => ("Bool", vec![("value", b.to_string())]),
+        ASTNode::Identifier(name, _) => ("Identifier", vec![("name", json_string(name))]),
+        ASTNode::ArrayLiteral(elements, _) => ("Array", vec![("elements", json_nodes(elements))]),
+        ASTNode::Index { name, index, .. } => (
+            "Index",
+            vec![("name", json_string(name)), ("index", json_node(index))],
+        ),
+        ASTNode::StructInit { name, fields, .. } => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(field, value)| {
+                    format!(
+                        "{{\"name\":{},\"value\":{}}}",
+                        json_string(field),
+                        json_node(value)
+                    )
+                })
+                .collect();
+            (
+                "StructInit",
+                vec![
+                    ("name", json_string(name)),
+                    ("fields", format!("[{}]", fields.join(","))),
+                ],
+            )
+        }
+        ASTNode::FieldAccess { name, field, .. } => (
+            "FieldAccess",
+            vec![("name", json_string(name)), ("field", json_string(field))],
+        ),
+        ASTNode::BinaryOp { op, lhs, rhs, .. } => (
+            "BinaryOp",
+            vec![
+                ("op", json_string(&op.to_string())),
+                ("lhs", json_node(lhs)),
+                ("rhs", json_node(rhs)),
+            ],
+        ),
+        ASTNode::UnaryOp { op, operand, .. } => (
+            "UnaryOp",
+            vec![
+                ("op", json_string(&op.to_string())),
+                ("operand", json_node(operand)),
+            ],
+        ),
+        ASTNode::Show(values, _) => ("Show", vec![("values", json_nodes(values))]),
+        ASTNode::If {
+            cond,
+            then_block,
+            else_block,
+            ..
+        } => (
+            "If",
+            vec![
+                ("cond", json_node(cond)),
+                ("then", json_nodes(then_block)),
+                (
+                    "else",
+                    else_block.as_deref().map_or("null".to_string(), json_nodes),
+                ),
+            ],
+        ),
+        ASTNode::For {
+            var,
+            start,
+            end,
+            body,
+            ..
+        } => (
+            "For",
+            vec![
+                ("var", json_string(var)),
+                ("start", json_node(start)),
+                ("end", json_node(end)),
+                ("body", json_nodes(body)),
+            ],
+        ),
+        ASTNode::Block(nodes) => ("Block", vec![("body", json_nodes(nodes))]),
+        ASTNode::Break(_) => ("Break", vec![]),
+        ASTNode::Continue(_) => ("Continue", vec![]),
+        ASTNode::Function {
+            name, params, body, ..
+        } => {
+            let params: Vec<String> = params
+                .iter()
+                .map(|param| json_typed(&param.name, &param.ty, param.span))
+                .collect();
+            (
+                "Function",
+                vec![
+                    ("name", json_string(name)),
+                    ("params", format!("[{}]", params.join(","))),
+                    ("body", json_nodes(body)),
+                ],
+            )
+        }
+        ASTNode::Call { name, args, .. } => (
+            "Call",
+            vec![("name", json_string(name)), ("args", json_nodes(args))],
+        ),
+        ASTNode::ExternFunction {
+            name,
+            params,
+            result,
+            ..
+        } => {
+            let params: Vec<String> = params
+                .iter()
+                .map(|param| json_typed(&param.name, &param.ty, param.span))
+                .collect();
+            (
+                "ExternFunction",
+                vec![
+                    ("name", json_string(name)),
+                    ("params", format!("[{}]", params.join(","))),
+                    ("result", json_optional(result.as_deref())),
+                ],
+            )
+        }
+        ASTNode::StructDef { name, fields, .. } => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|field| json_typed(&field.name, &field.ty, field.span))
+                .collect();
+            (
+                "StructDef",
+                vec![
+                    ("name", json_string(name)),
+                    ("fields", format!("[{}]", fields.join(","))),
+                ],
+            )
+        }
+        ASTNode::EnumDef { name, variants, .. } => {
+            let variants: Vec<String> = variants.iter().map(|v| json_string(v)).collect();
+            (
+                "EnumDef",
+                vec![
+                    ("name", json_string(name)),
+                    ("variants", format!("[{}]", variants.join(","))),
+                ],
+            )
+        }
+        ASTNode::Match {
+            value,
+            arms,
+            else_block,
+            ..
+        } => {
+            let arms: Vec<String> = arms
+                .iter()
+                .map(|arm| {
+                    format!(
+                        "{{\"variant\":{},\"body\":{},\"span\":{}}}",
+                        json_string(&arm.variant),
+                        json_nodes(&arm.body),
+                        json_span(Some(arm.span))
+                    )
+                })
+                .collect();
+            (
+                "Match",
+                vec![
+                    ("value", json_node(value)),
+                    ("arms", format!("[{}]", arms.join(","))),
+                    (
+                        "else",
+                        else_block.as_deref().map_or("null".to_string(), json_nodes),
+                    ),
+                ],
+            )
+        }
+        ASTNode::Give { value, .. } => ("Give", vec![("value", json_node(value))]),
+        ASTNode::Exit { value, .. } => ("Exit", vec![("value", json_node(value))]),
+        ASTNode::Assert { cond, message, .. } => (
+            "Assert",
+            vec![
+                ("cond", json_node(cond)),
+                ("message", json_optional(message.as_deref())),
+            ],
+        ),
+        ASTNode::Input { name, .. } => ("Input", vec![("name", json_string(name))]),
+        ASTNode::CRaw { code, .. } => ("CRaw", vec![("code", json_string(code))]),
+        ASTNode::CInclude { header, .. } => ("CInclude", vec![("header", json_string(header))]),
+        ASTNode::Link { library, .. } => ("Link", vec![("library", json_string(library))]),
+        ASTNode::Use { path, .. } => ("Use", vec![("path", json_string(path))]),
+    };
+    let mut json = format!(
+        "{{\"kind\":{},\"span\":{}",
+        json_string(kind),
+        json_span(node.span())
+    );
+    for (name, value) in fields {
+        json.push_str(&format!(",\"{}\":{}", name, value));
+    }
+    json.push('}');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::FileId;
+    use crate::parser::parse_source;
+
+    fn parsed(input: &str) -> Vec<ASTNode> {
+        parse_source(input, FileId::MAIN).unwrap()
+    }
+
+    #[test]
+    fn tree_shows_nesting_and_positions() {
+        assert_eq!(
+            ast_tree(&parsed("m x = -(1 + 2);\nshow x;")),
+            "VariableDeclaration m x @1:3\n  UnaryOp -\n    BinaryOp +\n      Number 1\n      Number 2\n\
+             Show @2:1\n  Identifier x\n"
+        );
+    }
+
+    #[test]
+    fn json_is_versioned_and_escaped() {
+        assert_eq!(
+            ast_json(&parsed("m x = 1;\nshow \"a\\\"b\", x;")),
+            format!(
+                "{{\"schema_version\":{},\"nodes\":[\
+                 {{\"kind\":\"VariableDeclaration\",\"span\":{{\"line\":1,\"col\":3,\"len\":1}},\
+                 \"name\":\"x\",\"mutable\":true,\"type\":null,\
+                 \"value\":{{\"kind\":\"Number\",\"span\":null,\"value\":1}}}},\
+                 {{\"kind\":\"Show\",\"span\":{{\"line\":2,\"col\":1,\"len\":4}},\"values\":[\
+                 {{\"kind\":\"String\",\"span\":null,\"value\":\"a\\\"b\"}},\
+                 {{\"kind\":\"Identifier\",\"span\":{{\"line\":2,\"col\":14,\"len\":1}},\"name\":\"x\"}}]}}]}}",
+                AST_SCHEMA_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn operators_display_as_written_in_bp() {
+        assert_eq!(BinaryOperator::And.to_string(), "and");
+        assert_eq!(BinaryOperator::And.c_symbol(), "&&");
+        assert_eq!(BinaryOperator::Le.to_string(), "<=");
+        assert_eq!(UnaryOperator::Not.to_string(), "not");
+        assert_eq!(UnaryOperator::Not.c_symbol(), "!");
+    }
+}
@@ -0,0 +1,651 @@
+//! Code generation targets: each turns a checked program into source code
+//! in another language, and that source into something the driver can run
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use crate::ast::ASTNode;
+use crate::codegen::transpile;
+use crate::driver::Options;
+use crate::error::{CcError, CodegenError, CompileError};
+use crate::interpreter::interprets_extern;
+use crate::js;
+use crate::lexer::Span;
+use crate::llvm;
+use crate::python;
+use crate::rust;
+use crate::visit::{walk_expression, walk_statement, Visitor};
+
+// Source generated for a program, and what building it needs
+pub struct Generated {
+    pub code: String,
+    // Library names to pass to the C compiler with `-l`, e.g. `m` for libm
+    pub libraries: Vec<String>,
+}
+
+// A language BP programs can be translated to
+pub trait Backend {
+    // Name of the language, for messages
+    fn language(&self) -> &'static str;
+
+    // Extension of the generated source file, without the dot
+    fn extension(&self) -> &'static str;
+
+    // Translate a program that passed `analyze`. `source` names the program
+    // in the messages of failed assertions.
+    fn generate(
+        &self,
+        ast: &[ASTNode],
+        source: &Path,
+        options: &Options,
+    ) -> Result<Generated, CodegenError>;
+
+    // Build the generated code, written at `code_file`, into an executable
+    // at `output`
+    fn build(
+        &self,
+        generated: &Generated,
+        code_file: &Path,
+        output: &Path,
+        options: &Options,
+    ) -> Result<(), CompileError>;
+
+    // Whether `build` links C libraries, so `link` directives and `--link`
+    // add to `Generated::libraries`
+    fn links_libraries(&self) -> bool {
+        false
+    }
+
+    // Command running the executable `build` made at `output`
+    fn run_command(&self, output: &Path) -> Command {
+        Command::new(output)
+    }
+}
+
+// The first `craw` or `cinclude` in `nodes` or the blocks nested in them,
+// or `extern fn`, unless for the `interpreter` it declares a function the
+// interpreter runs itself, as what to call it and its position
+fn find_c_only(nodes: &[ASTNode], interpreter: bool) -> Option<(String, Span)> {
+    nodes.iter().find_map(|node| match node {
+        ASTNode::CRaw { span, .. } => Some(("'craw'".to_string(), *span)),
+        ASTNode::CInclude { span, .. } => Some(("'cinclude'".to_string(), *span)),
+        ASTNode::ExternFunction {
+            name,
+            params,
+            result,
+            span,
+        } if !(interpreter && interprets_extern(name, params, result.as_deref())) => {
+            Some((format!("extern function '{}'", name), *span))
+        }
+        ASTNode::If {
+            then_block,
+            else_block,
+            ..
+        } => find_c_only(then_block, interpreter)
+            .or_else(|| find_c_only(else_block.as_deref()?, interpreter)),
+        ASTNode::Block(body) | ASTNode::For { body, .. } | ASTNode::Function { body, .. } => {
+            find_c_only(body, interpreter)
+        }
+        ASTNode::Match {
+            arms, else_block, ..
+        } => arms
+            .iter()
+            .find_map(|arm| find_c_only(&arm.body, interpreter))
+            .or_else(|| find_c_only(else_block.as_deref()?, interpreter)),
+        _ => None,
+    })
+}
+
+fn c_only_error(found: Option<(String, Span)>, by: &str) -> Result<(), CodegenError> {
+    match found {
+        Some((what, span)) => Err(CodegenError {
+            message: format!("{} only works with the C target, not {}", what, by),
+            span,
+        }),
+        None => Ok(()),
+    }
+}
+
+// Fail on raw C in a program about to be run by the interpreter, named by
+// `by`, and on extern functions other than the few it runs itself, before
+// any of the program runs
+pub fn reject_inline_c(ast: &[ASTNode], by: &str) -> Result<(), CodegenError> {
+    c_only_error(find_c_only(ast, true), by)
+}
+
+// Fail on raw C or extern functions in a program about to be translated or
+// run by something other than the C target, named by `by`
+pub fn reject_c_only(ast: &[ASTNode], by: &str) -> Result<(), CodegenError> {
+    c_only_error(find_c_only(ast, false), by)
+}
+
+// Finds the first long in a program: a literal too large for an int, or a
+// variable, parameter or field annotated `long`. Literals have no position
+// of their own, so they are reported at the enclosing node's.
+#[derive(Default)]
+struct LongFinder {
+    near: Option<Span>,
+    found: Option<Span>,
+}
+
+impl LongFinder {
+    fn check(&mut self, ty: Option<&str>, span: Span) {
+        if self.found.is_none() && ty == Some("long") {
+            self.found = Some(span);
+        }
+    }
+}
+
+impl Visitor for LongFinder {
+    fn visit_statement(&mut self, node: &ASTNode) {
+        self.near = node.span().or(self.near);
+        match node {
+            ASTNode::VariableDeclaration { ty, span, .. } => self.check(ty.as_deref(), *span),
+            ASTNode::Function { params, .. } => {
+                for param in params {
+                    self.check(param.ty.as_deref(), param.span);
+                }
+            }
+            ASTNode::StructDef { fields, .. } => {
+                for field in fields {
+                    self.check(field.ty.as_deref(), field.span);
+                }
+            }
+            _ => {}
+        }
+        walk_statement(self, node);
+    }
+
+    fn visit_expression(&mut self, node: &ASTNode) {
+        let near = self.near;
+        self.near = node.span().or(near);
+        if let (ASTNode::LongLiteral(_), None, Some(span)) = (node, self.found, self.near) {
+            self.found = Some(span);
+        }
+        walk_expression(self, node);
+        self.near = near;
+    }
+}
+
+// Fail on longs in a program about to be translated by a target other than
+// C, named by `by`. The interpreter and the vm run them.
+pub fn reject_long(ast: &[ASTNode], by: &str) -> Result<(), CodegenError> {
+    let mut finder = LongFinder::default();
+    finder.visit_block(ast);
+    match finder.found {
+        Some(span) => Err(CodegenError {
+            message: format!(
+                "'long' only works with the C target and the interpreters, not {}",
+                by
+            ),
+            span,
+        }),
+        None => Ok(()),
+    }
+}
+
+// Target chosen with `--target`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Target {
+    #[default]
+    C,
+    Js,
+    Python,
+    Rust,
+    Llvm,
+}
+
+impl Target {
+    // Names accepted by `--target`
+    pub const NAMES: &'static [&'static str] = &["c", "js", "py", "rust", "llvm"];
+
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name {
+            "c" => Some(Target::C),
+            "js" => Some(Target::Js),
+            "py" => Some(Target::Python),
+            "rust" => Some(Target::Rust),
+            "llvm" => Some(Target::Llvm),
+            _ => None,
+        }
+    }
+
+    pub fn backend(self) -> Box<dyn Backend> {
+        match self {
+            Target::C => Box::new(CBackend),
+            Target::Js => Box::new(JsBackend),
+            Target::Python => Box::new(PyBackend),
+            Target::Rust => Box::new(RustBackend),
+            Target::Llvm => Box::new(LlvmBackend),
+        }
+    }
+}
+
+// Compilers probed in PATH when neither `--cc` nor `CC` names one
+const PROBED_COMPILERS: &[&str] = &["cc", "gcc", "clang"];
+
+// A C compiler command, possibly with leading arguments (`CC="zig cc"`)
+struct CCompiler {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CCompiler {
+    fn parse(command: &str) -> Option<Self> {
+        let mut words = command.split_whitespace().map(String::from);
+        let program = words.next()?;
+        Some(CCompiler {
+            program,
+            args: words.collect(),
+        })
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        command
+    }
+}
+
+// Pick the C compiler: `--cc` first, then `$CC`, then the first of
+// `PROBED_COMPILERS` found in PATH
+fn find_c_compiler(options: &Options) -> Result<CCompiler, CompileError> {
+    let configured = options.cc.clone().or_else(|| env::var("CC").ok());
+    if let Some(compiler) = configured.as_deref().and_then(CCompiler::parse) {
+        return Ok(compiler);
+    }
+    let path = env::var_os("PATH").unwrap_or_default();
+    for name in PROBED_COMPILERS {
+        let found = env::split_paths(&path).any(|dir| with_exe_suffix(&dir.join(name)).is_file());
+        if found {
+            return Ok(CCompiler::parse(name).expect("probed names are not empty"));
+        }
+    }
+    Err(CompileError::NoCompiler {
+        tried: PROBED_COMPILERS
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    })
+}
+
+// Shell-like rendering of a command for `--verbose`
+fn command_line(command: &Command) -> String {
+    let mut words = vec![command.get_program().to_string_lossy().into_owned()];
+    words.extend(
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned()),
+    );
+    words.join(" ")
+}
+
+// Add the platform's executable suffix (`.exe` on Windows) to a path
+// without an extension
+pub(crate) fn with_exe_suffix(path: &Path) -> PathBuf {
+    if path.extension().is_some() {
+        return path.to_path_buf();
+    }
+    let mut path = path.as_os_str().to_owned();
+    path.push(env::consts::EXE_SUFFIX);
+    PathBuf::from(path)
+}
+
+// The original target: C, compiled by the system's C compiler
+pub struct CBackend;
+
+impl Backend for CBackend {
+    fn language(&self) -> &'static str {
+        "C"
+    }
+
+    fn extension(&self) -> &'static str {
+        "c"
+    }
+
+    fn generate(
+        &self,
+        ast: &[ASTNode],
+        source: &Path,
+        options: &Options,
+    ) -> Result<Generated, CodegenError> {
+        Ok(transpile(
+            ast,
+            source,
+            !options.no_line_directives,
+            options.trap_overflow,
+        ))
+    }
+
+    fn links_libraries(&self) -> bool {
+        true
+    }
+
+    fn build(
+        &self,
+        generated: &Generated,
+        code_file: &Path,
+        output: &Path,
+        options: &Options,
+    ) -> Result<(), CompileError> {
+        let compiler = find_c_compiler(options)?;
+        let mut command = compiler.command();
+        command.arg(code_file);
+        // Int overflow wraps, as in the interpreter, rather than being
+        // undefined behavior the C compiler may assume never happens
+        command.arg("-fwrapv");
+        if let Some(level) = &options.opt_level {
+            command.arg(format!("-O{}", level));
+        }
+        if options.debug {
+            command.arg("-g");
+        }
+        command.args(&options.cflags);
+        command.arg("-o").arg(output);
+        // Libraries go after the file using them, for linkers that care
+        for library in &generated.libraries {
+            command.arg(format!("-l{}", library));
+        }
+        if options.verbose {
+            println!("Using C compiler {}", compiler.program);
+            println!("Running {}", command_line(&command));
+        }
+        let result = command.output().map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => CompileError::NoCompiler {
+                tried: vec![compiler.program.clone()],
+            },
+            _ => CompileError::Io(err),
+        })?;
+        check_compiled(self.language(), &command, &result, code_file)
+    }
+}
+
+// Turn a failed run of the target's compiler into an error showing what it
+// printed
+fn check_compiled(
+    language: &'static str,
+    command: &Command,
+    result: &Output,
+    code_file: &Path,
+) -> Result<(), CompileError> {
+    if !result.status.success() {
+        return Err(CcError {
+            language,
+            command: command_line(command),
+            status: result.status.to_string(),
+            stderr: String::from_utf8_lossy(&result.stderr).into_owned(),
+            code_file: code_file.to_path_buf(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// Write `code` as an executable script started with `interpreter`, so a
+// built program also runs on its own
+fn write_script(output: &Path, interpreter: &str, code: &str) -> Result<(), CompileError> {
+    fs::write(
+        output,
+        format!("#!/usr/bin/env {}\n{}\n", interpreter, code),
+    )?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(output, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+// JavaScript, run by Node.js. There is nothing to compile: the executable
+// is the script itself, started through `node`.
+pub struct JsBackend;
+
+impl Backend for JsBackend {
+    fn language(&self) -> &'static str {
+        "JavaScript"
+    }
+
+    fn extension(&self) -> &'static str {
+        "js"
+    }
+
+    fn generate(
+        &self,
+        ast: &[ASTNode],
+        source: &Path,
+        _options: &Options,
+    ) -> Result<Generated, CodegenError> {
+        reject_c_only(ast, self.language())?;
+        reject_long(ast, self.language())?;
+        Ok(js::transpile(ast, source))
+    }
+
+    fn build(
+        &self,
+        generated: &Generated,
+        _code_file: &Path,
+        output: &Path,
+        options: &Options,
+    ) -> Result<(), CompileError> {
+        if options.verbose {
+            println!("Writing Node.js script {}", output.display());
+        }
+        write_script(output, "node", &generated.code)
+    }
+
+    fn run_command(&self, output: &Path) -> Command {
+        let mut command = Command::new("node");
+        command.arg(output);
+        command
+    }
+}
+
+// Python 3, run by `python3`. Like JavaScript, the executable is the
+// script itself.
+pub struct PyBackend;
+
+impl Backend for PyBackend {
+    fn language(&self) -> &'static str {
+        "Python"
+    }
+
+    fn extension(&self) -> &'static str {
+        "py"
+    }
+
+    fn generate(
+        &self,
+        ast: &[ASTNode],
+        source: &Path,
+        _options: &Options,
+    ) -> Result<Generated, CodegenError> {
+        reject_c_only(ast, self.language())?;
+        reject_long(ast, self.language())?;
+        Ok(python::transpile(ast, source))
+    }
+
+    fn build(
+        &self,
+        generated: &Generated,
+        _code_file: &Path,
+        output: &Path,
+        options: &Options,
+    ) -> Result<(), CompileError> {
+        if options.verbose {
+            println!("Writing Python script {}", output.display());
+        }
+        write_script(output, "python3", &generated.code)
+    }
+
+    fn run_command(&self, output: &Path) -> Command {
+        let mut command = Command::new("python3");
+        command.arg(output);
+        command
+    }
+}
+
+// Rust, compiled by `rustc`. The Rust compiler checks the types of the
+// generated code, which makes this target a check on the others.
+pub struct RustBackend;
+
+impl Backend for RustBackend {
+    fn language(&self) -> &'static str {
+        "Rust"
+    }
+
+    fn extension(&self) -> &'static str {
+        "rs"
+    }
+
+    fn generate(
+        &self,
+        ast: &[ASTNode],
+        source: &Path,
+        _options: &Options,
+    ) -> Result<Generated, CodegenError> {
+        reject_c_only(ast, self.language())?;
+        reject_long(ast, self.language())?;
+        Ok(rust::transpile(ast, source))
+    }
+
+    fn build(
+        &self,
+        _generated: &Generated,
+        code_file: &Path,
+        output: &Path,
+        options: &Options,
+    ) -> Result<(), CompileError> {
+        let mut command = Command::new("rustc");
+        command.arg("--edition=2021").arg(code_file);
+        if let Some(level) = &options.opt_level {
+            command.arg("-C").arg(format!("opt-level={}", level));
+        }
+        if options.debug {
+            command.arg("-g");
+        }
+        command.arg("-o").arg(output);
+        if options.verbose {
+            println!("Running {}", command_line(&command));
+        }
+        let result = command.output().map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => CompileError::NoTool {
+                program: "rustc".to_string(),
+                purpose: "build Rust programs".to_string(),
+            },
+            _ => CompileError::Io(err),
+        })?;
+        check_compiled(self.language(), &command, &result, code_file)
+    }
+}
+
+// LLVM IR, compiled by clang when it is the chosen C compiler, and
+// otherwise by `llc` into an object file the C compiler links
+pub struct LlvmBackend;
+
+// Major version of the LLVM `llc` belongs to, from `llc --version`
+fn llc_major_version() -> Option<u32> {
+    let output = Command::new("llc").arg("--version").output().ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+    let version = output.split("LLVM version ").nth(1)?;
+    version.split('.').next()?.trim().parse().ok()
+}
+
+impl Backend for LlvmBackend {
+    fn language(&self) -> &'static str {
+        "LLVM IR"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ll"
+    }
+
+    fn generate(
+        &self,
+        ast: &[ASTNode],
+        source: &Path,
+        _options: &Options,
+    ) -> Result<Generated, CodegenError> {
+        reject_c_only(ast, self.language())?;
+        reject_long(ast, self.language())?;
+        llvm::transpile(ast, source)
+    }
+
+    fn links_libraries(&self) -> bool {
+        true
+    }
+
+    fn build(
+        &self,
+        generated: &Generated,
+        code_file: &Path,
+        output: &Path,
+        options: &Options,
+    ) -> Result<(), CompileError> {
+        let compiler = find_c_compiler(options)?;
+        // clang reads IR itself; any other compiler only links
+        let clang = compiler.program.contains("clang");
+        let object = code_file.with_extension("o");
+        if !clang {
+            let mut command = Command::new("llc");
+            // The IR uses opaque pointers, the default from LLVM 15 on
+            if llc_major_version().is_some_and(|major| major < 15) {
+                command.arg("-opaque-pointers");
+            }
+            command.arg("-relocation-model=pic").arg("-filetype=obj");
+            if let Some(level) = &options.opt_level {
+                // `llc` has no size levels
+                let level = if level == "s" || level == "z" {
+                    "2"
+                } else {
+                    level
+                };
+                command.arg(format!("-O={}", level));
+            }
+            command.arg(code_file).arg("-o").arg(&object);
+            if options.verbose {
+                println!("Running {}", command_line(&command));
+            }
+            let result = command.output().map_err(|err| match err.kind() {
+                io::ErrorKind::NotFound => CompileError::NoTool {
+                    program: "llc".to_string(),
+                    purpose: "build LLVM IR programs".to_string(),
+                },
+                _ => CompileError::Io(err),
+            })?;
+            check_compiled(self.language(), &command, &result, code_file)?;
+        }
+        let mut command = compiler.command();
+        command.arg(if clang { code_file } else { &object });
+        if clang {
+            if let Some(level) = &options.opt_level {
+                command.arg(format!("-O{}", level));
+            }
+        }
+        if options.debug {
+            command.arg("-g");
+        }
+        command.args(&options.cflags);
+        command.arg("-o").arg(output);
+        for library in &generated.libraries {
+            command.arg(format!("-l{}", library));
+        }
+        if options.verbose {
+            println!("Using C compiler {}", compiler.program);
+            println!("Running {}", command_line(&command));
+        }
+        let result = command.output().map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => CompileError::NoCompiler {
+                tried: vec![compiler.program.clone()],
+            },
+            _ => CompileError::Io(err),
+        });
+        if !clang {
+            // Only an intermediate step, whatever the outcome
+            let _ = fs::remove_file(&object);
+        }
+        check_compiled(self.language(), &command, &result?, code_file)
+    }
+}
@@ -0,0 +1,895 @@
+//! Compiles a checked program to bytecode for the stack machine in `vm`
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{ASTNode, BinaryOperator, UnaryOperator};
+use crate::interpreter::{zero, Value};
+use crate::lexer::{FileId, Span};
+use crate::semantic::{expression_type, parameter_type, Definitions, Type, BUILTINS};
+
+// One instruction of the stack machine. Operands are local slots of the
+// running function, indices into the program's tables, or the position of
+// an instruction of the same function to jump to.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    // Push an int
+    Int(i32),
+    // Push a constant from the program's pool
+    Const(u32),
+    Pop,
+    Dup,
+    // Push the value of a local
+    Load(u32),
+    // Pop the value of a local declared here
+    Init(u32),
+    // Pop a value assigned to a local. Ints assigned to longs or floats
+    // become longs or floats, as in C.
+    Store(u32),
+    // Pop an index and push the element of the array in a local
+    LoadIndex(u32),
+    // Pop a value and an index, and store the value in that element
+    StoreIndex(u32),
+    // Push a field of the struct in a local, by position in its definition
+    LoadField(u32, u32),
+    // Pop a value assigned to a field of the struct in a local
+    StoreField(u32, u32),
+    // Pop that many ints and push an array of them
+    Array(u32),
+    // Pop the field values of a struct literal and push the struct its
+    // layout describes
+    Struct(u32),
+    // Turn an int on top of the stack into a long
+    ToLong,
+    // Turn an int on top of the stack into a float
+    ToFloat,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Neg,
+    Not,
+    Jump(u32),
+    // Pop a bool and jump if it is false
+    JumpIfFalse(u32),
+    // Pop a bool and jump if it is true
+    JumpIfTrue(u32),
+    // Pop the arguments of a function and run it, which pushes its result
+    Call(u32),
+    // Pop the arguments of a builtin, by position in `BUILTINS`, and push
+    // its result
+    Builtin(u32),
+    // Pop the result of the running function and go back to its caller
+    Return,
+    // Pop that many values and show them on one line
+    Print(u32),
+    // Read a line into a local
+    Input(u32),
+    // Pop a bool and stop the program when it is false, with the message
+    // constant if there is one
+    Assert(Option<u32>),
+    // Pop an int and stop the program with it as exit code
+    Exit,
+}
+
+impl Op {
+    // Instruction for a binary operator that does not short-circuit
+    fn binary(op: BinaryOperator) -> Op {
+        match op {
+            BinaryOperator::Add => Op::Add,
+            BinaryOperator::Sub => Op::Sub,
+            BinaryOperator::Mul => Op::Mul,
+            BinaryOperator::Div => Op::Div,
+            BinaryOperator::Mod => Op::Mod,
+            BinaryOperator::Eq => Op::Eq,
+            BinaryOperator::Ne => Op::Ne,
+            BinaryOperator::Lt => Op::Lt,
+            BinaryOperator::Gt => Op::Gt,
+            BinaryOperator::Le => Op::Le,
+            BinaryOperator::Ge => Op::Ge,
+            BinaryOperator::And | BinaryOperator::Or => unreachable!("compiled as jumps"),
+        }
+    }
+
+    // The operator an arithmetic or comparison instruction applies
+    pub(crate) fn operator(self) -> Option<BinaryOperator> {
+        match self {
+            Op::Add => Some(BinaryOperator::Add),
+            Op::Sub => Some(BinaryOperator::Sub),
+            Op::Mul => Some(BinaryOperator::Mul),
+            Op::Div => Some(BinaryOperator::Div),
+            Op::Mod => Some(BinaryOperator::Mod),
+            Op::Eq => Some(BinaryOperator::Eq),
+            Op::Ne => Some(BinaryOperator::Ne),
+            Op::Lt => Some(BinaryOperator::Lt),
+            Op::Gt => Some(BinaryOperator::Gt),
+            Op::Le => Some(BinaryOperator::Le),
+            Op::Ge => Some(BinaryOperator::Ge),
+            _ => None,
+        }
+    }
+
+    // Name of the instruction in disassembly
+    fn mnemonic(self) -> &'static str {
+        match self {
+            Op::Int(_) => "int",
+            Op::Const(_) => "const",
+            Op::Pop => "pop",
+            Op::Dup => "dup",
+            Op::Load(_) => "load",
+            Op::Init(_) => "init",
+            Op::Store(_) => "store",
+            Op::LoadIndex(_) => "load_index",
+            Op::StoreIndex(_) => "store_index",
+            Op::LoadField(..) => "load_field",
+            Op::StoreField(..) => "store_field",
+            Op::Array(_) => "array",
+            Op::Struct(_) => "struct",
+            Op::ToLong => "to_long",
+            Op::ToFloat => "to_float",
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Mul => "mul",
+            Op::Div => "div",
+            Op::Mod => "mod",
+            Op::Eq => "eq",
+            Op::Ne => "ne",
+            Op::Lt => "lt",
+            Op::Gt => "gt",
+            Op::Le => "le",
+            Op::Ge => "ge",
+            Op::Neg => "neg",
+            Op::Not => "not",
+            Op::Jump(_) => "jump",
+            Op::JumpIfFalse(_) => "jump_if_false",
+            Op::JumpIfTrue(_) => "jump_if_true",
+            Op::Call(_) => "call",
+            Op::Builtin(_) => "builtin",
+            Op::Return => "return",
+            Op::Print(_) => "print",
+            Op::Input(_) => "input",
+            Op::Assert(_) => "assert",
+            Op::Exit => "exit",
+        }
+    }
+}
+
+// The top-level code of a program, or one of its functions
+#[derive(Default)]
+pub struct Function {
+    pub name: String,
+    // Types of the parameters, which take the first local slots
+    pub params: Vec<Type>,
+    // Name of each local slot. Every declaration gets a slot of its own, so
+    // shadowing variables never share one.
+    pub locals: Vec<String>,
+    pub code: Vec<Op>,
+    // Source position of each instruction, for runtime errors
+    pub spans: Vec<Span>,
+}
+
+// How `Op::Struct` builds a struct from a literal's values, which are
+// pushed in the order they are written
+pub struct StructLayout {
+    pub name: String,
+    // Every field in definition order, with the position of its value
+    // among the pushed ones and its type
+    pub fields: Vec<(String, usize, Type)>,
+}
+
+// A whole program in bytecode. The top-level code is function 0.
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub constants: Vec<Value>,
+    pub structs: Vec<StructLayout>,
+}
+
+// Name of the function holding the top-level code, which no BP function
+// can have
+const MAIN: &str = "<main>";
+
+// A variable visible to the code being compiled
+struct Local {
+    slot: u32,
+    ty: Type,
+}
+
+// Jumps out of a loop, patched once the loop's end is known
+#[derive(Default)]
+struct Loop {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+// Compiles one function at a time, keeping block scopes of locals like
+// the analyzer does
+#[derive(Default)]
+struct Compiler {
+    definitions: Definitions,
+    function_ids: HashMap<String, u32>,
+    constants: Vec<Value>,
+    structs: Vec<StructLayout>,
+    function: Function,
+    scopes: Vec<HashMap<String, Local>>,
+    loops: Vec<Loop>,
+}
+
+impl Compiler {
+    // Append an instruction, returning its position
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.function.code.push(op);
+        self.function.spans.push(span);
+        self.function.code.len() - 1
+    }
+
+    // Position the next instruction will have
+    fn here(&self) -> u32 {
+        self.function.code.len() as u32
+    }
+
+    // Point the jump at `at` to the next instruction
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        self.function.code[at] = match self.function.code[at] {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::JumpIfTrue(_) => Op::JumpIfTrue(target),
+            op => unreachable!("not a jump: {:?}", op),
+        };
+    }
+
+    fn constant(&mut self, value: Value) -> u32 {
+        if let Some(index) = self.constants.iter().position(|other| *other == value) {
+            return index as u32;
+        }
+        self.constants.push(value);
+        (self.constants.len() - 1) as u32
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Local> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn slot(&self, name: &str) -> u32 {
+        self.lookup(name)
+            .expect("variable was checked by analyze")
+            .slot
+    }
+
+    // A new slot for a variable declared in the innermost scope
+    fn declare(&mut self, name: &str, ty: Type) -> u32 {
+        let slot = self.function.locals.len() as u32;
+        self.function.locals.push(name.to_string());
+        self.scopes
+            .last_mut()
+            .expect("compiler always has a scope")
+            .insert(name.to_string(), Local { slot, ty });
+        slot
+    }
+
+    fn checked_type(&self, node: &ASTNode) -> Type {
+        let variables = |name: &str| self.lookup(name).map(|local| local.ty.clone());
+        expression_type(node, &variables, &self.definitions)
+            .expect("expression was type checked by analyze")
+    }
+
+    // Position of `field` in the definition of the struct held by `name`
+    fn field(&self, name: &str, field: &str) -> u32 {
+        let Some(Local {
+            ty: Type::Struct(struct_name),
+            ..
+        }) = self.lookup(name)
+        else {
+            unreachable!("type checked as a struct: {}", name);
+        };
+        self.definitions.structs[struct_name]
+            .iter()
+            .position(|(other, _)| other == field)
+            .expect("field was type checked") as u32
+    }
+
+    // Compile an expression leaving its value on the stack. `span` is the
+    // position of the nearest enclosing node that has one.
+    fn expression(&mut self, node: &ASTNode, span: Span) {
+        match node {
+            ASTNode::NumberLiteral(num) => {
+                self.emit(Op::Int(*num), span);
+            }
+            ASTNode::LongLiteral(num) => {
+                let index = self.constant(Value::Long(*num));
+                self.emit(Op::Const(index), span);
+            }
+            ASTNode::FloatLiteral(num) => {
+                let index = self.constant(Value::Float(*num));
+                self.emit(Op::Const(index), span);
+            }
+            ASTNode::BoolLiteral(b) => {
+                let index = self.constant(Value::Bool(*b));
+                self.emit(Op::Const(index), span);
+            }
+            ASTNode::StringLiteral(s) => {
+                let index = self.constant(Value::Str(s.clone()));
+                self.emit(Op::Const(index), span);
+            }
+            ASTNode::Identifier(name, span) => {
+                let slot = self.slot(name);
+                self.emit(Op::Load(slot), *span);
+            }
+            ASTNode::ArrayLiteral(elements, span) => {
+                for element in elements {
+                    self.expression(element, *span);
+                }
+                self.emit(Op::Array(elements.len() as u32), *span);
+            }
+            ASTNode::Index { name, index, span } => {
+                self.expression(index, *span);
+                let slot = self.slot(name);
+                self.emit(Op::LoadIndex(slot), *span);
+            }
+            // Not a variable, so an enum variant
+            ASTNode::FieldAccess { name, field, span } if self.lookup(name).is_none() => {
+                let index = self.constant(Value::Enum {
+                    name: name.clone(),
+                    variant: field.clone(),
+                });
+                self.emit(Op::Const(index), *span);
+            }
+            ASTNode::FieldAccess { name, field, span } => {
+                let op = Op::LoadField(self.slot(name), self.field(name, field));
+                self.emit(op, *span);
+            }
+            ASTNode::StructInit { name, fields, span } => {
+                for (_, value) in fields {
+                    self.expression(value, *span);
+                }
+                let layout = StructLayout {
+                    name: name.clone(),
+                    fields: self.definitions.structs[name]
+                        .iter()
+                        .map(|(field, ty)| {
+                            let given = fields
+                                .iter()
+                                .position(|(given, _)| given == field)
+                                .expect("every field was given");
+                            (field.clone(), given, ty.clone())
+                        })
+                        .collect(),
+                };
+                self.structs.push(layout);
+                self.emit(Op::Struct(self.structs.len() as u32 - 1), *span);
+            }
+            ASTNode::UnaryOp { op, operand, span } => {
+                self.expression(operand, *span);
+                let op = match op {
+                    UnaryOperator::Neg => Op::Neg,
+                    UnaryOperator::Not => Op::Not,
+                };
+                self.emit(op, *span);
+            }
+            // `and` and `or` leave their left operand when it decides the
+            // result, without evaluating the right one
+            ASTNode::BinaryOp {
+                op: op @ (BinaryOperator::And | BinaryOperator::Or),
+                lhs,
+                rhs,
+                span,
+            } => {
+                self.expression(lhs, *span);
+                self.emit(Op::Dup, *span);
+                let jump = match op {
+                    BinaryOperator::And => Op::JumpIfFalse(0),
+                    _ => Op::JumpIfTrue(0),
+                };
+                let end = self.emit(jump, *span);
+                self.emit(Op::Pop, *span);
+                self.expression(rhs, *span);
+                self.patch(end);
+            }
+            ASTNode::BinaryOp { op, lhs, rhs, span } => {
+                self.expression(lhs, *span);
+                self.expression(rhs, *span);
+                self.emit(Op::binary(*op), *span);
+            }
+            ASTNode::Call { name, args, span } => {
+                for arg in args {
+                    self.expression(arg, *span);
+                }
+                let op = match BUILTINS.iter().position(|builtin| builtin.name == name) {
+                    Some(index) => Op::Builtin(index as u32),
+                    None => Op::Call(self.function_ids[name]),
+                };
+                self.emit(op, *span);
+            }
+            _ => unreachable!("not an expression: {:?}", node),
+        }
+    }
+
+    // Compile statements in a scope of their own
+    fn block(&mut self, nodes: &[ASTNode], span: Span) {
+        self.scopes.push(HashMap::new());
+        self.statements(nodes, span);
+        self.scopes.pop();
+    }
+
+    fn statements(&mut self, nodes: &[ASTNode], span: Span) {
+        for node in nodes {
+            self.statement(node, span);
+        }
+    }
+
+    fn statement(&mut self, node: &ASTNode, outer: Span) {
+        match node {
+            ASTNode::VariableDeclaration {
+                name,
+                ty,
+                value,
+                span,
+                ..
+            } => {
+                let declared = ty
+                    .as_ref()
+                    .map(|ty| Type::from_name(ty).expect("annotation was checked by analyze"));
+                let ty = match (value, declared) {
+                    (Some(value), declared) => {
+                        let ty = match value.as_ref() {
+                            ASTNode::ArrayLiteral(elements, _) => Type::Array(elements.len()),
+                            ASTNode::StructInit { name, .. } => Type::Struct(name.clone()),
+                            value => self.checked_type(value),
+                        };
+                        self.expression(value, *span);
+                        match declared {
+                            Some(Type::Long) if ty == Type::Int => {
+                                self.emit(Op::ToLong, *span);
+                                Type::Long
+                            }
+                            Some(Type::Float) if ty == Type::Int => {
+                                self.emit(Op::ToFloat, *span);
+                                Type::Float
+                            }
+                            Some(declared) => declared,
+                            None => ty,
+                        }
+                    }
+                    // Assigned before it is read, as checked by analyze
+                    (None, Some(ty)) => {
+                        let index = self.constant(zero(&ty));
+                        self.emit(Op::Const(index), *span);
+                        ty
+                    }
+                    (None, None) => unreachable!("the parser requires a value or a type"),
+                };
+                // Declared after its value, which may read the variable it
+                // shadows
+                let slot = self.declare(name, ty);
+                self.emit(Op::Init(slot), *span);
+            }
+            ASTNode::Assignment { name, value, span } => {
+                self.expression(value, *span);
+                let slot = self.slot(name);
+                self.emit(Op::Store(slot), *span);
+            }
+            ASTNode::FieldAssignment {
+                name,
+                field,
+                value,
+                span,
+            } => {
+                self.expression(value, *span);
+                let op = Op::StoreField(self.slot(name), self.field(name, field));
+                self.emit(op, *span);
+            }
+            ASTNode::IndexAssignment {
+                name,
+                index,
+                value,
+                span,
+            } => {
+                self.expression(index, *span);
+                self.expression(value, *span);
+                let slot = self.slot(name);
+                self.emit(Op::StoreIndex(slot), *span);
+            }
+            ASTNode::Show(values, _) => {
+                for value in values {
+                    self.expression(value, outer);
+                }
+                self.emit(Op::Print(values.len() as u32), outer);
+            }
+            ASTNode::If {
+                cond,
+                then_block,
+                else_block,
+                span,
+            } => {
+                self.expression(cond, *span);
+                let skip_then = self.emit(Op::JumpIfFalse(0), *span);
+                self.block(then_block, *span);
+                match else_block {
+                    Some(else_block) => {
+                        let skip_else = self.emit(Op::Jump(0), *span);
+                        self.patch(skip_then);
+                        self.block(else_block, *span);
+                        self.patch(skip_else);
+                    }
+                    None => self.patch(skip_then),
+                }
+            }
+            // Like the generated C loop: the end is evaluated before every
+            // iteration, the body may change the counter, and the loop stops
+            // once the counter reaches the largest int
+            ASTNode::For {
+                var,
+                start,
+                end,
+                body,
+                span,
+            } => {
+                self.expression(start, *span);
+                self.scopes.push(HashMap::new());
+                let counter = self.declare(var, Type::Int);
+                self.emit(Op::Init(counter), *span);
+                let top = self.here();
+                self.emit(Op::Load(counter), *span);
+                self.expression(end, *span);
+                self.emit(Op::Le, *span);
+                let exit = self.emit(Op::JumpIfFalse(0), *span);
+                self.loops.push(Loop::default());
+                self.block(body, *span);
+                let jumps = self.loops.pop().expect("pushed above");
+                for jump in jumps.continues {
+                    self.patch(jump);
+                }
+                self.emit(Op::Load(counter), *span);
+                self.emit(Op::Int(i32::MAX), *span);
+                self.emit(Op::Lt, *span);
+                let last = self.emit(Op::JumpIfFalse(0), *span);
+                self.emit(Op::Load(counter), *span);
+                self.emit(Op::Int(1), *span);
+                self.emit(Op::Add, *span);
+                self.emit(Op::Init(counter), *span);
+                self.emit(Op::Jump(top), *span);
+                self.patch(exit);
+                self.patch(last);
+                for jump in jumps.breaks {
+                    self.patch(jump);
+                }
+                self.scopes.pop();
+            }
+            ASTNode::Block(nodes) => self.block(nodes, outer),
+            // The value stays on the stack while the arms compare it with
+            // their variant
+            ASTNode::Match {
+                value,
+                arms,
+                else_block,
+                span,
+            } => {
+                let Type::Enum(enum_name) = self.checked_type(value) else {
+                    unreachable!("match value was type checked as an enum");
+                };
+                self.expression(value, *span);
+                let mut ends = Vec::new();
+                for arm in arms {
+                    self.emit(Op::Dup, arm.span);
+                    let variant = self.constant(Value::Enum {
+                        name: enum_name.clone(),
+                        variant: arm.variant.clone(),
+                    });
+                    self.emit(Op::Const(variant), arm.span);
+                    self.emit(Op::Eq, arm.span);
+                    let next = self.emit(Op::JumpIfFalse(0), arm.span);
+                    self.emit(Op::Pop, arm.span);
+                    self.block(&arm.body, arm.span);
+                    ends.push(self.emit(Op::Jump(0), arm.span));
+                    self.patch(next);
+                }
+                self.emit(Op::Pop, *span);
+                if let Some(else_block) = else_block {
+                    self.block(else_block, *span);
+                }
+                for end in ends {
+                    self.patch(end);
+                }
+            }
+            // The result of a statement call is discarded
+            ASTNode::Call { span, .. } => {
+                self.expression(node, *span);
+                self.emit(Op::Pop, *span);
+            }
+            ASTNode::Give { value, span } => {
+                self.expression(value, *span);
+                self.emit(Op::Return, *span);
+            }
+            ASTNode::Input { name, span } => {
+                let slot = self.slot(name);
+                self.emit(Op::Input(slot), *span);
+            }
+            ASTNode::Exit { value, span } => {
+                self.expression(value, *span);
+                self.emit(Op::Exit, *span);
+            }
+            ASTNode::Assert {
+                cond,
+                message,
+                span,
+            } => {
+                self.expression(cond, *span);
+                let message = message
+                    .as_ref()
+                    .map(|message| self.constant(Value::Str(message.clone())));
+                self.emit(Op::Assert(message), *span);
+            }
+            ASTNode::Break(span) => {
+                let jump = self.emit(Op::Jump(0), *span);
+                let innermost = self
+                    .loops
+                    .last_mut()
+                    .expect("analyze allows break in loops");
+                innermost.breaks.push(jump);
+            }
+            ASTNode::Continue(span) => {
+                let jump = self.emit(Op::Jump(0), *span);
+                let innermost = self
+                    .loops
+                    .last_mut()
+                    .expect("analyze allows continue in loops");
+                innermost.continues.push(jump);
+            }
+            // Compiled on their own by `compile`
+            ASTNode::Function { .. }
+            | ASTNode::StructDef { .. }
+            | ASTNode::EnumDef { .. }
+            | ASTNode::Link { .. } => {}
+            _ => unreachable!("not a statement: {:?}", node),
+        }
+    }
+
+    // Compile a function body, or the top-level code, into a function of
+    // its own. Falling off the end gives 0.
+    fn function(&mut self, name: &str, params: Vec<(String, Type)>, body: &[ASTNode], span: Span) {
+        self.function = Function {
+            name: name.to_string(),
+            params: params.iter().map(|(_, ty)| ty.clone()).collect(),
+            ..Function::default()
+        };
+        self.scopes = vec![HashMap::new()];
+        for (param, ty) in params {
+            self.declare(&param, ty);
+        }
+        self.statements(body, span);
+        self.emit(Op::Int(0), span);
+        self.emit(Op::Return, span);
+    }
+}
+
+// Compile a program that passed `analyze` to bytecode
+pub fn compile(ast: &[ASTNode]) -> Program {
+    let mut compiler = Compiler::default();
+    compiler.definitions.add_checked(ast);
+    let functions: Vec<_> = ast
+        .iter()
+        .filter_map(|node| match node {
+            ASTNode::Function {
+                name,
+                params,
+                body,
+                span,
+            } => Some((name, params, body, *span)),
+            _ => None,
+        })
+        .collect();
+    for (id, (name, ..)) in functions.iter().enumerate() {
+        compiler
+            .function_ids
+            .insert(name.to_string(), id as u32 + 1);
+    }
+
+    let start = Span {
+        line: 1,
+        col: 1,
+        len: 0,
+        file: FileId::MAIN,
+    };
+    compiler.function(MAIN, Vec::new(), ast, start);
+    let mut compiled = vec![std::mem::take(&mut compiler.function)];
+    for (name, params, body, span) in functions {
+        let params = params
+            .iter()
+            .map(|param| {
+                let ty = parameter_type(param).expect("parameter was checked by analyze");
+                (param.name.clone(), ty)
+            })
+            .collect();
+        compiler.function(name, params, body, span);
+        compiled.push(std::mem::take(&mut compiler.function));
+    }
+    Program {
+        functions: compiled,
+        constants: compiler.constants,
+        structs: compiler.structs,
+    }
+}
+
+// Constants are shown as BP literals
+fn literal(value: &Value) -> String {
+    match value {
+        Value::Str(s) => format!("{:?}", s),
+        Value::Float(num) => format!("{:?}", num),
+        Value::Enum { name, variant } => format!("{}.{}", name, variant),
+        value => value.to_string(),
+    }
+}
+
+// Disassembly for `--emit-bytecode`: each function with one instruction per
+// line, its position, source line and column, and what its operands refer to
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (id, function) in self.functions.iter().enumerate() {
+            if id > 0 {
+                writeln!(f)?;
+            }
+            writeln!(
+                f,
+                "fn {} ({} params, {} locals)",
+                function.name,
+                function.params.len(),
+                function.locals.len()
+            )?;
+            for (at, (op, span)) in function.code.iter().zip(&function.spans).enumerate() {
+                let local = |slot: &u32| &function.locals[*slot as usize];
+                let operands = match op {
+                    Op::Int(num) => num.to_string(),
+                    Op::Const(index) => literal(&self.constants[*index as usize]),
+                    Op::Load(slot)
+                    | Op::Init(slot)
+                    | Op::Store(slot)
+                    | Op::LoadIndex(slot)
+                    | Op::StoreIndex(slot)
+                    | Op::Input(slot) => format!("{} ({})", slot, local(slot)),
+                    Op::LoadField(slot, field) | Op::StoreField(slot, field) => {
+                        format!("{} {} ({}.{})", slot, field, local(slot), field)
+                    }
+                    Op::Array(len) | Op::Print(len) => len.to_string(),
+                    Op::Struct(index) => self.structs[*index as usize].name.clone(),
+                    Op::Jump(target) | Op::JumpIfFalse(target) | Op::JumpIfTrue(target) => {
+                        target.to_string()
+                    }
+                    Op::Call(id) => self.functions[*id as usize].name.clone(),
+                    Op::Builtin(index) => BUILTINS[*index as usize].name.to_string(),
+                    Op::Assert(Some(index)) => literal(&self.constants[*index as usize]),
+                    _ => String::new(),
+                };
+                let instruction = format!("{} {}", op.mnemonic(), operands);
+                writeln!(
+                    f,
+                    "{:>5} {:>4}:{:<4} {}",
+                    at,
+                    span.line,
+                    span.col,
+                    instruction.trim_end()
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+    use crate::semantic::analyze;
+
+    // Disassembly of `input`'s bytecode, without positions
+    fn ops(input: &str) -> Vec<String> {
+        let ast = parse_source(input, FileId::MAIN).unwrap();
+        analyze(&ast).unwrap();
+        compile(&ast)
+            .to_string()
+            .lines()
+            .map(|line| match line.split_once(':') {
+                Some((_, rest)) if !line.starts_with("fn ") => rest
+                    .trim_start_matches(|ch: char| ch.is_ascii_digit())
+                    .trim()
+                    .to_string(),
+                _ => line.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn declarations_and_show() {
+        assert_eq!(
+            ops("m x = 2;\nshow x + 1;"),
+            [
+                "fn <main> (0 params, 1 locals)",
+                "int 2",
+                "init 0 (x)",
+                "load 0 (x)",
+                "int 1",
+                "add",
+                "print 1",
+                "int 0",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_share_constants() {
+        assert_eq!(
+            ops("show \"a\", \"a\";"),
+            [
+                "fn <main> (0 params, 0 locals)",
+                "const \"a\"",
+                "const \"a\"",
+                "print 2",
+                "int 0",
+                "return",
+            ]
+        );
+        let ast = parse_source("show \"a\", \"a\";", FileId::MAIN).unwrap();
+        assert_eq!(compile(&ast).constants, [Value::Str("a".to_string())]);
+    }
+
+    #[test]
+    fn and_short_circuits_with_a_jump() {
+        assert_eq!(
+            ops("m t = true;\nshow t and t;"),
+            [
+                "fn <main> (0 params, 1 locals)",
+                "const true",
+                "init 0 (t)",
+                "load 0 (t)",
+                "dup",
+                "jump_if_false 7",
+                "pop",
+                "load 0 (t)",
+                "print 1",
+                "int 0",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn functions_follow_the_top_level_code() {
+        assert_eq!(
+            ops("fn inc(n) {\n    give n + 1;\n}\nshow inc(1);"),
+            [
+                "fn <main> (0 params, 0 locals)",
+                "int 1",
+                "call inc",
+                "print 1",
+                "int 0",
+                "return",
+                "",
+                "fn inc (1 params, 1 locals)",
+                "load 0 (n)",
+                "int 1",
+                "add",
+                "return",
+                "int 0",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn for_stops_before_stepping_past_the_largest_int() {
+        let ops = ops("for i = 1 to 3 {\n    show i;\n}");
+        let check = ops
+            .iter()
+            .position(|op| op == "int 2147483647")
+            .expect("a check of the counter");
+        assert_eq!(ops[check + 1], "lt");
+        assert!(ops[check + 2].starts_with("jump_if_false "));
+    }
+}
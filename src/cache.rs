@@ -0,0 +1,117 @@
+//! Cache of built programs, so running an unchanged program skips the
+//! whole pipeline and the C compiler
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::driver::Options;
+
+// Directory used when no home directory is known
+const LOCAL_CACHE: &str = ".bp-cache";
+
+// Where built programs are kept: `$XDG_CACHE_HOME/bplang`, then
+// `~/.cache/bplang`, and `.bp-cache` in the current directory otherwise
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir).join("bplang");
+    }
+    match env::var_os("HOME").filter(|dir| !dir.is_empty()) {
+        Some(home) => PathBuf::from(home).join(".cache").join("bplang"),
+        None => PathBuf::from(LOCAL_CACHE),
+    }
+}
+
+// 128-bit FNV-1a. Unlike `DefaultHasher` it gives the same hash across Rust
+// releases, so cached programs outlive a toolchain upgrade.
+struct Fnv(u128);
+
+impl Fnv {
+    const OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u128;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+        // A separator, so ("ab", "c") and ("a", "bc") differ
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(Self::PRIME);
+    }
+}
+
+// Name of the cached build of a program with `options`: a hash of its
+// `sources`, the program's and those of the files it uses, each with the
+// path messages name it by, the bplang version and every setting that
+// changes the binary or whether it is built
+pub fn key(sources: &[(&Path, &str)], options: &Options) -> String {
+    let mut hash = Fnv(Fnv::OFFSET);
+    hash.write(env!("CARGO_PKG_VERSION").as_bytes());
+    for (path, source_code) in sources {
+        hash.write(path.to_string_lossy().as_bytes());
+        hash.write(source_code.as_bytes());
+    }
+    hash.write(format!("{:?}", options.target).as_bytes());
+    let compiler = options.cc.clone().or_else(|| env::var("CC").ok());
+    hash.write(compiler.unwrap_or_default().as_bytes());
+    hash.write(options.opt_level.as_deref().unwrap_or_default().as_bytes());
+    hash.write(&[
+        options.debug as u8,
+        options.no_opt as u8,
+        options.no_line_directives as u8,
+        options.trap_overflow as u8,
+        options.deny_warnings as u8,
+    ]);
+    for flag in &options.cflags {
+        hash.write(flag.as_bytes());
+    }
+    for library in &options.links {
+        hash.write(library.as_bytes());
+    }
+    format!("{:032x}", hash.0)
+}
+
+// Path of the cached build named `key`
+fn entry(key: &str) -> PathBuf {
+    cache_dir().join(format!("{}{}", key, env::consts::EXE_SUFFIX))
+}
+
+// The cached build named `key`, if there is one
+pub fn lookup(key: &str) -> Option<PathBuf> {
+    let path = entry(key);
+    path.is_file().then_some(path)
+}
+
+// Keep a copy of the program built at `binary` as `key`. The copy is
+// renamed into place, so a concurrent run never sees half of it.
+pub fn store(key: &str, binary: &Path) -> io::Result<()> {
+    let dir = cache_dir();
+    fs::create_dir_all(&dir)?;
+    let partial = dir.join(format!("{}.{}.tmp", key, std::process::id()));
+    fs::copy(binary, &partial)?;
+    fs::rename(&partial, entry(key)).inspect_err(|_| {
+        let _ = fs::remove_file(&partial);
+    })
+}
+
+// Remove every cached build, returning how many there were
+pub fn clean() -> io::Result<usize> {
+    let dir = cache_dir();
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let mut removed = 0;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    fs::remove_dir(&dir)?;
+    Ok(removed)
+}
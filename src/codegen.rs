@@ -0,0 +1,1904 @@
+//! Translates a checked program into C source
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::ast::{ASTNode, BinaryOperator, Parameter, UnaryOperator};
+use crate::backend::Generated;
+use crate::error::{assertion_failure, ASSERT_EXIT_CODE};
+use crate::lexer::Span;
+use crate::module;
+use crate::semantic::{builtin, expression_type, parameter_type, Definitions, Returns, Type};
+use crate::writer::CodeWriter;
+
+// C identifier for a BP variable, parameter or function. Every user name
+// gets a prefix so it can never collide with a C keyword, a libc function or
+// the generated `main`. Diagnostics keep using the BP name.
+fn c_name(name: &str) -> String {
+    format!("bp_{}", name)
+}
+
+// BP names that raw C from `craw` refers to by their C identifiers, so the
+// variables it uses count as read
+pub(crate) fn raw_c_names(code: &str) -> impl Iterator<Item = &str> {
+    code.split(|ch: char| !ch.is_ascii_alphanumeric() && ch != '_')
+        .filter_map(|word| word.strip_prefix("bp_"))
+}
+
+// C name under which a function receives string parameter `name`
+fn c_param_name(name: &str) -> String {
+    format!("bprt_param_{}", name)
+}
+
+// C name of a struct or enum type. Types get a prefix of their own, so a
+// variable may share a type's name.
+fn c_type_name(name: &str) -> String {
+    format!("bpt_{}", name)
+}
+
+// C enumeration constant for a variant of enum `name`
+fn c_variant(name: &str, variant: &str) -> String {
+    format!("bpv_{}_{}", name, variant)
+}
+
+// Array of the variant names of enum `name`, indexed by value, used to show
+// enum values
+fn c_variant_names(name: &str) -> String {
+    format!("bprt_names_{}", name)
+}
+
+// C type holding a value of type `ty`
+fn c_type(ty: &Type) -> String {
+    match ty {
+        Type::Struct(name) | Type::Enum(name) => c_type_name(name),
+        ty => ty.c_type().to_string(),
+    }
+}
+
+// Stands where the C after a body goes back to being the C file's own, until
+// `c_file_lines` knows the line numbers to put in its place
+const BACK_TO_C: &str = "#line bprt_back_to_c";
+
+// Headers every program includes
+const BASE_HEADERS: &[&str] = &["stdbool.h", "stdio.h", "stdlib.h"];
+
+// Runtime support for `input`. Helper names start with `bprt_`, which no
+// mangled user name can. Bad or missing input ends the program with a
+// message, like a failed `input` in the interpreter.
+const INPUT_RUNTIME: &str = r#"static void bprt_input_error(const char *message, const char *line) {
+    fflush(stdout);
+    if (line) {
+        fprintf(stderr, "error: %s: '%s'\n", message, line);
+    } else {
+        fprintf(stderr, "error: %s\n", message);
+    }
+    exit(1);
+}
+
+// Read one line from stdin without its line ending
+static char *bprt_input_line(void) {
+    size_t capacity = 128, length = 0;
+    char *line = malloc(capacity);
+    fflush(stdout);
+    while (line && fgets(line + length, (int)(capacity - length), stdin)) {
+        length += strlen(line + length);
+        if (length > 0 && line[length - 1] == '\n') {
+            break;
+        }
+        capacity *= 2;
+        line = realloc(line, capacity);
+    }
+    if (!line) {
+        bprt_input_error("out of memory reading input", NULL);
+    }
+    if (length == 0) {
+        bprt_input_error("unexpected end of input", NULL);
+    }
+    while (length > 0 && (line[length - 1] == '\n' || line[length - 1] == '\r')) {
+        line[--length] = '\0';
+    }
+    return line;
+}
+
+static int bprt_input_int(void) {
+    char *line = bprt_input_line();
+    int value = 0;
+    switch (bprt_parse_int(line, &value)) {
+    case BPRT_NOT_AN_INT:
+        bprt_input_error("input is not an integer", line);
+        break;
+    case BPRT_OUT_OF_RANGE:
+        bprt_input_error("input integer out of range", line);
+        break;
+    }
+    free(line);
+    return value;
+}
+"#;
+
+// Parsing text as an int for `input` and `to_int`: the whole text must be
+// a decimal number, optionally surrounded by blanks
+const PARSE_INT_RUNTIME: &str = r#"enum { BPRT_PARSED, BPRT_NOT_AN_INT, BPRT_OUT_OF_RANGE };
+
+static int bprt_parse_int(const char *text, int *value) {
+    char *end;
+    errno = 0;
+    long result = strtol(text, &end, 10);
+    while (*end == ' ' || *end == '\t') {
+        end++;
+    }
+    if (end == text || *end != '\0') {
+        return BPRT_NOT_AN_INT;
+    }
+    if (errno == ERANGE || result < INT_MIN || result > INT_MAX) {
+        return BPRT_OUT_OF_RANGE;
+    }
+    *value = (int)result;
+    return BPRT_PARSED;
+}
+"#;
+
+// `to_int`, which ends the program on text that is not an int
+const TO_INT_RUNTIME: &str = r#"static int bprt_to_int(const char *s) {
+    int value = 0;
+    switch (bprt_parse_int(s, &value)) {
+    case BPRT_NOT_AN_INT:
+        fflush(stdout);
+        fprintf(stderr, "error: to_int: '%s' is not an integer\n", s);
+        exit(1);
+    case BPRT_OUT_OF_RANGE:
+        fflush(stdout);
+        fprintf(stderr, "error: to_int: '%s' is out of range\n", s);
+        exit(1);
+    }
+    return value;
+}
+"#;
+
+// Runtime support for strings made while the program runs. Every string
+// variable owns a heap copy of its value, freed when the variable is
+// reassigned or goes out of scope. Intermediate strings, such as the result
+// of `+`, are temporaries: each statement frees the ones it made, back to the
+// mark its function took on entry, so nothing piles up in loops and a call
+// never frees its caller's temporaries.
+const STRING_RUNTIME: &str = r#"static char **bprt_temps;
+static size_t bprt_temp_count, bprt_temp_capacity;
+
+static void bprt_out_of_memory(void) {
+    fflush(stdout);
+    fputs("error: out of memory\n", stderr);
+    exit(1);
+}
+
+// Register a fresh heap string as a temporary
+static char *bprt_temp(char *s) {
+    if (!s) {
+        bprt_out_of_memory();
+    }
+    if (bprt_temp_count == bprt_temp_capacity) {
+        bprt_temp_capacity = bprt_temp_capacity ? 2 * bprt_temp_capacity : 16;
+        bprt_temps = realloc(bprt_temps, bprt_temp_capacity * sizeof *bprt_temps);
+        if (!bprt_temps) {
+            bprt_out_of_memory();
+        }
+    }
+    bprt_temps[bprt_temp_count++] = s;
+    return s;
+}
+
+static void bprt_free_temps(size_t mark) {
+    while (bprt_temp_count > mark) {
+        free(bprt_temps[--bprt_temp_count]);
+    }
+}
+
+static char *bprt_strdup(const char *s) {
+    size_t size = strlen(s) + 1;
+    char *copy = malloc(size);
+    if (!copy) {
+        bprt_out_of_memory();
+    }
+    return memcpy(copy, s, size);
+}
+
+// Store a copy of `value` in a string variable, freeing its old value
+static void bprt_str_set(char **var, const char *value) {
+    char *copy = bprt_strdup(value);
+    free(*var);
+    *var = copy;
+}
+
+static const char *bprt_concat(const char *a, const char *b) {
+    size_t a_len = strlen(a), b_len = strlen(b);
+    char *result = bprt_temp(malloc(a_len + b_len + 1));
+    memcpy(result, a, a_len);
+    memcpy(result + a_len, b, b_len + 1);
+    return result;
+}
+
+static const char *bprt_int_str(int value) {
+    char *result = bprt_temp(malloc(12));
+    snprintf(result, 12, "%d", value);
+    return result;
+}
+
+static const char *bprt_float_str(double value) {
+    int size = snprintf(NULL, 0, "%g", value) + 1;
+    char *result = bprt_temp(malloc(size));
+    snprintf(result, size, "%g", value);
+    return result;
+}
+"#;
+
+// Case conversion for `upper` and `lower`, byte by byte as in the C locale
+const CASE_RUNTIME: &str = r#"static const char *bprt_upper(const char *s) {
+    char *result = bprt_temp(bprt_strdup(s));
+    for (char *c = result; *c; c++) {
+        *c = (char)toupper((unsigned char)*c);
+    }
+    return result;
+}
+
+static const char *bprt_lower(const char *s) {
+    char *result = bprt_temp(bprt_strdup(s));
+    for (char *c = result; *c; c++) {
+        *c = (char)tolower((unsigned char)*c);
+    }
+    return result;
+}
+"#;
+
+// `substr`: at most `len` bytes from `start`, clamped to the string
+const SUBSTR_RUNTIME: &str = r#"static const char *bprt_substr(const char *s, int start, int len) {
+    size_t length = strlen(s);
+    size_t from = start < 0 ? 0 : (size_t)start;
+    size_t count = len < 0 ? 0 : (size_t)len;
+    if (from > length) {
+        from = length;
+    }
+    if (count > length - from) {
+        count = length - from;
+    }
+    char *result = bprt_temp(malloc(count + 1));
+    memcpy(result, s + from, count);
+    result[count] = '\0';
+    return result;
+}
+"#;
+
+// Concatenation of a long, needing more room than an int
+const LONG_STR_RUNTIME: &str = r#"static const char *bprt_long_str(long long value) {
+    char *result = bprt_temp(malloc(21));
+    snprintf(result, 21, "%lld", value);
+    return result;
+}
+"#;
+
+// Division that wraps like the rest of the arithmetic: `-fwrapv` leaves
+// the smallest value divided by -1 to the processor, which traps
+const WRAPPING_DIVISION_RUNTIME: &str = r#"static int bprt_wrapping_div_int(int a, int b) {
+    return b == -1 ? (int)(0u - (unsigned)a) : a / b;
+}
+
+static int bprt_wrapping_mod_int(int a, int b) {
+    return b == -1 ? 0 : a % b;
+}
+
+static long long bprt_wrapping_div_long(long long a, long long b) {
+    return b == -1 ? (long long)(0ull - (unsigned long long)a) : a / b;
+}
+
+static long long bprt_wrapping_mod_long(long long a, long long b) {
+    return b == -1 ? 0 : a % b;
+}
+"#;
+
+// `--trap-overflow`: int and long arithmetic that stops the program, naming
+// the BP line, when the result does not fit. `__builtin_*_overflow` come
+// with GCC and Clang.
+const OVERFLOW_RUNTIME: &str = r#"static void bprt_overflow(const char *where, long long a, const char *op,
+                          long long b, const char *type) {
+    fflush(stdout);
+    fprintf(stderr, "error at %s: integer overflow: %lld %s %lld does not fit in %s\n",
+            where, a, op, b, type);
+    exit(1);
+}
+"#;
+
+// The checked operations on ints
+const INT_OVERFLOW_RUNTIME: &str = r#"static int bprt_add_int(int a, int b, const char *where) {
+    int result;
+    if (__builtin_add_overflow(a, b, &result)) {
+        bprt_overflow(where, a, "+", b, "an int");
+    }
+    return result;
+}
+
+static int bprt_sub_int(int a, int b, const char *where) {
+    int result;
+    if (__builtin_sub_overflow(a, b, &result)) {
+        bprt_overflow(where, a, "-", b, "an int");
+    }
+    return result;
+}
+
+static int bprt_mul_int(int a, int b, const char *where) {
+    int result;
+    if (__builtin_mul_overflow(a, b, &result)) {
+        bprt_overflow(where, a, "*", b, "an int");
+    }
+    return result;
+}
+
+static int bprt_div_int(int a, int b, const char *where) {
+    if (a == INT_MIN && b == -1) {
+        bprt_overflow(where, a, "/", b, "an int");
+    }
+    return a / b;
+}
+
+static int bprt_mod_int(int a, int b, const char *where) {
+    if (a == INT_MIN && b == -1) {
+        bprt_overflow(where, a, "%", b, "an int");
+    }
+    return a % b;
+}
+
+static int bprt_neg_int(int a, const char *where) {
+    if (a == INT_MIN) {
+        fflush(stdout);
+        fprintf(stderr, "error at %s: integer overflow: -(%d) does not fit in an int\n", where, a);
+        exit(1);
+    }
+    return -a;
+}
+"#;
+
+// The checked operations on longs
+const LONG_OVERFLOW_RUNTIME: &str = r#"static long long bprt_add_long(long long a, long long b, const char *where) {
+    long long result;
+    if (__builtin_add_overflow(a, b, &result)) {
+        bprt_overflow(where, a, "+", b, "a long");
+    }
+    return result;
+}
+
+static long long bprt_sub_long(long long a, long long b, const char *where) {
+    long long result;
+    if (__builtin_sub_overflow(a, b, &result)) {
+        bprt_overflow(where, a, "-", b, "a long");
+    }
+    return result;
+}
+
+static long long bprt_mul_long(long long a, long long b, const char *where) {
+    long long result;
+    if (__builtin_mul_overflow(a, b, &result)) {
+        bprt_overflow(where, a, "*", b, "a long");
+    }
+    return result;
+}
+
+static long long bprt_div_long(long long a, long long b, const char *where) {
+    if (a == LLONG_MIN && b == -1) {
+        bprt_overflow(where, a, "/", b, "a long");
+    }
+    return a / b;
+}
+
+static long long bprt_mod_long(long long a, long long b, const char *where) {
+    if (a == LLONG_MIN && b == -1) {
+        bprt_overflow(where, a, "%", b, "a long");
+    }
+    return a % b;
+}
+
+static long long bprt_neg_long(long long a, const char *where) {
+    if (a == LLONG_MIN) {
+        fflush(stdout);
+        fprintf(stderr, "error at %s: integer overflow: -(%lld) does not fit in a long\n", where, a);
+        exit(1);
+    }
+    return -a;
+}
+"#;
+
+// `min` and `max` on ints
+const INT_MIN_MAX_RUNTIME: &str = r#"static int bprt_min(int a, int b) {
+    return a < b ? a : b;
+}
+
+static int bprt_max(int a, int b) {
+    return a > b ? a : b;
+}
+"#;
+
+// `random` and `seed`. A 64-bit linear congruential generator rather than
+// `rand()`, so a seeded program gives the same numbers with every C library
+// and in the interpreter.
+const RANDOM_RUNTIME: &str = r#"static uint64_t bprt_rng_state;
+
+// Mix the seed, so nearby seeds start far apart
+static void bprt_seed(int seed) {
+    uint64_t z = (uint64_t)seed + 0x9E3779B97F4A7C15u;
+    z = (z ^ (z >> 30)) * 0xBF58476D1CE4E5B9u;
+    z = (z ^ (z >> 27)) * 0x94D049BB133111EBu;
+    bprt_rng_state = z ^ (z >> 31);
+}
+
+static int bprt_random(int n) {
+    if (n <= 0) {
+        fflush(stdout);
+        fprintf(stderr, "error: random: the bound must be positive, not %d\n", n);
+        exit(1);
+    }
+    bprt_rng_state = bprt_rng_state * 6364136223846793005u + 1442695040888963407u;
+    return (int)((bprt_rng_state >> 33) % (uint64_t)n);
+}
+"#;
+
+// `env`: the value of an environment variable, or "" when it is unset
+const ENV_RUNTIME: &str = r#"static const char *bprt_env(const char *name) {
+    const char *value = getenv(name);
+    return value ? value : "";
+}
+"#;
+
+// `read_file` and `write_file`. BP has no error values, so failing to
+// read or write stops the program.
+const FILE_RUNTIME: &str = r#"static void bprt_file_error(const char *function, const char *path) {
+    const char *reason = strerror(errno);
+    fflush(stdout);
+    fprintf(stderr, "error: %s: cannot access '%s': %s\n", function, path, reason);
+    exit(1);
+}
+
+static const char *bprt_read_file(const char *path) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        bprt_file_error("read_file", path);
+    }
+    size_t length = 0, capacity = 4096;
+    char *buffer = malloc(capacity);
+    if (!buffer) {
+        bprt_out_of_memory();
+    }
+    for (;;) {
+        // Leave room for the terminator
+        length += fread(buffer + length, 1, capacity - length - 1, file);
+        if (length < capacity - 1) {
+            break;
+        }
+        capacity *= 2;
+        buffer = realloc(buffer, capacity);
+        if (!buffer) {
+            bprt_out_of_memory();
+        }
+    }
+    if (ferror(file)) {
+        bprt_file_error("read_file", path);
+    }
+    fclose(file);
+    buffer[length] = '\0';
+    return bprt_temp(buffer);
+}
+
+static void bprt_write_file(const char *path, const char *content) {
+    FILE *file = fopen(path, "wb");
+    if (!file) {
+        bprt_file_error("write_file", path);
+    }
+    size_t length = strlen(content);
+    // Closing flushes, so it can fail too
+    if (fwrite(content, 1, length, file) != length || fclose(file) != 0) {
+        bprt_file_error("write_file", path);
+    }
+}
+"#;
+
+// `argc` and `arg`: the command line `main` was given. Arguments out of
+// range are "".
+const ARGS_RUNTIME: &str = r#"static int bprt_arg_count;
+static char **bprt_args;
+
+static int bprt_argc(void) {
+    return bprt_arg_count;
+}
+
+static const char *bprt_arg(int i) {
+    return i >= 0 && i < bprt_arg_count ? bprt_args[i] : "";
+}
+"#;
+
+// `sleep_ms`, for Unix and Windows. Computed negative durations do not sleep.
+const SLEEP_RUNTIME: &str = r#"#ifdef _WIN32
+#include <windows.h>
+#else
+#include <errno.h>
+#include <time.h>
+#endif
+
+static void bprt_sleep_ms(int ms) {
+    // Show what was printed so far before pausing
+    fflush(stdout);
+    if (ms <= 0) {
+        return;
+    }
+#ifdef _WIN32
+    Sleep((DWORD)ms);
+#else
+    struct timespec wait = {ms / 1000, (long)(ms % 1000) * 1000000L};
+    // A signal cuts the sleep short; carry on with the time left
+    while (nanosleep(&wait, &wait) == -1 && errno == EINTR) {
+    }
+#endif
+}
+"#;
+
+// Variable types known at the current point of code generation
+type SymbolTable = HashMap<String, Type>;
+
+// Escape a string so it can be placed between double quotes in C source
+fn escape_c(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    let mut previous = None;
+    for ch in s.chars() {
+        match ch {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            // `??` starts a trigraph in some C modes
+            '?' if previous == Some('?') => escaped.push_str("\\?"),
+            // Other control characters as three octal digits, so a following
+            // digit is not absorbed into the escape
+            ch if ch.is_ascii_control() => escaped.push_str(&format!("\\{:03o}", ch as u32)),
+            _ => escaped.push(ch),
+        }
+        previous = Some(ch);
+    }
+    escaped
+}
+
+// Escape a string for use as literal text in a printf format string
+fn escape_format(s: &str) -> String {
+    escape_c(s).replace('%', "%%")
+}
+
+// C signature of a BP function, whose result is an int. A string parameter
+// is borrowed from the caller under a name of its own; the body works on a
+// copy, like any string variable.
+fn function_signature(name: &str, params: &[Parameter]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(
+            |param| match parameter_type(param).expect("parameter was checked by analyze") {
+                Type::Str => format!("const char *{}", c_param_name(&param.name)),
+                ty => format!("{} {}", c_type(&ty), c_name(&param.name)),
+            },
+        )
+        .collect();
+    if params.is_empty() {
+        format!("int {}(void)", c_name(name))
+    } else {
+        format!("int {}({})", c_name(name), params.join(", "))
+    }
+}
+
+// Whether a statement always leaves its block, so nothing after it runs
+fn is_jump(node: &ASTNode) -> bool {
+    matches!(
+        node,
+        ASTNode::Break(_) | ASTNode::Continue(_) | ASTNode::Give { .. } | ASTNode::Exit { .. }
+    )
+}
+
+// Whether evaluating an expression calls a function, which may print or
+// draw a random number. C leaves the order of printf arguments open, so
+// `show` must not put two such values in one call.
+fn contains_call(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::Call { .. } => true,
+        ASTNode::Index { index, .. } => contains_call(index),
+        ASTNode::BinaryOp { lhs, rhs, .. } => contains_call(lhs) || contains_call(rhs),
+        ASTNode::UnaryOp { operand, .. } => contains_call(operand),
+        _ => false,
+    }
+}
+
+// Whether an expression reads the variable `name`
+fn mentions(node: &ASTNode, name: &str) -> bool {
+    match node {
+        ASTNode::Identifier(var, _) => var == name,
+        ASTNode::Index {
+            name: var, index, ..
+        } => var == name || mentions(index, name),
+        ASTNode::FieldAccess { name: var, .. } => var == name,
+        ASTNode::StructInit { fields, .. } => fields.iter().any(|(_, value)| mentions(value, name)),
+        ASTNode::BinaryOp { lhs, rhs, .. } => mentions(lhs, name) || mentions(rhs, name),
+        ASTNode::UnaryOp { operand, .. } => mentions(operand, name),
+        ASTNode::Call { args, .. } | ASTNode::ArrayLiteral(args, _) => {
+            args.iter().any(|arg| mentions(arg, name))
+        }
+        _ => false,
+    }
+}
+
+// What kind of BP construct a C block belongs to
+#[derive(PartialEq, Clone, Copy)]
+enum ScopeKind {
+    Block,
+    // The body of a loop, with the loop's number among the generated ones
+    Loop(usize),
+    // A `case` of the `switch` a `match` becomes
+    Arm,
+}
+
+// A C block being emitted, with the string variables it must free
+struct Scope {
+    owned: Vec<String>,
+    kind: ScopeKind,
+}
+
+// Emits C for statements and expressions, remembering the headers and
+// runtime helpers the emitted code relies on so only those are written
+// ahead of it
+#[derive(Default)]
+struct Generator {
+    headers: BTreeSet<&'static str>,
+    // Headers the program asks for with `cinclude`, in order
+    includes: Vec<String>,
+    helpers: Vec<&'static str>,
+    // Libraries the generated code needs, by the name given to `-l`
+    libraries: BTreeSet<&'static str>,
+    // Whether the program calls `random`, and whether it seeds it itself
+    uses_random: bool,
+    seeded: bool,
+    // The BP source, named by failed assertions and `#line` directives
+    source: PathBuf,
+    // Whether statements are preceded by `#line` directives, so the C
+    // compiler's messages point at the BP source
+    line_directives: bool,
+    // Whether int arithmetic goes through helpers stopping the program on
+    // overflow, rather than wrapping
+    trap_overflow: bool,
+    // Whether the program reads its command line, which main must save
+    uses_args: bool,
+    // Whether the program calls `sleep_ms`, which needs POSIX declarations
+    uses_sleep: bool,
+    // The structs and enums the program defines
+    definitions: Definitions,
+    // Enums whose values are shown, which need their variant names
+    shown_enums: BTreeSet<String>,
+    // Loops generated so far, and those a `break` leaves with a `goto`
+    // because it sits in a `switch`, where a C `break` would only end the
+    // `switch`
+    loop_count: usize,
+    goto_breaks: BTreeSet<usize>,
+    // Open blocks of the function being emitted, innermost last
+    scopes: Vec<Scope>,
+    // Whether the expressions emitted since the flag was last cleared
+    // made string temporaries
+    made_temps: bool,
+    // Whether the function being emitted frees temporaries, and so needs
+    // its mark
+    uses_mark: bool,
+}
+
+impl Generator {
+    // Type of an expression the analyzer has already accepted
+    fn checked_type(&self, node: &ASTNode, symbols: &SymbolTable) -> Type {
+        expression_type(node, &|name| symbols.get(name).cloned(), &self.definitions)
+            .expect("expression was type checked by analyze")
+    }
+
+    // Type of a field of the struct `name`
+    fn field_type(&self, name: &str, field: &str) -> Type {
+        self.definitions.structs[name]
+            .iter()
+            .find(|(other, _)| other == field)
+            .map(|(_, ty)| ty.clone())
+            .expect("field was checked by analyze")
+    }
+
+    // Write a `#line` directive giving the BP line of the C that follows,
+    // unless they are turned off
+    fn line_directive(&self, span: Option<Span>, out: &mut CodeWriter) {
+        if let Some(span) = span.filter(|_| self.line_directives) {
+            out.directive(format!(
+                "#line {} \"{}\"",
+                span.line,
+                escape_c(
+                    &module::source_path(span.file, &self.source)
+                        .display()
+                        .to_string()
+                )
+            ));
+        }
+    }
+
+    // After the last statement of a body, map the C that follows back to
+    // the C file, so the compiler does not blame the BP line before it
+    fn back_to_c(&self, out: &mut CodeWriter) {
+        if self.line_directives {
+            out.directive(BACK_TO_C);
+        }
+    }
+
+    // Call of the runtime helper doing `operation` on ints or longs, as
+    // `ty` says, with a check for overflow. `operands` are C expressions;
+    // the helper names the BP position at `span` if it stops the program.
+    fn checked_arithmetic(
+        &mut self,
+        operation: &str,
+        ty: &Type,
+        operands: &[String],
+        span: Span,
+    ) -> String {
+        self.require_helper(OVERFLOW_RUNTIME, &[]);
+        let suffix = match ty {
+            Type::Int => "int",
+            _ => "long",
+        };
+        let helper = if *ty == Type::Int {
+            INT_OVERFLOW_RUNTIME
+        } else {
+            LONG_OVERFLOW_RUNTIME
+        };
+        self.require_helper(helper, &["limits.h"]);
+        let position = format!(
+            "{}:{}",
+            module::source_path(span.file, &self.source).display(),
+            span.line
+        );
+        format!(
+            "bprt_{}_{}({}, \"{}\")",
+            operation,
+            suffix,
+            operands.join(", "),
+            escape_c(&position)
+        )
+    }
+
+    fn require_header(&mut self, header: &'static str) {
+        if !BASE_HEADERS.contains(&header) {
+            self.headers.insert(header);
+        }
+    }
+
+    // Add a runtime helper, once, along with the headers it uses
+    fn require_helper(&mut self, helper: &'static str, headers: &[&'static str]) {
+        for header in headers {
+            self.require_header(header);
+        }
+        if !self.helpers.contains(&helper) {
+            self.helpers.push(helper);
+        }
+    }
+
+    fn require_strings(&mut self) {
+        self.require_helper(STRING_RUNTIME, &["string.h"]);
+    }
+
+    // Free the temporaries made since the function's mark
+    fn free_temps(&mut self, out: &mut CodeWriter) {
+        self.uses_mark = true;
+        out.line("bprt_free_temps(bprt_mark);");
+    }
+
+    // Whether any open scope owns a string variable
+    fn owns_strings(&self) -> bool {
+        self.scopes.iter().any(|scope| !scope.owned.is_empty())
+    }
+
+    // Free the string variables of the innermost scopes: up to the
+    // enclosing loop's body if `to_loop`, else the whole function
+    fn free_owned(&self, to_loop: bool, out: &mut CodeWriter) {
+        for scope in self.scopes.iter().rev() {
+            for name in scope.owned.iter().rev() {
+                out.line(format!("free({});", name));
+            }
+            if to_loop && matches!(scope.kind, ScopeKind::Loop(_)) {
+                break;
+            }
+        }
+    }
+
+    // Operand of a string `+`, converted to a string if needed
+    fn string_operand(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        match self.checked_type(node, symbols) {
+            Type::Str => self.expression(node, symbols),
+            Type::Int => {
+                self.made_temps = true;
+                format!("bprt_int_str({})", self.expression(node, symbols))
+            }
+            Type::Long => {
+                self.made_temps = true;
+                self.require_helper(LONG_STR_RUNTIME, &[]);
+                format!("bprt_long_str({})", self.expression(node, symbols))
+            }
+            Type::Float => {
+                self.made_temps = true;
+                format!("bprt_float_str({})", self.expression(node, symbols))
+            }
+            Type::Bool => format!("({} ? \"true\" : \"false\")", self.condition(node, symbols)),
+            Type::Enum(name) => self.variant_name(&name, node, symbols),
+            Type::Array(_) | Type::Struct(_) => unreachable!("aggregates cannot be concatenated"),
+        }
+    }
+
+    // Name of the variant of enum `name` that `node` evaluates to
+    fn variant_name(&mut self, name: &str, node: &ASTNode, symbols: &SymbolTable) -> String {
+        self.shown_enums.insert(name.to_string());
+        format!(
+            "{}[{}]",
+            c_variant_names(name),
+            self.expression(node, symbols)
+        )
+    }
+
+    // Translate an expression node into the equivalent C expression. Binary
+    // operations are always parenthesized so C re-derives the BP grouping.
+    fn expression(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        match node {
+            // C has no literal for the smallest int, only for its negation
+            ASTNode::NumberLiteral(i32::MIN) => format!("({} - 1)", i32::MIN + 1),
+            ASTNode::NumberLiteral(num) => num.to_string(),
+            ASTNode::LongLiteral(i64::MIN) => format!("({}LL - 1)", i64::MIN + 1),
+            ASTNode::LongLiteral(num) => format!("{}LL", num),
+            // Debug formatting always keeps a decimal point or exponent, so C
+            // treats the literal as a double
+            ASTNode::FloatLiteral(num) => format!("{:?}", num),
+            ASTNode::BoolLiteral(b) => b.to_string(),
+            ASTNode::StringLiteral(s) => format!("\"{}\"", escape_c(s)),
+            ASTNode::Identifier(name, _) => c_name(name),
+            // Arrays are not bounds checked at run time
+            ASTNode::Index { name, index, .. } => {
+                format!("{}[{}]", c_name(name), self.expression(index, symbols))
+            }
+            ASTNode::ArrayLiteral(elements, _) => {
+                let elements: Vec<String> = elements
+                    .iter()
+                    .map(|element| self.expression(element, symbols))
+                    .collect();
+                format!("{{{}}}", elements.join(", "))
+            }
+            ASTNode::FieldAccess { name, field, .. } if symbols.contains_key(name) => {
+                format!("{}.{}", c_name(name), c_name(field))
+            }
+            // Not a variable, so an enum variant
+            ASTNode::FieldAccess { name, field, .. } => c_variant(name, field),
+            // String fields get copies the struct owns, like string variables
+            ASTNode::StructInit { name, fields, .. } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| {
+                        let value_code = self.expression(value, symbols);
+                        if self.field_type(name, field) == Type::Str {
+                            self.require_strings();
+                            format!(".{} = bprt_strdup({})", c_name(field), value_code)
+                        } else {
+                            format!(".{} = {}", c_name(field), value_code)
+                        }
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            ASTNode::BinaryOp { lhs, rhs, .. } if self.checked_type(node, symbols) == Type::Str => {
+                self.require_strings();
+                self.made_temps = true;
+                format!(
+                    "bprt_concat({}, {})",
+                    self.string_operand(lhs, symbols),
+                    self.string_operand(rhs, symbols)
+                )
+            }
+            ASTNode::BinaryOp {
+                op,
+                lhs,
+                rhs: rhs_node,
+                span,
+            } => {
+                let lhs = self.expression(lhs, symbols);
+                let rhs = self.expression(rhs_node, symbols);
+                let operation = match op {
+                    BinaryOperator::Add => "add",
+                    BinaryOperator::Sub => "sub",
+                    BinaryOperator::Mul => "mul",
+                    BinaryOperator::Div => "div",
+                    BinaryOperator::Mod => "mod",
+                    _ => "",
+                };
+                match self.checked_type(node, symbols) {
+                    ty @ (Type::Int | Type::Long)
+                        if self.trap_overflow && !operation.is_empty() =>
+                    {
+                        self.checked_arithmetic(operation, &ty, &[lhs, rhs], *span)
+                    }
+                    // A literal divisor other than -1 cannot overflow
+                    ty @ (Type::Int | Type::Long)
+                        if matches!(op, BinaryOperator::Div | BinaryOperator::Mod)
+                            && !matches!(**rhs_node, ASTNode::NumberLiteral(n) if n != -1)
+                            && !matches!(**rhs_node, ASTNode::LongLiteral(n) if n != -1) =>
+                    {
+                        self.require_helper(WRAPPING_DIVISION_RUNTIME, &[]);
+                        let suffix = if ty == Type::Int { "int" } else { "long" };
+                        format!("bprt_wrapping_{}_{}({}, {})", operation, suffix, lhs, rhs)
+                    }
+                    _ => format!("({} {} {})", lhs, op.c_symbol(), rhs),
+                }
+            }
+            ASTNode::UnaryOp { op, operand, span } => {
+                let operand_code = self.expression(operand, symbols);
+                match (op, self.checked_type(node, symbols)) {
+                    (UnaryOperator::Neg, ty @ (Type::Int | Type::Long)) if self.trap_overflow => {
+                        self.checked_arithmetic("neg", &ty, &[operand_code], *span)
+                    }
+                    _ => format!("({}{})", op.c_symbol(), operand_code),
+                }
+            }
+            ASTNode::Call { name, args, .. } if name == "len" => {
+                match self.checked_type(&args[0], symbols) {
+                    // Array lengths are known at compile time
+                    Type::Array(len) => len.to_string(),
+                    _ => {
+                        self.require_header("string.h");
+                        format!("((int)strlen({}))", self.expression(&args[0], symbols))
+                    }
+                }
+            }
+            // The other builtins call runtime helpers or libc; those giving
+            // strings return temporaries
+            ASTNode::Call { name, args, .. } if builtin(name).is_some() => {
+                // `seed` gives nothing
+                let result = match builtin(name).map(|builtin| &builtin.result) {
+                    Some(Returns::Nothing) => None,
+                    _ => Some(self.checked_type(node, symbols)),
+                };
+                let arg_values: Vec<String> = args
+                    .iter()
+                    .map(|arg| self.expression(arg, symbols))
+                    .collect();
+                let function = match name.as_str() {
+                    "upper" => {
+                        self.require_strings();
+                        self.require_helper(CASE_RUNTIME, &["ctype.h"]);
+                        "bprt_upper"
+                    }
+                    "lower" => {
+                        self.require_strings();
+                        self.require_helper(CASE_RUNTIME, &["ctype.h"]);
+                        "bprt_lower"
+                    }
+                    "substr" => {
+                        self.require_strings();
+                        self.require_helper(SUBSTR_RUNTIME, &[]);
+                        "bprt_substr"
+                    }
+                    // Concatenation already converts ints this way
+                    "to_str" => {
+                        self.require_strings();
+                        "bprt_int_str"
+                    }
+                    "to_int" => {
+                        self.require_helper(PARSE_INT_RUNTIME, &["errno.h", "limits.h"]);
+                        self.require_helper(TO_INT_RUNTIME, &[]);
+                        "bprt_to_int"
+                    }
+                    // `abs` is in stdlib.h; helpers rather than macros, so
+                    // arguments are evaluated once
+                    "abs" if result == Some(Type::Int) => "abs",
+                    "min" if result == Some(Type::Int) => {
+                        self.require_helper(INT_MIN_MAX_RUNTIME, &[]);
+                        "bprt_min"
+                    }
+                    "max" if result == Some(Type::Int) => {
+                        self.require_helper(INT_MIN_MAX_RUNTIME, &[]);
+                        "bprt_max"
+                    }
+                    "abs" | "min" | "max" | "pow" | "sqrt" => {
+                        self.require_header("math.h");
+                        self.libraries.insert("m");
+                        match name.as_str() {
+                            "abs" => "fabs",
+                            "min" => "fmin",
+                            "max" => "fmax",
+                            other => other,
+                        }
+                    }
+                    "random" | "seed" => {
+                        self.require_helper(RANDOM_RUNTIME, &["stdint.h"]);
+                        if name == "seed" {
+                            self.seeded = true;
+                            "bprt_seed"
+                        } else {
+                            self.uses_random = true;
+                            "bprt_random"
+                        }
+                    }
+                    "env" => {
+                        self.require_helper(ENV_RUNTIME, &[]);
+                        "bprt_env"
+                    }
+                    "read_file" | "write_file" => {
+                        self.require_strings();
+                        self.require_helper(FILE_RUNTIME, &["errno.h"]);
+                        if name == "read_file" {
+                            "bprt_read_file"
+                        } else {
+                            "bprt_write_file"
+                        }
+                    }
+                    "argc" | "arg" => {
+                        self.require_helper(ARGS_RUNTIME, &[]);
+                        self.uses_args = true;
+                        if name == "argc" {
+                            "bprt_argc"
+                        } else {
+                            "bprt_arg"
+                        }
+                    }
+                    "sleep_ms" => {
+                        self.require_helper(SLEEP_RUNTIME, &[]);
+                        self.uses_sleep = true;
+                        "bprt_sleep_ms"
+                    }
+                    _ => unreachable!("builtin without C translation: {}", name),
+                };
+                // `env` and `arg` hand back strings of the environment and
+                // the command line rather than fresh ones to free
+                if result == Some(Type::Str) && !matches!(name.as_str(), "env" | "arg") {
+                    self.made_temps = true;
+                }
+                format!("{}({})", function, arg_values.join(", "))
+            }
+            ASTNode::Call { name, args, .. } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| self.expression(arg, symbols))
+                    .collect();
+                // C library functions keep their own names
+                let function = if self.definitions.externs.contains_key(name) {
+                    name.clone()
+                } else {
+                    c_name(name)
+                };
+                format!("{}({})", function, args.join(", "))
+            }
+            _ => unreachable!("not an expression: {:?}", node),
+        }
+    }
+
+    // C expression passed to printf to show `node` with `printf_format`
+    fn show_argument(&mut self, node: &ASTNode, ty: &Type, symbols: &SymbolTable) -> String {
+        match ty {
+            Type::Bool => format!("{} ? \"true\" : \"false\"", self.condition(node, symbols)),
+            Type::Enum(name) => self.variant_name(name, node, symbols),
+            _ => self.expression(node, symbols),
+        }
+    }
+
+    // Wrap an expression in the parentheses required around C conditions,
+    // unless it already carries them
+    fn condition(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        let expr = self.expression(node, symbols);
+        match node {
+            ASTNode::BinaryOp { .. } | ASTNode::UnaryOp { .. } => expr,
+            _ => format!("({})", expr),
+        }
+    }
+
+    // Write the C translation of `nodes` to `out`. Variables declared here
+    // are recorded in `symbols`.
+    fn statements(&mut self, nodes: &[ASTNode], symbols: &mut SymbolTable, out: &mut CodeWriter) {
+        for node in nodes {
+            // Definitions are emitted ahead of main, where they get their own
+            if !matches!(
+                node,
+                ASTNode::Function { .. }
+                    | ASTNode::ExternFunction { .. }
+                    | ASTNode::StructDef { .. }
+                    | ASTNode::EnumDef { .. }
+                    | ASTNode::CInclude { .. }
+                    | ASTNode::Link { .. }
+            ) {
+                self.line_directive(node.span(), out);
+            }
+            match node {
+                ASTNode::VariableDeclaration {
+                    name,
+                    mutable,
+                    ty,
+                    value,
+                    ..
+                } => {
+                    let declared = ty
+                        .as_ref()
+                        .map(|ty| Type::from_name(ty).expect("annotation was checked by analyze"));
+                    // The analyzer made sure a variable declared without a
+                    // value is assigned before it is read. A string starts
+                    // out null, which freeing it accepts.
+                    let Some(value) = value else {
+                        let ty = declared.expect("the parser requires a value or a type");
+                        if ty == Type::Str {
+                            self.require_strings();
+                            out.line(format!("char *{} = NULL;", c_name(name)));
+                            self.scopes
+                                .last_mut()
+                                .expect("statements are emitted inside a scope")
+                                .owned
+                                .push(c_name(name));
+                        } else {
+                            out.line(format!("{} {};", c_type(&ty), c_name(name)));
+                        }
+                        symbols.insert(name.clone(), ty);
+                        continue;
+                    };
+                    // Constant string literals become arrays the compiler can see into
+                    if let (ASTNode::StringLiteral(s), false) = (value.as_ref(), *mutable) {
+                        out.line(format!(
+                            "const char {}[] = \"{}\";",
+                            c_name(name),
+                            escape_c(s)
+                        ));
+                        symbols.insert(name.clone(), Type::Str);
+                        continue;
+                    }
+                    let value_type = match value.as_ref() {
+                        ASTNode::ArrayLiteral(elements, _) => Type::Array(elements.len()),
+                        ASTNode::StructInit { name, .. } => Type::Struct(name.clone()),
+                        value => match declared {
+                            Some(declared) => declared,
+                            None => self.checked_type(value, symbols),
+                        },
+                    };
+                    let mut value_code = self.expression(value, symbols);
+                    // A C variable is in scope from its own declarator, so a
+                    // value reading the outer variable it shadows is computed
+                    // into a temporary first
+                    if mentions(value, name) {
+                        let temp = format!("bprt_init_{}", name);
+                        if let Type::Array(len) = value_type {
+                            out.line(format!("const int {}[] = {};", temp, value_code));
+                            let elements: Vec<String> =
+                                (0..len).map(|i| format!("{}[{}]", temp, i)).collect();
+                            value_code = format!("{{{}}}", elements.join(", "));
+                        } else {
+                            out.line(format!(
+                                "{} {} = {};",
+                                c_type(&value_type),
+                                temp,
+                                value_code
+                            ));
+                            value_code = temp;
+                        }
+                    }
+                    if let Type::Array(_) = value_type {
+                        let qualifier = if *mutable { "" } else { "const " };
+                        out.line(format!(
+                            "{}int {}[] = {};",
+                            qualifier,
+                            c_name(name),
+                            value_code
+                        ));
+                        symbols.insert(name.clone(), value_type);
+                        continue;
+                    }
+                    if value_type == Type::Str {
+                        // Strings are copied into storage the variable owns;
+                        // a constant also freezes the pointer
+                        self.require_strings();
+                        let declaration = if *mutable { "char *" } else { "char *const " };
+                        out.line(format!(
+                            "{}{} = bprt_strdup({});",
+                            declaration,
+                            c_name(name),
+                            value_code
+                        ));
+                        self.scopes
+                            .last_mut()
+                            .expect("statements are emitted inside a scope")
+                            .owned
+                            .push(c_name(name));
+                    } else {
+                        let qualifier = if *mutable { "" } else { "const " };
+                        out.line(format!(
+                            "{}{} {} = {};",
+                            qualifier,
+                            c_type(&value_type),
+                            c_name(name),
+                            value_code
+                        ));
+                    }
+                    // A struct owns the strings in its fields
+                    if let Type::Struct(struct_name) = &value_type {
+                        let owned: Vec<String> = self.definitions.structs[struct_name]
+                            .iter()
+                            .filter(|(_, ty)| *ty == Type::Str)
+                            .map(|(field, _)| format!("{}.{}", c_name(name), c_name(field)))
+                            .collect();
+                        self.scopes
+                            .last_mut()
+                            .expect("statements are emitted inside a scope")
+                            .owned
+                            .extend(owned);
+                    }
+                    symbols.insert(name.clone(), value_type);
+                }
+                ASTNode::Assignment { name, value, .. }
+                    if symbols.get(name) == Some(&Type::Str) =>
+                {
+                    out.line(format!(
+                        "bprt_str_set(&{}, {});",
+                        c_name(name),
+                        self.expression(value, symbols)
+                    ));
+                }
+                ASTNode::Assignment { name, value, .. } => {
+                    out.line(format!(
+                        "{} = {};",
+                        c_name(name),
+                        self.expression(value, symbols)
+                    ));
+                }
+                ASTNode::FieldAssignment {
+                    name, field, value, ..
+                } => {
+                    let Some(Type::Struct(struct_name)) = symbols.get(name) else {
+                        unreachable!("field assignment to '{}' was type checked", name);
+                    };
+                    let target = format!("{}.{}", c_name(name), c_name(field));
+                    let value = self.expression(value, symbols);
+                    if self.field_type(struct_name, field) == Type::Str {
+                        out.line(format!("bprt_str_set(&{}, {});", target, value));
+                    } else {
+                        out.line(format!("{} = {};", target, value));
+                    }
+                }
+                ASTNode::IndexAssignment {
+                    name, index, value, ..
+                } => {
+                    out.line(format!(
+                        "{}[{}] = {};",
+                        c_name(name),
+                        self.expression(index, symbols),
+                        self.expression(value, symbols)
+                    ));
+                }
+                ASTNode::Show(values, _) => {
+                    // Literal parts go straight into the format string, every
+                    // other value becomes a conversion plus a printf argument
+                    let mut parts = Vec::new();
+                    for value in values {
+                        match value {
+                            ASTNode::StringLiteral(s) => {
+                                parts.push((escape_format(s), String::new()))
+                            }
+                            value => {
+                                let value_type = self.checked_type(value, symbols);
+                                let argument = self.show_argument(value, &value_type, symbols);
+                                parts.push((
+                                    value_type.printf_format().to_string(),
+                                    format!(", {}", argument),
+                                ));
+                            }
+                        }
+                    }
+                    // Values that call functions are printed one at a time, in order
+                    if values.iter().filter(|value| contains_call(value)).count() > 1 {
+                        let last = parts.len() - 1;
+                        for (i, (format, argument)) in parts.iter().enumerate() {
+                            let newline = if i == last { "\\n" } else { "" };
+                            out.line(format!("printf(\"{}{}\"{});", format, newline, argument));
+                        }
+                    } else {
+                        let format: String =
+                            parts.iter().map(|(format, _)| format.as_str()).collect();
+                        let arguments: String = parts
+                            .iter()
+                            .map(|(_, argument)| argument.as_str())
+                            .collect();
+                        out.line(format!("printf(\"{}\\n\"{});", format, arguments));
+                    }
+                }
+                ASTNode::If {
+                    cond,
+                    then_block,
+                    else_block,
+                    ..
+                } => {
+                    out.line(format!("if {} {{", self.condition(cond, symbols)));
+                    let cond_temps = std::mem::take(&mut self.made_temps);
+                    // Blocks get their own copy of the symbols, matching C scoping
+                    self.block(then_block, &mut symbols.clone(), ScopeKind::Block, out);
+                    if let Some(else_block) = else_block {
+                        out.line("} else {");
+                        self.block(else_block, &mut symbols.clone(), ScopeKind::Block, out);
+                    }
+                    out.line("}");
+                    if cond_temps {
+                        self.free_temps(out);
+                    }
+                }
+                ASTNode::For {
+                    var,
+                    start,
+                    end,
+                    body,
+                    ..
+                } => {
+                    let c_var = c_name(var);
+                    let id = self.loop_count;
+                    self.loop_count += 1;
+                    let start = self.expression(start, symbols);
+                    // The end is evaluated before every iteration. Up to a
+                    // literal below the largest int the counter cannot
+                    // overflow; otherwise the loop stops once it reaches
+                    // INT_MAX instead of stepping past it.
+                    let bounded = matches!(**end, ASTNode::NumberLiteral(end) if end < i32::MAX);
+                    let end = self.expression(end, symbols);
+                    if bounded {
+                        out.line(format!(
+                            "for (int {c_var} = {start}; {c_var} <= {end}; {c_var}++) {{"
+                        ));
+                    } else {
+                        self.require_header("limits.h");
+                        let more = format!("bprt_more_{}", id);
+                        out.line(format!(
+                            "for (int {c_var} = {start}, {more} = 1; {more} && {c_var} <= {end}; \
+                             {more} = {c_var} != INT_MAX, {c_var} += {more}) {{"
+                        ));
+                    }
+                    let header_temps = std::mem::take(&mut self.made_temps);
+                    if header_temps {
+                        out.indent();
+                        self.free_temps(out);
+                        out.dedent();
+                    }
+                    let mut body_symbols = symbols.clone();
+                    body_symbols.insert(var.clone(), Type::Int);
+                    self.block(body, &mut body_symbols, ScopeKind::Loop(id), out);
+                    out.line("}");
+                    if self.goto_breaks.contains(&id) {
+                        out.line(format!("bprt_break_{}:;", id));
+                    }
+                    if header_temps {
+                        self.free_temps(out);
+                    }
+                }
+                ASTNode::Block(nodes) => {
+                    out.line("{");
+                    self.block(nodes, &mut symbols.clone(), ScopeKind::Block, out);
+                    out.line("}");
+                }
+                // Each arm is a braced `case`, so its declarations stay local
+                ASTNode::Match {
+                    value,
+                    arms,
+                    else_block,
+                    ..
+                } => {
+                    let Type::Enum(enum_name) = self.checked_type(value, symbols) else {
+                        unreachable!("match value was type checked as an enum");
+                    };
+                    out.line(format!("switch ({}) {{", self.expression(value, symbols)));
+                    let value_temps = std::mem::take(&mut self.made_temps);
+                    let cases = arms
+                        .iter()
+                        .map(|arm| {
+                            let label = format!("case {}:", c_variant(&enum_name, &arm.variant));
+                            (label, &arm.body)
+                        })
+                        .chain(else_block.iter().map(|body| ("default:".to_string(), body)));
+                    for (label, body) in cases {
+                        out.line(format!("{} {{", label));
+                        self.block(body, &mut symbols.clone(), ScopeKind::Arm, out);
+                        if !body.last().is_some_and(is_jump) {
+                            out.indent();
+                            out.line("break;");
+                            out.dedent();
+                        }
+                        out.line("}");
+                    }
+                    out.line("}");
+                    if value_temps {
+                        self.free_temps(out);
+                    }
+                }
+                ASTNode::Call { .. } => {
+                    out.line(format!("{};", self.expression(node, symbols)));
+                }
+                ASTNode::Give { value, .. } => {
+                    let value = self.expression(value, symbols);
+                    if !self.made_temps && !self.owns_strings() {
+                        out.line(format!("return {};", value));
+                        continue;
+                    }
+                    // The value may read the strings about to be freed
+                    out.line("{");
+                    out.indent();
+                    out.line(format!("int bprt_result = {};", value));
+                    if std::mem::take(&mut self.made_temps) {
+                        self.free_temps(out);
+                    }
+                    self.free_owned(false, out);
+                    out.line("return bprt_result;");
+                    out.dedent();
+                    out.line("}");
+                }
+                ASTNode::Input { name, .. } => {
+                    let helper = match symbols.get(name) {
+                        Some(Type::Int) => "bprt_input_int",
+                        Some(Type::Str) => "bprt_input_line",
+                        ty => unreachable!("input into {:?} was type checked", ty),
+                    };
+                    self.require_helper(PARSE_INT_RUNTIME, &["errno.h", "limits.h"]);
+                    self.require_helper(INPUT_RUNTIME, &["string.h"]);
+                    // A string variable owns its value, and the line read is
+                    // already a fresh heap string
+                    if helper == "bprt_input_line" {
+                        out.line(format!("free({});", c_name(name)));
+                    }
+                    out.line(format!("{} = {}();", c_name(name), helper));
+                }
+                // The message is fixed when compiling, position included
+                ASTNode::Assert {
+                    cond,
+                    message,
+                    span,
+                } => {
+                    let failure = assertion_failure(&self.source, *span, message.as_deref());
+                    out.line(format!("if (!{}) {{", self.condition(cond, symbols)));
+                    out.indent();
+                    out.line("fflush(stdout);");
+                    out.line(format!("fputs(\"{}\\n\", stderr);", escape_c(&failure)));
+                    out.line(format!("exit({});", ASSERT_EXIT_CODE));
+                    out.dedent();
+                    out.line("}");
+                }
+                // `exit` rather than `return`, so it also ends the program from
+                // inside a function
+                ASTNode::Exit { value, .. } => {
+                    out.line(format!("exit({});", self.expression(value, symbols)));
+                }
+                ASTNode::Break(_) => {
+                    self.free_owned(true, out);
+                    // Inside a `case`, C's `break` would only leave the
+                    // `switch`
+                    let mut in_switch = false;
+                    for scope in self.scopes.iter().rev() {
+                        match scope.kind {
+                            ScopeKind::Arm => in_switch = true,
+                            ScopeKind::Loop(id) if in_switch => {
+                                self.goto_breaks.insert(id);
+                                out.line(format!("goto bprt_break_{};", id));
+                                break;
+                            }
+                            ScopeKind::Loop(_) => {
+                                out.line("break;");
+                                break;
+                            }
+                            ScopeKind::Block => {}
+                        }
+                    }
+                }
+                ASTNode::Continue(_) => {
+                    self.free_owned(true, out);
+                    out.line("continue;");
+                }
+                // Copied as written, a line at a time
+                ASTNode::CRaw { code, .. } => {
+                    for line in code.lines() {
+                        out.line(line);
+                    }
+                }
+                // Emitted ahead of main by `transpile`. Extern functions are
+                // declared by the headers the program includes.
+                ASTNode::Function { .. }
+                | ASTNode::ExternFunction { .. }
+                | ASTNode::StructDef { .. }
+                | ASTNode::EnumDef { .. }
+                | ASTNode::CInclude { .. } => {}
+                _ => {} // Handles other unhandled ASTNode variants
+            }
+            // Block statements free their own temporaries
+            if std::mem::take(&mut self.made_temps) {
+                self.free_temps(out);
+            }
+        }
+    }
+
+    // Emit `nodes` one level deeper, as the body of a C block, freeing the
+    // string variables it declares when it ends
+    fn block(
+        &mut self,
+        nodes: &[ASTNode],
+        symbols: &mut SymbolTable,
+        kind: ScopeKind,
+        out: &mut CodeWriter,
+    ) {
+        out.indent();
+        self.scopes.push(Scope {
+            owned: Vec::new(),
+            kind,
+        });
+        self.statements(nodes, symbols, out);
+        let scope = self.scopes.pop().expect("block scope was pushed");
+        // Nothing after a jump runs; it freed what it had to itself
+        if !nodes.last().is_some_and(is_jump) {
+            for name in scope.owned.iter().rev() {
+                out.line(format!("free({});", name));
+            }
+        }
+        out.dedent();
+    }
+
+    // Emit a function body into a writer of its own, one level deep, since
+    // whether it needs the temporaries mark, which `prologue` writes, is
+    // only known at its end. `symbols` holds the parameters; string
+    // parameters are copied into variables the body owns.
+    fn function_body(&mut self, nodes: &[ASTNode], symbols: &mut SymbolTable) -> CodeWriter {
+        self.uses_mark = false;
+        let mut out = CodeWriter::new();
+        out.indent();
+        let mut copies: Vec<&String> = symbols
+            .iter()
+            .filter(|(_, ty)| **ty == Type::Str)
+            .map(|(name, _)| name)
+            .collect();
+        copies.sort();
+        let mut owned = Vec::new();
+        for name in copies {
+            self.require_strings();
+            out.line(format!(
+                "char *{} = bprt_strdup({});",
+                c_name(name),
+                c_param_name(name)
+            ));
+            owned.push(c_name(name));
+        }
+        self.scopes.push(Scope {
+            owned,
+            kind: ScopeKind::Block,
+        });
+        self.statements(nodes, symbols, &mut out);
+        let scope = self.scopes.pop().expect("function scope was pushed");
+        if !nodes.last().is_some_and(is_jump) {
+            for name in scope.owned.iter().rev() {
+                out.line(format!("free({});", name));
+            }
+        }
+        out
+    }
+
+    // Write the statements a function starts with, ahead of its body
+    fn prologue(&self, out: &mut CodeWriter) {
+        if self.uses_mark {
+            out.line("size_t bprt_mark = bprt_temp_count;");
+        }
+    }
+}
+
+// Transpile a checked program into a complete C translation unit. `source`
+// names the program in the header comment, in the messages of failed
+// assertions and, with `line_directives`, in the `#line` directives before
+// each statement. With `trap_overflow`, int arithmetic that overflows stops
+// the program.
+pub fn transpile(
+    ast: &[ASTNode],
+    source: &Path,
+    line_directives: bool,
+    trap_overflow: bool,
+) -> Generated {
+    let mut generator = Generator {
+        source: source.to_path_buf(),
+        line_directives,
+        trap_overflow,
+        ..Generator::default()
+    };
+    let mut out = CodeWriter::new();
+
+    // Typedefs first, so prototypes and bodies can use them. Included
+    // headers are gathered on the way.
+    generator.definitions.add_checked(ast);
+    for node in ast {
+        match node {
+            ASTNode::StructDef { name, .. } => {
+                out.line("typedef struct {");
+                out.indent();
+                for (field, ty) in &generator.definitions.structs[name] {
+                    // String fields own their value, like string variables
+                    let field_type = match ty {
+                        Type::Str => "char *".to_string(),
+                        ty => format!("{} ", c_type(ty)),
+                    };
+                    out.line(format!("{}{};", field_type, c_name(field)));
+                }
+                out.dedent();
+                out.line(format!("}} {};", c_type_name(name)));
+                out.blank();
+            }
+            ASTNode::EnumDef { name, variants, .. } => {
+                let constants: Vec<String> = variants
+                    .iter()
+                    .map(|variant| c_variant(name, variant))
+                    .collect();
+                out.line(format!(
+                    "typedef enum {{ {} }} {};",
+                    constants.join(", "),
+                    c_type_name(name)
+                ));
+                out.blank();
+            }
+            ASTNode::CInclude { header, .. } if !generator.includes.contains(header) => {
+                generator.includes.push(header.clone());
+            }
+            _ => {}
+        }
+    }
+
+    let functions: Vec<&ASTNode> = ast
+        .iter()
+        .filter(|node| matches!(node, ASTNode::Function { .. }))
+        .collect();
+
+    // Prototypes first, so functions can call each other in any order
+    for function in &functions {
+        if let ASTNode::Function { name, params, .. } = function {
+            out.line(format!("{};", function_signature(name, params)));
+        }
+    }
+    if !functions.is_empty() {
+        out.blank();
+    }
+
+    for function in &functions {
+        if let ASTNode::Function {
+            name,
+            params,
+            body,
+            span,
+        } = function
+        {
+            generator.line_directive(Some(*span), &mut out);
+            out.line(format!("{} {{", function_signature(name, params)));
+            let mut body_symbols: SymbolTable = params
+                .iter()
+                .map(|param| {
+                    let ty = parameter_type(param).expect("parameter was checked by analyze");
+                    (param.name.clone(), ty)
+                })
+                .collect();
+            let body = generator.function_body(body, &mut body_symbols);
+            out.indent();
+            generator.prologue(&mut out);
+            out.append(body);
+            generator.back_to_c(&mut out);
+            // Falling off the end of a function gives 0
+            out.line("return 0;");
+            out.dedent();
+            out.line("}");
+            out.blank();
+        }
+    }
+
+    // Function definitions are skipped by `statements`, leaving the
+    // top-level statements for main
+    let mut symbols = SymbolTable::new();
+    let body = generator.function_body(ast, &mut symbols);
+    out.line("int main(int argc, char **argv) {");
+    out.indent();
+    if generator.uses_args {
+        out.line("bprt_arg_count = argc;");
+        out.line("bprt_args = argv;");
+    }
+    // Unseeded programs get different numbers on every run
+    if generator.uses_random && !generator.seeded {
+        generator.require_header("time.h");
+        out.line("bprt_seed((int)time(NULL));");
+    }
+    generator.prologue(&mut out);
+    out.append(body);
+    generator.back_to_c(&mut out);
+    out.line("return 0;");
+    out.dedent();
+    out.line("}");
+
+    // Only now is it known which headers and helpers the code needs
+    let mut prelude = format!(
+        "/* generated by bplang v{} from {} */\n\n",
+        env!("CARGO_PKG_VERSION"),
+        source.display()
+    );
+    // `nanosleep` is POSIX, hidden by strict C modes unless asked for
+    // before the first include
+    if generator.uses_sleep {
+        prelude.push_str("#ifndef _WIN32\n#define _POSIX_C_SOURCE 199309L\n#endif\n");
+    }
+    for header in BASE_HEADERS.iter().chain(&generator.headers) {
+        prelude.push_str(&format!("#include <{}>\n", header));
+    }
+    for header in &generator.includes {
+        if !BASE_HEADERS.contains(&header.as_str()) && !generator.headers.contains(header.as_str())
+        {
+            prelude.push_str(&format!("#include <{}>\n", header));
+        }
+    }
+    prelude.push('\n');
+    for helper in &generator.helpers {
+        prelude.push_str(helper);
+        prelude.push('\n');
+    }
+    for name in &generator.shown_enums {
+        let names: Vec<String> = generator.definitions.enums[name]
+            .iter()
+            .map(|variant| format!("\"{}\"", variant))
+            .collect();
+        prelude.push_str(&format!(
+            "static const char *const {}[] = {{{}}};\n\n",
+            c_variant_names(name),
+            names.join(", ")
+        ));
+    }
+    Generated {
+        code: c_file_lines(prelude + &out.finish(), source),
+        libraries: generator.libraries.into_iter().map(String::from).collect(),
+    }
+}
+
+// Put `#line` directives giving the C file's own lines where the C after a
+// body was marked. The file is named like the one `--keep-c` writes.
+fn c_file_lines(code: String, source: &Path) -> String {
+    if !code.contains(BACK_TO_C) {
+        return code;
+    }
+    let c_file = source.with_extension("c");
+    let name = escape_c(&c_file.file_name().unwrap_or_default().to_string_lossy());
+    code.split_inclusive('\n')
+        .enumerate()
+        .map(|(at, line)| {
+            if line.trim_end() == BACK_TO_C {
+                // The directive is line `at + 1`, and numbers the next
+                format!("#line {} \"{}\"\n", at + 2, name)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::FileId;
+    use crate::parser::parse_source;
+    use crate::semantic::analyze;
+
+    // The C generated for `input`, with `#line` directives if `lines`
+    fn generate(input: &str, lines: bool) -> String {
+        let ast = parse_source(input, FileId::MAIN).unwrap();
+        analyze(&ast).unwrap();
+        transpile(&ast, Path::new("test.bp"), lines, false).code
+    }
+
+    // The C generated for `input`, without `#line` directives
+    fn c_code(input: &str) -> String {
+        generate(input, false)
+    }
+
+    // Each `#line` directive of the C generated for `input`, with the line
+    // it is on
+    fn directives(input: &str) -> Vec<(usize, String)> {
+        generate(input, true)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.starts_with("#line"))
+            .map(|(at, line)| (at + 1, line.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn hello_is_a_complete_translation_unit() {
+        assert_eq!(
+            c_code("show \"hi\";\n"),
+            format!(
+                "/* generated by bplang v{} from test.bp */\n\n\
+                 #include <stdbool.h>\n#include <stdio.h>\n#include <stdlib.h>\n\n\
+                 int main(int argc, char **argv) {{\n    printf(\"hi\\n\");\n    return 0;\n}}\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn arithmetic_keeps_its_grouping() {
+        let code = c_code("m x = 10 - 3 - 2;\nm y = 2 + 3 * 4;\nshow x, y;\n");
+        assert!(code.contains("int bp_x = ((10 - 3) - 2);"), "{}", code);
+        assert!(code.contains("int bp_y = (2 + (3 * 4));"), "{}", code);
+    }
+
+    #[test]
+    fn c_names_are_prefixed_everywhere() {
+        let code = c_code(
+            "m int = 5;\nm char = \"c\";\nfn printf(main) {\n    give main * 2;\n}\n\
+             m main = printf(int);\nint = int + main;\nshow char, int, main;\n",
+        );
+        for expected in [
+            "int bp_printf(int bp_main) {",
+            "return (bp_main * 2);",
+            "int bp_int = 5;",
+            "char *bp_char = bprt_strdup(\"c\");",
+            "int bp_main = bp_printf(bp_int);",
+            "bp_int = (bp_int + bp_main);",
+            "printf(\"%s%d%d\\n\", bp_char, bp_int, bp_main);",
+        ] {
+            assert!(code.contains(expected), "{}\n{}", expected, code);
+        }
+    }
+
+    #[test]
+    fn comparisons_become_c_operators() {
+        let code = c_code("m x = 1;\nshow x <= 2, x != 3, x == 1 == true;\n");
+        assert!(code.contains("(bp_x <= 2)"), "{}", code);
+        assert!(code.contains("(bp_x != 3)"), "{}", code);
+        assert!(code.contains("((bp_x == 1) == true)"), "{}", code);
+    }
+
+    #[test]
+    fn for_loop_up_to_the_largest_int_ends() {
+        let code = c_code("for i = 1 to 10 { show i; }\n");
+        assert!(
+            code.contains("for (int bp_i = 1; bp_i <= 10; bp_i++) {"),
+            "{}",
+            code
+        );
+        let code = c_code("for i = 1 to 2147483647 { show i; }\n");
+        assert!(
+            code.contains(
+                "for (int bp_i = 1, bprt_more_0 = 1; bprt_more_0 && bp_i <= 2147483647; \
+                 bprt_more_0 = bp_i != INT_MAX, bp_i += bprt_more_0) {"
+            ),
+            "{}",
+            code
+        );
+        assert!(code.contains("#include <limits.h>"), "{}", code);
+    }
+
+    #[test]
+    fn statements_get_their_bp_line() {
+        let found = directives("show 1;\n\nshow 2;\n");
+        let bp: Vec<&str> = found
+            .iter()
+            .map(|(_, line)| line.as_str())
+            .filter(|line| line.ends_with("\"test.bp\""))
+            .collect();
+        assert_eq!(bp, ["#line 1 \"test.bp\"", "#line 3 \"test.bp\""]);
+    }
+
+    #[test]
+    fn code_after_a_body_gets_its_c_line() {
+        let found = directives("fn f() {\n    give 1;\n}\nshow f();\n");
+        let c: Vec<&(usize, String)> = found
+            .iter()
+            .filter(|(_, line)| line.ends_with("\"test.c\""))
+            .collect();
+        // One after the function's body and one after main's
+        assert_eq!(c.len(), 2, "{:?}", found);
+        for (at, line) in c {
+            assert_eq!(*line, format!("#line {} \"test.c\"", at + 1));
+        }
+        // Nothing else comes after the last one but main's end
+        let code = generate("show 1;\n", true);
+        let tail: Vec<&str> = code.lines().rev().take(3).collect();
+        assert_eq!(
+            tail[2],
+            format!("#line {} \"test.c\"", code.lines().count() - 1)
+        );
+    }
+
+    #[test]
+    fn no_directives_when_turned_off() {
+        assert!(!c_code("fn f() {\n    give 1;\n}\nshow f();\n").contains("#line"));
+    }
+}
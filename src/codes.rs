@@ -0,0 +1,610 @@
+//! Stable codes for every error and warning, with the longer explanations
+//! printed by `bplang explain`
+
+// Lexer errors
+pub const UNEXPECTED_CHARACTER: &str = "E0001";
+pub const UNTERMINATED_STRING: &str = "E0002";
+pub const UNTERMINATED_COMMENT: &str = "E0003";
+pub const UNKNOWN_ESCAPE: &str = "E0004";
+pub const MALFORMED_NUMBER: &str = "E0005";
+pub const LONE_BANG: &str = "E0006";
+pub const INTEGER_OUT_OF_RANGE: &str = "E0007";
+
+// Name resolution errors
+pub const UNDECLARED_VARIABLE: &str = "E0010";
+pub const UNDEFINED_FUNCTION: &str = "E0011";
+pub const UNKNOWN_TYPE: &str = "E0012";
+pub const NO_SUCH_MEMBER: &str = "E0013";
+pub const USED_BEFORE_ASSIGNED: &str = "E0014";
+
+// Syntax errors
+pub const EXPECTED_SYNTAX: &str = "E0020";
+pub const UNEXPECTED_TOKEN: &str = "E0021";
+pub const UNBALANCED_DELIMITER: &str = "E0022";
+pub const OUTSIDE_LOOP: &str = "E0023";
+pub const ELSE_ARM_NOT_LAST: &str = "E0024";
+pub const BAD_INTERPOLATION: &str = "E0025";
+pub const NESTED_TOO_DEEPLY: &str = "E0026";
+
+// Semantic errors
+pub const TYPE_MISMATCH: &str = "E0030";
+pub const ARGUMENT_COUNT: &str = "E0031";
+pub const DUPLICATE_DEFINITION: &str = "E0032";
+pub const ASSIGN_TO_CONSTANT: &str = "E0033";
+pub const MISSING_INITIALIZER: &str = "E0034";
+pub const WHOLE_VALUE_ASSIGNED: &str = "E0035";
+pub const NOT_AT_TOP_LEVEL: &str = "E0036";
+pub const EMPTY_DEFINITION: &str = "E0037";
+pub const MISPLACED_LITERAL: &str = "E0038";
+pub const MISSING_FIELD: &str = "E0039";
+pub const GIVE_OUTSIDE_FUNCTION: &str = "E0040";
+pub const NO_VALUE: &str = "E0041";
+pub const OUT_OF_RANGE: &str = "E0042";
+pub const CONSTANT_ARITHMETIC: &str = "E0043";
+
+// Errors loading the files a program uses
+pub const IMPORT_NOT_FOUND: &str = "E0050";
+pub const IMPORT_CYCLE: &str = "E0051";
+pub const STATEMENT_IN_MODULE: &str = "E0052";
+
+// Errors while running or building the program
+pub const RUNTIME_ERROR: &str = "E0060";
+pub const UNSUPPORTED_BY_TARGET: &str = "E0070";
+pub const COMPILER_FAILED: &str = "E0080";
+pub const MISSING_TOOL: &str = "E0081";
+pub const IO_ERROR: &str = "E0082";
+
+// Warnings
+pub const UNUSED_VARIABLE: &str = "W0001";
+pub const SHADOWED_VARIABLE: &str = "W0002";
+pub const NON_EXHAUSTIVE_MATCH: &str = "W0003";
+pub const UNREACHABLE_CODE: &str = "W0004";
+pub const IGNORED_LINK: &str = "W0005";
+
+// What `bplang explain` says about a code
+#[derive(Debug)]
+pub struct CodeInfo {
+    pub code: &'static str,
+    // One line saying what is wrong
+    pub summary: &'static str,
+    // What causes it and how to fix it
+    pub explanation: &'static str,
+    // A program with the problem, then the same program fixed
+    pub example: Option<(&'static str, &'static str)>,
+}
+
+// Every code a diagnostic can carry
+pub const REGISTRY: &[CodeInfo] = &[
+    CodeInfo {
+        code: UNEXPECTED_CHARACTER,
+        summary: "unexpected character",
+        explanation: "The source contains a character that is not part of any BP token, \
+                      outside of strings and comments.",
+        example: Some((
+            "m cost = 10 @ 2;\nshow cost;\n",
+            "m cost = 10 * 2;\nshow cost;\n",
+        )),
+    },
+    CodeInfo {
+        code: UNTERMINATED_STRING,
+        summary: "unterminated string literal",
+        explanation: "A string literal has no closing quote. Strings must end on the line \
+                      they start on; write `\\n` to put a line break in one.",
+        example: Some(("show \"hello;\n", "show \"hello\";\n")),
+    },
+    CodeInfo {
+        code: UNTERMINATED_COMMENT,
+        summary: "unterminated block comment",
+        explanation: "A `/*` comment is never closed by `*/`. Block comments nest, so every \
+                      `/*` inside one needs its own `*/` too.",
+        example: Some(("/* draft\nshow 1;\n", "/* draft */\nshow 1;\n")),
+    },
+    CodeInfo {
+        code: UNKNOWN_ESCAPE,
+        summary: "unknown escape sequence",
+        explanation: "A backslash in a string literal is followed by a character that does \
+                      not form an escape. The escapes are `\\n`, `\\t`, `\\r`, `\\0`, `\\\"` \
+                      and `\\\\`.",
+        example: Some(("show \"50\\% off\";\n", "show \"50% off\";\n")),
+    },
+    CodeInfo {
+        code: MALFORMED_NUMBER,
+        summary: "malformed number",
+        explanation: "A number literal is not well formed, such as a float without digits \
+                      on both sides of its point, a digit its base does not have as in \
+                      `0b102`, or a `_` separator not followed by a digit.",
+        example: Some((
+            "m ratio = 3.;\nshow ratio;\n",
+            "m ratio = 3.0;\nshow ratio;\n",
+        )),
+    },
+    CodeInfo {
+        code: LONE_BANG,
+        summary: "'!' without '='",
+        explanation: "`!` only appears in the operator `!=`. Booleans are negated with the \
+                      keyword `not`.",
+        example: Some((
+            "m done = false;\nif !done {\n    show \"working\";\n}\n",
+            "m done = false;\nif not done {\n    show \"working\";\n}\n",
+        )),
+    },
+    CodeInfo {
+        code: INTEGER_OUT_OF_RANGE,
+        summary: "integer literal out of range",
+        explanation: "An int literal is larger than the biggest long, 9223372036854775807, \
+                      whether written in decimal or with a `0x`, `0b` or `0o` prefix. Only \
+                      after a `-`, as the smallest long `-9223372036854775808`, may it be \
+                      one more. Literals too large for an int but not for a long are longs.",
+        example: Some((
+            "m grains = 20000000000000000000;\nshow grains;\n",
+            "m grains = 2000000000000000000;\nshow grains;\n",
+        )),
+    },
+    CodeInfo {
+        code: UNDECLARED_VARIABLE,
+        summary: "use of an undeclared variable",
+        explanation: "A name is read, assigned or given to `input` but no variable of that \
+                      name is declared in scope. Declare it with `m` or `c` first, or check \
+                      its spelling. Variables declared in a block are gone after the block.",
+        example: Some(("m total = 1;\nshow totl;\n", "m total = 1;\nshow total;\n")),
+    },
+    CodeInfo {
+        code: UNDEFINED_FUNCTION,
+        summary: "call to an undefined function",
+        explanation: "A called function is neither a builtin nor defined with `fn` anywhere \
+                      in the program.",
+        example: Some((
+            "show square(3);\n",
+            "fn square(n) {\n    give n * n;\n}\n\nshow square(3);\n",
+        )),
+    },
+    CodeInfo {
+        code: UNKNOWN_TYPE,
+        summary: "unknown type",
+        explanation: "A type annotation names a type that does not exist, or a struct \
+                      literal names an undefined struct. The builtin types are `int`, \
+                      `float`, `bool` and `str`.",
+        example: Some((
+            "m ratio: double = 1.5;\nshow ratio;\n",
+            "m ratio: float = 1.5;\nshow ratio;\n",
+        )),
+    },
+    CodeInfo {
+        code: NO_SUCH_MEMBER,
+        summary: "no such field or variant",
+        explanation: "A field is used that its struct does not define, or a variant that \
+                      its enum does not list.",
+        example: Some((
+            "struct Point {\n    x;\n    y;\n}\n\nm p = Point { x = 1, y = 2 };\nshow p.z;\n",
+            "struct Point {\n    x;\n    y;\n}\n\nm p = Point { x = 1, y = 2 };\nshow p.y;\n",
+        )),
+    },
+    CodeInfo {
+        code: USED_BEFORE_ASSIGNED,
+        summary: "variable used before it is assigned",
+        explanation: "A variable declared without a value is read on a path where nothing \
+                      has been assigned to it yet. Give it a value on every path before \
+                      reading it.",
+        example: Some((
+            "m label: str;\nshow label;\n",
+            "m label: str;\nlabel = \"ready\";\nshow label;\n",
+        )),
+    },
+    CodeInfo {
+        code: EXPECTED_SYNTAX,
+        summary: "expected syntax is missing",
+        explanation: "The parser needed a particular token here, such as the `;` ending a \
+                      statement or a name after a keyword, and found something else.",
+        example: Some(("show \"hi\"\n", "show \"hi\";\n")),
+    },
+    CodeInfo {
+        code: UNEXPECTED_TOKEN,
+        summary: "unexpected token",
+        explanation: "A token cannot start or continue a statement where it appears. An \
+                      expression on its own is not a statement: assign it, show it or pass \
+                      it to a function.",
+        example: Some(("m x = 1;\nx + 1;\n", "m x = 1;\nx = x + 1;\n")),
+    },
+    CodeInfo {
+        code: UNBALANCED_DELIMITER,
+        summary: "unbalanced brackets",
+        explanation: "A `(`, `[` or `{` is never closed, or a closing one has no opening \
+                      match.",
+        example: Some(("m x = (1 + 2;\nshow x;\n", "m x = (1 + 2);\nshow x;\n")),
+    },
+    CodeInfo {
+        code: OUTSIDE_LOOP,
+        summary: "'break' or 'continue' outside of a loop",
+        explanation: "`break` and `continue` act on the innermost loop, so they can only \
+                      appear inside the body of a `for`.",
+        example: Some((
+            "m n = 3;\nif n > 2 {\n    break;\n}\n",
+            "for n = 1 to 5 {\n    if n > 2 {\n        break;\n    }\n}\n",
+        )),
+    },
+    CodeInfo {
+        code: ELSE_ARM_NOT_LAST,
+        summary: "'else' arm before other arms",
+        explanation: "The `else` arm of a `match` handles every variant not named before \
+                      it, so it must be the last arm.",
+        example: Some((
+            "enum Color { Red, Green }\n\nm color = Color.Red;\nmatch color {\n    \
+             else { show \"other\"; }\n    Red { show \"red\"; }\n}\n",
+            "enum Color { Red, Green }\n\nm color = Color.Red;\nmatch color {\n    \
+             Red { show \"red\"; }\n    else { show \"other\"; }\n}\n",
+        )),
+    },
+    CodeInfo {
+        code: BAD_INTERPOLATION,
+        summary: "invalid string interpolation",
+        explanation: "Braces in a string given to `show` must hold a variable name or a \
+                      struct field like `{p.x}`. Write `{{` and `}}` for literal braces.",
+        example: Some((
+            "m count = 3;\nshow \"{count items\";\n",
+            "m count = 3;\nshow \"{count} items\";\n",
+        )),
+    },
+    CodeInfo {
+        code: NESTED_TOO_DEEPLY,
+        summary: "nested too deeply",
+        explanation: "Parentheses, prefix operators and blocks nest at most 64 deep. Give a \
+                      part of the expression a variable of its own, or move an inner block \
+                      into a function.",
+        example: None,
+    },
+    CodeInfo {
+        code: TYPE_MISMATCH,
+        summary: "type mismatch",
+        explanation: "A value has a type the place it is used does not accept, such as a \
+                      string assigned to an int variable or an operator applied to values \
+                      it does not work on. A variable keeps the type it is declared with.",
+        example: Some((
+            "m count = 1;\ncount = \"two\";\nshow count;\n",
+            "m count = 1;\ncount = 2;\nshow count;\n",
+        )),
+    },
+    CodeInfo {
+        code: ARGUMENT_COUNT,
+        summary: "wrong number of arguments",
+        explanation: "A function is called with more or fewer arguments than it has \
+                      parameters.",
+        example: Some((
+            "fn add(a, b) {\n    give a + b;\n}\n\nshow add(1);\n",
+            "fn add(a, b) {\n    give a + b;\n}\n\nshow add(1, 2);\n",
+        )),
+    },
+    CodeInfo {
+        code: DUPLICATE_DEFINITION,
+        summary: "defined more than once",
+        explanation: "A name is declared twice in the same block, or a function, type, \
+                      field, parameter or variant is defined twice. A builtin function \
+                      cannot be redefined either. To change a variable, assign to it \
+                      instead of declaring it again.",
+        example: Some((
+            "m x = 1;\nm x = 2;\nshow x;\n",
+            "m x = 1;\nx = 2;\nshow x;\n",
+        )),
+    },
+    CodeInfo {
+        code: ASSIGN_TO_CONSTANT,
+        summary: "assignment to a constant",
+        explanation: "A constant declared with `c`, or one of its fields or elements, is \
+                      assigned or read into with `input`. Declare it with `m` to change it.",
+        example: Some((
+            "c limit = 10;\nlimit = 20;\nshow limit;\n",
+            "m limit = 10;\nlimit = 20;\nshow limit;\n",
+        )),
+    },
+    CodeInfo {
+        code: MISSING_INITIALIZER,
+        summary: "declaration without the value it needs",
+        explanation: "A constant must be given a value where it is declared, and an array \
+                      or struct variable must be initialized with a literal, not copied \
+                      from another variable.",
+        example: Some((
+            "m a = [1, 2];\nm b = a;\nshow a[0], b[0];\n",
+            "m a = [1, 2];\nm b = [1, 2];\nshow a[0], b[0];\n",
+        )),
+    },
+    CodeInfo {
+        code: WHOLE_VALUE_ASSIGNED,
+        summary: "assignment to a whole array or struct",
+        explanation: "Arrays and structs cannot be reassigned as a whole. Assign to their \
+                      elements or fields one by one.",
+        example: Some((
+            "m a = [1, 2];\nm b = [3, 4];\na = b;\nshow a[0];\n",
+            "m a = [1, 2];\nm b = [3, 4];\na[0] = b[0];\na[1] = b[1];\nshow a[0];\n",
+        )),
+    },
+    CodeInfo {
+        code: NOT_AT_TOP_LEVEL,
+        summary: "definition inside a block",
+        explanation: "Functions, structs and enums can only be defined at the top level of \
+                      the program, not inside a block or another function. The same goes \
+                      for `cinclude`, `link` and `use`.",
+        example: Some((
+            "if true {\n    fn hello() {\n        show \"hello\";\n    }\n}\n",
+            "fn hello() {\n    show \"hello\";\n}\n\nif true {\n    hello();\n}\n",
+        )),
+    },
+    CodeInfo {
+        code: EMPTY_DEFINITION,
+        summary: "empty struct, enum or array",
+        explanation: "A struct needs at least one field, an enum at least one variant and \
+                      an array literal at least one element.",
+        example: Some(("struct Empty {}\n", "struct Empty {\n    x;\n}\n")),
+    },
+    CodeInfo {
+        code: MISPLACED_LITERAL,
+        summary: "array or struct literal outside a declaration",
+        explanation: "Array and struct literals can only initialize a variable. Declare \
+                      one and use it instead.",
+        example: Some((
+            "show [1, 2];\n",
+            "m nums = [1, 2];\nshow nums[0], nums[1];\n",
+        )),
+    },
+    CodeInfo {
+        code: MISSING_FIELD,
+        summary: "struct literal without every field",
+        explanation: "A struct literal must give a value to every field of the struct, in \
+                      any order.",
+        example: Some((
+            "struct Point {\n    x;\n    y;\n}\n\nm p = Point { x = 1 };\nshow p.x;\n",
+            "struct Point {\n    x;\n    y;\n}\n\nm p = Point { x = 1, y = 0 };\nshow p.x;\n",
+        )),
+    },
+    CodeInfo {
+        code: GIVE_OUTSIDE_FUNCTION,
+        summary: "'give' outside of a function",
+        explanation: "`give` returns a value from a function, so it can only appear in a \
+                      function body. Use `exit` to stop the program.",
+        example: Some(("give 1;\n", "exit 1;\n")),
+    },
+    CodeInfo {
+        code: NO_VALUE,
+        summary: "function without a value used as one",
+        explanation: "A builtin such as `seed` or `write_file` does not give a value, so \
+                      its call can only be a statement.",
+        example: Some(("m x = seed(42);\n", "seed(42);\n")),
+    },
+    CodeInfo {
+        code: OUT_OF_RANGE,
+        summary: "value out of range",
+        explanation: "A literal is outside the range its use allows: an array index past \
+                      the end, or a negative count or duration given to a builtin.",
+        example: Some((
+            "m nums = [1, 2, 3];\nshow nums[3];\n",
+            "m nums = [1, 2, 3];\nshow nums[2];\n",
+        )),
+    },
+    CodeInfo {
+        code: CONSTANT_ARITHMETIC,
+        summary: "arithmetic on literals overflows or divides by zero",
+        explanation: "Expressions built only from literals are computed when the program is \
+                      built. One that divides by zero is an error. So is one whose result \
+                      does not fit in an int, or a long when it involves one, when built \
+                      with `--trap-overflow`; otherwise the result wraps around.",
+        example: Some(("m x = 10 / 0;\nshow x;\n", "m x = 10 / 2;\nshow x;\n")),
+    },
+    CodeInfo {
+        code: IMPORT_NOT_FOUND,
+        summary: "used file not found",
+        explanation: "A `use` statement names a file that cannot be read. The path is \
+                      relative to the directory of the file containing the `use`, not to \
+                      the current directory.",
+        example: None,
+    },
+    CodeInfo {
+        code: IMPORT_CYCLE,
+        summary: "files use each other in a cycle",
+        explanation: "A file uses itself, directly or through other files, as in `a.bp` \
+                      using `b.bp` which uses `a.bp`. Move the definitions both need into a \
+                      third file that both use.",
+        example: None,
+    },
+    CodeInfo {
+        code: STATEMENT_IN_MODULE,
+        summary: "statement in a used file",
+        explanation: "A file loaded with `use` may only contain definitions: functions, \
+                      structs, enums, `extern fn`, `cinclude`, `link` and further `use` \
+                      statements. Move other statements into the program itself.",
+        example: None,
+    },
+    CodeInfo {
+        code: RUNTIME_ERROR,
+        summary: "error while running the program",
+        explanation: "The interpreter or the bytecode vm stopped the program, for example \
+                      on a division by zero, an index out of range or input that is not a \
+                      number. Compiled programs stop with the same message on stderr.",
+        example: None,
+    },
+    CodeInfo {
+        code: UNSUPPORTED_BY_TARGET,
+        summary: "feature not supported by the target",
+        explanation: "The language chosen with `--target` cannot express the feature yet. \
+                      Use another target, or do without the feature. Raw C written with \
+                      `craw` or `cinclude` only works with the C target, and cannot be \
+                      run by the `tree` and `vm` engines or the REPL either. Neither can \
+                      functions declared with `extern fn`, apart from `puts(s: str)` and \
+                      `putchar(ch)`, which the `tree` engine and the REPL run themselves.",
+        example: None,
+    },
+    CodeInfo {
+        code: COMPILER_FAILED,
+        summary: "the generated code failed to build",
+        explanation: "The C compiler, or the target's own tool, rejected the code bplang \
+                      generated. This is a bug in bplang unless extra flags given with \
+                      `--cflag` caused it; the generated file is kept for a report.",
+        example: None,
+    },
+    CodeInfo {
+        code: MISSING_TOOL,
+        summary: "a needed tool is not installed",
+        explanation: "Building or running the program needs a program that is not on the \
+                      PATH, such as a C compiler, `node` or `llc`. Install it, or choose a \
+                      C compiler with `--cc`.",
+        example: None,
+    },
+    CodeInfo {
+        code: IO_ERROR,
+        summary: "a file could not be read or written",
+        explanation: "bplang could not read the source, or write the generated code or the \
+                      executable. Check that the paths exist and are writable.",
+        example: None,
+    },
+    CodeInfo {
+        code: UNUSED_VARIABLE,
+        summary: "unused variable",
+        explanation: "A variable is declared but never read; assigning to it does not count. \
+                      Remove it, or start its name with `_` to keep it quietly.",
+        example: Some((
+            "m temp = 1;\nshow \"done\";\n",
+            "m _temp = 1;\nshow \"done\";\n",
+        )),
+    },
+    CodeInfo {
+        code: SHADOWED_VARIABLE,
+        summary: "variable shadows another",
+        explanation: "A block declares a variable with the name of one from an enclosing \
+                      block, which cannot be reached until the block ends. Pick another \
+                      name, or assign to the outer variable.",
+        example: Some((
+            "m x = 1;\n{\n    m x = 2;\n    show x;\n}\nshow x;\n",
+            "m x = 1;\n{\n    x = 2;\n    show x;\n}\nshow x;\n",
+        )),
+    },
+    CodeInfo {
+        code: NON_EXHAUSTIVE_MATCH,
+        summary: "'match' does not handle every variant",
+        explanation: "A `match` without an `else` arm leaves some variants of its enum \
+                      unhandled, so nothing runs for them. Add their arms or an `else`.",
+        example: Some((
+            "enum Color { Red, Green }\n\nm color = Color.Red;\nmatch color {\n    \
+             Red { show \"red\"; }\n}\n",
+            "enum Color { Red, Green }\n\nm color = Color.Red;\nmatch color {\n    \
+             Red { show \"red\"; }\n    else { show \"other\"; }\n}\n",
+        )),
+    },
+    CodeInfo {
+        code: UNREACHABLE_CODE,
+        summary: "unreachable code",
+        explanation: "Statements after `break`, `continue`, `give` or `exit`, or after an \
+                      `if` or `match` whose every branch ends in one, can never run and are \
+                      left out of the program.",
+        example: Some((
+            "fn one() {\n    give 1;\n    show \"never\";\n}\n\nshow one();\n",
+            "fn one() {\n    give 1;\n}\n\nshow one();\n",
+        )),
+    },
+    CodeInfo {
+        code: IGNORED_LINK,
+        summary: "library not linked by the target",
+        explanation: "Only the C and LLVM targets link C libraries. With any other target, \
+                      a `link` directive or a `--link` option does nothing.",
+        example: None,
+    },
+];
+
+// What is known about `code`, in any letter case
+pub fn lookup(code: &str) -> Option<&'static CodeInfo> {
+    REGISTRY
+        .iter()
+        .find(|info| info.code.eq_ignore_ascii_case(code))
+}
+
+// Columns the explanation is wrapped to
+const WIDTH: usize = 76;
+
+// `text` broken into lines at spaces, none longer than `WIDTH` unless a word
+// is
+fn wrap(text: &str) -> String {
+    let mut wrapped = String::new();
+    let mut line_len = 0;
+    for word in text.split_whitespace() {
+        if line_len > 0 && line_len + 1 + word.len() > WIDTH {
+            wrapped.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            wrapped.push(' ');
+            line_len += 1;
+        }
+        wrapped.push_str(word);
+        line_len += word.len();
+    }
+    wrapped
+}
+
+// The text `bplang explain` prints for `info`
+pub fn explain(info: &CodeInfo) -> String {
+    let mut text = format!(
+        "{}: {}\n\n{}\n",
+        info.code,
+        info.summary,
+        wrap(info.explanation)
+    );
+    if let Some((broken, fixed)) = info.example {
+        let indent = |code: &str| {
+            code.lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        String::new()
+                    } else {
+                        format!("    {}", line)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        text.push_str(&format!(
+            "\nFor example, this program has the problem:\n\n{}\n\nand this one does not:\n\n{}\n",
+            indent(broken),
+            indent(fixed)
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_registered_once() {
+        let mut seen = HashSet::new();
+        for info in REGISTRY {
+            assert!(seen.insert(info.code), "{} registered twice", info.code);
+            assert!(!info.summary.is_empty(), "{} has no summary", info.code);
+            let kind = info.code.as_bytes()[0];
+            assert!(
+                kind == b'E' || kind == b'W',
+                "{} is neither E nor W",
+                info.code
+            );
+            assert_eq!(info.code.len(), 5, "{}", info.code);
+        }
+    }
+
+    #[test]
+    fn lookup_ignores_case() {
+        assert_eq!(
+            lookup("e0010").map(|info| info.code),
+            Some(UNDECLARED_VARIABLE)
+        );
+        assert_eq!(lookup("W0001").map(|info| info.code), Some(UNUSED_VARIABLE));
+        assert!(lookup("E9999").is_none());
+    }
+
+    #[test]
+    fn explain_shows_summary_and_example() {
+        let text = explain(lookup(UNEXPECTED_CHARACTER).unwrap());
+        assert!(
+            text.starts_with("E0001: unexpected character\n\n"),
+            "{}",
+            text
+        );
+        assert!(text.contains("m cost = 10 @ 2;"), "{}", text);
+        assert!(text.contains("m cost = 10 * 2;"), "{}", text);
+    }
+}
@@ -0,0 +1,318 @@
+//! Project settings read from `bp.toml`, so a project builds without
+//! repeating its options on every command line
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::driver::OPT_LEVELS;
+
+// Name of the project file, looked for in the current directory and its
+// parents
+pub const CONFIG_FILE: &str = "bp.toml";
+
+// Settings of a project. Paths are relative to the directory holding
+// `bp.toml`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    // The program's source (`entry = "src/main.bp"`)
+    pub entry: Option<PathBuf>,
+    // Where the executable goes (`output = "bin/app"`)
+    pub output: Option<PathBuf>,
+    // The C compiler (`cc = "clang"`)
+    pub cc: Option<String>,
+    // Extra C compiler flags (`cflags = ["-Wall"]`)
+    pub cflags: Vec<String>,
+    // Optimization level (`opt = 2` or `opt = "s"`)
+    pub opt: Option<String>,
+}
+
+// A `bp.toml` that cannot be read or does not make sense
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    // 1-based line of the problem, when there is one
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+// A value of the small part of TOML `bp.toml` uses
+#[derive(Debug)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "a string",
+            Value::Int(_) => "an integer",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+// Reads `key = value` lines, with `#` comments. Values are basic or literal
+// strings, integers, and arrays of those, which may span lines.
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, message: String) -> (usize, String) {
+        (self.line, message)
+    }
+
+    // Skip spaces and tabs, and with `newlines` also line ends and comments
+    fn skip_blank(&mut self, newlines: bool) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => self.line += 1,
+                '#' if newlines => {
+                    while self.chars.next_if(|&c| c != '\n').is_some() {}
+                    continue;
+                }
+                _ => return,
+            }
+            self.chars.next();
+        }
+    }
+
+    // The rest of the line must be empty or a comment
+    fn end_of_line(&mut self) -> Result<(), (usize, String)> {
+        self.skip_blank(false);
+        match self.chars.peek().copied() {
+            None | Some('\n') | Some('#') => Ok(()),
+            Some(c) => Err(self.error(format!("unexpected '{}' after the value", c))),
+        }
+    }
+
+    fn key(&mut self) -> Result<Option<String>, (usize, String)> {
+        self.skip_blank(true);
+        let mut key = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        {
+            key.push(c);
+        }
+        match self.chars.peek().copied() {
+            None if key.is_empty() => Ok(None),
+            Some('[') if key.is_empty() => Err(self.error("tables are not supported".to_string())),
+            Some(c) if key.is_empty() => Err(self.error(format!("expected a key, found '{}'", c))),
+            _ => Ok(Some(key)),
+        }
+    }
+
+    fn value(&mut self, key: &str) -> Result<Value, (usize, String)> {
+        self.skip_blank(false);
+        match self.chars.peek().copied() {
+            Some(quote @ ('"' | '\'')) => {
+                self.chars.next();
+                self.string(key, quote)
+            }
+            Some('[') => {
+                self.chars.next();
+                let mut values = Vec::new();
+                loop {
+                    self.skip_blank(true);
+                    if self.chars.next_if_eq(&']').is_some() {
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value(key)?);
+                    self.skip_blank(true);
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Value::Array(values)),
+                        _ => return Err(self.error(format!("{}: expected ',' or ']'", key))),
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '_'))
+                {
+                    number.push(c);
+                }
+                number
+                    .replace('_', "")
+                    .parse()
+                    .map(Value::Int)
+                    .map_err(|_| self.error(format!("{}: invalid integer '{}'", key, number)))
+            }
+            _ => Err(self.error(format!("{}: expected a value", key))),
+        }
+    }
+
+    // A string whose opening `quote` was read. Only `"` strings have escapes.
+    fn string(&mut self, key: &str, quote: char) -> Result<Value, (usize, String)> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                None | Some('\n') => {
+                    return Err(self.error(format!("{}: unterminated string", key)))
+                }
+                Some(c) if c == quote => return Ok(Value::Str(s)),
+                Some('\\') if quote == '"' => match self.chars.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    other => {
+                        let escape = other.map(String::from).unwrap_or_default();
+                        return Err(self.error(format!("{}: invalid escape '\\{}'", key, escape)));
+                    }
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+}
+
+// The value of `key` as a string
+fn string(key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::Str(s) => Ok(s),
+        value => Err(format!(
+            "{}: expected a string, found {}",
+            key,
+            value.kind()
+        )),
+    }
+}
+
+// Parse the text of a `bp.toml`. Errors give the line and message.
+fn parse(text: &str) -> Result<Config, (usize, String)> {
+    let mut reader = Reader {
+        chars: text.chars().peekable(),
+        line: 1,
+    };
+    let mut config = Config::default();
+    while let Some(key) = reader.key()? {
+        reader.skip_blank(false);
+        if reader.chars.next_if_eq(&'=').is_none() {
+            return Err(reader.error(format!("expected '=' after '{}'", key)));
+        }
+        let line = reader.line;
+        let value = reader.value(&key)?;
+        reader.end_of_line()?;
+        let at_line = |message| (line, message);
+        match key.as_str() {
+            "entry" => config.entry = Some(string(&key, value).map_err(at_line)?.into()),
+            "output" => config.output = Some(string(&key, value).map_err(at_line)?.into()),
+            "cc" => config.cc = Some(string(&key, value).map_err(at_line)?),
+            "cflags" => {
+                let Value::Array(values) = value else {
+                    let message = format!("cflags: expected an array, found {}", value.kind());
+                    return Err(at_line(message));
+                };
+                config.cflags = values
+                    .into_iter()
+                    .map(|value| string("cflags", value))
+                    .collect::<Result<_, _>>()
+                    .map_err(at_line)?;
+            }
+            "opt" => {
+                let level = match value {
+                    Value::Int(level) => level.to_string(),
+                    Value::Str(level) => level,
+                    value => {
+                        let message = format!("opt: expected a level, found {}", value.kind());
+                        return Err(at_line(message));
+                    }
+                };
+                if !OPT_LEVELS.contains(&level.as_str()) {
+                    return Err(at_line(format!(
+                        "opt: invalid optimization level {}",
+                        level
+                    )));
+                }
+                config.opt = Some(level);
+            }
+            _ => return Err(at_line(format!("unknown key '{}'", key))),
+        }
+    }
+    Ok(config)
+}
+
+// Read the `bp.toml` at `path`
+pub fn load(path: &Path) -> Result<Config, ConfigError> {
+    let text = fs::read_to_string(path).map_err(|err| ConfigError {
+        path: path.to_path_buf(),
+        line: None,
+        message: err.to_string(),
+    })?;
+    parse(&text).map_err(|(line, message)| ConfigError {
+        path: path.to_path_buf(),
+        line: Some(line),
+        message,
+    })
+}
+
+// The nearest `bp.toml`, in the current directory or one of its parents,
+// as a path relative to the current directory
+pub fn find() -> Option<PathBuf> {
+    let dir = env::current_dir().ok()?;
+    let mut relative = PathBuf::new();
+    for dir in dir.ancestors() {
+        if dir.join(CONFIG_FILE).is_file() {
+            return Some(relative.join(CONFIG_FILE));
+        }
+        relative.push("..");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_key_is_read() {
+        let config = parse(
+            "# project\nentry = \"src/main.bp\"\noutput = \"bin/app\"\ncc = \"clang\"\n\
+             cflags = [\"-Wall\", \"-Wextra\"]\nopt = 2\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                entry: Some(PathBuf::from("src/main.bp")),
+                output: Some(PathBuf::from("bin/app")),
+                cc: Some("clang".to_string()),
+                cflags: vec!["-Wall".to_string(), "-Wextra".to_string()],
+                opt: Some("2".to_string()),
+            }
+        );
+        assert_eq!(parse("opt = \"s\"").unwrap().opt.as_deref(), Some("s"));
+    }
+
+    #[test]
+    fn mistakes_give_their_line() {
+        assert_eq!(
+            parse("entry = \"a.bp\"\nnope = 1\n").unwrap_err(),
+            (2, "unknown key 'nope'".to_string())
+        );
+        assert_eq!(parse("opt = 9").unwrap_err().0, 1);
+        assert_eq!(parse("\ncc = 3").unwrap_err().0, 2);
+    }
+}
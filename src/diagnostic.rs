@@ -0,0 +1,377 @@
+//! Renders diagnostics for the user: a `file:line:col` header, then the
+//! source line with the offending span underlined by carets
+
+use std::env;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::lexer::Span;
+use crate::module;
+
+// Columns a tab advances to the next multiple of in source excerpts
+const TAB_WIDTH: usize = 4;
+
+// ANSI escapes for the parts of a colored diagnostic
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+// How serious a reported diagnostic is
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn color(self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// When diagnostics are colored, chosen with `--color`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum ColorChoice {
+    // When stderr is a terminal and `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub const NAMES: &'static [&'static str] = &["auto", "always", "never"];
+
+    pub fn from_name(name: &str) -> Option<ColorChoice> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+static COLOR: OnceLock<bool> = OnceLock::new();
+
+// Decide once for the whole process whether diagnostics on stderr are
+// colored. Later calls have no effect.
+pub fn set_color(choice: ColorChoice) {
+    COLOR.get_or_init(|| choice.enabled());
+}
+
+// Whether diagnostics printed on stderr are colored
+pub fn color() -> bool {
+    *COLOR.get_or_init(|| ColorChoice::Auto.enabled())
+}
+
+// How diagnostics are written, chosen with `--error-format`
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum ErrorFormat {
+    // For people: on stderr, with the source line under each
+    #[default]
+    Human,
+    // For editors and other tools: one JSON object per line on stdout
+    Json,
+}
+
+impl ErrorFormat {
+    pub const NAMES: &'static [&'static str] = &["human", "json"];
+
+    pub fn from_name(name: &str) -> Option<ErrorFormat> {
+        match name {
+            "human" => Some(ErrorFormat::Human),
+            "json" => Some(ErrorFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// Something to tell the user about a program, at a place in its source
+// when there is one
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // Registered in `codes`, explained by `bplang explain`
+    pub code: &'static str,
+    pub path: PathBuf,
+    pub span: Option<Span>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    // A diagnostic at `span` in the program at `path`, or in the file it
+    // uses that the span points into
+    pub fn new(
+        severity: Severity,
+        code: &'static str,
+        path: &Path,
+        span: Span,
+        message: &dyn fmt::Display,
+    ) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            path: module::source_path(span.file, path),
+            span: Some(span),
+            message: message.to_string(),
+        }
+    }
+
+    // A diagnostic about the program at `path` as a whole, such as a failed
+    // C compiler
+    pub fn without_span(
+        severity: Severity,
+        code: &'static str,
+        path: &Path,
+        message: &dyn fmt::Display,
+    ) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            path: path.to_path_buf(),
+            span: None,
+            message: message.to_string(),
+        }
+    }
+
+    // The diagnostic as printed: `error[E0010] at main.bp:3:12: <message>`, then
+    // the line of `source` it points at, when given, with the span
+    // underlined. A span in a used file shows that file's line instead.
+    pub fn render(&self, source: Option<&str>, color: bool) -> String {
+        let paint = |text: &str, style: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        let severity = paint(
+            &format!("{}[{}]", self.severity, self.code),
+            self.severity.color(),
+        );
+        let Some(span) = self.span else {
+            return format!("{}: {}", severity, self.message);
+        };
+        let mut rendered = format!(
+            "{} at {}:{}:{}: {}",
+            severity,
+            self.path.display(),
+            span.line,
+            span.col,
+            self.message
+        );
+        let used = module::module(span.file);
+        let source = match &used {
+            Some(module) => Some(module.text.as_str()),
+            None => source,
+        };
+        let line = span
+            .line
+            .checked_sub(1)
+            .and_then(|i| source?.lines().nth(i));
+        let Some(line) = line else {
+            return rendered;
+        };
+        let line = line.trim_end_matches('\r');
+        let (text, start, end) = excerpt(line, span);
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        rendered.push_str(&format!(
+            "\n{} {}\n{} {}{}",
+            paint(&format!("{} |", number), BLUE),
+            text,
+            paint(&format!("{} |", gutter), BLUE),
+            " ".repeat(start),
+            paint(&"^".repeat(end - start), self.severity.color())
+        ));
+        rendered
+    }
+
+    // The diagnostic as a single line of JSON:
+    // `{"severity":"error","code":"E0010","message":"...","file":"main.bp","line":3,"col":5,"end_col":9}`.
+    // `end_col` is the column just past the span. Without a span the
+    // position fields are `null`.
+    pub fn to_json(&self) -> String {
+        let (line, col, end_col) = match self.span {
+            Some(span) => (
+                span.line.to_string(),
+                span.col.to_string(),
+                (span.col + span.len.max(1)).to_string(),
+            ),
+            None => ("null".to_string(), "null".to_string(), "null".to_string()),
+        };
+        format!(
+            "{{\"severity\":{},\"code\":{},\"message\":{},\"file\":{},\"line\":{},\"col\":{},\"end_col\":{}}}",
+            json_string(&self.severity.to_string()),
+            json_string(self.code),
+            json_string(&self.message),
+            json_string(&self.path.display().to_string()),
+            line,
+            col,
+            end_col
+        )
+    }
+}
+
+// `s` as a quoted JSON string
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            ch if ch.is_control() => quoted.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// `line` with its tabs expanded, and the display columns where the carets
+// under `span` start and end. Columns count characters, not bytes; a span
+// running past the line is cut at its end, and one starting past it points
+// just after the last character.
+fn excerpt(line: &str, span: Span) -> (String, usize, usize) {
+    let mut text = String::with_capacity(line.len());
+    let mut column = 0;
+    let mut start = None;
+    let mut end = None;
+    for (i, ch) in line.chars().enumerate() {
+        if i + 1 == span.col {
+            start = Some(column);
+        }
+        if i + 1 == span.col + span.len.max(1) {
+            end = Some(column);
+        }
+        if ch == '\t' {
+            let width = TAB_WIDTH - column % TAB_WIDTH;
+            text.push_str(&" ".repeat(width));
+            column += width;
+        } else {
+            text.push(ch);
+            column += 1;
+        }
+    }
+    let start = start.unwrap_or(column);
+    let end = end.unwrap_or(column).max(start + 1);
+    (text, start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::FileId;
+
+    fn span(line: usize, col: usize, len: usize) -> Span {
+        Span {
+            line,
+            col,
+            len,
+            file: FileId::MAIN,
+        }
+    }
+
+    fn diagnostic(span: Span) -> Diagnostic {
+        Diagnostic::new(
+            Severity::Error,
+            "E0010",
+            Path::new("main.bp"),
+            span,
+            &"use of undeclared variable 'y'",
+        )
+    }
+
+    #[test]
+    fn render_underlines_the_span() {
+        let source = "m x = 1;\nshow y + x;\n";
+        assert_eq!(
+            diagnostic(span(2, 6, 1)).render(Some(source), false),
+            "error[E0010] at main.bp:2:6: use of undeclared variable 'y'\n\
+             2 | show y + x;\n  |      ^"
+        );
+    }
+
+    #[test]
+    fn render_expands_tabs_and_clamps_to_the_line() {
+        let rendered = diagnostic(span(1, 2, 10)).render(Some("\tyy;"), false);
+        assert!(
+            rendered.ends_with("1 |     yy;\n  |     ^^^"),
+            "{}",
+            rendered
+        );
+        let rendered = diagnostic(span(1, 9, 1)).render(Some("show y"), false);
+        assert!(
+            rendered.ends_with("1 | show y\n  |       ^"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn render_without_source_or_span() {
+        assert_eq!(
+            diagnostic(span(4, 1, 1)).render(None, false),
+            "error[E0010] at main.bp:4:1: use of undeclared variable 'y'"
+        );
+        let whole =
+            Diagnostic::without_span(Severity::Warning, "W0005", Path::new("main.bp"), &"ignored");
+        assert_eq!(whole.render(None, false), "warning[W0005]: ignored");
+    }
+
+    #[test]
+    fn render_colors_only_when_asked() {
+        let rendered = diagnostic(span(1, 1, 1)).render(Some("y;"), true);
+        assert!(
+            rendered.starts_with("\x1b[1;31merror[E0010]\x1b[0m"),
+            "{}",
+            rendered
+        );
+    }
+
+    #[test]
+    fn json_has_every_field() {
+        assert_eq!(
+            diagnostic(span(2, 6, 3)).to_json(),
+            r#"{"severity":"error","code":"E0010","message":"use of undeclared variable 'y'","file":"main.bp","line":2,"col":6,"end_col":9}"#
+        );
+        let whole =
+            Diagnostic::without_span(Severity::Error, "E0080", Path::new("main.bp"), &"failed");
+        assert!(whole
+            .to_json()
+            .ends_with(r#""line":null,"col":null,"end_col":null}"#));
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), r#""a\"b\\c\nd\u0001""#);
+    }
+}
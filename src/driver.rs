@@ -0,0 +1,742 @@
+//! Runs the whole pipeline: BP source to C, C to a binary, then the binary
+
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitStatus};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::ast::{ast_json, ast_tree, ASTNode};
+use crate::backend::{reject_c_only, reject_inline_c, with_exe_suffix, Backend, Generated, Target};
+use crate::bytecode;
+use crate::cache;
+use crate::codes;
+use crate::diagnostic::{self, Diagnostic, ErrorFormat, Severity};
+use crate::error::{assertion_failure, CompileError, ASSERT_EXIT_CODE};
+use crate::format::to_source;
+use crate::interpreter::{interpret, InterpretError};
+use crate::lexer::{tokenize, FileId, Span};
+use crate::module;
+use crate::optimize::optimize;
+use crate::parser::parse_source;
+use crate::semantic::{analyze, SemanticError, Warning};
+use crate::vm;
+
+// Number of syntax errors printed before the rest are summarized
+const MAX_REPORTED_ERRORS: usize = 20;
+
+// Source path meaning "read the program from stdin"
+pub const STDIN_SOURCE: &str = "-";
+
+// Values accepted by `--opt`, each passed on as `-O<level>`
+pub const OPT_LEVELS: &[&str] = &["0", "1", "2", "3", "s", "z"];
+
+// Output name used for a program read from stdin, which has no file stem
+const STDIN_OUTPUT: &str = "bp_out";
+
+// Name of the source as shown in diagnostics
+fn display_path(source: &Path) -> &Path {
+    if source == Path::new(STDIN_SOURCE) {
+        Path::new("<stdin>")
+    } else {
+        source
+    }
+}
+
+// Derive the generated code and binary paths from the BP source path,
+// keeping them next to the source (`dir/hello.bp` -> `dir/hello.c`, `dir/hello`
+// for C). A program read from stdin gets `./bp_out.c` and `./bp_out`.
+fn output_paths(source: &Path, extension: &str) -> io::Result<(PathBuf, PathBuf)> {
+    if source == Path::new(STDIN_SOURCE) {
+        return output_paths(Path::new(STDIN_OUTPUT), extension);
+    }
+    let stem = source.file_stem().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid source path: {}", source.display()),
+        )
+    })?;
+
+    let dir = match source.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let code_file = dir.join(stem).with_extension(extension);
+    let mut binary = dir.join(stem).into_os_string();
+    binary.push(env::consts::EXE_SUFFIX);
+    Ok((code_file, PathBuf::from(binary)))
+}
+
+// Print a diagnostic on stderr, with the line of `text` it points at when
+// the source text is at hand
+pub(crate) fn report(
+    path: &Path,
+    text: Option<&str>,
+    severity: Severity,
+    code: &'static str,
+    span: Span,
+    message: &dyn fmt::Display,
+) {
+    let diagnostic = Diagnostic::new(severity, code, path, span, message);
+    eprintln!("{}", diagnostic.render(text, diagnostic::color()));
+}
+
+// How far the driver takes the program, chosen by the subcommand
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Mode {
+    // Stop after the semantic checks
+    Check,
+    // Produce the executable
+    Build,
+    // Produce the executable, then run it
+    #[default]
+    Run,
+}
+
+// Settings chosen on the command line
+#[derive(Debug, Default)]
+pub struct Options {
+    pub mode: Mode,
+    // Report warnings as errors and stop before generating C
+    pub deny_warnings: bool,
+    // Print an intermediate form instead of compiling
+    pub emit: Option<Emit>,
+    // Where to write the executable, instead of next to the source
+    pub output: Option<PathBuf>,
+    // Language the program is translated to before building it
+    pub target: Target,
+    // C compiler command given with `--cc`, taking precedence over `CC`
+    pub cc: Option<String>,
+    // How `run` executes the program
+    pub engine: Engine,
+    // Print the C compiler and command line used
+    pub verbose: bool,
+    // Generate C for the program as written, without folding constants
+    pub no_opt: bool,
+    // Optimization level passed as `-O<level>`
+    pub opt_level: Option<String>,
+    // Build with debug information (`-g`)
+    pub debug: bool,
+    // Extra C compiler flags, in command-line order
+    pub cflags: Vec<String>,
+    // Write the generated C next to the source instead of a temporary
+    // build directory
+    pub keep_c: bool,
+    // Collect the program's output and print it once it exits, instead of
+    // letting it write to the terminal directly
+    pub capture: bool,
+    // Arguments given after `--`, passed on to the program when it runs
+    pub program_args: Vec<String>,
+    // Always build, neither using nor filling the cache of built programs
+    pub no_cache: bool,
+    // How errors and warnings are written
+    pub error_format: ErrorFormat,
+    // Leave the `#line` directives pointing back at the BP source out of
+    // the generated C
+    pub no_line_directives: bool,
+    // C libraries given with `--link`, linked after those the program names
+    pub links: Vec<String>,
+    // Print how long each step of the pipeline took on stderr
+    pub timings: bool,
+    // Stop the program when int arithmetic overflows, instead of wrapping
+    pub trap_overflow: bool,
+}
+
+impl Options {
+    // Whether `run` may reuse a program built before. Anything asking for
+    // files besides the program's output, or for no cache, builds afresh.
+    fn uses_cache(&self) -> bool {
+        self.mode == Mode::Run
+            && self.engine == Engine::Compile
+            && self.emit.is_none()
+            && self.output.is_none()
+            && !self.keep_c
+            && !self.no_cache
+    }
+}
+
+// Ways `run` can execute a program
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum Engine {
+    // Build it for the target and run the result
+    #[default]
+    Compile,
+    // Walk the syntax tree
+    Tree,
+    // Compile it to bytecode for the stack machine
+    Vm,
+}
+
+impl Engine {
+    // Names accepted by `--engine`
+    pub const NAMES: &'static [&'static str] = &["compile", "tree", "vm"];
+
+    pub fn from_name(name: &str) -> Option<Engine> {
+        match name {
+            "compile" => Some(Engine::Compile),
+            "tree" => Some(Engine::Tree),
+            "vm" => Some(Engine::Vm),
+            _ => None,
+        }
+    }
+}
+
+// Intermediate forms that can be dumped for debugging
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Emit {
+    // The token stream, one token per line with its position
+    Tokens,
+    // The syntax tree as an indented outline
+    Ast,
+    // The syntax tree as JSON, for tools
+    AstJson,
+    // The program as BP text, after optimization
+    Source,
+    // The bytecode the `vm` engine runs, disassembled
+    Bytecode,
+    // The generated C, written next to the source as usual
+    CFile,
+    // The generated C, printed on stdout
+    CStdout,
+}
+
+// How many names `BuildDir::create` tries before giving up
+const BUILD_DIR_ATTEMPTS: usize = 16;
+
+// Per-invocation directory for intermediate files, so compiles never clobber
+// the user's files or each other. Removed on drop unless `keep` is set.
+pub(crate) struct BuildDir {
+    pub(crate) path: PathBuf,
+    pub(crate) keep: bool,
+}
+
+impl BuildDir {
+    // A new directory under the system's temporary directory, with a name
+    // nobody can guess ahead. A path that already exists, such as a
+    // directory or link another user planted there, is never reused: the
+    // binary built inside could be swapped before it runs, and dropping it
+    // would remove someone else's files.
+    pub(crate) fn create() -> io::Result<Self> {
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        let mut taken = None;
+        for attempt in 0..BUILD_DIR_ATTEMPTS {
+            let path = env::temp_dir().join(format!(
+                "bplang-{}-{:016x}",
+                process::id(),
+                random_suffix(attempt)
+            ));
+            match builder.create(&path) {
+                Ok(()) => return Ok(BuildDir { path, keep: false }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => taken = Some(err),
+                Err(err) => return Err(err),
+            }
+        }
+        Err(taken.expect("every attempt found its name taken"))
+    }
+}
+
+// Unpredictable bits for a directory name: std's hasher keys are random per
+// process, and mixed with the time and `attempt`
+fn random_suffix(attempt: usize) -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_usize(attempt);
+    if let Ok(elapsed) = SystemTime::now().duration_since(UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+    hasher.finish()
+}
+
+impl Drop for BuildDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            // Best effort: a leftover temp directory is harmless
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+// Time spent in each step of the pipeline, printed for `--timings`
+struct Timings {
+    enabled: bool,
+    last: Instant,
+}
+
+impl Timings {
+    fn start(enabled: bool) -> Self {
+        Timings {
+            enabled,
+            last: Instant::now(),
+        }
+    }
+
+    // Print the time since the previous step ended as that of `step`
+    fn step(&mut self, step: &str) {
+        if self.enabled {
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.last);
+            eprintln!("{:>10}: {:.2} ms", step, elapsed.as_secs_f64() * 1000.0);
+            self.last = now;
+        }
+    }
+}
+
+// Take the BP program at `source` as far as `options.mode` asks. Progress and
+// warnings are printed along the way; errors are returned for the caller to
+// report with `report_compile_error`. On success returns the exit code the
+// driver should finish with: the program's own in run mode, 0 otherwise.
+pub fn run(source: &Path, options: &Options) -> Result<i32, CompileError> {
+    let backend = options.target.backend();
+    let (code_path, binary_path) = output_paths(source, backend.extension())?;
+    let mut timings = Timings::start(options.timings);
+
+    // Step 1: Read the BP source code, from stdin when the source is `-`
+    let mut source_code = String::new();
+    if source == Path::new(STDIN_SOURCE) {
+        io::stdin().read_to_string(&mut source_code)?;
+    } else {
+        File::open(source)?.read_to_string(&mut source_code)?;
+    }
+    timings.step("read");
+
+    // Step 2: Tokenize the BP source code
+    if options.emit == Some(Emit::Tokens) {
+        for token in tokenize(&source_code)? {
+            println!("{}", token);
+        }
+        return Ok(0);
+    }
+
+    // Step 3: Parse tokens into AST, tokenizing as the parser goes
+    let ast = parse_source(&source_code, FileId::MAIN)?;
+    timings.step("parse");
+    if options.emit == Some(Emit::Ast) {
+        print!("{}", ast_tree(&ast));
+        return Ok(0);
+    }
+    if options.emit == Some(Emit::AstJson) {
+        println!("{}", ast_json(&ast));
+        return Ok(0);
+    }
+
+    // Bring in the definitions of the files the program uses
+    let (ast, modules) = module::resolve(ast, source)?;
+    timings.step("use");
+
+    // Any error above has already returned, so no C file is written and gcc
+    // never sees a broken program
+    let warnings = analyze(&ast)?;
+    timings.step("check");
+    report_warnings(source, &source_code, warnings, options)?;
+    if options.emit == Some(Emit::Bytecode) {
+        reject_c_only(&ast, "the vm engine")?;
+        print!("{}", bytecode::compile(&ast));
+        return Ok(0);
+    }
+    if options.mode == Mode::Check {
+        return Ok(0);
+    }
+    if options.engine != Engine::Compile {
+        match options.engine {
+            Engine::Vm => reject_c_only(&ast, "the vm engine")?,
+            _ => reject_inline_c(&ast, "the tree engine")?,
+        }
+        // The program name is the source, there being no binary
+        let mut args = vec![display_path(source).display().to_string()];
+        args.extend(options.program_args.iter().cloned());
+        let (input, out) = (io::stdin().lock(), io::stdout().lock());
+        let result = match options.engine {
+            Engine::Vm => vm::execute(
+                &bytecode::compile(&ast),
+                args,
+                options.trap_overflow,
+                input,
+                out,
+            ),
+            _ => interpret(&ast, args, options.trap_overflow, input, out),
+        };
+        return match result {
+            Err(InterpretError::Assert { message, span }) => {
+                let failure = assertion_failure(display_path(source), span, message.as_deref());
+                eprintln!("{}", failure);
+                Ok(ASSERT_EXIT_CODE)
+            }
+            result => Ok(result?),
+        };
+    }
+
+    // Step 4: Translate the AST for the target. Unless asked to keep it next
+    // to the source, the code goes to a private build directory removed when
+    // we are done.
+    let ast = if options.no_opt {
+        ast
+    } else {
+        let (ast, warnings) = optimize(ast, options.trap_overflow)?;
+        timings.step("optimize");
+        report_warnings(source, &source_code, warnings, options)?;
+        ast
+    };
+
+    // A program built before from the same files with the same settings
+    // runs as it is. Only now, so its warnings are still reported, and
+    // `--deny-warnings` still stops it.
+    let cache_key = options.uses_cache().then(|| {
+        let mut sources = vec![(display_path(source), source_code.as_str())];
+        sources.extend(
+            modules
+                .iter()
+                .map(|module| (module.path.as_path(), module.text.as_str())),
+        );
+        cache::key(&sources, options)
+    });
+    if let Some(cached) = cache_key.as_deref().and_then(cache::lookup) {
+        if options.verbose {
+            println!("Using cached build {}", cached.display());
+        }
+        println!("Using the cached build, running the program...");
+        return execute(backend.as_ref(), &cached, options);
+    }
+    if options.emit == Some(Emit::Source) {
+        print!("{}", to_source(&ast));
+        return Ok(0);
+    }
+    let mut program = backend.generate(&ast, display_path(source), options)?;
+    timings.step("generate");
+    add_libraries(
+        &mut program,
+        &ast,
+        backend.as_ref(),
+        source,
+        &source_code,
+        options,
+    )?;
+    if options.emit == Some(Emit::CStdout) {
+        write!(io::stdout(), "{}", program.code)?;
+        return Ok(0);
+    }
+    let mut build_dir = BuildDir::create()?;
+    let keep_code = options.keep_c || options.emit == Some(Emit::CFile);
+    let code_path = if keep_code {
+        code_path
+    } else {
+        build_dir
+            .path
+            .join(code_path.file_name().expect("named after the source"))
+    };
+    fs::write(&code_path, &program.code)?;
+    if keep_code {
+        println!(
+            "{} code has been generated and written to {}",
+            backend.language(),
+            code_path.display()
+        );
+    }
+    if options.emit == Some(Emit::CFile) {
+        return Ok(0);
+    }
+
+    // Step 5: Build the generated code. `build` writes the binary next to
+    // the source unless `-o` says otherwise; `run` only needs it for a
+    // moment.
+    let binary_path = match &options.output {
+        Some(output) => with_exe_suffix(output),
+        None if options.mode == Mode::Build => binary_path,
+        None => build_dir
+            .path
+            .join(binary_path.file_name().expect("named after the source")),
+    };
+    if let Some(parent) = binary_path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    // On failure the generated code is kept so it can be investigated
+    if let Err(err) = backend.build(&program, &code_path, &binary_path, options) {
+        build_dir.keep = true;
+        return Err(err);
+    }
+    timings.step("build");
+
+    if options.mode == Mode::Build {
+        println!("Built {}", binary_path.display());
+        return Ok(0);
+    }
+    // Caching is an optimization: failing to fill it loses nothing
+    if let Some(key) = &cache_key {
+        if let Err(err) = cache::store(key, &binary_path) {
+            eprintln!("warning: could not cache the build: {}", err);
+        }
+    }
+    println!("Compilation successful, running the program...");
+    execute(backend.as_ref(), &binary_path, options)
+}
+
+// Step 6: Execute the built program. By default it shares our stdin, stdout
+// and stderr, so output appears as it is written and the program can read
+// from the terminal.
+fn execute(
+    backend: &dyn Backend,
+    binary_path: &Path,
+    options: &Options,
+) -> Result<i32, CompileError> {
+    // An absolute path, so a bare `-o hello` is not looked up in PATH
+    let binary_path = fs::canonicalize(binary_path)?;
+
+    println!("Program output:");
+    let mut command = backend.run_command(&binary_path);
+    command.args(&options.program_args);
+    // Targets run through an interpreter, such as `node`, need it in PATH
+    let program = command.get_program().to_string_lossy().into_owned();
+    let missing = |err: io::Error| match err.kind() {
+        io::ErrorKind::NotFound => CompileError::NoTool {
+            program: program.clone(),
+            purpose: format!("run {} programs", backend.language()),
+        },
+        _ => CompileError::Io(err),
+    };
+    let status = if options.capture {
+        let execution_output = command.output().map_err(missing)?;
+        io::stdout().write_all(&execution_output.stdout)?;
+        io::stderr().write_all(&execution_output.stderr)?;
+        execution_output.status
+    } else {
+        command.status().map_err(missing)?
+    };
+
+    Ok(exit_code(status))
+}
+
+// Add the libraries named by the program's `link` directives and by
+// `--link` to those the generated code needs. A target that links no C
+// libraries gets a warning about each instead.
+fn add_libraries(
+    program: &mut Generated,
+    ast: &[ASTNode],
+    backend: &dyn Backend,
+    source: &Path,
+    source_code: &str,
+    options: &Options,
+) -> Result<(), CompileError> {
+    let directives = ast.iter().filter_map(|node| match node {
+        ASTNode::Link { library, span } => Some((library, *span)),
+        _ => None,
+    });
+    if !backend.links_libraries() {
+        let ignored = |what: &str, library: &str| {
+            format!(
+                "{} does nothing for the {} target, so '{}' is not linked",
+                what,
+                backend.language(),
+                library
+            )
+        };
+        let warnings = directives
+            .map(|(library, span)| Warning {
+                code: codes::IGNORED_LINK,
+                message: ignored("'link'", library),
+                span,
+            })
+            .collect();
+        report_warnings(source, source_code, warnings, options)?;
+        for library in &options.links {
+            let diagnostic = Diagnostic::without_span(
+                Severity::Warning,
+                codes::IGNORED_LINK,
+                display_path(source),
+                &ignored("--link", library),
+            );
+            if options.error_format == ErrorFormat::Json {
+                println!("{}", diagnostic.to_json());
+            } else {
+                eprintln!("{}", diagnostic.render(None, diagnostic::color()));
+            }
+        }
+        return Ok(());
+    }
+    link_libraries(program, ast, options);
+    Ok(())
+}
+
+// Add the libraries named by `link` directives and by `--link` to those
+// the generated code needs, for a target that links C libraries. The
+// program's own come first, since they may use the ones the generated
+// code needs but not the other way around.
+pub(crate) fn link_libraries(program: &mut Generated, ast: &[ASTNode], options: &Options) {
+    let needed = std::mem::take(&mut program.libraries);
+    let requested = ast
+        .iter()
+        .filter_map(|node| match node {
+            ASTNode::Link { library, .. } => Some(library),
+            _ => None,
+        })
+        .chain(&options.links)
+        .cloned();
+    for library in requested.chain(needed) {
+        if !program.libraries.contains(&library) {
+            program.libraries.push(library);
+        }
+    }
+}
+
+// Print warnings about the program at `source`, or with `--deny-warnings`
+// turn them into errors
+fn report_warnings(
+    source: &Path,
+    source_code: &str,
+    warnings: Vec<Warning>,
+    options: &Options,
+) -> Result<(), CompileError> {
+    if options.deny_warnings && !warnings.is_empty() {
+        let errors = warnings
+            .into_iter()
+            .map(|warning| SemanticError {
+                code: warning.code,
+                message: warning.message,
+                span: warning.span,
+            })
+            .collect();
+        return Err(CompileError::Semantic(errors));
+    }
+    for warning in &warnings {
+        if options.error_format == ErrorFormat::Json {
+            let diagnostic = Diagnostic::new(
+                Severity::Warning,
+                warning.code,
+                display_path(source),
+                warning.span,
+                warning,
+            );
+            println!("{}", diagnostic.to_json());
+            continue;
+        }
+        report(
+            display_path(source),
+            Some(source_code),
+            Severity::Warning,
+            warning.code,
+            warning.span,
+            warning,
+        );
+    }
+    Ok(())
+}
+
+// Exit code a shell would report for a finished process; a process killed by
+// a signal gives 128 plus the signal number
+fn exit_code(status: ExitStatus) -> i32 {
+    if let Some(code) = status.code() {
+        return code;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    1
+}
+
+// What to tell the user about a failed compilation, with file positions
+// for errors found in the BP source
+pub fn compile_error_diagnostics(source: &Path, err: &CompileError) -> Vec<Diagnostic> {
+    let source = display_path(source);
+    let error = |code, span, message: &dyn fmt::Display| {
+        Diagnostic::new(Severity::Error, code, source, span, message)
+    };
+    let whole = |code| vec![Diagnostic::without_span(Severity::Error, code, source, err)];
+    match err {
+        CompileError::Lex(err) => vec![error(err.code(), err.span, err)],
+        CompileError::Runtime(err) => vec![error(codes::RUNTIME_ERROR, err.span, err)],
+        CompileError::Codegen(err) => vec![error(codes::UNSUPPORTED_BY_TARGET, err.span, err)],
+        CompileError::Parse(errors) => errors
+            .iter()
+            .map(|err| error(err.code, err.span, err))
+            .collect(),
+        CompileError::Semantic(errors) => errors
+            .iter()
+            .map(|err| error(err.code, err.span, err))
+            .collect(),
+        CompileError::Cc(_) => whole(codes::COMPILER_FAILED),
+        CompileError::NoCompiler { .. } | CompileError::NoTool { .. } => whole(codes::MISSING_TOOL),
+        CompileError::Io(_) => whole(codes::IO_ERROR),
+    }
+}
+
+// The diagnostics of a failed compilation as printed, each with the line of
+// the source it points at. Past the first few, errors are only counted.
+pub fn compile_error_messages(source: &Path, err: &CompileError, color: bool) -> Vec<String> {
+    // Read again for the excerpts; a program from stdin is gone by now
+    let text = if source == Path::new(STDIN_SOURCE) {
+        None
+    } else {
+        fs::read_to_string(source).ok()
+    };
+    let diagnostics = compile_error_diagnostics(source, err);
+    let mut messages: Vec<String> = diagnostics
+        .iter()
+        .take(MAX_REPORTED_ERRORS)
+        .map(|diagnostic| diagnostic.render(text.as_deref(), color))
+        .collect();
+    if diagnostics.len() > MAX_REPORTED_ERRORS {
+        messages.push(format!(
+            "... and {} more errors",
+            diagnostics.len() - MAX_REPORTED_ERRORS
+        ));
+    }
+    messages
+}
+
+// Print a failed compilation for the user on stderr, or as JSON on stdout
+pub fn report_compile_error(source: &Path, err: &CompileError, format: ErrorFormat) {
+    if format == ErrorFormat::Json {
+        for diagnostic in compile_error_diagnostics(source, err) {
+            println!("{}", diagnostic.to_json());
+        }
+        return;
+    }
+    for message in compile_error_messages(source, err, diagnostic::color()) {
+        eprintln!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_dirs_are_fresh_and_removed() {
+        let first = BuildDir::create().unwrap();
+        let second = BuildDir::create().unwrap();
+        assert_ne!(first.path, second.path);
+        assert!(first.path.is_dir() && second.path.is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&first.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        let path = first.path.clone();
+        drop(first);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn kept_build_dir_stays() {
+        let mut dir = BuildDir::create().unwrap();
+        dir.keep = true;
+        let path = dir.path.clone();
+        drop(dir);
+        assert!(path.is_dir());
+        fs::remove_dir(path).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+//! The error type returned by every stage of the pipeline
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::interpreter::{InterpretError, RuntimeError};
+use crate::lexer::{LexError, Span};
+use crate::module;
+use crate::parser::ParseError;
+use crate::semantic::SemanticError;
+
+// The compiler of the target language rejected the generated code or crashed
+#[derive(Debug)]
+pub struct CcError {
+    // Language of the generated code, e.g. `C`
+    pub language: &'static str,
+    // The command line that was run
+    pub command: String,
+    // How the compiler ended, e.g. `exit status: 1`
+    pub status: String,
+    // What the compiler printed on stderr
+    pub stderr: String,
+    // The generated code, left in place for debugging
+    pub code_file: PathBuf,
+}
+
+impl fmt::Display for CcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} compilation failed ({})\n  command: {}\n  generated {}: {}",
+            self.language,
+            self.status,
+            self.command,
+            self.language,
+            self.code_file.display()
+        )?;
+        if !self.stderr.trim().is_empty() {
+            write!(f, "\n{}", self.stderr.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for CcError {}
+
+// A checked program uses something the chosen target cannot express
+#[derive(Debug)]
+pub struct CodegenError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for CodegenError {}
+
+// Exit code of a program stopped by a failed `assert`, compiled or
+// interpreted
+pub const ASSERT_EXIT_CODE: i32 = 101;
+
+// What a failed `assert` prints, with the position of the statement in the
+// BP source: the program at `source`, or a file it uses
+pub fn assertion_failure(source: &Path, span: Span, message: Option<&str>) -> String {
+    let source = module::source_path(span.file, source);
+    match message {
+        Some(message) => format!(
+            "assertion failed at {}:{}: {}",
+            source.display(),
+            span.line,
+            message
+        ),
+        None => format!("assertion failed at {}:{}", source.display(), span.line),
+    }
+}
+
+// Why compiling a BP program failed
+#[derive(Debug)]
+pub enum CompileError {
+    Lex(LexError),
+    Parse(Vec<ParseError>),
+    Semantic(Vec<SemanticError>),
+    Codegen(CodegenError),
+    Cc(CcError),
+    // None of the C compilers in `tried` could be found
+    NoCompiler { tried: Vec<String> },
+    // A program another target needs, such as `node`, could not be found;
+    // `purpose` completes "needed to ..."
+    NoTool { program: String, purpose: String },
+    // The interpreted program failed, e.g. by dividing by zero
+    Runtime(RuntimeError),
+    Io(io::Error),
+}
+
+impl CompileError {
+    // Process exit code reporting this kind of failure, so scripts can tell
+    // a broken program from a broken toolchain
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CompileError::Io(_) => 1,
+            CompileError::Lex(_) => 2,
+            CompileError::Parse(_) => 3,
+            CompileError::Semantic(_) => 4,
+            CompileError::Cc(_) => 5,
+            CompileError::NoCompiler { .. } | CompileError::NoTool { .. } => 6,
+            CompileError::Runtime(_) => 7,
+            CompileError::Codegen(_) => 8,
+        }
+    }
+}
+
+// One `line:col: message` line per diagnostic; the driver adds the file name
+// when reporting to the user
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Lex(err) => write!(f, "{}:{}: {}", err.span.line, err.span.col, err),
+            CompileError::Parse(errors) => {
+                let lines: Vec<String> = errors
+                    .iter()
+                    .map(|err| format!("{}:{}: {}", err.span.line, err.span.col, err))
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            CompileError::Semantic(errors) => {
+                let lines: Vec<String> = errors
+                    .iter()
+                    .map(|err| format!("{}:{}: {}", err.span.line, err.span.col, err))
+                    .collect();
+                write!(f, "{}", lines.join("\n"))
+            }
+            CompileError::Codegen(err) => {
+                write!(f, "{}:{}: {}", err.span.line, err.span.col, err)
+            }
+            CompileError::Cc(err) => write!(f, "{}", err),
+            CompileError::NoCompiler { tried } => match tried.as_slice() {
+                // A path was given explicitly, so PATH played no part
+                [compiler] if compiler.contains(std::path::MAIN_SEPARATOR) => write!(
+                    f,
+                    "C compiler '{}' not found; pass --cc <compiler>",
+                    compiler
+                ),
+                [compiler] => write!(
+                    f,
+                    "C compiler '{}' not found in PATH; install {} or pass --cc <compiler>",
+                    compiler, compiler
+                ),
+                _ => write!(
+                    f,
+                    "no C compiler found; tried {} in PATH. Pass --cc <compiler> or set CC",
+                    tried.join(", ")
+                ),
+            },
+            CompileError::NoTool { program, purpose } => write!(
+                f,
+                "'{}' not found in PATH; it is needed to {}",
+                program, purpose
+            ),
+            CompileError::Runtime(err) => {
+                write!(f, "{}:{}: {}", err.span.line, err.span.col, err)
+            }
+            CompileError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for CompileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CompileError::Lex(err) => Some(err),
+            CompileError::Codegen(err) => Some(err),
+            CompileError::Cc(err) => Some(err),
+            CompileError::Runtime(err) => Some(err),
+            CompileError::Io(err) => Some(err),
+            CompileError::Parse(_)
+            | CompileError::Semantic(_)
+            | CompileError::NoCompiler { .. }
+            | CompileError::NoTool { .. } => None,
+        }
+    }
+}
+
+impl From<LexError> for CompileError {
+    fn from(err: LexError) -> Self {
+        CompileError::Lex(err)
+    }
+}
+
+impl From<Vec<ParseError>> for CompileError {
+    fn from(errors: Vec<ParseError>) -> Self {
+        CompileError::Parse(errors)
+    }
+}
+
+impl From<SemanticError> for CompileError {
+    fn from(err: SemanticError) -> Self {
+        CompileError::Semantic(vec![err])
+    }
+}
+
+impl From<CodegenError> for CompileError {
+    fn from(err: CodegenError) -> Self {
+        CompileError::Codegen(err)
+    }
+}
+
+impl From<CcError> for CompileError {
+    fn from(err: CcError) -> Self {
+        CompileError::Cc(err)
+    }
+}
+
+impl From<InterpretError> for CompileError {
+    fn from(err: InterpretError) -> Self {
+        match err {
+            InterpretError::Runtime(err) => CompileError::Runtime(err),
+            InterpretError::Io(err) => CompileError::Io(err),
+            InterpretError::Exit(_) => unreachable!("`interpret` turns exits into exit codes"),
+            InterpretError::Assert { .. } => {
+                unreachable!("the driver reports failed assertions itself")
+            }
+        }
+    }
+}
+
+impl From<io::Error> for CompileError {
+    fn from(err: io::Error) -> Self {
+        CompileError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::FileId;
+
+    #[test]
+    fn assertion_failure_names_the_line() {
+        let span = Span {
+            line: 4,
+            col: 1,
+            len: 6,
+            file: FileId::MAIN,
+        };
+        assert_eq!(
+            assertion_failure(Path::new("prog.bp"), span, None),
+            "assertion failed at prog.bp:4"
+        );
+        assert_eq!(
+            assertion_failure(Path::new("prog.bp"), span, Some("x is positive")),
+            "assertion failed at prog.bp:4: x is positive"
+        );
+    }
+
+    #[test]
+    fn exit_codes_tell_failures_apart() {
+        let errors = [
+            CompileError::Io(io::Error::other("disk")),
+            CompileError::Parse(Vec::new()),
+            CompileError::Semantic(Vec::new()),
+            CompileError::NoCompiler { tried: Vec::new() },
+        ];
+        let codes: Vec<i32> = errors.iter().map(CompileError::exit_code).collect();
+        assert_eq!(codes, [1, 3, 4, 6]);
+    }
+}
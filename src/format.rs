@@ -0,0 +1,717 @@
+//! `bplang fmt`: rewrites BP source in a canonical layout. Works on the
+//! token stream, so comments between tokens are kept. `to_source` writes a
+//! syntax tree back out as BP in the same layout.
+
+use std::io;
+
+use crate::ast::{ASTNode, BinaryOperator, Parameter, UnaryOperator};
+use crate::error::CompileError;
+use crate::lexer::{tokenize, SpannedToken, Token};
+use crate::parser::parse;
+
+// Spaces per level of block nesting
+const INDENT: &str = "    ";
+
+// What a `{` opens, which decides how its contents are laid out
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Brace {
+    // Statements, one per line
+    Block,
+    // The arms of a `match`, one block each
+    Match,
+    // The fields of a struct definition, one per line
+    StructDef,
+    // The variants of an enum, on one line
+    EnumDef,
+    // The fields of a struct literal, on one line
+    Inline,
+}
+
+// A string literal with the escapes the lexer reads, and only those needed
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Whether a `-` after `prev` subtracts, rather than negates
+fn ends_operand(prev: Option<&Token>) -> bool {
+    match prev {
+        Some(Token::Keyword(k)) => *k == "true" || *k == "false",
+        Some(token) => matches!(
+            token,
+            Token::Identifier(_)
+                | Token::Number(_)
+                | Token::Float(_)
+                | Token::StringLiteral(_)
+                | Token::RParen
+                | Token::RBracket
+        ),
+        None => false,
+    }
+}
+
+// Byte offsets of tokens in the source, from their line and column
+struct Positions<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Positions<'a> {
+    fn new(source: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Positions {
+            source,
+            line_starts,
+        }
+    }
+
+    // Byte offset `chars` characters after the start of column `col` of
+    // `line`
+    fn offset(&self, line: usize, col: usize, chars: usize) -> usize {
+        let start = self.line_starts[line - 1];
+        self.source[start..]
+            .char_indices()
+            .nth(col - 1 + chars)
+            .map_or(self.source.len(), |(i, _)| start + i)
+    }
+
+    fn start(&self, token: &SpannedToken) -> usize {
+        self.offset(token.span.line, token.span.col, 0)
+    }
+
+    fn end(&self, token: &SpannedToken) -> usize {
+        self.offset(token.span.line, token.span.col, token.span.len)
+    }
+}
+
+// A comment found between two tokens, and how many line breaks came
+// before it since the previous token or comment
+struct Comment<'a> {
+    text: &'a str,
+    newlines_before: usize,
+}
+
+// The comments in `gap`, the text between two tokens, and the number of
+// line breaks after the last of them
+fn comments(gap: &str) -> (Vec<Comment<'_>>, usize) {
+    let mut found = Vec::new();
+    let mut newlines = 0;
+    let mut rest = gap;
+    while let Some(ch) = rest.chars().next() {
+        if rest.starts_with("//") {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            found.push(Comment {
+                text: rest[..end].trim_end(),
+                newlines_before: newlines,
+            });
+            newlines = 0;
+            rest = &rest[end..];
+        } else if rest.starts_with("/*") {
+            // Nested like in the lexer
+            let mut depth = 0;
+            let mut end = rest.len();
+            let mut i = 0;
+            while i < rest.len() {
+                if rest[i..].starts_with("/*") {
+                    depth += 1;
+                    i += 2;
+                } else if rest[i..].starts_with("*/") {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        end = i;
+                        break;
+                    }
+                } else {
+                    i += rest[i..].chars().next().map_or(1, char::len_utf8);
+                }
+            }
+            found.push(Comment {
+                text: &rest[..end],
+                newlines_before: newlines,
+            });
+            newlines = 0;
+            rest = &rest[end..];
+        } else {
+            if ch == '\n' {
+                newlines += 1;
+            }
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    (found, newlines)
+}
+
+// Builds the formatted text line by line. A line is only ended when more
+// text follows, so a comment can still join the line of the code before it.
+#[derive(Default)]
+struct Writer {
+    lines: Vec<String>,
+    line: String,
+    depth: usize,
+    newline_pending: bool,
+    // The source had a blank line here
+    blank_pending: bool,
+}
+
+impl Writer {
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            self.lines.push(std::mem::take(&mut self.line));
+        }
+        self.newline_pending = false;
+    }
+
+    // Start a new line if one is due, keeping one blank line where the
+    // source had any, but not right after an opening brace
+    fn start_line(&mut self) {
+        if self.newline_pending {
+            self.flush();
+        }
+        if self.line.is_empty() {
+            if self.blank_pending
+                && self
+                    .lines
+                    .last()
+                    .is_some_and(|last| !last.is_empty() && !last.ends_with('{'))
+            {
+                self.lines.push(String::new());
+            }
+            self.line = INDENT.repeat(self.depth);
+        }
+        self.blank_pending = false;
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.newline_pending || self.line.trim().is_empty()
+    }
+
+    // Add `text`, after a space unless `space` is false or it starts a line
+    fn push(&mut self, text: &str, space: bool) {
+        let starts_line = self.at_line_start();
+        self.start_line();
+        if space && !starts_line {
+            self.line.push(' ');
+        }
+        self.line.push_str(text);
+    }
+
+    // A comment on the line of the code before it, even if that line is
+    // complete
+    fn push_trailing(&mut self, text: &str) {
+        if self.line.trim().is_empty() {
+            return self.push_comment_line(text);
+        }
+        self.line.push(' ');
+        self.line.push_str(text);
+        if text.starts_with("//") {
+            self.newline_pending = true;
+        }
+    }
+
+    // A comment on a line of its own
+    fn push_comment_line(&mut self, text: &str) {
+        self.flush();
+        self.start_line();
+        self.line.push_str(text);
+        self.newline_pending = true;
+    }
+
+    fn finish(mut self) -> String {
+        self.flush();
+        let mut text = self.lines.join("\n");
+        text.push('\n');
+        text
+    }
+}
+
+// Lay out the tokens of a program that parses, with the comments found in
+// `source` between them
+fn layout(source: &str, tokens: &[SpannedToken]) -> String {
+    let positions = Positions::new(source);
+    let mut out = Writer::default();
+    let mut braces: Vec<Brace> = Vec::new();
+    // What the next `{` opens, when a keyword such as `if` announced it
+    let mut header: Option<Brace> = None;
+    // Nesting of `(` and `[`
+    let mut nesting = 0;
+    let mut prev: Option<&Token> = None;
+    let mut prev_end = 0;
+    // The previous token was a `-` negating what follows
+    let mut prev_unary = false;
+
+    for spanned in tokens {
+        let start = match spanned.token {
+            Token::EndOfFile => source.len(),
+            _ => positions.start(spanned),
+        };
+        let (found, newlines_after) = comments(&source[prev_end..start]);
+        for comment in found {
+            if comment.newlines_before == 0 && prev.is_some() {
+                out.push_trailing(comment.text);
+            } else {
+                out.blank_pending |= comment.newlines_before > 1;
+                out.push_comment_line(comment.text);
+            }
+        }
+        out.blank_pending |= newlines_after > 1;
+        let token = &spanned.token;
+        if *token == Token::EndOfFile {
+            break;
+        }
+        prev_end = positions.end(spanned);
+
+        let space = match (prev, token) {
+            _ if prev_unary => false,
+            (Some(Token::LParen | Token::LBracket | Token::Dot), _) => false,
+            (
+                _,
+                Token::RParen
+                | Token::RBracket
+                | Token::Comma
+                | Token::Semicolon
+                | Token::Dot
+                | Token::Colon,
+            ) => false,
+            (Some(Token::Identifier(_)), Token::LParen | Token::LBracket) => false,
+            _ => true,
+        };
+        prev_unary = false;
+        match token {
+            Token::LBrace => {
+                let brace = match header.take() {
+                    Some(brace) if nesting == 0 => brace,
+                    _ if braces.last() == Some(&Brace::Match) => Brace::Block,
+                    _ if out.at_line_start() => Brace::Block,
+                    _ => Brace::Inline,
+                };
+                out.blank_pending = false;
+                out.push("{", true);
+                braces.push(brace);
+                if brace != Brace::Inline && brace != Brace::EnumDef {
+                    out.depth += 1;
+                    out.newline_pending = true;
+                }
+            }
+            Token::RBrace => {
+                out.blank_pending = false;
+                match braces.pop() {
+                    Some(Brace::Inline) | None => out.push("}", prev != Some(&Token::LBrace)),
+                    // A definition, so the end of a statement
+                    Some(Brace::EnumDef) => {
+                        out.push("}", prev != Some(&Token::LBrace));
+                        out.newline_pending = true;
+                    }
+                    Some(_) => {
+                        out.depth -= 1;
+                        if out.newline_pending && out.line.ends_with('{') {
+                            // An empty block
+                            out.line.push('}');
+                        } else {
+                            out.flush();
+                            out.push("}", false);
+                        }
+                        out.newline_pending = true;
+                    }
+                }
+            }
+            Token::Semicolon => {
+                out.push(";", false);
+                if nesting == 0 {
+                    out.newline_pending = true;
+                }
+            }
+            Token::Keyword(k) if *k == "else" => {
+                // `} else {` on one line, but a match's `else` arm on a
+                // line of its own like the other arms
+                if out.newline_pending
+                    && out.line.trim() == "}"
+                    && braces.last() != Some(&Brace::Match)
+                {
+                    out.newline_pending = false;
+                }
+                header = Some(Brace::Block);
+                out.push(k, true);
+            }
+            Token::Keyword(k) => {
+                header = match *k {
+                    "if" | "for" | "fn" => Some(Brace::Block),
+                    "match" => Some(Brace::Match),
+                    "struct" => Some(Brace::StructDef),
+                    "enum" => Some(Brace::EnumDef),
+                    _ => header,
+                };
+                out.push(k, space);
+            }
+            Token::LParen | Token::LBracket => {
+                out.push(&source[positions.start(spanned)..prev_end], space);
+                nesting += 1;
+            }
+            Token::RParen | Token::RBracket => {
+                out.push(&source[positions.start(spanned)..prev_end], space);
+                nesting -= 1;
+            }
+            Token::Minus if !ends_operand(prev) => {
+                out.push("-", space);
+                prev_unary = true;
+            }
+            Token::StringLiteral(s) => out.push(&quote(s), space),
+            // Everything else as written, numbers included
+            _ => out.push(&source[positions.start(spanned)..prev_end], space),
+        }
+        prev = Some(token);
+    }
+    out.finish()
+}
+
+// The canonical layout of the BP program `source`, which must parse
+pub fn format_source(source: &str) -> Result<String, CompileError> {
+    let tokens = tokenize(source)?;
+    parse(&tokens)?;
+    let formatted = layout(source, &tokens);
+    // Only spacing may change, never the tokens
+    let same = tokenize(&formatted).is_ok_and(|reformatted| {
+        reformatted
+            .iter()
+            .map(|spanned| &spanned.token)
+            .eq(tokens.iter().map(|spanned| &spanned.token))
+    });
+    if !same {
+        return Err(CompileError::Io(io::Error::other(
+            "formatting would change the program; it was left as it is",
+        )));
+    }
+    Ok(formatted)
+}
+
+// How tightly `op` binds, following the parser's precedence levels
+fn precedence(op: BinaryOperator) -> u8 {
+    match op {
+        BinaryOperator::Or => 0,
+        BinaryOperator::And => 1,
+        BinaryOperator::Eq | BinaryOperator::Ne => 2,
+        BinaryOperator::Lt | BinaryOperator::Gt | BinaryOperator::Le | BinaryOperator::Ge => 3,
+        BinaryOperator::Add | BinaryOperator::Sub => 4,
+        BinaryOperator::Mul | BinaryOperator::Div | BinaryOperator::Mod => 5,
+    }
+}
+
+// Binds tighter than any binary operator, as prefix operators do
+const UNARY: u8 = 6;
+
+// How tightly the written form of `expr` holds together. A negative literal,
+// which only the optimizer makes, is written with a `-` in front.
+fn binding(expr: &ASTNode) -> u8 {
+    match expr {
+        ASTNode::BinaryOp { op, .. } => precedence(*op),
+        _ => UNARY + 1,
+    }
+}
+
+// `expr` written as an operand needing to bind at least as tightly as `min`
+fn operand(expr: &ASTNode, min: u8) -> String {
+    let text = expression(expr);
+    if binding(expr) < min {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+// The BP text of an expression, with only the parentheses its structure
+// needs. Every binary operator is left-associative, so a right operand of
+// the same precedence keeps its parentheses.
+fn expression(expr: &ASTNode) -> String {
+    match expr {
+        ASTNode::StringLiteral(s) => quote(s),
+        ASTNode::NumberLiteral(num) => num.to_string(),
+        ASTNode::LongLiteral(num) => num.to_string(),
+        ASTNode::FloatLiteral(num) if num.is_nan() => "(0.0 / 0.0)".to_string(),
+        ASTNode::FloatLiteral(num) if num.is_infinite() => {
+            let sign = if *num < 0.0 { "-" } else { "" };
+            format!("({}1.0 / 0.0)", sign)
+        }
+        ASTNode::FloatLiteral(num) => {
+            let text = num.to_string();
+            if text.contains('.') {
+                text
+            } else {
+                format!("{}.0", text)
+            }
+        }
+        ASTNode::BoolLiteral(b) => b.to_string(),
+        ASTNode::Identifier(name, _) => name.clone(),
+        ASTNode::ArrayLiteral(elements, _) => {
+            let elements: Vec<String> = elements.iter().map(expression).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        ASTNode::Index { name, index, .. } => format!("{}[{}]", name, expression(index)),
+        ASTNode::StructInit { name, fields, .. } => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(field, value)| format!("{} = {}", field, expression(value)))
+                .collect();
+            if fields.is_empty() {
+                format!("{} {{}}", name)
+            } else {
+                format!("{} {{ {} }}", name, fields.join(", "))
+            }
+        }
+        ASTNode::FieldAccess { name, field, .. } => format!("{}.{}", name, field),
+        ASTNode::Call { name, args, .. } => {
+            let args: Vec<String> = args.iter().map(expression).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        ASTNode::BinaryOp { op, lhs, rhs, .. } => {
+            let level = precedence(*op);
+            format!("{} {} {}", operand(lhs, level), op, operand(rhs, level + 1))
+        }
+        ASTNode::UnaryOp {
+            op, operand: inner, ..
+        } => {
+            let inner = operand(inner, UNARY);
+            match op {
+                UnaryOperator::Not => format!("not {}", inner),
+                UnaryOperator::Neg => format!("-{}", inner),
+            }
+        }
+        // A statement where an expression belongs; no parser makes one
+        statement => to_source(std::slice::from_ref(statement))
+            .trim()
+            .to_string(),
+    }
+}
+
+// A `{ ... }` block of statements at `depth`, the `{` going at the end of
+// the line so far
+fn block(out: &mut String, nodes: &[ASTNode], depth: usize) {
+    if nodes.is_empty() {
+        out.push_str("{}");
+        return;
+    }
+    out.push_str("{\n");
+    statements(out, nodes, depth + 1);
+    out.push_str(&INDENT.repeat(depth));
+    out.push('}');
+}
+
+// Whether `node` defines something, set apart by blank lines at the top level
+fn is_definition(node: &ASTNode) -> bool {
+    matches!(
+        node,
+        ASTNode::Function { .. } | ASTNode::StructDef { .. } | ASTNode::EnumDef { .. }
+    )
+}
+
+fn statements(out: &mut String, nodes: &[ASTNode], depth: usize) {
+    for (i, node) in nodes.iter().enumerate() {
+        if depth == 0 && i > 0 && (is_definition(node) || is_definition(&nodes[i - 1])) {
+            out.push('\n');
+        }
+        out.push_str(&INDENT.repeat(depth));
+        statement(out, node, depth);
+        out.push('\n');
+    }
+}
+
+// The parameter list of a function, with its parentheses
+fn parameters(params: &[Parameter]) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| match &param.ty {
+            Some(ty) => format!("{}: {}", param.name, ty),
+            None => param.name.clone(),
+        })
+        .collect();
+    format!("({})", params.join(", "))
+}
+
+// One statement at `depth`, from the current indentation to its end without
+// the newline
+fn statement(out: &mut String, node: &ASTNode, depth: usize) {
+    let text = match node {
+        ASTNode::VariableDeclaration {
+            name,
+            mutable,
+            ty,
+            value,
+            ..
+        } => {
+            let mut text = format!("{} {}", if *mutable { "m" } else { "c" }, name);
+            if let Some(ty) = ty {
+                text.push_str(&format!(": {}", ty));
+            }
+            if let Some(value) = value {
+                text.push_str(&format!(" = {}", expression(value)));
+            }
+            text
+        }
+        ASTNode::Assignment { name, value, .. } => format!("{} = {}", name, expression(value)),
+        ASTNode::IndexAssignment {
+            name, index, value, ..
+        } => format!("{}[{}] = {}", name, expression(index), expression(value)),
+        ASTNode::FieldAssignment {
+            name, field, value, ..
+        } => format!("{}.{} = {}", name, field, expression(value)),
+        // The parser splits interpolated strings into parts, so a brace in a
+        // literal part is doubled to stay literal
+        ASTNode::Show(values, _) => {
+            let values: Vec<String> = values
+                .iter()
+                .map(|value| match value {
+                    ASTNode::StringLiteral(s) => quote(&s.replace('{', "{{").replace('}', "}}")),
+                    value => expression(value),
+                })
+                .collect();
+            format!("show {}", values.join(", "))
+        }
+        ASTNode::If {
+            cond,
+            then_block,
+            else_block,
+            ..
+        } => {
+            out.push_str(&format!("if {} ", expression(cond)));
+            block(out, then_block, depth);
+            match else_block.as_deref() {
+                Some([nested @ ASTNode::If { .. }]) => {
+                    out.push_str(" else ");
+                    statement(out, nested, depth);
+                }
+                Some(else_block) => {
+                    out.push_str(" else ");
+                    block(out, else_block, depth);
+                }
+                None => {}
+            }
+            return;
+        }
+        ASTNode::For {
+            var,
+            start,
+            end,
+            body,
+            ..
+        } => {
+            out.push_str(&format!(
+                "for {} = {} to {} ",
+                var,
+                expression(start),
+                expression(end)
+            ));
+            block(out, body, depth);
+            return;
+        }
+        ASTNode::Block(nodes) => {
+            block(out, nodes, depth);
+            return;
+        }
+        ASTNode::Break(_) => "break".to_string(),
+        ASTNode::Continue(_) => "continue".to_string(),
+        ASTNode::Function {
+            name, params, body, ..
+        } => {
+            out.push_str(&format!("fn {}{} ", name, parameters(params)));
+            block(out, body, depth);
+            return;
+        }
+        ASTNode::ExternFunction {
+            name,
+            params,
+            result,
+            ..
+        } => {
+            let result = result
+                .as_ref()
+                .map_or(String::new(), |ty| format!(": {}", ty));
+            format!("extern fn {}{}{}", name, parameters(params), result)
+        }
+        ASTNode::StructDef { name, fields, .. } => {
+            if fields.is_empty() {
+                out.push_str(&format!("struct {} {{}}", name));
+                return;
+            }
+            out.push_str(&format!("struct {} {{\n", name));
+            for field in fields {
+                out.push_str(&INDENT.repeat(depth + 1));
+                out.push_str(&field.name);
+                if let Some(ty) = &field.ty {
+                    out.push_str(&format!(": {}", ty));
+                }
+                out.push_str(";\n");
+            }
+            out.push_str(&INDENT.repeat(depth));
+            out.push('}');
+            return;
+        }
+        ASTNode::EnumDef { name, variants, .. } => {
+            if variants.is_empty() {
+                out.push_str(&format!("enum {} {{}}", name));
+            } else {
+                out.push_str(&format!("enum {} {{ {} }}", name, variants.join(", ")));
+            }
+            return;
+        }
+        ASTNode::Match {
+            value,
+            arms,
+            else_block,
+            ..
+        } => {
+            out.push_str(&format!("match {} {{\n", expression(value)));
+            for arm in arms {
+                out.push_str(&INDENT.repeat(depth + 1));
+                out.push_str(&format!("{} ", arm.variant));
+                block(out, &arm.body, depth + 1);
+                out.push('\n');
+            }
+            if let Some(else_block) = else_block {
+                out.push_str(&INDENT.repeat(depth + 1));
+                out.push_str("else ");
+                block(out, else_block, depth + 1);
+                out.push('\n');
+            }
+            out.push_str(&INDENT.repeat(depth));
+            out.push('}');
+            return;
+        }
+        ASTNode::Give { value, .. } => format!("give {}", expression(value)),
+        ASTNode::Exit { value, .. } => format!("exit {}", expression(value)),
+        ASTNode::Assert { cond, message, .. } => match message {
+            Some(message) => format!("assert {}, {}", expression(cond), quote(message)),
+            None => format!("assert {}", expression(cond)),
+        },
+        ASTNode::Input { name, .. } => format!("input {}", name),
+        ASTNode::CRaw { code, .. } => format!("craw {}", quote(code)),
+        ASTNode::CInclude { header, .. } => format!("cinclude {}", quote(header)),
+        ASTNode::Link { library, .. } => format!("link {}", quote(library)),
+        ASTNode::Use { path, .. } => format!("use {}", quote(path)),
+        // A call, run for its side effects
+        expr => expression(expr),
+    };
+    out.push_str(&text);
+    out.push(';');
+}
+
+// The BP text of a program, laid out the way `bplang fmt` lays it out.
+// Parsing the text gives back the same tree, apart from positions and
+// comments, which the tree does not keep.
+pub fn to_source(ast: &[ASTNode]) -> String {
+    let mut out = String::new();
+    statements(&mut out, ast, 0);
+    out
+}
@@ -0,0 +1,318 @@
+//! `bplang test`: runs BP programs and checks what they print against the
+//! `//! expect:` comments they contain
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::backend::{reject_c_only, reject_inline_c, with_exe_suffix};
+use crate::bytecode;
+use crate::driver::{compile_error_messages, link_libraries, BuildDir, Engine, Options};
+use crate::error::{assertion_failure, CompileError, ASSERT_EXIT_CODE};
+use crate::interpreter::{interpret, InterpretError};
+use crate::lexer::FileId;
+use crate::module;
+use crate::optimize::optimize;
+use crate::parser::parse_source;
+use crate::semantic::{analyze, SemanticError, Warning};
+use crate::vm;
+
+// Directory searched when no test is named
+pub const TEST_DIR: &str = "tests";
+
+// What a test expects: the lines its program prints, one per
+// `//! expect: <line>` comment, or with `//! expect-error: <text>` a
+// compile error whose message contains the text
+#[derive(Debug, Default)]
+struct Expectations {
+    output: Vec<String>,
+    error: Option<String>,
+}
+
+fn expectations(source: &str) -> Expectations {
+    let mut expected = Expectations::default();
+    for line in source.lines() {
+        let Some(directive) = line.trim().strip_prefix("//!") else {
+            continue;
+        };
+        let directive = directive.trim_start();
+        if let Some(text) = directive.strip_prefix("expect-error:") {
+            expected.error = Some(text.trim().to_string());
+        } else if let Some(text) = directive.strip_prefix("expect:") {
+            // One space separates the directive from the line
+            let text = text.strip_prefix(' ').unwrap_or(text);
+            expected.output.push(text.to_string());
+        }
+    }
+    expected
+}
+
+// How a test program ended
+struct Finished {
+    stdout: String,
+    stderr: String,
+    code: i32,
+}
+
+// Turn warnings into errors when asked to
+fn check_warnings(warnings: Vec<Warning>, options: &Options) -> Result<(), CompileError> {
+    if options.deny_warnings && !warnings.is_empty() {
+        let errors = warnings
+            .into_iter()
+            .map(|warning| SemanticError {
+                code: warning.code,
+                message: warning.message,
+                span: warning.span,
+            })
+            .collect();
+        return Err(CompileError::Semantic(errors));
+    }
+    Ok(())
+}
+
+// Compile the program at `path` and run it with no input, capturing what
+// it prints. The build goes to a directory of its own, removed after.
+fn compile_and_run(path: &Path, source: &str, options: &Options) -> Result<Finished, CompileError> {
+    let (ast, _) = module::resolve(parse_source(source, FileId::MAIN)?, path)?;
+    check_warnings(analyze(&ast)?, options)?;
+    let args = vec![path.display().to_string()];
+
+    if options.engine != Engine::Compile {
+        match options.engine {
+            Engine::Vm => reject_c_only(&ast, "the vm engine")?,
+            _ => reject_inline_c(&ast, "the tree engine")?,
+        }
+        let mut out = Vec::new();
+        let result = match options.engine {
+            Engine::Vm => vm::execute(
+                &bytecode::compile(&ast),
+                args,
+                options.trap_overflow,
+                io::empty(),
+                &mut out,
+            ),
+            _ => interpret(&ast, args, options.trap_overflow, io::empty(), &mut out),
+        };
+        let (stderr, code) = match result {
+            Ok(code) => (String::new(), code),
+            Err(InterpretError::Assert { message, span }) => (
+                assertion_failure(path, span, message.as_deref()),
+                ASSERT_EXIT_CODE,
+            ),
+            Err(err) => {
+                let err = CompileError::from(err);
+                (
+                    compile_error_messages(path, &err, false).join("\n"),
+                    err.exit_code(),
+                )
+            }
+        };
+        return Ok(Finished {
+            stdout: String::from_utf8_lossy(&out).into_owned(),
+            stderr,
+            code,
+        });
+    }
+
+    let ast = if options.no_opt {
+        ast
+    } else {
+        let (ast, warnings) = optimize(ast, options.trap_overflow)?;
+        check_warnings(warnings, options)?;
+        ast
+    };
+    let backend = options.target.backend();
+    let mut program = backend.generate(&ast, path, options)?;
+    if backend.links_libraries() {
+        link_libraries(&mut program, &ast, options);
+    }
+    let stem = path.file_stem().unwrap_or_default();
+    let scratch = BuildDir::create()?;
+    let code_path = scratch.path.join(stem).with_extension(backend.extension());
+    let binary = with_exe_suffix(&scratch.path.join(stem));
+    fs::write(&code_path, &program.code)?;
+    backend.build(&program, &code_path, &binary, options)?;
+    let output = backend
+        .run_command(&fs::canonicalize(&binary)?)
+        .stdin(Stdio::null())
+        .output()?;
+    Ok(Finished {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        code: output.status.code().unwrap_or(1),
+    })
+}
+
+// Run the test at `path`, returning why it failed if it did
+fn run_test(path: &Path, options: &Options) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|err| format!("cannot read it: {}", err))?;
+    let expected = expectations(&source);
+    let result = compile_and_run(path, &source, options);
+
+    let finished = match (result, &expected.error) {
+        (Err(err), Some(text)) => {
+            let messages = compile_error_messages(path, &err, false).join("\n");
+            if messages.contains(text.as_str()) {
+                return Ok(());
+            }
+            return Err(format!(
+                "expected an error containing '{}', got:\n{}",
+                text, messages
+            ));
+        }
+        (Err(err), None) => {
+            let messages = compile_error_messages(path, &err, false).join("\n");
+            return Err(format!("compilation failed:\n{}", messages));
+        }
+        (Ok(_), Some(text)) => {
+            return Err(format!(
+                "expected an error containing '{}', but the program compiled",
+                text
+            ))
+        }
+        (Ok(finished), None) => finished,
+    };
+    if finished.code != 0 {
+        return Err(format!(
+            "the program exited with code {}\n{}",
+            finished.code,
+            finished.stderr.trim_end()
+        ));
+    }
+    let printed: Vec<&str> = finished.stdout.lines().collect();
+    for (i, line) in expected.output.iter().enumerate() {
+        match printed.get(i) {
+            Some(got) if got == line => {}
+            Some(got) => {
+                return Err(format!(
+                    "line {}: expected '{}', got '{}'",
+                    i + 1,
+                    line,
+                    got
+                ))
+            }
+            None => return Err(format!("line {}: expected '{}', got nothing", i + 1, line)),
+        }
+    }
+    if printed.len() > expected.output.len() {
+        return Err(format!(
+            "line {}: expected nothing, got '{}'",
+            expected.output.len() + 1,
+            printed[expected.output.len()]
+        ));
+    }
+    Ok(())
+}
+
+// The tests named by `paths`: `.bp` files, and those directly inside
+// directories. Without paths, the ones in `tests`.
+fn collect(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let default = [PathBuf::from(TEST_DIR)];
+    let paths = if paths.is_empty() { &default } else { paths };
+    let mut tests = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            tests.push(path.clone());
+            continue;
+        }
+        let mut found = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let file = entry?.path();
+            if file.extension().is_some_and(|ext| ext == "bp") && file.is_file() {
+                found.push(file);
+            }
+        }
+        found.sort();
+        tests.extend(found);
+    }
+    Ok(tests)
+}
+
+// Run the tests named by `paths`, several at a time, and print a summary.
+// Returns the exit code for `bplang`: 1 if any test failed.
+pub fn run_tests(paths: &[PathBuf], options: &Options) -> i32 {
+    let tests = match collect(paths) {
+        Ok(tests) => tests,
+        Err(err) => {
+            eprintln!("error: cannot list the tests: {}", err);
+            return 1;
+        }
+    };
+    println!(
+        "running {} test{}",
+        tests.len(),
+        if tests.len() == 1 { "" } else { "s" }
+    );
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; tests.len()]);
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(tests.len());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(test) = tests.get(i) else {
+                    break;
+                };
+                let result = run_test(test, options);
+                results.lock().expect("no worker panics")[i] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().expect("no worker panics");
+    let mut failures = Vec::new();
+    for (test, result) in tests.iter().zip(results) {
+        match result.expect("every test ran") {
+            Ok(()) => println!("test {} ... ok", test.display()),
+            Err(why) => {
+                println!("test {} ... FAILED", test.display());
+                failures.push((test, why));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (test, why) in &failures {
+            println!("\n---- {} ----\n{}", test.display(), why);
+        }
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        tests.len() - failures.len(),
+        failures.len()
+    );
+    if failures.is_empty() {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expect_comments_give_lines_in_order() {
+        let expected = expectations(
+            "show 1;\n//! expect: 1\n  //!expect:   two spaces\n//! expect:\n// expect: no\n",
+        );
+        assert_eq!(expected.output, ["1", "  two spaces", ""]);
+        assert_eq!(expected.error, None);
+    }
+
+    #[test]
+    fn expect_error_keeps_the_trimmed_text() {
+        let expected = expectations("show x;\n//! expect-error:  undeclared variable  \n");
+        assert!(expected.output.is_empty());
+        assert_eq!(expected.error.as_deref(), Some("undeclared variable"));
+    }
+}
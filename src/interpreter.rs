@@ -0,0 +1,1090 @@
+//! Evaluates a checked program directly, without going through C
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::num::{IntErrorKind, ParseIntError};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::ast::{ASTNode, BinaryOperator, Parameter, UnaryOperator};
+use crate::lexer::Span;
+use crate::semantic::{builtin, parameter_type, Definitions, Type};
+
+// Error raised while running a program, e.g. a division by zero
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+// Reasons interpreting stops early: the program's own errors, failing to
+// write its output, an `exit` statement unwinding with its code, or a
+// failed `assert`
+#[derive(Debug)]
+pub enum InterpretError {
+    Runtime(RuntimeError),
+    Io(io::Error),
+    Exit(i32),
+    Assert { message: Option<String>, span: Span },
+}
+
+impl From<io::Error> for InterpretError {
+    fn from(err: io::Error) -> Self {
+        InterpretError::Io(err)
+    }
+}
+
+// A BP value at run time. Ints and longs wrap on overflow like the C `int`
+// and `long long` the compiled program uses, unless overflow is trapped.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Int(i32),
+    Long(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Array(Vec<i32>),
+    // A struct's fields in definition order
+    Struct {
+        name: String,
+        fields: Vec<(String, Value)>,
+    },
+    Enum {
+        name: String,
+        variant: String,
+    },
+}
+
+impl Value {
+    fn as_f64(&self) -> f64 {
+        match self {
+            Value::Int(num) => *num as f64,
+            Value::Long(num) => *num as f64,
+            Value::Float(num) => *num,
+            _ => unreachable!("type checked as a number: {:?}", self),
+        }
+    }
+
+    // Value of an int or a long, widened to a long
+    fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Int(num) => Some(i64::from(*num)),
+            Value::Long(num) => Some(*num),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            _ => unreachable!("type checked as a bool: {:?}", self),
+        }
+    }
+
+    pub(crate) fn as_int(&self) -> i32 {
+        match self {
+            Value::Int(num) => *num,
+            _ => unreachable!("type checked as an int: {:?}", self),
+        }
+    }
+}
+
+// Values are shown the way the generated C prints them
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(num) => write!(f, "{}", num),
+            Value::Long(num) => write!(f, "{}", num),
+            Value::Float(num) => write!(f, "{}", format_g(*num)),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(i32::to_string).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+            Value::Struct { name, fields } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| format!("{} = {}", field, value))
+                    .collect();
+                write!(f, "{} {{ {} }}", name, fields.join(", "))
+            }
+            Value::Enum { variant, .. } => write!(f, "{}", variant),
+        }
+    }
+}
+
+// Format a double like C's `printf("%g")`: six significant digits, trailing
+// zeros removed, and an exponent when it is below -4 or at least 6
+fn format_g(num: f64) -> String {
+    const PRECISION: i32 = 6;
+    if num.is_nan() {
+        return if num.is_sign_negative() {
+            "-nan"
+        } else {
+            "nan"
+        }
+        .to_string();
+    }
+    if num.is_infinite() {
+        return if num < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+    if num == 0.0 {
+        return if num.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+    // The exponent after rounding to the precision decides the style
+    let scientific = format!("{:.*e}", (PRECISION - 1) as usize, num);
+    let (mantissa, exponent) = scientific.split_once('e').expect("exponent format");
+    let exponent: i32 = exponent.parse().expect("exponent is an integer");
+    if !(-4..PRECISION).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!(
+            "{}e{}{:02}",
+            strip_zeros(mantissa),
+            sign,
+            exponent.unsigned_abs()
+        )
+    } else {
+        let decimals = (PRECISION - 1 - exponent) as usize;
+        strip_zeros(&format!("{:.*}", decimals, num)).to_string()
+    }
+}
+
+// Drop trailing zeros after a decimal point, and the point if nothing is left
+fn strip_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+// How a statement finished, so loops and functions can react
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Give(i32),
+}
+
+// Parameters and body of a defined function
+struct Function {
+    params: Vec<(String, Type)>,
+    body: Vec<ASTNode>,
+}
+
+// Walks the AST, keeping variables in a stack of block scopes like the
+// analyzer does. A function call swaps in a fresh stack holding only its
+// parameters. Globals and functions persist across `run` calls, which is
+// what the REPL builds on.
+pub struct Interpreter<R: BufRead, W: Write> {
+    functions: HashMap<String, Rc<Function>>,
+    definitions: Definitions,
+    scopes: Vec<HashMap<String, Value>>,
+    input: R,
+    out: W,
+    // State of `random`, the same generator the C runtime uses
+    rng_state: u64,
+    // What `argc` and `arg` see, starting with the program name
+    args: Vec<String>,
+    // Stop with an error when int arithmetic overflows, instead of wrapping
+    trap_overflow: bool,
+}
+
+impl<R: BufRead, W: Write> Interpreter<R, W> {
+    // An interpreter reading `input` statements from `input` and writing
+    // what the program shows to `out`
+    pub fn new(input: R, out: W) -> Self {
+        Interpreter {
+            functions: HashMap::new(),
+            definitions: Definitions::default(),
+            scopes: vec![HashMap::new()],
+            input,
+            out,
+            // Unseeded programs get different numbers on every run
+            rng_state: seeded_state(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs() as i32),
+            ),
+            args: Vec::new(),
+            trap_overflow: false,
+        }
+    }
+
+    // Give the program a command line, the program name first
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    // Make int overflow an error, as `--trap-overflow` does
+    pub fn with_trap_overflow(mut self, trap_overflow: bool) -> Self {
+        self.trap_overflow = trap_overflow;
+        self
+    }
+
+    // The reader `input` statements consume, for callers sharing it
+    pub fn input(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    // Run statements that passed the semantic checks
+    pub fn run(&mut self, ast: &[ASTNode]) -> Result<(), InterpretError> {
+        for node in ast {
+            if let ASTNode::Function {
+                name, params, body, ..
+            } = node
+            {
+                let params = params
+                    .iter()
+                    .map(|param| {
+                        let ty = parameter_type(param).expect("parameter was checked by analyze");
+                        (param.name.clone(), ty)
+                    })
+                    .collect();
+                let function = Function {
+                    params,
+                    body: body.clone(),
+                };
+                self.functions.insert(name.clone(), Rc::new(function));
+            }
+        }
+        self.definitions.add_checked(ast);
+        let flow = self.exec_statements(ast);
+        self.out.flush()?;
+        flow.map(|_| ())
+    }
+
+    // Evaluate an expression that passed the semantic checks
+    pub fn evaluate(&mut self, expr: &ASTNode) -> Result<Value, InterpretError> {
+        let value = self.eval(expr);
+        self.out.flush()?;
+        value
+    }
+
+    fn lookup(&self, name: &str, span: Span) -> Result<&Value, InterpretError> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .ok_or_else(|| runtime_error(format!("use of undeclared variable '{}'", name), span))
+    }
+
+    fn lookup_mut(&mut self, name: &str, span: Span) -> Result<&mut Value, InterpretError> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+            .ok_or_else(|| {
+                runtime_error(
+                    format!("assignment to undeclared variable '{}'", name),
+                    span,
+                )
+            })
+    }
+
+    fn assign(&mut self, name: &str, value: Value, span: Span) -> Result<(), InterpretError> {
+        let slot = self.lookup_mut(name, span)?;
+        store(slot, value);
+        Ok(())
+    }
+
+    // The field `field` of struct variable `name`
+    fn field(&mut self, name: &str, field: &str, span: Span) -> Result<&mut Value, InterpretError> {
+        let Value::Struct { fields, .. } = self.lookup_mut(name, span)? else {
+            unreachable!("type checked as a struct: {}", name);
+        };
+        Ok(fields
+            .iter_mut()
+            .find(|(other, _)| other == field)
+            .map(|(_, value)| value)
+            .expect("field was type checked"))
+    }
+
+    fn declare(&mut self, name: &str, value: Value) {
+        self.scopes
+            .last_mut()
+            .expect("interpreter always has a scope")
+            .insert(name.to_string(), value);
+    }
+
+    // The element of array `name` at `index`. Unlike the compiled program,
+    // the interpreter checks the bounds.
+    fn element(&mut self, name: &str, index: i32, span: Span) -> Result<&mut i32, InterpretError> {
+        let Value::Array(elements) = self.lookup_mut(name, span)? else {
+            unreachable!("type checked as an array: {}", name);
+        };
+        let len = elements.len();
+        usize::try_from(index)
+            .ok()
+            .and_then(|position| elements.get_mut(position))
+            .ok_or_else(|| {
+                runtime_error(
+                    format!(
+                        "index {} is out of range for '{}' of length {}",
+                        index, name, len
+                    ),
+                    span,
+                )
+            })
+    }
+
+    // Read a line for `input name;`, parsed to the variable's current type
+    fn read_input(&mut self, name: &str, span: Span) -> Result<(), InterpretError> {
+        // Prompts shown before the read must be visible
+        self.out.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Err(runtime_error("unexpected end of input".to_string(), span));
+        }
+        let value = input_value(&line, self.lookup(name, span)?, span)?;
+        self.assign(name, value, span)
+    }
+
+    fn eval(&mut self, node: &ASTNode) -> Result<Value, InterpretError> {
+        match node {
+            ASTNode::NumberLiteral(num) => Ok(Value::Int(*num)),
+            ASTNode::LongLiteral(num) => Ok(Value::Long(*num)),
+            ASTNode::FloatLiteral(num) => Ok(Value::Float(*num)),
+            ASTNode::BoolLiteral(b) => Ok(Value::Bool(*b)),
+            ASTNode::StringLiteral(s) => Ok(Value::Str(s.clone())),
+            ASTNode::Identifier(name, span) => self.lookup(name, *span).cloned(),
+            ASTNode::ArrayLiteral(elements, _) => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.eval(element)?.as_int());
+                }
+                Ok(Value::Array(values))
+            }
+            ASTNode::Index { name, index, span } => {
+                let index = self.eval(index)?.as_int();
+                self.element(name, index, *span)
+                    .map(|element| Value::Int(*element))
+            }
+            // Not a variable, so an enum variant
+            ASTNode::FieldAccess { name, field, .. }
+                if !self.scopes.iter().any(|scope| scope.contains_key(name)) =>
+            {
+                Ok(Value::Enum {
+                    name: name.clone(),
+                    variant: field.clone(),
+                })
+            }
+            ASTNode::FieldAccess { name, field, span } => {
+                self.field(name, field, *span).map(|value| value.clone())
+            }
+            // Fields are evaluated as written, then stored in definition
+            // order with ints converted for long and float fields
+            ASTNode::StructInit { name, fields, .. } => {
+                let mut given = Vec::with_capacity(fields.len());
+                for (field, value) in fields {
+                    given.push((field, self.eval(value)?));
+                }
+                let fields = self.definitions.structs[name]
+                    .iter()
+                    .map(|(field, ty)| {
+                        let (_, value) = given
+                            .iter()
+                            .find(|(given, _)| *given == field)
+                            .expect("every field was given");
+                        (field.clone(), typed(ty, value.clone()))
+                    })
+                    .collect();
+                Ok(Value::Struct {
+                    name: name.clone(),
+                    fields,
+                })
+            }
+            ASTNode::UnaryOp { op, operand, span } => {
+                let operand = self.eval(operand)?;
+                match op {
+                    UnaryOperator::Neg => negate(operand, self.trap_overflow, *span),
+                    UnaryOperator::Not => Ok(Value::Bool(!operand.as_bool())),
+                }
+            }
+            // `and`/`or` short-circuit like C's `&&`/`||`
+            ASTNode::BinaryOp {
+                op: BinaryOperator::And,
+                lhs,
+                rhs,
+                ..
+            } => Ok(Value::Bool(
+                self.eval(lhs)?.as_bool() && self.eval(rhs)?.as_bool(),
+            )),
+            ASTNode::BinaryOp {
+                op: BinaryOperator::Or,
+                lhs,
+                rhs,
+                ..
+            } => Ok(Value::Bool(
+                self.eval(lhs)?.as_bool() || self.eval(rhs)?.as_bool(),
+            )),
+            ASTNode::BinaryOp { op, lhs, rhs, span } => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                binary_op(*op, &lhs, &rhs, self.trap_overflow, *span)
+            }
+            ASTNode::Call { name, args, span } if name == "random" || name == "seed" => {
+                let arg = self.eval(&args[0])?.as_int();
+                if name == "seed" {
+                    self.rng_state = seeded_state(arg);
+                    // The value of a statement call is discarded
+                    return Ok(Value::Int(0));
+                }
+                if arg <= 0 {
+                    return Err(runtime_error(
+                        format!("random: the bound must be positive, not {}", arg),
+                        *span,
+                    ));
+                }
+                self.rng_state = self
+                    .rng_state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                Ok(Value::Int(((self.rng_state >> 33) % arg as u64) as i32))
+            }
+            ASTNode::Call { name, args, .. } if name == "argc" || name == "arg" => {
+                if name == "argc" {
+                    return Ok(Value::Int(self.args.len() as i32));
+                }
+                let i = self.eval(&args[0])?.as_int();
+                // Out of range gives "", as in the C runtime
+                let arg = usize::try_from(i).ok().and_then(|i| self.args.get(i));
+                Ok(Value::Str(arg.cloned().unwrap_or_default()))
+            }
+            ASTNode::Call { name, args, span } if builtin(name).is_some() => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                call_builtin(name, &values, *span)
+            }
+            ASTNode::Call { name, args, span } => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                if let Some(result) = self.definitions.externs.get(name) {
+                    let result = result.clone();
+                    return Ok(typed(&result, self.call_extern(name, &values, *span)?));
+                }
+                self.call(name, values, *span).map(Value::Int)
+            }
+            _ => unreachable!("not an expression: {:?}", node),
+        }
+    }
+
+    // Call a C library function declared with `extern fn`. Only those
+    // `interprets_extern` accepts have an equivalent here, and programs
+    // declaring others are rejected before they run.
+    fn call_extern(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        span: Span,
+    ) -> Result<Value, InterpretError> {
+        match (name, args) {
+            ("puts", [Value::Str(s)]) => {
+                writeln!(self.out, "{}", s)?;
+                Ok(Value::Int(0))
+            }
+            ("putchar", [Value::Int(ch)]) => {
+                self.out.write_all(&[*ch as u8])?;
+                Ok(Value::Int(*ch))
+            }
+            _ => Err(runtime_error(
+                format!(
+                    "extern function '{}' cannot be interpreted; build the program for the C target",
+                    name
+                ),
+                span,
+            )),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>, span: Span) -> Result<i32, InterpretError> {
+        let function =
+            self.functions.get(name).cloned().ok_or_else(|| {
+                runtime_error(format!("call to undefined function '{}'", name), span)
+            })?;
+        let frame = function
+            .params
+            .iter()
+            .zip(args)
+            .map(|((param, ty), arg)| (param.clone(), typed(ty, arg)))
+            .collect();
+        let outer = std::mem::replace(&mut self.scopes, vec![frame]);
+        let flow = self.exec_statements(&function.body);
+        self.scopes = outer;
+        // Falling off the end of a function gives 0
+        match flow? {
+            Flow::Give(value) => Ok(value),
+            _ => Ok(0),
+        }
+    }
+
+    fn exec_block(&mut self, nodes: &[ASTNode]) -> Result<Flow, InterpretError> {
+        self.scopes.push(HashMap::new());
+        let flow = self.exec_statements(nodes);
+        self.scopes.pop();
+        flow
+    }
+
+    fn exec_statements(&mut self, nodes: &[ASTNode]) -> Result<Flow, InterpretError> {
+        for node in nodes {
+            let flow = match node {
+                ASTNode::VariableDeclaration {
+                    name, ty, value, ..
+                } => {
+                    let ty = ty
+                        .as_ref()
+                        .map(|ty| Type::from_name(ty).expect("annotation was checked by analyze"));
+                    let value = match (value, ty) {
+                        (Some(value), None) => self.eval(value)?,
+                        (Some(value), Some(ty)) => typed(&ty, self.eval(value)?),
+                        // Assigned before it is read, as checked by analyze
+                        (None, Some(ty)) => zero(&ty),
+                        (None, None) => unreachable!("the parser requires a value or a type"),
+                    };
+                    self.declare(name, value);
+                    Flow::Normal
+                }
+                ASTNode::Assignment { name, value, span } => {
+                    let value = self.eval(value)?;
+                    self.assign(name, value, *span)?;
+                    Flow::Normal
+                }
+                ASTNode::FieldAssignment {
+                    name,
+                    field,
+                    value,
+                    span,
+                } => {
+                    let value = self.eval(value)?;
+                    store(self.field(name, field, *span)?, value);
+                    Flow::Normal
+                }
+                ASTNode::IndexAssignment {
+                    name,
+                    index,
+                    value,
+                    span,
+                } => {
+                    let index = self.eval(index)?.as_int();
+                    let value = self.eval(value)?.as_int();
+                    *self.element(name, index, *span)? = value;
+                    Flow::Normal
+                }
+                ASTNode::Show(values, _) => {
+                    let mut line = String::new();
+                    for value in values {
+                        line.push_str(&self.eval(value)?.to_string());
+                    }
+                    writeln!(self.out, "{}", line)?;
+                    Flow::Normal
+                }
+                ASTNode::If {
+                    cond,
+                    then_block,
+                    else_block,
+                    ..
+                } => {
+                    if self.eval(cond)?.as_bool() {
+                        self.exec_block(then_block)?
+                    } else if let Some(else_block) = else_block {
+                        self.exec_block(else_block)?
+                    } else {
+                        Flow::Normal
+                    }
+                }
+                ASTNode::For {
+                    var,
+                    start,
+                    end,
+                    body,
+                    span,
+                } => self.exec_for(var, start, end, body, *span)?,
+                ASTNode::Call { .. } => {
+                    self.eval(node)?;
+                    Flow::Normal
+                }
+                ASTNode::Give { value, .. } => Flow::Give(self.eval(value)?.as_int()),
+                ASTNode::Input { name, span } => {
+                    self.read_input(name, *span)?;
+                    Flow::Normal
+                }
+                ASTNode::Exit { value, .. } => {
+                    return Err(InterpretError::Exit(self.eval(value)?.as_int()));
+                }
+                ASTNode::Assert {
+                    cond,
+                    message,
+                    span,
+                } => {
+                    if self.eval(cond)? != Value::Bool(true) {
+                        return Err(InterpretError::Assert {
+                            message: message.clone(),
+                            span: *span,
+                        });
+                    }
+                    Flow::Normal
+                }
+                ASTNode::Block(nodes) => self.exec_block(nodes)?,
+                ASTNode::Match {
+                    value,
+                    arms,
+                    else_block,
+                    ..
+                } => {
+                    let Value::Enum { variant, .. } = self.eval(value)? else {
+                        unreachable!("match value was type checked as an enum");
+                    };
+                    match arms.iter().find(|arm| arm.variant == variant) {
+                        Some(arm) => self.exec_block(&arm.body)?,
+                        None => match else_block {
+                            Some(else_block) => self.exec_block(else_block)?,
+                            None => Flow::Normal,
+                        },
+                    }
+                }
+                ASTNode::Break(_) => Flow::Break,
+                ASTNode::Continue(_) => Flow::Continue,
+                // Collected up front by `run`
+                ASTNode::Function { .. }
+                | ASTNode::ExternFunction { .. }
+                | ASTNode::StructDef { .. }
+                | ASTNode::EnumDef { .. } => Flow::Normal,
+                // Nothing is linked when interpreting
+                ASTNode::Link { .. } => Flow::Normal,
+                _ => unreachable!("not a statement: {:?}", node),
+            };
+            if !matches!(flow, Flow::Normal) {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // Mirrors the generated C loop: the end is evaluated before every
+    // iteration, the body may change the counter, and the loop stops once
+    // the counter reaches the largest int
+    fn exec_for(
+        &mut self,
+        var: &str,
+        start: &ASTNode,
+        end: &ASTNode,
+        body: &[ASTNode],
+        span: Span,
+    ) -> Result<Flow, InterpretError> {
+        let start = self.eval(start)?;
+        self.scopes.push(HashMap::new());
+        self.declare(var, start);
+        let result = loop {
+            let counter = match self.lookup(var, span) {
+                Ok(counter) => counter.as_int(),
+                Err(err) => break Err(err),
+            };
+            match self.eval(end) {
+                Ok(end) if counter <= end.as_int() => {}
+                Ok(_) => break Ok(Flow::Normal),
+                Err(err) => break Err(err),
+            }
+            match self.exec_block(body) {
+                Ok(Flow::Break) => break Ok(Flow::Normal),
+                Ok(Flow::Give(value)) => break Ok(Flow::Give(value)),
+                Ok(Flow::Normal | Flow::Continue) => {}
+                Err(err) => break Err(err),
+            }
+            let counter = match self.lookup(var, span) {
+                Ok(counter) => counter.as_int(),
+                Err(err) => break Err(err),
+            };
+            // No int comes after the largest one, so the loop is over
+            if counter == i32::MAX {
+                break Ok(Flow::Normal);
+            }
+            self.declare(var, Value::Int(counter + 1));
+        };
+        self.scopes.pop();
+        result
+    }
+}
+
+// Store `value` in a variable or field. An int stored in a long or a float
+// is converted, as in C.
+pub(crate) fn store(slot: &mut Value, value: Value) {
+    *slot = match (&*slot, value) {
+        (Value::Long(_), Value::Int(num)) => Value::Long(i64::from(num)),
+        (Value::Float(_), Value::Int(num)) => Value::Float(num as f64),
+        (_, value) => value,
+    };
+}
+
+// A value of the scalar type `ty`, before anything is assigned
+pub(crate) fn zero(ty: &Type) -> Value {
+    match ty {
+        Type::Int => Value::Int(0),
+        Type::Long => Value::Long(0),
+        Type::Float => Value::Float(0.0),
+        Type::Bool => Value::Bool(false),
+        Type::Str => Value::Str(String::new()),
+        _ => unreachable!("only scalars are declared without a value: {:?}", ty),
+    }
+}
+
+// `value` converted to a variable of type `ty`, which type checking allowed
+pub(crate) fn typed(ty: &Type, value: Value) -> Value {
+    let mut slot = zero(ty);
+    store(&mut slot, value);
+    slot
+}
+
+fn file_error(function: &str, path: &str, err: &io::Error, span: Span) -> InterpretError {
+    runtime_error(
+        format!("{}: cannot access '{}': {}", function, path, err),
+        span,
+    )
+}
+
+// Generator state for a `seed`, mixed like the C runtime's `bprt_seed` so
+// nearby seeds start far apart
+pub(crate) fn seeded_state(seed: i32) -> u64 {
+    let mut z = (seed as u64).wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+// Parse text as an int the way the C runtime does: a decimal number,
+// optionally surrounded by blanks
+fn parse_int(text: &str) -> Result<i32, ParseIntError> {
+    text.trim_matches([' ', '\t']).parse()
+}
+
+fn is_out_of_range(err: &ParseIntError) -> bool {
+    matches!(
+        err.kind(),
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow
+    )
+}
+
+// The value `input` stores from a line read for a variable holding
+// `current`: an int for int variables, else the line itself
+pub(crate) fn input_value(
+    line: &str,
+    current: &Value,
+    span: Span,
+) -> Result<Value, InterpretError> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    match current {
+        Value::Int(_) => match parse_int(line) {
+            Ok(num) => Ok(Value::Int(num)),
+            Err(err) => {
+                let problem = if is_out_of_range(&err) {
+                    "input integer out of range"
+                } else {
+                    "input is not an integer"
+                };
+                Err(runtime_error(format!("{}: '{}'", problem, line), span))
+            }
+        },
+        _ => Ok(Value::Str(line.to_string())),
+    }
+}
+
+// Apply a builtin function to type checked arguments. Strings are handled
+// as bytes, like the C helpers do.
+pub(crate) fn call_builtin(
+    name: &str,
+    args: &[Value],
+    span: Span,
+) -> Result<Value, InterpretError> {
+    Ok(match (name, args) {
+        ("len", [Value::Array(elements)]) => Value::Int(elements.len() as i32),
+        ("len", [Value::Str(s)]) => Value::Int(s.len() as i32),
+        ("upper", [Value::Str(s)]) => Value::Str(s.to_ascii_uppercase()),
+        ("lower", [Value::Str(s)]) => Value::Str(s.to_ascii_lowercase()),
+        ("substr", [Value::Str(s), Value::Int(start), Value::Int(len)]) => {
+            let from = (*start).clamp(0, s.len() as i32) as usize;
+            let count = (*len).clamp(0, (s.len() - from) as i32) as usize;
+            Value::Str(String::from_utf8_lossy(&s.as_bytes()[from..from + count]).into_owned())
+        }
+        ("to_str", [Value::Int(num)]) => Value::Str(num.to_string()),
+        // Ints stay ints, as with the C `abs` and the min/max helpers
+        ("abs", [Value::Int(num)]) => Value::Int(num.wrapping_abs()),
+        ("min", [Value::Int(a), Value::Int(b)]) => Value::Int(*a.min(b)),
+        ("max", [Value::Int(a), Value::Int(b)]) => Value::Int(*a.max(b)),
+        ("abs", [num]) => Value::Float(num.as_f64().abs()),
+        ("min", [a, b]) => Value::Float(a.as_f64().min(b.as_f64())),
+        ("max", [a, b]) => Value::Float(a.as_f64().max(b.as_f64())),
+        ("pow", [a, b]) => Value::Float(a.as_f64().powf(b.as_f64())),
+        ("sqrt", [num]) => Value::Float(num.as_f64().sqrt()),
+        // BP has no error values, so failing to read or write stops the
+        // program, as in the C runtime
+        ("read_file", [Value::Str(path)]) => match fs::read(path) {
+            Ok(bytes) => Value::Str(String::from_utf8_lossy(&bytes).into_owned()),
+            Err(err) => return Err(file_error(name, path, &err, span)),
+        },
+        ("write_file", [Value::Str(path), Value::Str(content)]) => match fs::write(path, content) {
+            Ok(()) => Value::Int(0),
+            Err(err) => return Err(file_error(name, path, &err, span)),
+        },
+        ("env", [Value::Str(name)]) => Value::Str(
+            env::var_os(name)
+                .map_or_else(String::new, |value| value.to_string_lossy().into_owned()),
+        ),
+        // Like the C runtime, a computed negative duration does not sleep
+        ("sleep_ms", [Value::Int(ms)]) => {
+            thread::sleep(Duration::from_millis((*ms).max(0) as u64));
+            Value::Int(0)
+        }
+        ("to_int", [Value::Str(s)]) => match parse_int(s) {
+            Ok(num) => Value::Int(num),
+            Err(err) => {
+                let problem = if is_out_of_range(&err) {
+                    "is out of range"
+                } else {
+                    "is not an integer"
+                };
+                return Err(runtime_error(format!("to_int: '{}' {}", s, problem), span));
+            }
+        },
+        _ => unreachable!("type checked builtin call: {}{:?}", name, args),
+    })
+}
+
+// Whether the interpreter runs the C library function `name` itself when
+// declared with `params` and `result`: `puts(s: str)` and `putchar(ch)`
+pub(crate) fn interprets_extern(name: &str, params: &[Parameter], result: Option<&str>) -> bool {
+    let param = match params {
+        [param] => param.ty.as_deref().unwrap_or("int"),
+        _ => return false,
+    };
+    let gives_int = matches!(result, None | Some("int"));
+    match name {
+        "puts" => param == "str" && gives_int,
+        "putchar" => param == "int" && gives_int,
+        _ => false,
+    }
+}
+
+pub(crate) fn runtime_error(message: String, span: Span) -> InterpretError {
+    InterpretError::Runtime(RuntimeError { message, span })
+}
+
+// Why arithmetic overflowed, `operation` showing it on the operands
+pub(crate) fn overflow_message(operation: &str, long: bool) -> String {
+    let ty = if long { "a long" } else { "an int" };
+    format!("integer overflow: {} does not fit in {}", operation, ty)
+}
+
+// Int or long arithmetic on operands widened to 64 bits, for a long result
+// if `long` and else an int one. Gives the wrapped result and whether it
+// overflowed; `%` overflows exactly when `/` does, as in C. The divisor is
+// not zero.
+pub(crate) fn integer_arithmetic(op: BinaryOperator, a: i64, b: i64, long: bool) -> (i64, bool) {
+    let (result, mut overflow) = match op {
+        BinaryOperator::Add => a.overflowing_add(b),
+        BinaryOperator::Sub => a.overflowing_sub(b),
+        BinaryOperator::Mul => a.overflowing_mul(b),
+        BinaryOperator::Div | BinaryOperator::Mod => a.overflowing_div(b),
+        _ => unreachable!("not arithmetic: {:?}", op),
+    };
+    if !long {
+        overflow |= i32::try_from(result).is_err();
+    }
+    let result = match op {
+        BinaryOperator::Mod => a.wrapping_rem(b),
+        _ => result,
+    };
+    // Cutting the result of two ints back to 32 bits wraps it as an int
+    // would
+    (if long { result } else { result as i32 as i64 }, overflow)
+}
+
+// `-value` for a type checked number
+pub(crate) fn negate(
+    value: Value,
+    trap_overflow: bool,
+    span: Span,
+) -> Result<Value, InterpretError> {
+    let (negated, overflow, long) = match value {
+        Value::Int(num) => {
+            let (negated, overflow) = num.overflowing_neg();
+            (Value::Int(negated), overflow, false)
+        }
+        Value::Long(num) => {
+            let (negated, overflow) = num.overflowing_neg();
+            (Value::Long(negated), overflow, true)
+        }
+        Value::Float(num) => return Ok(Value::Float(-num)),
+        value => unreachable!("type checked operand: {:?}", value),
+    };
+    if overflow && trap_overflow {
+        let message = overflow_message(&format!("-({})", value), long);
+        return Err(runtime_error(message, span));
+    }
+    Ok(negated)
+}
+
+// Apply a non-short-circuiting binary operator to type checked operands.
+// Int overflow wraps, or with `trap_overflow` is an error.
+pub(crate) fn binary_op(
+    op: BinaryOperator,
+    lhs: &Value,
+    rhs: &Value,
+    trap_overflow: bool,
+    span: Span,
+) -> Result<Value, InterpretError> {
+    if let (BinaryOperator::Add, Value::Str(_), _) | (BinaryOperator::Add, _, Value::Str(_)) =
+        (op, lhs, rhs)
+    {
+        return Ok(Value::Str(format!("{}{}", lhs, rhs)));
+    }
+    // A long if either operand is one
+    if let (Some(a), Some(b)) = (lhs.as_integer(), rhs.as_integer()) {
+        if matches!(op, BinaryOperator::Div | BinaryOperator::Mod) && b == 0 {
+            return Err(runtime_error("division by zero".to_string(), span));
+        }
+        let long = matches!(lhs, Value::Long(_)) || matches!(rhs, Value::Long(_));
+        return Ok(match op {
+            BinaryOperator::Add
+            | BinaryOperator::Sub
+            | BinaryOperator::Mul
+            | BinaryOperator::Div
+            | BinaryOperator::Mod => {
+                let (result, overflow) = integer_arithmetic(op, a, b, long);
+                if overflow && trap_overflow {
+                    let message = overflow_message(&format!("{} {} {}", a, op, b), long);
+                    return Err(runtime_error(message, span));
+                }
+                if long {
+                    Value::Long(result)
+                } else {
+                    Value::Int(result as i32)
+                }
+            }
+            BinaryOperator::Eq => Value::Bool(a == b),
+            BinaryOperator::Ne => Value::Bool(a != b),
+            BinaryOperator::Lt => Value::Bool(a < b),
+            BinaryOperator::Gt => Value::Bool(a > b),
+            BinaryOperator::Le => Value::Bool(a <= b),
+            BinaryOperator::Ge => Value::Bool(a >= b),
+            BinaryOperator::And | BinaryOperator::Or => unreachable!("short-circuited"),
+        });
+    }
+    if let Value::Bool(_) | Value::Enum { .. } = lhs {
+        return Ok(match op {
+            BinaryOperator::Eq => Value::Bool(lhs == rhs),
+            BinaryOperator::Ne => Value::Bool(lhs != rhs),
+            _ => unreachable!("type checked operator: {:?}", op),
+        });
+    }
+    // Mixed int or long and float operands are computed in double, as in C
+    let (a, b) = (lhs.as_f64(), rhs.as_f64());
+    Ok(match op {
+        BinaryOperator::Add => Value::Float(a + b),
+        BinaryOperator::Sub => Value::Float(a - b),
+        BinaryOperator::Mul => Value::Float(a * b),
+        BinaryOperator::Div => Value::Float(a / b),
+        BinaryOperator::Eq => Value::Bool(a == b),
+        BinaryOperator::Ne => Value::Bool(a != b),
+        BinaryOperator::Lt => Value::Bool(a < b),
+        BinaryOperator::Gt => Value::Bool(a > b),
+        BinaryOperator::Le => Value::Bool(a <= b),
+        BinaryOperator::Ge => Value::Bool(a >= b),
+        _ => unreachable!("type checked float operator: {:?}", op),
+    })
+}
+
+// Run a program that passed `analyze`, reading its input from `input` and
+// writing what it shows to `out`, with int overflow an error if
+// `trap_overflow`. Returns the program's exit code.
+pub fn interpret(
+    ast: &[ASTNode],
+    args: Vec<String>,
+    trap_overflow: bool,
+    input: impl BufRead,
+    out: impl Write,
+) -> Result<i32, InterpretError> {
+    let mut interpreter = Interpreter::new(input, out)
+        .with_args(args)
+        .with_trap_overflow(trap_overflow);
+    match interpreter.run(ast) {
+        Ok(()) => Ok(0),
+        Err(InterpretError::Exit(code)) => Ok(code),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::FileId;
+    use crate::parser::parse_source;
+    use crate::semantic::analyze;
+
+    // What running `input` shows, and its exit code
+    fn run(input: &str, trap_overflow: bool) -> Result<(String, i32), InterpretError> {
+        let ast = parse_source(input, FileId::MAIN).unwrap();
+        analyze(&ast).unwrap();
+        let mut out = Vec::new();
+        let code = interpret(&ast, Vec::new(), trap_overflow, io::empty(), &mut out)?;
+        Ok((String::from_utf8(out).unwrap(), code))
+    }
+
+    fn output(input: &str) -> String {
+        run(input, false).unwrap().0
+    }
+
+    #[test]
+    fn for_includes_both_bounds() {
+        assert_eq!(output("for i = 1 to 3 { show i; }"), "1\n2\n3\n");
+        assert_eq!(output("for i = 5 to 5 { show i; }"), "5\n");
+        assert_eq!(output("for i = -1 to 1 { show i; }"), "-1\n0\n1\n");
+    }
+
+    #[test]
+    fn for_with_end_below_start_never_runs() {
+        assert_eq!(output("for i = 3 to 2 { show i; }"), "");
+    }
+
+    #[test]
+    fn for_end_is_evaluated_before_every_iteration() {
+        assert_eq!(
+            output("m n = 3;\nfor i = 1 to n { n = n - 1; show i; }"),
+            "1\n2\n"
+        );
+    }
+
+    #[test]
+    fn for_stops_at_the_largest_int() {
+        assert_eq!(
+            output("for i = 2147483646 to 2147483647 { show i; }"),
+            "2147483646\n2147483647\n"
+        );
+        assert_eq!(
+            output("for i = 2147483646 to 2147483647 { continue; }\nshow \"done\";"),
+            "done\n"
+        );
+        assert_eq!(
+            run("for i = 2147483647 to 2147483647 { show i; }", true)
+                .unwrap()
+                .0,
+            "2147483647\n"
+        );
+    }
+}
@@ -0,0 +1,855 @@
+//! Translates a checked program into JavaScript for Node.js
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{ASTNode, BinaryOperator, UnaryOperator};
+use crate::backend::Generated;
+use crate::error::{assertion_failure, ASSERT_EXIT_CODE};
+use crate::semantic::{builtin, expression_type, parameter_type, Definitions, Returns, Type};
+
+// JavaScript identifier for a BP variable, parameter, function or field.
+// The prefix keeps user names clear of JavaScript keywords and globals.
+fn js_name(name: &str) -> String {
+    format!("bp_{}", name)
+}
+
+// Runtime support, written ahead of the program only when it is used.
+// Helper names start with `bprt_`, which no mangled user name can. Errors
+// are reported like the C runtime does.
+const FAIL_RUNTIME: &str = r#"function bprt_fail(message) {
+    console.error("error: " + message);
+    process.exit(1);
+}
+"#;
+
+// Int division truncates like C's. Dividing by zero stops the program, as
+// in the interpreter, rather than giving a float infinity.
+const DIV_RUNTIME: &str = r#"function bprt_div(a, b) {
+    if (b === 0) {
+        bprt_fail("division by zero");
+    }
+    return (a / b) | 0;
+}
+
+function bprt_mod(a, b) {
+    if (b === 0) {
+        bprt_fail("division by zero");
+    }
+    return (a % b) | 0;
+}
+"#;
+
+// Arrays are bounds checked, like in the interpreter: JavaScript would read
+// `undefined` or grow the array instead
+const INDEX_RUNTIME: &str = r#"function bprt_index(array, index, name) {
+    if (index < 0 || index >= array.length) {
+        bprt_fail("index " + index + " is out of range for '" + name + "' of length " + array.length);
+    }
+    return index;
+}
+"#;
+
+// Floats are shown like C's `printf("%g")`: six significant digits, trailing
+// zeros removed, and an exponent when it is below -4 or at least 6
+const FLOAT_RUNTIME: &str = r#"function bprt_strip_zeros(s) {
+    return s.includes(".") ? s.replace(/0+$/, "").replace(/\.$/, "") : s;
+}
+
+function bprt_float_str(x) {
+    if (Number.isNaN(x)) {
+        // The sign of a NaN is only visible in its bits
+        const negative = new Uint8Array(new Float64Array([x]).buffer)[7] >= 0x80;
+        return negative ? "-nan" : "nan";
+    }
+    if (!Number.isFinite(x)) {
+        return x < 0 ? "-inf" : "inf";
+    }
+    if (x === 0) {
+        return Object.is(x, -0) ? "-0" : "0";
+    }
+    // The exponent after rounding to the precision decides the style
+    const [mantissa, exponent] = x.toExponential(5).split("e");
+    const power = Number(exponent);
+    if (power < -4 || power >= 6) {
+        const digits = String(Math.abs(power)).padStart(2, "0");
+        return bprt_strip_zeros(mantissa) + "e" + (power < 0 ? "-" : "+") + digits;
+    }
+    return bprt_strip_zeros(x.toFixed(5 - power));
+}
+"#;
+
+// `len` and `substr` count UTF-8 bytes, like the C helpers
+const LEN_RUNTIME: &str = r#"function bprt_len(s) {
+    return Buffer.byteLength(s);
+}
+"#;
+
+const SUBSTR_RUNTIME: &str = r#"function bprt_substr(s, start, len) {
+    const bytes = Buffer.from(s);
+    const from = Math.min(Math.max(start, 0), bytes.length);
+    const count = Math.min(Math.max(len, 0), bytes.length - from);
+    return bytes.subarray(from, from + count).toString();
+}
+"#;
+
+// Case conversion for `upper` and `lower`, of ASCII letters only as in the
+// C locale
+const CASE_RUNTIME: &str = r#"function bprt_upper(s) {
+    return s.replace(/[a-z]+/g, (letters) => letters.toUpperCase());
+}
+
+function bprt_lower(s) {
+    return s.replace(/[A-Z]+/g, (letters) => letters.toLowerCase());
+}
+"#;
+
+// Parsing text as an int for `input` and `to_int`: the whole text must be
+// a decimal number, optionally surrounded by blanks. Gives the int, or
+// the reason the text is not one.
+const PARSE_INT_RUNTIME: &str = r#"const BPRT_NOT_AN_INT = "not an int";
+const BPRT_OUT_OF_RANGE = "out of range";
+
+function bprt_parse_int(text) {
+    if (!/^[ \t]*[+-]?[0-9]+[ \t]*$/.test(text)) {
+        return BPRT_NOT_AN_INT;
+    }
+    const value = Number(text);
+    if (value < -2147483648 || value > 2147483647) {
+        return BPRT_OUT_OF_RANGE;
+    }
+    return value | 0;
+}
+"#;
+
+const TO_INT_RUNTIME: &str = r#"function bprt_to_int(s) {
+    const value = bprt_parse_int(s);
+    if (value === BPRT_NOT_AN_INT) {
+        bprt_fail("to_int: '" + s + "' is not an integer");
+    }
+    if (value === BPRT_OUT_OF_RANGE) {
+        bprt_fail("to_int: '" + s + "' is out of range");
+    }
+    return value;
+}
+"#;
+
+// Node's file functions, for `input` and the file builtins
+const FS_RUNTIME: &str = r#"const bprt_fs = require("fs");
+"#;
+
+// Runtime support for `input`. Stdin is read a byte at a time, so nothing
+// past the line is consumed and a program piped several lines sees them all.
+const INPUT_RUNTIME: &str = r#"function bprt_input_line() {
+    const bytes = [];
+    const byte = Buffer.alloc(1);
+    for (;;) {
+        let count;
+        try {
+            count = bprt_fs.readSync(0, byte, 0, 1, null);
+        } catch (err) {
+            // A terminal in non-blocking mode has nothing to read yet
+            if (err.code === "EAGAIN") {
+                continue;
+            }
+            throw err;
+        }
+        if (count === 0) {
+            break;
+        }
+        bytes.push(byte[0]);
+        if (byte[0] === 10) {
+            break;
+        }
+    }
+    if (bytes.length === 0) {
+        bprt_fail("unexpected end of input");
+    }
+    while (bytes.length > 0 && (bytes[bytes.length - 1] === 10 || bytes[bytes.length - 1] === 13)) {
+        bytes.pop();
+    }
+    return Buffer.from(bytes).toString();
+}
+
+function bprt_input_int() {
+    const line = bprt_input_line();
+    const value = bprt_parse_int(line);
+    if (value === BPRT_NOT_AN_INT) {
+        bprt_fail("input is not an integer: '" + line + "'");
+    }
+    if (value === BPRT_OUT_OF_RANGE) {
+        bprt_fail("input integer out of range: '" + line + "'");
+    }
+    return value;
+}
+"#;
+
+// `random` and `seed`: the 64-bit linear congruential generator of the C
+// runtime, on BigInts, so a seeded program gives the same numbers
+const RANDOM_RUNTIME: &str = r#"let bprt_rng_state = 0n;
+
+// Mix the seed, so nearby seeds start far apart
+function bprt_seed(seed) {
+    let z = BigInt.asUintN(64, BigInt(seed) + 0x9E3779B97F4A7C15n);
+    z = BigInt.asUintN(64, (z ^ (z >> 30n)) * 0xBF58476D1CE4E5B9n);
+    z = BigInt.asUintN(64, (z ^ (z >> 27n)) * 0x94D049BB133111EBn);
+    bprt_rng_state = z ^ (z >> 31n);
+}
+
+function bprt_random(n) {
+    if (n <= 0) {
+        bprt_fail("random: the bound must be positive, not " + n);
+    }
+    bprt_rng_state = BigInt.asUintN(64, bprt_rng_state * 6364136223846793005n + 1442695040888963407n);
+    return Number((bprt_rng_state >> 33n) % BigInt(n));
+}
+"#;
+
+// `env`: the value of an environment variable, or "" when it is unset
+const ENV_RUNTIME: &str = r#"function bprt_env(name) {
+    return process.env[name] ?? "";
+}
+"#;
+
+// `read_file` and `write_file`, which stop the program when they fail.
+// Node words the reason like `ENOENT: no such file or directory, open 'x'`;
+// only the middle is kept, capitalized as strerror has it.
+const FILE_RUNTIME: &str = r#"function bprt_file_error(name, path, err) {
+    const reason = err.message.replace(/^[A-Z]+: /, "").replace(/, \w+ '.*'$/, "");
+    bprt_fail(name + ": cannot access '" + path + "': " + reason.charAt(0).toUpperCase() + reason.slice(1));
+}
+
+function bprt_read_file(path) {
+    try {
+        return bprt_fs.readFileSync(path, "utf8");
+    } catch (err) {
+        bprt_file_error("read_file", path, err);
+    }
+}
+
+function bprt_write_file(path, content) {
+    try {
+        bprt_fs.writeFileSync(path, content);
+    } catch (err) {
+        bprt_file_error("write_file", path, err);
+    }
+}
+"#;
+
+// `argc` and `arg`: the script path and the arguments after it, as C's
+// `argv`. Arguments out of range are "".
+const ARGS_RUNTIME: &str = r#"const bprt_args = process.argv.slice(1);
+
+function bprt_argc() {
+    return bprt_args.length;
+}
+
+function bprt_arg(i) {
+    return i >= 0 && i < bprt_args.length ? bprt_args[i] : "";
+}
+"#;
+
+// `sleep_ms` blocks like the C `nanosleep`, rather than scheduling a timer.
+// Computed negative durations do not sleep.
+const SLEEP_RUNTIME: &str = r#"function bprt_sleep_ms(ms) {
+    if (ms > 0) {
+        Atomics.wait(new Int32Array(new SharedArrayBuffer(4)), 0, 0, ms);
+    }
+}
+"#;
+
+// A variable in scope at the current point of code generation
+#[derive(Clone)]
+struct Variable {
+    ty: Type,
+    // Its JavaScript name, which differs from `js_name` when it shadows
+    // another variable
+    js_name: String,
+}
+
+// Variables known at the current point of code generation, by BP name
+type SymbolTable = HashMap<String, Variable>;
+
+// JavaScript name of the variable `name`, which analyze made sure is in scope
+fn variable<'a>(symbols: &'a SymbolTable, name: &str) -> &'a str {
+    &symbols
+        .get(name)
+        .expect("variable was checked by analyze")
+        .js_name
+}
+
+// Escape a string so it can be placed between double quotes in JavaScript
+fn escape_js(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            ch if ch.is_ascii_control() => escaped.push_str(&format!("\\x{:02x}", ch as u32)),
+            // Line terminators in older JavaScript
+            '\u{2028}' | '\u{2029}' => escaped.push_str(&format!("\\u{:04x}", ch as u32)),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+// Emits JavaScript for statements and expressions, remembering the runtime
+// helpers the emitted code relies on so only those are written ahead of it
+#[derive(Default)]
+struct Generator {
+    helpers: Vec<&'static str>,
+    // Whether the program calls `random`, and whether it seeds it itself
+    uses_random: bool,
+    seeded: bool,
+    // The BP source, named by failed assertions
+    source: PathBuf,
+    // The structs and enums the program defines
+    definitions: Definitions,
+    // Variables renamed so far because they shadow another
+    shadow_count: usize,
+}
+
+impl Generator {
+    // Type of an expression the analyzer has already accepted
+    fn checked_type(&self, node: &ASTNode, symbols: &SymbolTable) -> Type {
+        expression_type(
+            node,
+            &|name| symbols.get(name).map(|variable| variable.ty.clone()),
+            &self.definitions,
+        )
+        .expect("expression was type checked by analyze")
+    }
+
+    // Record a variable declared in the current block and give its
+    // JavaScript name. A `let` is unusable from the start of its block, not
+    // just from the declaration, so a variable shadowing an outer one gets a
+    // name of its own and reads of the outer one before it keep working.
+    // BP names cannot contain `$`.
+    fn declare(&mut self, name: &str, ty: Type, symbols: &mut SymbolTable) -> String {
+        let js_name = if symbols.contains_key(name) {
+            self.shadow_count += 1;
+            format!("{}${}", js_name(name), self.shadow_count)
+        } else {
+            js_name(name)
+        };
+        symbols.insert(
+            name.to_string(),
+            Variable {
+                ty,
+                js_name: js_name.clone(),
+            },
+        );
+        js_name
+    }
+
+    // Add runtime helpers, once each, after the ones they use
+    fn require(&mut self, helpers: &[&'static str]) {
+        for helper in helpers {
+            if !self.helpers.contains(helper) {
+                self.helpers.push(helper);
+            }
+        }
+    }
+
+    // Operand of a string `+` or `show`, converted to a string as `show`
+    // prints it
+    fn string_operand(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        let value = self.expression(node, symbols);
+        match self.checked_type(node, symbols) {
+            // Enum values are the names of their variants
+            Type::Str | Type::Enum(_) => value,
+            Type::Int | Type::Bool => format!("String({})", value),
+            Type::Float => {
+                self.require(&[FLOAT_RUNTIME]);
+                format!("bprt_float_str({})", value)
+            }
+            Type::Long => unreachable!("longs are rejected by reject_long"),
+            Type::Array(_) | Type::Struct(_) => unreachable!("aggregates cannot be concatenated"),
+        }
+    }
+
+    // Element `index` of the array `name`. Indices known when compiling were
+    // checked by analyze.
+    fn element(&mut self, name: &str, index: &ASTNode, symbols: &SymbolTable) -> String {
+        let array = variable(symbols, name);
+        if let ASTNode::NumberLiteral(index) = index {
+            return format!("{}[{}]", array, index);
+        }
+        self.require(&[FAIL_RUNTIME, INDEX_RUNTIME]);
+        format!(
+            "{}[bprt_index({}, {}, \"{}\")]",
+            array,
+            array,
+            self.expression(index, symbols),
+            name
+        )
+    }
+
+    // Translate an expression node into the equivalent JavaScript
+    // expression. Numbers are all doubles, so int arithmetic is truncated to
+    // 32 bits after every operation, wrapping like the interpreter's.
+    fn expression(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        match node {
+            // Negative literals come from folding; parentheses keep them from
+            // running into a preceding `-`
+            ASTNode::NumberLiteral(num) if *num < 0 => format!("({})", num),
+            ASTNode::NumberLiteral(num) => num.to_string(),
+            ASTNode::FloatLiteral(num) if num.is_sign_negative() => format!("({:?})", num),
+            ASTNode::FloatLiteral(num) => format!("{:?}", num),
+            ASTNode::BoolLiteral(b) => b.to_string(),
+            ASTNode::StringLiteral(s) => format!("\"{}\"", escape_js(s)),
+            ASTNode::Identifier(name, _) => variable(symbols, name).to_string(),
+            ASTNode::Index { name, index, .. } => self.element(name, index, symbols),
+            ASTNode::ArrayLiteral(elements, _) => {
+                let elements: Vec<String> = elements
+                    .iter()
+                    .map(|element| self.expression(element, symbols))
+                    .collect();
+                format!("[{}]", elements.join(", "))
+            }
+            ASTNode::FieldAccess { name, field, .. } if symbols.contains_key(name) => {
+                format!("{}.{}", variable(symbols, name), js_name(field))
+            }
+            // Not a variable, so an enum variant, which is its name
+            ASTNode::FieldAccess { field, .. } => format!("\"{}\"", field),
+            ASTNode::StructInit { fields, .. } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| {
+                        format!("{}: {}", js_name(field), self.expression(value, symbols))
+                    })
+                    .collect();
+                format!("{{ {} }}", fields.join(", "))
+            }
+            ASTNode::BinaryOp { op, lhs, rhs, .. } => {
+                let ty = self.checked_type(node, symbols);
+                if ty == Type::Str {
+                    return format!(
+                        "({} + {})",
+                        self.string_operand(lhs, symbols),
+                        self.string_operand(rhs, symbols)
+                    );
+                }
+                let lhs = self.expression(lhs, symbols);
+                let rhs = self.expression(rhs, symbols);
+                match (op, ty) {
+                    (BinaryOperator::Add | BinaryOperator::Sub, Type::Int) => {
+                        format!("(({} {} {}) | 0)", lhs, op.c_symbol(), rhs)
+                    }
+                    (BinaryOperator::Mul, Type::Int) => format!("Math.imul({}, {})", lhs, rhs),
+                    (BinaryOperator::Div | BinaryOperator::Mod, Type::Int) => {
+                        self.require(&[FAIL_RUNTIME, DIV_RUNTIME]);
+                        let helper = if *op == BinaryOperator::Div {
+                            "bprt_div"
+                        } else {
+                            "bprt_mod"
+                        };
+                        format!("{}({}, {})", helper, lhs, rhs)
+                    }
+                    (BinaryOperator::Eq, _) => format!("({} === {})", lhs, rhs),
+                    (BinaryOperator::Ne, _) => format!("({} !== {})", lhs, rhs),
+                    (op, _) => format!("({} {} {})", lhs, op.c_symbol(), rhs),
+                }
+            }
+            ASTNode::UnaryOp { op, operand, .. } => {
+                let value = self.expression(operand, symbols);
+                match (op, self.checked_type(node, symbols)) {
+                    (UnaryOperator::Neg, Type::Int) => format!("(-{} | 0)", value),
+                    (op, _) => format!("({}{})", op.c_symbol(), value),
+                }
+            }
+            ASTNode::Call { name, args, .. } if name == "len" => {
+                match self.checked_type(&args[0], symbols) {
+                    // Array lengths are known at compile time
+                    Type::Array(len) => len.to_string(),
+                    _ => {
+                        self.require(&[LEN_RUNTIME]);
+                        format!("bprt_len({})", self.expression(&args[0], symbols))
+                    }
+                }
+            }
+            // The other builtins call runtime helpers or Node
+            ASTNode::Call { name, args, .. } if builtin(name).is_some() => {
+                let result = match builtin(name).map(|builtin| &builtin.result) {
+                    Some(Returns::Nothing) => None,
+                    _ => Some(self.checked_type(node, symbols)),
+                };
+                let arg_values: Vec<String> = args
+                    .iter()
+                    .map(|arg| self.expression(arg, symbols))
+                    .collect();
+                let function = match name.as_str() {
+                    "upper" | "lower" => {
+                        self.require(&[CASE_RUNTIME]);
+                        if name == "upper" {
+                            "bprt_upper"
+                        } else {
+                            "bprt_lower"
+                        }
+                    }
+                    "substr" => {
+                        self.require(&[SUBSTR_RUNTIME]);
+                        "bprt_substr"
+                    }
+                    "to_str" => "String",
+                    "to_int" => {
+                        self.require(&[FAIL_RUNTIME, PARSE_INT_RUNTIME, TO_INT_RUNTIME]);
+                        "bprt_to_int"
+                    }
+                    // The absolute value of the smallest int wraps to itself
+                    "abs" if result == Some(Type::Int) => {
+                        return format!("(Math.abs({}) | 0)", arg_values[0]);
+                    }
+                    "abs" => "Math.abs",
+                    "min" => "Math.min",
+                    "max" => "Math.max",
+                    "pow" => "Math.pow",
+                    "sqrt" => "Math.sqrt",
+                    "random" | "seed" => {
+                        self.require(&[FAIL_RUNTIME, RANDOM_RUNTIME]);
+                        if name == "seed" {
+                            self.seeded = true;
+                            "bprt_seed"
+                        } else {
+                            self.uses_random = true;
+                            "bprt_random"
+                        }
+                    }
+                    "env" => {
+                        self.require(&[ENV_RUNTIME]);
+                        "bprt_env"
+                    }
+                    "read_file" | "write_file" => {
+                        self.require(&[FAIL_RUNTIME, FS_RUNTIME, FILE_RUNTIME]);
+                        if name == "read_file" {
+                            "bprt_read_file"
+                        } else {
+                            "bprt_write_file"
+                        }
+                    }
+                    "argc" | "arg" => {
+                        self.require(&[ARGS_RUNTIME]);
+                        if name == "argc" {
+                            "bprt_argc"
+                        } else {
+                            "bprt_arg"
+                        }
+                    }
+                    "sleep_ms" => {
+                        self.require(&[SLEEP_RUNTIME]);
+                        "bprt_sleep_ms"
+                    }
+                    _ => unreachable!("builtin without JavaScript translation: {}", name),
+                };
+                format!("{}({})", function, arg_values.join(", "))
+            }
+            ASTNode::Call { name, args, .. } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| self.expression(arg, symbols))
+                    .collect();
+                format!("{}({})", js_name(name), args.join(", "))
+            }
+            _ => unreachable!("not an expression: {:?}", node),
+        }
+    }
+
+    // Wrap an expression in the parentheses required around conditions,
+    // unless it already carries them
+    fn condition(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        let expr = self.expression(node, symbols);
+        match node {
+            ASTNode::BinaryOp { .. } | ASTNode::UnaryOp { .. } => expr,
+            _ => format!("({})", expr),
+        }
+    }
+
+    // Append the JavaScript translation of `nodes` to `code`, indented
+    // `depth` levels. Variables declared here are recorded in `symbols`.
+    fn statements(
+        &mut self,
+        nodes: &[ASTNode],
+        depth: usize,
+        symbols: &mut SymbolTable,
+        code: &mut String,
+    ) {
+        let indent = "    ".repeat(depth);
+
+        for node in nodes {
+            match node {
+                ASTNode::VariableDeclaration {
+                    name,
+                    mutable,
+                    ty,
+                    value,
+                    ..
+                } => {
+                    let declared = ty
+                        .as_ref()
+                        .map(|ty| Type::from_name(ty).expect("annotation was checked by analyze"));
+                    // The analyzer made sure it is assigned before it is read
+                    let Some(value) = value else {
+                        let ty = declared.expect("the parser requires a value or a type");
+                        let js_var = self.declare(name, ty, symbols);
+                        code.push_str(&format!("{}let {};\n", indent, js_var));
+                        continue;
+                    };
+                    let value_type = match value.as_ref() {
+                        ASTNode::ArrayLiteral(elements, _) => Type::Array(elements.len()),
+                        ASTNode::StructInit { name, .. } => Type::Struct(name.clone()),
+                        value => match declared {
+                            Some(declared) => declared,
+                            None => self.checked_type(value, symbols),
+                        },
+                    };
+                    // The value may read the variable being shadowed
+                    let value_code = self.expression(value, symbols);
+                    let js_var = self.declare(name, value_type, symbols);
+                    let keyword = if *mutable { "let" } else { "const" };
+                    code.push_str(&format!(
+                        "{}{} {} = {};\n",
+                        indent, keyword, js_var, value_code
+                    ));
+                }
+                ASTNode::Assignment { name, value, .. } => {
+                    code.push_str(&format!(
+                        "{}{} = {};\n",
+                        indent,
+                        variable(symbols, name),
+                        self.expression(value, symbols)
+                    ));
+                }
+                ASTNode::FieldAssignment {
+                    name, field, value, ..
+                } => {
+                    code.push_str(&format!(
+                        "{}{}.{} = {};\n",
+                        indent,
+                        variable(symbols, name),
+                        js_name(field),
+                        self.expression(value, symbols)
+                    ));
+                }
+                ASTNode::IndexAssignment {
+                    name, index, value, ..
+                } => {
+                    let target = self.element(name, index, symbols);
+                    code.push_str(&format!(
+                        "{}{} = {};\n",
+                        indent,
+                        target,
+                        self.expression(value, symbols)
+                    ));
+                }
+                // JavaScript evaluates left to right, so unlike printf
+                // arguments the values can always go in one call
+                ASTNode::Show(values, _) => {
+                    let parts: Vec<String> = values
+                        .iter()
+                        .map(|value| self.string_operand(value, symbols))
+                        .collect();
+                    code.push_str(&format!("{}console.log({});\n", indent, parts.join(" + ")));
+                }
+                ASTNode::If {
+                    cond,
+                    then_block,
+                    else_block,
+                    ..
+                } => {
+                    code.push_str(&format!(
+                        "{}if {} {{\n",
+                        indent,
+                        self.condition(cond, symbols)
+                    ));
+                    // Blocks get their own copy of the symbols, matching
+                    // JavaScript scoping
+                    self.statements(then_block, depth + 1, &mut symbols.clone(), code);
+                    if let Some(else_block) = else_block {
+                        code.push_str(&format!("{}}} else {{\n", indent));
+                        self.statements(else_block, depth + 1, &mut symbols.clone(), code);
+                    }
+                    code.push_str(&format!("{}}}\n", indent));
+                }
+                // The end is evaluated before every iteration. The counter
+                // is not wrapped like an int: past the largest one it is
+                // above any end, so the loop is over.
+                ASTNode::For {
+                    var,
+                    start,
+                    end,
+                    body,
+                    ..
+                } => {
+                    let start = self.expression(start, symbols);
+                    let mut body_symbols = symbols.clone();
+                    let js_var = self.declare(var, Type::Int, &mut body_symbols);
+                    let end = self.expression(end, &body_symbols);
+                    code.push_str(&format!(
+                        "{}for (let {js_var} = {}; {js_var} <= {}; {js_var}++) {{\n",
+                        indent, start, end,
+                    ));
+                    self.statements(body, depth + 1, &mut body_symbols, code);
+                    code.push_str(&format!("{}}}\n", indent));
+                }
+                ASTNode::Block(nodes) => {
+                    code.push_str(&format!("{}{{\n", indent));
+                    self.statements(nodes, depth + 1, &mut symbols.clone(), code);
+                    code.push_str(&format!("{}}}\n", indent));
+                }
+                // An `if` chain on the variant name rather than a `switch`,
+                // so a `break` in an arm still leaves the enclosing loop
+                ASTNode::Match {
+                    value,
+                    arms,
+                    else_block,
+                    ..
+                } => {
+                    code.push_str(&format!("{}{{\n", indent));
+                    code.push_str(&format!(
+                        "{}    const bprt_match = {};\n",
+                        indent,
+                        self.expression(value, symbols)
+                    ));
+                    let mut keyword = format!("{}    if", indent);
+                    for arm in arms {
+                        code.push_str(&format!(
+                            "{} (bprt_match === \"{}\") {{\n",
+                            keyword, arm.variant
+                        ));
+                        self.statements(&arm.body, depth + 2, &mut symbols.clone(), code);
+                        keyword = format!("{}    }} else if", indent);
+                    }
+                    if let Some(else_block) = else_block {
+                        if arms.is_empty() {
+                            code.push_str(&format!("{}    {{\n", indent));
+                        } else {
+                            code.push_str(&format!("{}    }} else {{\n", indent));
+                        }
+                        self.statements(else_block, depth + 2, &mut symbols.clone(), code);
+                    }
+                    if !arms.is_empty() || else_block.is_some() {
+                        code.push_str(&format!("{}    }}\n", indent));
+                    }
+                    code.push_str(&format!("{}}}\n", indent));
+                }
+                ASTNode::Call { .. } => {
+                    code.push_str(&format!("{}{};\n", indent, self.expression(node, symbols)));
+                }
+                ASTNode::Give { value, .. } => {
+                    code.push_str(&format!(
+                        "{}return {};\n",
+                        indent,
+                        self.expression(value, symbols)
+                    ));
+                }
+                ASTNode::Input { name, .. } => {
+                    let helper = match symbols.get(name).map(|variable| &variable.ty) {
+                        Some(Type::Int) => "bprt_input_int",
+                        Some(Type::Str) => "bprt_input_line",
+                        ty => unreachable!("input into {:?} was type checked", ty),
+                    };
+                    self.require(&[FAIL_RUNTIME, FS_RUNTIME, PARSE_INT_RUNTIME, INPUT_RUNTIME]);
+                    code.push_str(&format!(
+                        "{}{} = {}();\n",
+                        indent,
+                        variable(symbols, name),
+                        helper
+                    ));
+                }
+                // The message is fixed when compiling, position included
+                ASTNode::Assert {
+                    cond,
+                    message,
+                    span,
+                } => {
+                    let failure = assertion_failure(&self.source, *span, message.as_deref());
+                    code.push_str(&format!(
+                        "{indent}if (!{}) {{\n\
+                         {indent}    console.error(\"{}\");\n\
+                         {indent}    process.exit({});\n\
+                         {indent}}}\n",
+                        self.condition(cond, symbols),
+                        escape_js(&failure),
+                        ASSERT_EXIT_CODE,
+                    ));
+                }
+                ASTNode::Exit { value, .. } => {
+                    code.push_str(&format!(
+                        "{}process.exit({});\n",
+                        indent,
+                        self.expression(value, symbols)
+                    ));
+                }
+                ASTNode::Break(_) => code.push_str(&format!("{}break;\n", indent)),
+                ASTNode::Continue(_) => code.push_str(&format!("{}continue;\n", indent)),
+                // Emitted ahead of the top-level statements by `transpile`;
+                // structs and enums need no declaration
+                ASTNode::Function { .. } | ASTNode::StructDef { .. } | ASTNode::EnumDef { .. } => {}
+                _ => {} // Handles other unhandled ASTNode variants
+            }
+        }
+    }
+}
+
+// Transpile a checked program into a Node.js script. `source` names the
+// program in the messages of failed assertions.
+pub fn transpile(ast: &[ASTNode], source: &Path) -> Generated {
+    let mut generator = Generator {
+        source: source.to_path_buf(),
+        ..Generator::default()
+    };
+    generator.definitions.add_checked(ast);
+    let mut code = String::new();
+
+    // Function declarations are hoisted, so they can call each other in any
+    // order
+    for node in ast {
+        if let ASTNode::Function {
+            name, params, body, ..
+        } = node
+        {
+            let mut symbols = SymbolTable::new();
+            let names: Vec<String> = params
+                .iter()
+                .map(|param| {
+                    let ty = parameter_type(param).expect("parameter was checked by analyze");
+                    generator.declare(&param.name, ty, &mut symbols)
+                })
+                .collect();
+            code.push_str(&format!(
+                "function {}({}) {{\n",
+                js_name(name),
+                names.join(", ")
+            ));
+            generator.statements(body, 1, &mut symbols, &mut code);
+            // Falling off the end of a function gives 0
+            code.push_str("    return 0;\n}\n\n");
+        }
+    }
+
+    // The top-level statements run as the script itself
+    let main_start = code.len();
+    generator.statements(ast, 0, &mut SymbolTable::new(), &mut code);
+    // Unseeded programs get different numbers on every run
+    if generator.uses_random && !generator.seeded {
+        code.insert_str(
+            main_start,
+            "bprt_seed(Math.floor(Date.now() / 1000) | 0);\n",
+        );
+    }
+
+    // Only now is it known which helpers the code needs
+    let mut prelude = String::from("\"use strict\";\n\n");
+    for helper in &generator.helpers {
+        prelude.push_str(helper);
+        prelude.push('\n');
+    }
+    Generated {
+        code: prelude + &code,
+        libraries: Vec::new(),
+    }
+}
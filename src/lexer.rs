@@ -0,0 +1,981 @@
+//! Turns BP source text into a stream of tokens with source spans
+
+use std::borrow::Cow;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::codes;
+
+// Reserved words that lex as `Token::Keyword` instead of identifiers
+pub const KEYWORDS: &[&str] = &[
+    "m", "c", "show", "if", "else", "for", "to", "break", "continue", "fn", "give", "true",
+    "false", "and", "or", "not", "exit", "input", "assert", "struct", "enum", "match", "craw",
+    "cinclude", "extern", "link", "use",
+];
+
+// Define the different types of tokens. Names and strings borrow their text
+// from the source; only a string whose escapes were replaced owns it.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Token<'src> {
+    Keyword(&'src str),
+    Identifier(&'src str),
+    // Typed by the parser: an int if it fits, else a long. `-2147483648`
+    // is an int though its digits alone are not.
+    Number(u64),
+    Float(f64),
+    StringLiteral(Cow<'src, str>),
+    Equals,
+    EqEq,
+    NotEq,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Semicolon,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Comma,
+    Dot,
+    Colon,
+    EndOfFile,
+}
+
+// One token per line for `--emit-tokens`: its kind, then its value or
+// spelling
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Keyword(k) => write!(f, "keyword {}", k),
+            Token::Identifier(name) => write!(f, "identifier {}", name),
+            Token::Number(num) => write!(f, "number {}", num),
+            Token::Float(num) => write!(f, "float {:?}", num),
+            Token::StringLiteral(s) => write!(f, "string {:?}", s),
+            Token::EndOfFile => write!(f, "end of file"),
+            punct => {
+                let spelling = match punct {
+                    Token::Equals => "=",
+                    Token::EqEq => "==",
+                    Token::NotEq => "!=",
+                    Token::Lt => "<",
+                    Token::Gt => ">",
+                    Token::Le => "<=",
+                    Token::Ge => ">=",
+                    Token::Semicolon => ";",
+                    Token::Plus => "+",
+                    Token::Minus => "-",
+                    Token::Star => "*",
+                    Token::Slash => "/",
+                    Token::Percent => "%",
+                    Token::LParen => "(",
+                    Token::RParen => ")",
+                    Token::LBrace => "{",
+                    Token::RBrace => "}",
+                    Token::LBracket => "[",
+                    Token::RBracket => "]",
+                    Token::Comma => ",",
+                    Token::Dot => ".",
+                    Token::Colon => ":",
+                    _ => unreachable!(),
+                };
+                write!(f, "punct {}", spelling)
+            }
+        }
+    }
+}
+
+// Which source file a span points into: the program being compiled, or one
+// of the files it pulls in with `use` (see `module`)
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct FileId(pub u32);
+
+impl FileId {
+    pub const MAIN: FileId = FileId(0);
+}
+
+// Location of a token in the source: 1-based line and column, its length in
+// characters, and the file it is in
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+    pub file: FileId,
+}
+
+// A token together with the place it was found in the source
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedToken<'src> {
+    pub token: Token<'src>,
+    pub span: Span,
+}
+
+impl fmt::Display for SpannedToken<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{} {}", self.span.line, self.span.col, self.token)
+    }
+}
+
+// The different ways tokenizing can fail
+#[derive(Debug, PartialEq)]
+pub enum LexErrorKind {
+    UnexpectedChar(char),
+    UnterminatedBlockComment,
+    UnknownEscape(char),
+    UnterminatedString,
+    NewlineInString,
+    MalformedNumber(&'static str),
+    // A digit the base of the literal does not have, and the base's name
+    InvalidDigit(char, &'static str),
+    IntegerOutOfRange,
+    LoneBang,
+}
+
+// Error produced when the lexer cannot tokenize the source. `offset` is the
+// byte offset where the problem starts.
+#[derive(Debug, PartialEq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub offset: usize,
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            LexErrorKind::UnexpectedChar(ch) => write!(f, "unexpected character {:?}", ch),
+            LexErrorKind::UnterminatedBlockComment => {
+                write!(f, "unterminated block comment starting here")
+            }
+            LexErrorKind::UnterminatedString => write!(
+                f,
+                "unterminated string literal starting at line {}",
+                self.span.line
+            ),
+            LexErrorKind::NewlineInString => write!(f, "newline in string literal; use \\n"),
+            LexErrorKind::MalformedNumber(reason) => write!(f, "malformed number: {}", reason),
+            LexErrorKind::InvalidDigit(digit, base) => {
+                write!(f, "invalid digit '{}' in {} literal", digit, base)
+            }
+            LexErrorKind::IntegerOutOfRange => write!(f, "{}", OUT_OF_RANGE),
+            LexErrorKind::LoneBang => {
+                write!(f, "expected '=' after '!' (use 'not' for negation)")
+            }
+            LexErrorKind::UnknownEscape(ch) => write!(f, "unknown escape sequence '\\{}'", ch),
+        }
+    }
+}
+
+impl LexError {
+    // The error's code, registered in `codes`
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            LexErrorKind::UnexpectedChar(_) => codes::UNEXPECTED_CHARACTER,
+            LexErrorKind::UnterminatedBlockComment => codes::UNTERMINATED_COMMENT,
+            LexErrorKind::UnknownEscape(_) => codes::UNKNOWN_ESCAPE,
+            LexErrorKind::UnterminatedString | LexErrorKind::NewlineInString => {
+                codes::UNTERMINATED_STRING
+            }
+            LexErrorKind::MalformedNumber(_) | LexErrorKind::InvalidDigit(..) => {
+                codes::MALFORMED_NUMBER
+            }
+            LexErrorKind::IntegerOutOfRange => codes::INTEGER_OUT_OF_RANGE,
+            LexErrorKind::LoneBang => codes::LONE_BANG,
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+// Character iterator that keeps track of the current byte offset, line and
+// column while the lexer walks the source
+struct Cursor<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    col: usize,
+    consumed: usize,
+    file: FileId,
+}
+
+// Position saved by `Cursor::mark` so a span can be built once a token ends
+#[derive(Clone, Copy)]
+struct Mark {
+    line: usize,
+    col: usize,
+    consumed: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str, file: FileId) -> Self {
+        Cursor {
+            chars: input.char_indices().peekable(),
+            line: 1,
+            col: 1,
+            consumed: 0,
+            file,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|&(_, ch)| ch)
+    }
+
+    // Character after the next one, without consuming anything
+    fn peek_second(&self) -> Option<char> {
+        self.chars.clone().nth(1).map(|(_, ch)| ch)
+    }
+
+    fn offset(&mut self) -> Option<usize> {
+        self.chars.peek().map(|&(offset, _)| offset)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let (_, ch) = self.chars.next()?;
+        self.consumed += 1;
+        if ch == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(ch)
+    }
+
+    fn mark(&self) -> Mark {
+        Mark {
+            line: self.line,
+            col: self.col,
+            consumed: self.consumed,
+        }
+    }
+
+    // Span starting at `start` and ending at the current position
+    fn span_from(&self, start: Mark) -> Span {
+        Span {
+            line: start.line,
+            col: start.col,
+            len: self.consumed - start.consumed,
+            file: self.file,
+        }
+    }
+}
+
+// Whether every `_` in the digits of a number literal is followed by a
+// digit of base `radix`
+fn separated(digits: &str, radix: u32) -> bool {
+    let mut chars = digits.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '_' && !chars.peek().is_some_and(|next| next.is_digit(radix)) {
+            return false;
+        }
+    }
+    true
+}
+
+// A number literal starting at the cursor: decimal digits with at most one
+// `.` followed by more digits, or an int in hexadecimal, binary or octal
+// after `0x`, `0b` or `0o`. `_` may separate digits. `3.` and `1.2.3` are
+// rejected, and so is an int too large for a `u64`.
+fn number<'a>(chars: &mut Cursor<'a>, input: &str, start: Mark) -> Result<Token<'a>, LexError> {
+    let offset = chars.offset().unwrap_or(input.len());
+    let error = |chars: &Cursor, kind| LexError {
+        kind,
+        offset,
+        span: chars.span_from(start),
+    };
+    let base = match (chars.peek(), chars.peek_second()) {
+        (Some('0'), Some('x')) => Some((16, "hexadecimal")),
+        (Some('0'), Some('b')) => Some((2, "binary")),
+        (Some('0'), Some('o')) => Some((8, "octal")),
+        _ => None,
+    };
+
+    let (digits, radix) = if let Some((radix, name)) = base {
+        chars.next();
+        chars.next();
+        let digits_start = chars.offset().unwrap_or(input.len());
+        // Letters are taken too, so `0xFG` is one bad literal rather than a
+        // number followed by a name
+        while chars
+            .peek()
+            .is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        {
+            chars.next();
+        }
+        let digits = &input[digits_start..chars.offset().unwrap_or(input.len())];
+        if let Some(digit) = digits.chars().find(|&ch| ch != '_' && !ch.is_digit(radix)) {
+            return Err(error(chars, LexErrorKind::InvalidDigit(digit, name)));
+        }
+        if !digits.chars().any(|ch| ch != '_') {
+            return Err(error(
+                chars,
+                LexErrorKind::MalformedNumber("expected digits after the base prefix"),
+            ));
+        }
+        (digits, radix)
+    } else {
+        let mut is_float = false;
+        while let Some(ch) = chars.peek() {
+            if ch == '.' {
+                let reason = if is_float {
+                    Some("more than one decimal point")
+                } else if !chars.peek_second().is_some_and(|c| c.is_ascii_digit()) {
+                    Some("expected digits after the decimal point")
+                } else {
+                    None
+                };
+                if let Some(reason) = reason {
+                    chars.next();
+                    return Err(error(chars, LexErrorKind::MalformedNumber(reason)));
+                }
+                is_float = true;
+            } else if !ch.is_ascii_digit() && ch != '_' {
+                break;
+            }
+            chars.next();
+        }
+        let digits = &input[offset..chars.offset().unwrap_or(input.len())];
+        if is_float {
+            if !separated(digits, 10) {
+                return Err(error(chars, LexErrorKind::MalformedNumber(SEPARATOR)));
+            }
+            let value = digits.replace('_', "");
+            return Ok(Token::Float(value.parse::<f64>().unwrap()));
+        }
+        (digits, 10)
+    };
+
+    if !separated(digits.trim_start_matches('_'), radix) {
+        return Err(error(chars, LexErrorKind::MalformedNumber(SEPARATOR)));
+    }
+    u64::from_str_radix(&digits.replace('_', ""), radix)
+        .map(Token::Number)
+        .map_err(|_| error(chars, LexErrorKind::IntegerOutOfRange))
+}
+
+// Why an int literal larger than any long is rejected, by the lexer or the
+// parser
+pub(crate) const OUT_OF_RANGE: &str =
+    "integer literal out of range for long (max 9223372036854775807)";
+
+// Why a number with a misplaced `_` is rejected
+const SEPARATOR: &str = "'_' must be followed by a digit";
+
+// The tokens of a source, read one at a time. It gives every token up to
+// and including `EndOfFile`, or up to the first error, then stops.
+pub struct Lexer<'a> {
+    input: &'a str,
+    chars: Cursor<'a>,
+    finished: bool,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer::for_file(input, FileId::MAIN)
+    }
+
+    // Tokens of the source of `file`, so every span points into it
+    pub fn for_file(input: &'a str, file: FileId) -> Self {
+        Lexer {
+            input,
+            chars: Cursor::new(input, file),
+            finished: false,
+        }
+    }
+
+    // The next token, skipping whitespace and comments; `EndOfFile` once
+    // the input is used up
+    fn next_token(&mut self) -> Result<SpannedToken<'a>, LexError> {
+        let input = self.input;
+        let file = self.chars.file;
+        let chars = &mut self.chars;
+        while let Some(ch) = chars.peek() {
+            let start = chars.mark();
+            let token = match ch {
+                '=' => {
+                    chars.next();
+                    if chars.peek() == Some('=') {
+                        chars.next();
+                        Token::EqEq
+                    } else {
+                        Token::Equals
+                    }
+                }
+                '!' => {
+                    let offset = chars.offset().unwrap_or(input.len());
+                    chars.next();
+                    if chars.peek() != Some('=') {
+                        return Err(LexError {
+                            kind: LexErrorKind::LoneBang,
+                            offset,
+                            span: chars.span_from(start),
+                        });
+                    }
+                    chars.next();
+                    Token::NotEq
+                }
+                '<' => {
+                    chars.next();
+                    if chars.peek() == Some('=') {
+                        chars.next();
+                        Token::Le
+                    } else {
+                        Token::Lt
+                    }
+                }
+                '>' => {
+                    chars.next();
+                    if chars.peek() == Some('=') {
+                        chars.next();
+                        Token::Ge
+                    } else {
+                        Token::Gt
+                    }
+                }
+                ';' => {
+                    chars.next();
+                    Token::Semicolon
+                }
+                '+' => {
+                    chars.next();
+                    Token::Plus
+                }
+                '-' => {
+                    chars.next();
+                    Token::Minus
+                }
+                '*' => {
+                    chars.next();
+                    Token::Star
+                }
+                '%' => {
+                    chars.next();
+                    Token::Percent
+                }
+                '(' => {
+                    chars.next();
+                    Token::LParen
+                }
+                ')' => {
+                    chars.next();
+                    Token::RParen
+                }
+                '{' => {
+                    chars.next();
+                    Token::LBrace
+                }
+                '[' => {
+                    chars.next();
+                    Token::LBracket
+                }
+                ']' => {
+                    chars.next();
+                    Token::RBracket
+                }
+                ',' => {
+                    chars.next();
+                    Token::Comma
+                }
+                ':' => {
+                    chars.next();
+                    Token::Colon
+                }
+                '}' => {
+                    chars.next();
+                    Token::RBrace
+                }
+                '"' => {
+                    // Strings must close on the line they start on; a raw newline
+                    // is reported where it is, as `\n` is what was meant or a
+                    // closing quote is missing
+                    let offset = chars.offset().unwrap_or(input.len());
+                    let unterminated = LexError {
+                        kind: LexErrorKind::UnterminatedString,
+                        offset,
+                        span: Span {
+                            line: start.line,
+                            col: start.col,
+                            len: 1,
+                            file,
+                        },
+                    };
+                    chars.next(); // skip starting quote
+                                  // The text is copied only once an escape has to be replaced
+                    let text_start = offset + 1;
+                    let mut owned: Option<String> = None;
+                    let newline = |chars: &mut Cursor| LexError {
+                        kind: LexErrorKind::NewlineInString,
+                        offset: chars.offset().unwrap_or(input.len()),
+                        span: chars.span_from(chars.mark()),
+                    };
+                    let text = loop {
+                        match chars.peek() {
+                            None => return Err(unterminated),
+                            Some('\n') => return Err(newline(chars)),
+                            Some('"') => {
+                                let text_end = chars.offset().unwrap_or(input.len());
+                                chars.next(); // skip ending quote
+                                break match owned {
+                                    Some(text) => Cow::Owned(text),
+                                    None => Cow::Borrowed(&input[text_start..text_end]),
+                                };
+                            }
+                            Some('\\') => {
+                                let escape_start = chars.mark();
+                                let offset = chars.offset().unwrap_or(input.len());
+                                chars.next(); // skip the backslash
+                                let escaped = match chars.peek() {
+                                    Some('n') => '\n',
+                                    Some('t') => '\t',
+                                    Some('r') => '\r',
+                                    Some('0') => '\0',
+                                    Some('"') => '"',
+                                    Some('\\') => '\\',
+                                    None => return Err(unterminated),
+                                    Some('\n') => return Err(newline(chars)),
+                                    Some(other) => {
+                                        chars.next();
+                                        return Err(LexError {
+                                            kind: LexErrorKind::UnknownEscape(other),
+                                            offset,
+                                            span: chars.span_from(escape_start),
+                                        });
+                                    }
+                                };
+                                chars.next();
+                                owned
+                                    .get_or_insert_with(|| input[text_start..offset].to_string())
+                                    .push(escaped);
+                            }
+                            Some(ch) => {
+                                if let Some(text) = &mut owned {
+                                    text.push(ch);
+                                }
+                                chars.next();
+                            }
+                        }
+                    };
+                    Token::StringLiteral(text)
+                }
+                '0'..='9' => number(chars, input, start)?,
+                '.' if chars.peek_second().is_some_and(|c| c.is_ascii_digit()) => {
+                    let offset = chars.offset().unwrap_or(input.len());
+                    chars.next();
+                    return Err(LexError {
+                        kind: LexErrorKind::MalformedNumber(
+                            "expected a digit before the decimal point (write 0.5, not .5)",
+                        ),
+                        offset,
+                        span: chars.span_from(start),
+                    });
+                }
+                '.' => {
+                    chars.next();
+                    Token::Dot
+                }
+                'a'..='z' | 'A'..='Z' | '_' => {
+                    let offset = chars.offset().unwrap_or(input.len());
+                    while chars
+                        .peek()
+                        .is_some_and(|ch| ch.is_alphanumeric() || ch == '_')
+                    {
+                        chars.next();
+                    }
+                    let ident = &input[offset..chars.offset().unwrap_or(input.len())];
+                    if KEYWORDS.contains(&ident) {
+                        Token::Keyword(ident)
+                    } else {
+                        Token::Identifier(ident)
+                    }
+                }
+                '/' if chars.peek_second() == Some('/') => {
+                    // Line comment: skip everything up to the end of the line
+                    while let Some(ch) = chars.peek() {
+                        if ch == '\n' {
+                            break;
+                        }
+                        chars.next();
+                    }
+                    continue;
+                }
+                '/' if chars.peek_second() == Some('*') => {
+                    // Block comment: skip up to the matching `*/`. Block comments
+                    // nest, so code that already contains comments can be
+                    // commented out as a whole.
+                    let offset = chars.offset().unwrap_or(input.len());
+                    chars.next();
+                    chars.next();
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match (chars.peek(), chars.peek_second()) {
+                            (Some('/'), Some('*')) => {
+                                chars.next();
+                                chars.next();
+                                depth += 1;
+                            }
+                            (Some('*'), Some('/')) => {
+                                chars.next();
+                                chars.next();
+                                depth -= 1;
+                            }
+                            (Some(_), _) => {
+                                chars.next();
+                            }
+                            (None, _) => {
+                                return Err(LexError {
+                                    kind: LexErrorKind::UnterminatedBlockComment,
+                                    offset,
+                                    span: Span {
+                                        line: start.line,
+                                        col: start.col,
+                                        len: 2,
+                                        file,
+                                    },
+                                })
+                            }
+                        }
+                    }
+                    continue;
+                }
+                '/' => {
+                    chars.next();
+                    Token::Slash
+                }
+                ' ' | '\n' | '\t' | '\r' => {
+                    chars.next(); // skip whitespace
+                    continue;
+                }
+                _ => {
+                    let offset = chars.offset().unwrap_or(input.len());
+                    chars.next();
+                    return Err(LexError {
+                        kind: LexErrorKind::UnexpectedChar(ch),
+                        offset,
+                        span: chars.span_from(start),
+                    });
+                }
+            };
+            return Ok(SpannedToken {
+                token,
+                span: chars.span_from(start),
+            });
+        }
+        Ok(SpannedToken {
+            token: Token::EndOfFile,
+            span: chars.span_from(chars.mark()),
+        })
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<SpannedToken<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_token();
+        self.finished = !matches!(&token, Ok(token) if token.token != Token::EndOfFile);
+        Some(token)
+    }
+}
+
+// Tokenize the BP source code into a list of tokens
+pub fn tokenize(input: &str) -> Result<Vec<SpannedToken<'_>>, LexError> {
+    Lexer::new(input).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: &str) -> LexError {
+        tokenize(input).expect_err("the input should not tokenize")
+    }
+
+    #[test]
+    fn stray_character_is_an_error() {
+        let err = error("m x = 1 @ 2;");
+        assert_eq!(err.kind, LexErrorKind::UnexpectedChar('@'));
+        assert_eq!(err.offset, 8);
+        assert_eq!(err.code(), codes::UNEXPECTED_CHARACTER);
+    }
+
+    #[test]
+    fn offset_counts_bytes() {
+        let err = error("show \"é\"; $");
+        assert_eq!(err.kind, LexErrorKind::UnexpectedChar('$'));
+        assert_eq!(err.offset, 11);
+        assert_eq!(err.span.col, 11);
+    }
+
+    #[test]
+    fn garbage_fails_at_the_first_bad_character() {
+        for (input, ch) in [
+            ("#", '#'),
+            ("`x`", '`'),
+            ("m x = 1;\n?", '?'),
+            ("\u{7f}", '\u{7f}'),
+        ] {
+            assert_eq!(
+                error(input).kind,
+                LexErrorKind::UnexpectedChar(ch),
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn unterminated_string_and_comment() {
+        assert_eq!(error("show \"abc").kind, LexErrorKind::UnterminatedString);
+        assert_eq!(
+            error("/* never closed").kind,
+            LexErrorKind::UnterminatedBlockComment
+        );
+        assert_eq!(
+            error(r#"show "\q";"#).kind,
+            LexErrorKind::UnknownEscape('q')
+        );
+    }
+
+    #[test]
+    fn newline_in_a_string_is_reported_where_it_is() {
+        let err = error("show \"abc\n\";\nshow 2;");
+        assert_eq!(err.kind, LexErrorKind::NewlineInString);
+        assert_eq!(err.to_string(), "newline in string literal; use \\n");
+        assert_eq!(err.code(), codes::UNTERMINATED_STRING);
+        assert_eq!((err.span.line, err.span.col, err.offset), (1, 10, 9));
+        // Also after a backslash, which escapes nothing at the end of a line
+        let err = error("m x = 1;\nshow \"abc\\\n\";");
+        assert_eq!(err.kind, LexErrorKind::NewlineInString);
+        assert_eq!((err.span.line, err.span.col), (2, 11));
+    }
+
+    #[test]
+    fn lone_bang_is_an_error() {
+        let err = error("if !x { }");
+        assert_eq!(err.kind, LexErrorKind::LoneBang);
+        assert_eq!(err.offset, 3);
+    }
+
+    // `line:col+len` of each token
+    fn positions(input: &str) -> Vec<(usize, usize, usize)> {
+        tokenize(input)
+            .unwrap()
+            .iter()
+            .map(|token| (token.span.line, token.span.col, token.span.len))
+            .collect()
+    }
+
+    #[test]
+    fn spans_count_lines_and_columns() {
+        assert_eq!(
+            positions("m x = 5;\n  show x;"),
+            [
+                (1, 1, 1),
+                (1, 3, 1),
+                (1, 5, 1),
+                (1, 7, 1),
+                (1, 8, 1),
+                (2, 3, 4),
+                (2, 8, 1),
+                (2, 9, 1),
+                (2, 10, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_cover_whole_tokens() {
+        // A number, a name, a string with an escape, a two-character operator
+        assert_eq!(
+            positions("12345 total \"a\\nb\" <="),
+            [(1, 1, 5), (1, 7, 5), (1, 13, 6), (1, 20, 2), (1, 22, 0)]
+        );
+    }
+
+    #[test]
+    fn comments_spanning_lines_move_the_line() {
+        let tokens = tokenize("/* one\ntwo */ m\n// three\nx").unwrap();
+        assert_eq!((tokens[0].span.line, tokens[0].span.col), (2, 8));
+        assert_eq!((tokens[1].span.line, tokens[1].span.col), (4, 1));
+    }
+
+    #[test]
+    fn line_comments_leave_the_tokens_unchanged() {
+        let plain = "m x = 5;\nshow x / 2;\n";
+        let commented = "// a total\nm x = 5; // five\n// halved:\nshow x / 2;\n// the end";
+        assert_eq!(kinds(commented), kinds(plain));
+        // A comment as the very last line, with no newline after it
+        assert_eq!(kinds("show 1;\n// done"), kinds("show 1;"));
+        assert_eq!(kinds("// only a comment"), [Token::EndOfFile]);
+    }
+
+    #[test]
+    fn block_comments_nest_and_hide_anything() {
+        assert_eq!(
+            kinds("m /* outer /* inner */ still out */ x = 1;"),
+            kinds("m x = 1;")
+        );
+        assert_eq!(
+            kinds("show 1; /* show \"two\"; m y = \"; */ show 3;"),
+            kinds("show 1; show 3;")
+        );
+        // Only the inner comment is closed
+        assert_eq!(
+            error("/* /* */ show 1;").kind,
+            LexErrorKind::UnterminatedBlockComment
+        );
+    }
+
+    #[test]
+    fn errors_on_later_lines_have_their_position() {
+        let err = error("m x = 1;\nm y = 2;\n   @");
+        assert_eq!((err.span.line, err.span.col), (3, 4));
+        let err = error("show 1;\nshow \"open");
+        assert_eq!((err.span.line, err.span.col), (2, 6));
+    }
+
+    fn kinds(input: &str) -> Vec<Token<'_>> {
+        tokenize(input)
+            .unwrap()
+            .into_iter()
+            .map(|token| token.token)
+            .collect()
+    }
+
+    #[test]
+    fn comparison_operators_take_the_longest_match() {
+        assert_eq!(
+            kinds("< <= > >= == != ="),
+            [
+                Token::Lt,
+                Token::Le,
+                Token::Gt,
+                Token::Ge,
+                Token::EqEq,
+                Token::NotEq,
+                Token::Equals,
+                Token::EndOfFile,
+            ]
+        );
+        // No space needed between the operator and its operands
+        assert_eq!(
+            kinds("a<=b<d"),
+            [
+                Token::Identifier("a"),
+                Token::Le,
+                Token::Identifier("b"),
+                Token::Lt,
+                Token::Identifier("d"),
+                Token::EndOfFile,
+            ]
+        );
+        // `< =` is two tokens, `===` is `==` then `=`
+        assert_eq!(kinds("< ="), [Token::Lt, Token::Equals, Token::EndOfFile]);
+        assert_eq!(kinds("==="), [Token::EqEq, Token::Equals, Token::EndOfFile]);
+    }
+
+    #[test]
+    fn every_base_and_separators_give_the_value() {
+        assert_eq!(
+            kinds("0xFF 0x_ff 0b1010 0o755 1_000_000 0b1111_0000 1_0.2_5"),
+            [
+                Token::Number(255),
+                Token::Number(255),
+                Token::Number(10),
+                Token::Number(493),
+                Token::Number(1_000_000),
+                Token::Number(240),
+                Token::Float(10.25),
+                Token::EndOfFile,
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_literals_are_reported_where_they_stand() {
+        let err = error("m x = 0b102;");
+        assert_eq!(err.kind, LexErrorKind::InvalidDigit('2', "binary"));
+        assert_eq!(err.to_string(), "invalid digit '2' in binary literal");
+        assert_eq!((err.span.col, err.span.len), (7, 5));
+        assert_eq!(err.code(), codes::MALFORMED_NUMBER);
+
+        let err = error("m x = 0x;");
+        assert_eq!(
+            err.to_string(),
+            "malformed number: expected digits after the base prefix"
+        );
+        assert_eq!((err.span.col, err.span.len), (7, 2));
+        assert_eq!(error("m x = 0x_;").kind, err.kind);
+
+        let err = error("m x = 1__0;");
+        assert_eq!(
+            err.to_string(),
+            "malformed number: '_' must be followed by a digit"
+        );
+        assert_eq!((err.span.col, err.span.len), (7, 4));
+        for input in ["1_;", "0xF_;", "1_.5;", "1._5;"] {
+            assert_eq!(error(input).code(), codes::MALFORMED_NUMBER, "{}", input);
+        }
+    }
+
+    #[test]
+    fn int_literals_up_to_the_largest_long_are_numbers() {
+        // The parser makes the first an int and the second a long
+        assert_eq!(
+            kinds("2147483647 2147483648 9223372036854775807"),
+            [
+                Token::Number(i32::MAX as u64),
+                Token::Number(i32::MAX as u64 + 1),
+                Token::Number(i64::MAX as u64),
+                Token::EndOfFile,
+            ]
+        );
+        let ast = crate::parser::parse(&tokenize("show 2147483647 + 2147483648;").unwrap());
+        assert_eq!(
+            crate::ast::ast_tree(&ast.unwrap()),
+            "Show @1:1\n  BinaryOp +\n    Number 2147483647\n    Long 2147483648\n"
+        );
+    }
+
+    #[test]
+    fn hundred_digit_literal_is_out_of_range() {
+        let digits = "9".repeat(100);
+        let err = error(&format!("m x = {};", digits));
+        assert_eq!(err.kind, LexErrorKind::IntegerOutOfRange);
+        assert_eq!(err.code(), codes::INTEGER_OUT_OF_RANGE);
+        assert_eq!(
+            err.to_string(),
+            "integer literal out of range for long (max 9223372036854775807)"
+        );
+        assert_eq!((err.span.line, err.span.col, err.span.len), (1, 7, 100));
+    }
+
+    #[test]
+    fn valid_source_ends_in_end_of_file() {
+        let tokens = tokenize("m x = 5;").unwrap();
+        let kinds: Vec<&Token> = tokens.iter().map(|token| &token.token).collect();
+        assert_eq!(
+            kinds,
+            [
+                &Token::Keyword("m"),
+                &Token::Identifier("x"),
+                &Token::Equals,
+                &Token::Number(5),
+                &Token::Semicolon,
+                &Token::EndOfFile,
+            ]
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! The BP language compiler: lexing, parsing, semantic checks and C code
+//! generation, plus a driver tying them to the C compiler
+
+pub mod ast;
+pub mod backend;
+pub mod bytecode;
+pub mod cache;
+pub mod codegen;
+pub mod codes;
+pub mod config;
+pub mod diagnostic;
+pub mod driver;
+pub mod error;
+pub mod format;
+pub mod harness;
+pub mod interpreter;
+pub mod js;
+pub mod lexer;
+pub mod llvm;
+pub mod module;
+pub mod optimize;
+pub mod parser;
+pub mod project;
+pub mod python;
+pub mod repl;
+pub mod rust;
+pub mod semantic;
+pub mod visit;
+pub mod vm;
+pub mod watch;
+pub mod writer;
@@ -0,0 +1,1295 @@
+//! Translates a checked program into textual LLVM IR
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::ast::{ASTNode, BinaryOperator, UnaryOperator};
+use crate::backend::Generated;
+use crate::error::{assertion_failure, CodegenError, ASSERT_EXIT_CODE};
+use crate::lexer::Span;
+use crate::semantic::{expression_type, parameter_type, Definitions, Type};
+use crate::writer::CodeWriter;
+
+// LLVM name of a BP function
+fn ll_function(name: &str) -> String {
+    format!("@bp_{}", name)
+}
+
+// LLVM name of a struct type
+fn ll_type_name(name: &str) -> String {
+    format!("%bpt_{}", name)
+}
+
+// Global table of the variant names of enum `name`, indexed by value, used
+// to show enum values
+fn ll_variant_names(name: &str) -> String {
+    format!("@bprt_names_{}", name)
+}
+
+// LLVM type holding a value of type `ty`. Strings are pointers to
+// NUL-terminated bytes and enums the index of their variant.
+fn ll_type(ty: &Type) -> String {
+    match ty {
+        Type::Int | Type::Enum(_) => "i32".to_string(),
+        Type::Float => "double".to_string(),
+        Type::Bool => "i1".to_string(),
+        Type::Str => "ptr".to_string(),
+        Type::Array(len) => format!("[{} x i32]", len),
+        Type::Struct(name) => ll_type_name(name),
+        Type::Long => unreachable!("longs are rejected by reject_long"),
+    }
+}
+
+// A double constant. The hexadecimal form is exact for every value, which
+// LLVM requires of decimal constants.
+fn ll_float(num: f64) -> String {
+    format!("0x{:016X}", num.to_bits())
+}
+
+// Bytes of a string as the body of an LLVM `c"..."` constant, NUL included
+fn escape_ll(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 3);
+    for byte in s.bytes() {
+        match byte {
+            b'"' | b'\\' => escaped.push_str(&format!("\\{:02X}", byte)),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:02X}", byte)),
+        }
+    }
+    escaped.push_str("\\00");
+    escaped
+}
+
+// Functions of the C library and LLVM intrinsics the generated code calls,
+// declared only when used
+const PRINTF: &str = "declare i32 @printf(ptr, ...)";
+const SNPRINTF: &str = "declare i32 @snprintf(ptr, i64, ptr, ...)";
+const MALLOC: &str = "declare ptr @malloc(i64)";
+const STRLEN: &str = "declare i64 @strlen(ptr)";
+const MEMCPY: &str = "declare ptr @memcpy(ptr, ptr, i64)";
+const EXIT: &str = "declare void @exit(i32)";
+const FFLUSH: &str = "declare i32 @fflush(ptr)";
+const WRITE: &str = "declare i64 @write(i32, ptr, i64)";
+const GETENV: &str = "declare ptr @getenv(ptr)";
+
+// Runtime support, written after the program only when it is used. Helper
+// names start with `bprt_`, which no mangled user name can. Strings made
+// while the program runs are allocated with `malloc` and never freed.
+const ALLOC_RUNTIME: &str = r#"@bprt_out_of_memory = private unnamed_addr constant [22 x i8] c"error: out of memory\0A\00"
+
+define private ptr @bprt_alloc(i64 %size) {
+entry:
+    %result = call ptr @malloc(i64 %size)
+    %failed = icmp eq ptr %result, null
+    br i1 %failed, label %fail, label %done
+fail:
+    call i32 @fflush(ptr null)
+    call i64 @write(i32 2, ptr @bprt_out_of_memory, i64 21)
+    call void @exit(i32 1)
+    unreachable
+done:
+    ret ptr %result
+}
+"#;
+
+const CONCAT_RUNTIME: &str = r#"define private ptr @bprt_concat(ptr %a, ptr %b) {
+entry:
+    %a_len = call i64 @strlen(ptr %a)
+    %b_len = call i64 @strlen(ptr %b)
+    %b_size = add i64 %b_len, 1
+    %size = add i64 %a_len, %b_size
+    %result = call ptr @bprt_alloc(i64 %size)
+    call ptr @memcpy(ptr %result, ptr %a, i64 %a_len)
+    %tail = getelementptr i8, ptr %result, i64 %a_len
+    call ptr @memcpy(ptr %tail, ptr %b, i64 %b_size)
+    ret ptr %result
+}
+"#;
+
+// Division that wraps like the rest of the arithmetic, as in C: `sdiv` and
+// `srem` of the smallest int by -1 are undefined, and the processor traps
+const WRAPPING_DIVISION_RUNTIME: &str = r#"define private i32 @bprt_wrapping_div(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %negate, label %divide
+negate:
+    %negated = sub i32 0, %a
+    ret i32 %negated
+divide:
+    %quotient = sdiv i32 %a, %b
+    ret i32 %quotient
+}
+
+define private i32 @bprt_wrapping_mod(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %zero, label %divide
+zero:
+    ret i32 0
+divide:
+    %remainder = srem i32 %a, %b
+    ret i32 %remainder
+}
+"#;
+
+const INT_STR_RUNTIME: &str = r#"@bprt_int_format = private unnamed_addr constant [3 x i8] c"%d\00"
+
+define private ptr @bprt_int_str(i32 %value) {
+entry:
+    %result = call ptr @bprt_alloc(i64 12)
+    call i32 (ptr, i64, ptr, ...) @snprintf(ptr %result, i64 12, ptr @bprt_int_format, i32 %value)
+    ret ptr %result
+}
+"#;
+
+const FLOAT_STR_RUNTIME: &str = r#"@bprt_float_format = private unnamed_addr constant [3 x i8] c"%g\00"
+
+define private ptr @bprt_float_str(double %value) {
+entry:
+    %length = call i32 (ptr, i64, ptr, ...) @snprintf(ptr null, i64 0, ptr @bprt_float_format, double %value)
+    %size32 = add i32 %length, 1
+    %size = sext i32 %size32 to i64
+    %result = call ptr @bprt_alloc(i64 %size)
+    call i32 (ptr, i64, ptr, ...) @snprintf(ptr %result, i64 %size, ptr @bprt_float_format, double %value)
+    ret ptr %result
+}
+"#;
+
+// `argc` and `arg`: the command line `main` was given. Arguments out of
+// range are "".
+const ARGS_RUNTIME: &str = r#"@bprt_arg_count = private global i32 0
+@bprt_args = private global ptr null
+@bprt_no_arg = private unnamed_addr constant [1 x i8] c"\00"
+
+define private ptr @bprt_arg(i32 %i) {
+entry:
+    %count = load i32, ptr @bprt_arg_count
+    %in_range = icmp ult i32 %i, %count
+    br i1 %in_range, label %found, label %missing
+found:
+    %args = load ptr, ptr @bprt_args
+    %index = zext i32 %i to i64
+    %slot = getelementptr ptr, ptr %args, i64 %index
+    %arg = load ptr, ptr %slot
+    ret ptr %arg
+missing:
+    ret ptr @bprt_no_arg
+}
+"#;
+
+// Builtins the LLVM target has no translation for yet
+const UNSUPPORTED_BUILTINS: &[&str] = &[
+    "upper",
+    "lower",
+    "substr",
+    "to_int",
+    "random",
+    "seed",
+    "read_file",
+    "write_file",
+    "sleep_ms",
+];
+
+// A variable: its type and the `alloca` holding it
+#[derive(Clone)]
+struct Local {
+    ty: Type,
+    ptr: String,
+}
+
+// Variables visible at the current point of code generation
+type SymbolTable = HashMap<String, Local>;
+
+fn unsupported(what: &str, span: Span) -> CodegenError {
+    CodegenError {
+        message: format!("{} is not supported by the LLVM target", what),
+        span,
+    }
+}
+
+// Emits IR for statements and expressions, remembering the declarations,
+// constants and runtime helpers the emitted code relies on
+#[derive(Default)]
+struct Generator {
+    declarations: BTreeSet<&'static str>,
+    helpers: Vec<&'static str>,
+    // Libraries to link, by the name given to `-l`
+    libraries: BTreeSet<&'static str>,
+    // Global constants, and the name of the one holding each string
+    globals: Vec<String>,
+    strings: HashMap<String, String>,
+    // The BP source, named by failed assertions
+    source: PathBuf,
+    // Whether the program reads its command line, which main must save
+    uses_args: bool,
+    // The structs and enums the program defines
+    definitions: Definitions,
+    // Parameter types of every function, for converting arguments
+    functions: HashMap<String, Vec<Type>>,
+    // Enums whose values are shown, which need their variant names
+    shown_enums: BTreeSet<String>,
+    // The function being emitted: its `alloca`s, which go first in the
+    // entry block so loops do not grow the stack, its other instructions,
+    // and the label of the block they are added to
+    allocas: CodeWriter,
+    body: CodeWriter,
+    block: String,
+    // Counter naming registers, labels and variables
+    next: usize,
+    // Labels a `continue` and a `break` jump to, innermost loop last
+    loops: Vec<(String, String)>,
+}
+
+impl Generator {
+    // Type of an expression the analyzer has already accepted
+    fn checked_type(&self, node: &ASTNode, symbols: &SymbolTable) -> Type {
+        let variables = |name: &str| symbols.get(name).map(|local| local.ty.clone());
+        expression_type(node, &variables, &self.definitions)
+            .expect("expression was type checked by analyze")
+    }
+
+    // Position and type of a field of the struct `name`
+    fn field(&self, name: &str, field: &str) -> (usize, Type) {
+        self.definitions.structs[name]
+            .iter()
+            .enumerate()
+            .find(|(_, (other, _))| other == field)
+            .map(|(index, (_, ty))| (index, ty.clone()))
+            .expect("field was checked by analyze")
+    }
+
+    // Add runtime helpers, once each, along with the declarations they use
+    fn require(&mut self, helpers: &[&'static str], declarations: &[&'static str]) {
+        for helper in helpers {
+            if !self.helpers.contains(helper) {
+                self.helpers.push(helper);
+            }
+        }
+        self.declarations.extend(declarations);
+    }
+
+    fn require_alloc(&mut self) {
+        self.require(&[ALLOC_RUNTIME], &[MALLOC, FFLUSH, WRITE, EXIT]);
+    }
+
+    // Global constant holding `s`, shared by every use of the same text
+    fn string(&mut self, s: &str) -> String {
+        if let Some(name) = self.strings.get(s) {
+            return name.clone();
+        }
+        let name = format!("@.str.{}", self.strings.len());
+        self.globals.push(format!(
+            "{} = private unnamed_addr constant [{} x i8] c\"{}\"",
+            name,
+            s.len() + 1,
+            escape_ll(s)
+        ));
+        self.strings.insert(s.to_string(), name.clone());
+        name
+    }
+
+    fn fresh(&mut self) -> usize {
+        self.next += 1;
+        self.next
+    }
+
+    fn register(&mut self) -> String {
+        format!("%t{}", self.fresh())
+    }
+
+    fn label(&mut self) -> String {
+        format!("L{}", self.fresh())
+    }
+
+    fn emit(&mut self, instruction: impl AsRef<str>) {
+        self.body.line(instruction);
+    }
+
+    // Emit an instruction giving a value, returning the register holding it
+    fn value(&mut self, instruction: impl AsRef<str>) -> String {
+        let register = self.register();
+        self.emit(format!("{} = {}", register, instruction.as_ref()));
+        register
+    }
+
+    // Start the block `label`. The previous block must have ended with a
+    // terminator.
+    fn start_block(&mut self, label: String) {
+        self.body.dedent();
+        self.body.line(format!("{}:", label));
+        self.body.indent();
+        self.block = label;
+    }
+
+    // End the current block with a jump and carry on in a new block, which
+    // nothing reaches, for the statements after a `break` or `give`
+    fn terminate(&mut self, terminator: impl AsRef<str>) {
+        self.emit(terminator);
+        let label = self.label();
+        self.start_block(label);
+    }
+
+    // A new variable of type `ty`
+    fn alloca(&mut self, name: &str, ty: &Type) -> String {
+        let ptr = format!("%bp_{}.{}", name, self.fresh());
+        self.allocas
+            .line(format!("{} = alloca {}", ptr, ll_type(ty)));
+        ptr
+    }
+
+    // An expression converted to `ty`, the type of the place it is stored
+    // in or passed to. Ints widen to floats, as in C.
+    fn converted(
+        &mut self,
+        node: &ASTNode,
+        ty: &Type,
+        symbols: &SymbolTable,
+    ) -> Result<String, CodegenError> {
+        if *ty != Type::Float || self.checked_type(node, symbols) != Type::Int {
+            return self.expression(node, symbols);
+        }
+        if let ASTNode::NumberLiteral(num) = node {
+            return Ok(ll_float(*num as f64));
+        }
+        let value = self.expression(node, symbols)?;
+        Ok(self.value(format!("sitofp i32 {} to double", value)))
+    }
+
+    // Name of the variant of enum `name` held in `value`
+    fn variant_name(&mut self, name: &str, value: &str) -> String {
+        self.shown_enums.insert(name.to_string());
+        let len = self.definitions.enums[name].len();
+        let slot = self.value(format!(
+            "getelementptr [{} x ptr], ptr {}, i32 0, i32 {}",
+            len,
+            ll_variant_names(name),
+            value
+        ));
+        self.value(format!("load ptr, ptr {}", slot))
+    }
+
+    // `true` or `false` as a string constant
+    fn bool_name(&mut self, value: &str) -> String {
+        let (yes, no) = (self.string("true"), self.string("false"));
+        self.value(format!("select i1 {}, ptr {}, ptr {}", value, yes, no))
+    }
+
+    // Operand of a string `+`, converted to a string if needed
+    fn string_operand(
+        &mut self,
+        node: &ASTNode,
+        symbols: &SymbolTable,
+    ) -> Result<String, CodegenError> {
+        let value = self.expression(node, symbols)?;
+        Ok(match self.checked_type(node, symbols) {
+            Type::Str => value,
+            Type::Int => {
+                self.require_alloc();
+                self.require(&[INT_STR_RUNTIME], &[SNPRINTF]);
+                self.value(format!("call ptr @bprt_int_str(i32 {})", value))
+            }
+            Type::Float => {
+                self.require_alloc();
+                self.require(&[FLOAT_STR_RUNTIME], &[SNPRINTF]);
+                self.value(format!("call ptr @bprt_float_str(double {})", value))
+            }
+            Type::Bool => self.bool_name(&value),
+            Type::Enum(name) => self.variant_name(&name, &value),
+            Type::Long => unreachable!("longs are rejected by reject_long"),
+            Type::Array(_) | Type::Struct(_) => unreachable!("aggregates cannot be concatenated"),
+        })
+    }
+
+    // Pointer to element `index` of the array variable `name`. Like in the
+    // C target, indices are not checked when the program runs.
+    fn element(
+        &mut self,
+        name: &str,
+        index: &ASTNode,
+        symbols: &SymbolTable,
+    ) -> Result<String, CodegenError> {
+        let index = self.expression(index, symbols)?;
+        let local = &symbols[name];
+        let instruction = format!(
+            "getelementptr {}, ptr {}, i32 0, i32 {}",
+            ll_type(&local.ty),
+            local.ptr,
+            index
+        );
+        Ok(self.value(instruction))
+    }
+
+    // Pointer to the field `field` of the struct variable `name`, and the
+    // field's type
+    fn field_ptr(&mut self, name: &str, field: &str, symbols: &SymbolTable) -> (String, Type) {
+        let local = &symbols[name];
+        let Type::Struct(struct_name) = &local.ty else {
+            unreachable!("type checked as a struct: {}", name);
+        };
+        let (index, ty) = self.field(struct_name, field);
+        let instruction = format!(
+            "getelementptr {}, ptr {}, i32 0, i32 {}",
+            ll_type_name(struct_name),
+            local.ptr,
+            index
+        );
+        (self.value(instruction), ty)
+    }
+
+    // `and` and `or`, which only evaluate their right operand when the left
+    // one does not decide the result
+    fn short_circuit(
+        &mut self,
+        op: BinaryOperator,
+        lhs: &ASTNode,
+        rhs: &ASTNode,
+        symbols: &SymbolTable,
+    ) -> Result<String, CodegenError> {
+        let lhs = self.expression(lhs, symbols)?;
+        let lhs_block = self.block.clone();
+        let (rhs_label, end_label) = (self.label(), self.label());
+        let (decided, branch) = match op {
+            BinaryOperator::And => (
+                "false",
+                format!("label %{}, label %{}", rhs_label, end_label),
+            ),
+            _ => (
+                "true",
+                format!("label %{}, label %{}", end_label, rhs_label),
+            ),
+        };
+        self.emit(format!("br i1 {}, {}", lhs, branch));
+        self.start_block(rhs_label);
+        let rhs = self.expression(rhs, symbols)?;
+        let rhs_block = self.block.clone();
+        self.emit(format!("br label %{}", end_label));
+        self.start_block(end_label);
+        Ok(self.value(format!(
+            "phi i1 [ {}, %{} ], [ {}, %{} ]",
+            decided, lhs_block, rhs, rhs_block
+        )))
+    }
+
+    // Translate an expression, returning the LLVM operand holding its
+    // value: a constant or the register of the last instruction emitted.
+    // Int arithmetic wraps, as the C the other target makes does in
+    // practice.
+    fn expression(
+        &mut self,
+        node: &ASTNode,
+        symbols: &SymbolTable,
+    ) -> Result<String, CodegenError> {
+        Ok(match node {
+            ASTNode::NumberLiteral(num) => num.to_string(),
+            ASTNode::FloatLiteral(num) => ll_float(*num),
+            ASTNode::BoolLiteral(b) => b.to_string(),
+            ASTNode::StringLiteral(s) => self.string(s),
+            ASTNode::Identifier(name, _) => {
+                let local = &symbols[name];
+                let instruction = format!("load {}, ptr {}", ll_type(&local.ty), local.ptr);
+                self.value(instruction)
+            }
+            ASTNode::Index { name, index, .. } => {
+                let element = self.element(name, index, symbols)?;
+                self.value(format!("load i32, ptr {}", element))
+            }
+            ASTNode::FieldAccess { name, field, .. } if symbols.contains_key(name) => {
+                let (ptr, ty) = self.field_ptr(name, field, symbols);
+                self.value(format!("load {}, ptr {}", ll_type(&ty), ptr))
+            }
+            // Not a variable, so an enum variant
+            ASTNode::FieldAccess { name, field, .. } => self.definitions.enums[name]
+                .iter()
+                .position(|variant| variant == field)
+                .expect("variant was checked by analyze")
+                .to_string(),
+            ASTNode::BinaryOp { op, lhs, rhs, .. }
+                if matches!(op, BinaryOperator::And | BinaryOperator::Or) =>
+            {
+                self.short_circuit(*op, lhs, rhs, symbols)?
+            }
+            ASTNode::BinaryOp { lhs, rhs, .. } if self.checked_type(node, symbols) == Type::Str => {
+                let lhs = self.string_operand(lhs, symbols)?;
+                let rhs = self.string_operand(rhs, symbols)?;
+                self.require_alloc();
+                self.require(&[CONCAT_RUNTIME], &[STRLEN, MEMCPY]);
+                self.value(format!("call ptr @bprt_concat(ptr {}, ptr {})", lhs, rhs))
+            }
+            ASTNode::BinaryOp { op, lhs, rhs, .. } => {
+                // Mixed int and float operands are computed in double
+                let lhs_type = self.checked_type(lhs, symbols);
+                let rhs_type = self.checked_type(rhs, symbols);
+                let operands = if lhs_type == Type::Float || rhs_type == Type::Float {
+                    Type::Float
+                } else {
+                    lhs_type
+                };
+                // A literal divisor other than -1 cannot overflow
+                let wraps = operands == Type::Int
+                    && matches!(op, BinaryOperator::Div | BinaryOperator::Mod)
+                    && !matches!(**rhs, ASTNode::NumberLiteral(n) if n != -1);
+                let lhs = self.converted(lhs, &operands, symbols)?;
+                let rhs = self.converted(rhs, &operands, symbols)?;
+                if wraps {
+                    self.require(&[WRAPPING_DIVISION_RUNTIME], &[]);
+                    let operation = if *op == BinaryOperator::Div {
+                        "div"
+                    } else {
+                        "mod"
+                    };
+                    return Ok(self.value(format!(
+                        "call i32 @bprt_wrapping_{}(i32 {}, i32 {})",
+                        operation, lhs, rhs
+                    )));
+                }
+                let instruction = match (&operands, op) {
+                    (Type::Float, op) => match op {
+                        BinaryOperator::Add => "fadd",
+                        BinaryOperator::Sub => "fsub",
+                        BinaryOperator::Mul => "fmul",
+                        BinaryOperator::Div => "fdiv",
+                        BinaryOperator::Eq => "fcmp oeq",
+                        // Unordered, so NaN differs from everything as in C
+                        BinaryOperator::Ne => "fcmp une",
+                        BinaryOperator::Lt => "fcmp olt",
+                        BinaryOperator::Gt => "fcmp ogt",
+                        BinaryOperator::Le => "fcmp ole",
+                        BinaryOperator::Ge => "fcmp oge",
+                        _ => unreachable!("type checked float operator: {:?}", op),
+                    },
+                    (_, op) => match op {
+                        BinaryOperator::Add => "add",
+                        BinaryOperator::Sub => "sub",
+                        BinaryOperator::Mul => "mul",
+                        BinaryOperator::Div => "sdiv",
+                        BinaryOperator::Mod => "srem",
+                        BinaryOperator::Eq => "icmp eq",
+                        BinaryOperator::Ne => "icmp ne",
+                        BinaryOperator::Lt => "icmp slt",
+                        BinaryOperator::Gt => "icmp sgt",
+                        BinaryOperator::Le => "icmp sle",
+                        BinaryOperator::Ge => "icmp sge",
+                        _ => unreachable!("short-circuited"),
+                    },
+                };
+                let ty = match operands {
+                    Type::Float => "double".to_string(),
+                    ref ty => ll_type(ty),
+                };
+                self.value(format!("{} {} {}, {}", instruction, ty, lhs, rhs))
+            }
+            ASTNode::UnaryOp { op, operand, .. } => {
+                let ty = self.checked_type(operand, symbols);
+                let value = self.expression(operand, symbols)?;
+                match (op, ty) {
+                    (UnaryOperator::Neg, Type::Float) => {
+                        self.value(format!("fneg double {}", value))
+                    }
+                    (UnaryOperator::Neg, _) => self.value(format!("sub i32 0, {}", value)),
+                    (UnaryOperator::Not, _) => self.value(format!("xor i1 {}, true", value)),
+                }
+            }
+            ASTNode::Call { name, span, .. } if UNSUPPORTED_BUILTINS.contains(&name.as_str()) => {
+                return Err(unsupported(&format!("'{}'", name), *span));
+            }
+            ASTNode::Call { name, args, .. } if name == "len" => {
+                match self.checked_type(&args[0], symbols) {
+                    // Array lengths are known at compile time
+                    Type::Array(len) => len.to_string(),
+                    _ => {
+                        let s = self.expression(&args[0], symbols)?;
+                        self.declarations.insert(STRLEN);
+                        let len = self.value(format!("call i64 @strlen(ptr {})", s));
+                        self.value(format!("trunc i64 {} to i32", len))
+                    }
+                }
+            }
+            // Ints stay ints, as in the C target
+            ASTNode::Call { name, args, .. }
+                if matches!(name.as_str(), "abs" | "min" | "max" | "pow" | "sqrt") =>
+            {
+                let ty = self.checked_type(node, symbols);
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.converted(arg, &ty, symbols)?);
+                }
+                let (intrinsic, declaration) = match (name.as_str(), &ty) {
+                    ("abs", Type::Int) => {
+                        // The smallest int is its own absolute value
+                        values.push("false".to_string());
+                        ("llvm.abs.i32", "declare i32 @llvm.abs.i32(i32, i1)")
+                    }
+                    ("min", Type::Int) => ("llvm.smin.i32", "declare i32 @llvm.smin.i32(i32, i32)"),
+                    ("max", Type::Int) => ("llvm.smax.i32", "declare i32 @llvm.smax.i32(i32, i32)"),
+                    ("abs", _) => ("llvm.fabs.f64", "declare double @llvm.fabs.f64(double)"),
+                    ("min", _) => (
+                        "llvm.minnum.f64",
+                        "declare double @llvm.minnum.f64(double, double)",
+                    ),
+                    ("max", _) => (
+                        "llvm.maxnum.f64",
+                        "declare double @llvm.maxnum.f64(double, double)",
+                    ),
+                    ("pow", _) => {
+                        self.libraries.insert("m");
+                        (
+                            "llvm.pow.f64",
+                            "declare double @llvm.pow.f64(double, double)",
+                        )
+                    }
+                    _ => {
+                        self.libraries.insert("m");
+                        ("llvm.sqrt.f64", "declare double @llvm.sqrt.f64(double)")
+                    }
+                };
+                self.declarations.insert(declaration);
+                let ll_ty = ll_type(&ty);
+                let args: Vec<String> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| match i {
+                        // The flag of `llvm.abs`
+                        1 if name == "abs" => format!("i1 {}", value),
+                        _ => format!("{} {}", ll_ty, value),
+                    })
+                    .collect();
+                self.value(format!(
+                    "call {} @{}({})",
+                    ll_ty,
+                    intrinsic,
+                    args.join(", ")
+                ))
+            }
+            ASTNode::Call { name, args, .. } if name == "to_str" => {
+                let value = self.expression(&args[0], symbols)?;
+                self.require_alloc();
+                self.require(&[INT_STR_RUNTIME], &[SNPRINTF]);
+                self.value(format!("call ptr @bprt_int_str(i32 {})", value))
+            }
+            // An unset variable gives ""
+            ASTNode::Call { name, args, .. } if name == "env" => {
+                let variable = self.expression(&args[0], symbols)?;
+                self.declarations.insert(GETENV);
+                let value = self.value(format!("call ptr @getenv(ptr {})", variable));
+                let unset = self.value(format!("icmp eq ptr {}, null", value));
+                let empty = self.string("");
+                self.value(format!("select i1 {}, ptr {}, ptr {}", unset, empty, value))
+            }
+            ASTNode::Call { name, args, .. } if name == "argc" || name == "arg" => {
+                self.require(&[ARGS_RUNTIME], &[]);
+                self.uses_args = true;
+                if name == "argc" {
+                    self.value("load i32, ptr @bprt_arg_count")
+                } else {
+                    let i = self.expression(&args[0], symbols)?;
+                    self.value(format!("call ptr @bprt_arg(i32 {})", i))
+                }
+            }
+            ASTNode::Call { name, args, .. } => {
+                let params = self.functions[name].clone();
+                let mut values = Vec::with_capacity(args.len());
+                for (arg, ty) in args.iter().zip(&params) {
+                    let value = self.converted(arg, ty, symbols)?;
+                    values.push(format!("{} {}", ll_type(ty), value));
+                }
+                self.value(format!(
+                    "call i32 {}({})",
+                    ll_function(name),
+                    values.join(", ")
+                ))
+            }
+            _ => unreachable!("not an expression: {:?}", node),
+        })
+    }
+
+    // Emit `nodes` as a block: its variables go out of scope after it
+    fn block(&mut self, nodes: &[ASTNode], symbols: &SymbolTable) -> Result<(), CodegenError> {
+        self.statements(nodes, &mut symbols.clone())
+    }
+
+    // Translate statements, recording the variables they declare in
+    // `symbols`
+    fn statements(
+        &mut self,
+        nodes: &[ASTNode],
+        symbols: &mut SymbolTable,
+    ) -> Result<(), CodegenError> {
+        for node in nodes {
+            self.statement(node, symbols)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, node: &ASTNode, symbols: &mut SymbolTable) -> Result<(), CodegenError> {
+        match node {
+            ASTNode::VariableDeclaration {
+                name, ty, value, ..
+            } => {
+                let declared = ty
+                    .as_ref()
+                    .map(|ty| Type::from_name(ty).expect("annotation was checked by analyze"));
+                let ty = match (value.as_deref(), declared) {
+                    (Some(ASTNode::ArrayLiteral(elements, _)), _) => Type::Array(elements.len()),
+                    (Some(ASTNode::StructInit { name, .. }), _) => Type::Struct(name.clone()),
+                    (_, Some(declared)) => declared,
+                    (Some(value), None) => self.checked_type(value, symbols),
+                    (None, None) => unreachable!("the parser requires a value or a type"),
+                };
+                let ptr = self.alloca(name, &ty);
+                // The value may read the variable being shadowed, so the new
+                // one is only visible after it
+                match value.as_deref() {
+                    Some(ASTNode::ArrayLiteral(elements, _)) => {
+                        for (i, element) in elements.iter().enumerate() {
+                            let value = self.expression(element, symbols)?;
+                            let slot = self.value(format!(
+                                "getelementptr {}, ptr {}, i32 0, i32 {}",
+                                ll_type(&ty),
+                                ptr,
+                                i
+                            ));
+                            self.emit(format!("store i32 {}, ptr {}", value, slot));
+                        }
+                    }
+                    Some(ASTNode::StructInit {
+                        name: struct_name,
+                        fields,
+                        ..
+                    }) => {
+                        for (field, value) in fields {
+                            let (index, field_type) = self.field(struct_name, field);
+                            let value = self.converted(value, &field_type, symbols)?;
+                            let slot = self.value(format!(
+                                "getelementptr {}, ptr {}, i32 0, i32 {}",
+                                ll_type_name(struct_name),
+                                ptr,
+                                index
+                            ));
+                            self.emit(format!(
+                                "store {} {}, ptr {}",
+                                ll_type(&field_type),
+                                value,
+                                slot
+                            ));
+                        }
+                    }
+                    Some(value) => {
+                        let value = self.converted(value, &ty, symbols)?;
+                        self.emit(format!("store {} {}, ptr {}", ll_type(&ty), value, ptr));
+                    }
+                    // Assigned before it is read, as checked by analyze, but
+                    // a string starts out empty rather than dangling
+                    None => {
+                        let zero = match ty {
+                            Type::Int => "0".to_string(),
+                            Type::Float => ll_float(0.0),
+                            Type::Bool => "false".to_string(),
+                            Type::Str => self.string(""),
+                            _ => unreachable!("only scalars are declared without a value"),
+                        };
+                        self.emit(format!("store {} {}, ptr {}", ll_type(&ty), zero, ptr));
+                    }
+                }
+                symbols.insert(name.clone(), Local { ty, ptr });
+            }
+            ASTNode::Assignment { name, value, .. } => {
+                let local = symbols[name].clone();
+                let value = self.converted(value, &local.ty, symbols)?;
+                self.emit(format!(
+                    "store {} {}, ptr {}",
+                    ll_type(&local.ty),
+                    value,
+                    local.ptr
+                ));
+            }
+            ASTNode::FieldAssignment {
+                name, field, value, ..
+            } => {
+                let (ptr, ty) = self.field_ptr(name, field, symbols);
+                let value = self.converted(value, &ty, symbols)?;
+                self.emit(format!("store {} {}, ptr {}", ll_type(&ty), value, ptr));
+            }
+            ASTNode::IndexAssignment {
+                name, index, value, ..
+            } => {
+                let element = self.element(name, index, symbols)?;
+                let value = self.expression(value, symbols)?;
+                self.emit(format!("store i32 {}, ptr {}", value, element));
+            }
+            // One `printf` per statement, with literal text in the format
+            ASTNode::Show(values, _) => {
+                let mut format = String::new();
+                let mut args = Vec::new();
+                for value in values {
+                    if let ASTNode::StringLiteral(s) = value {
+                        format.push_str(&s.replace('%', "%%"));
+                        continue;
+                    }
+                    let ty = self.checked_type(value, symbols);
+                    let operand = self.expression(value, symbols)?;
+                    let (conversion, arg) = match ty {
+                        Type::Int => ("%d", format!("i32 {}", operand)),
+                        Type::Float => ("%g", format!("double {}", operand)),
+                        Type::Str => ("%s", format!("ptr {}", operand)),
+                        Type::Bool => ("%s", format!("ptr {}", self.bool_name(&operand))),
+                        Type::Enum(name) => {
+                            ("%s", format!("ptr {}", self.variant_name(&name, &operand)))
+                        }
+                        Type::Long => unreachable!("longs are rejected by reject_long"),
+                        Type::Array(_) | Type::Struct(_) => {
+                            unreachable!("aggregates cannot be shown")
+                        }
+                    };
+                    format.push_str(conversion);
+                    args.push(format!(", {}", arg));
+                }
+                format.push('\n');
+                let format = self.string(&format);
+                self.declarations.insert(PRINTF);
+                self.emit(format!(
+                    "call i32 (ptr, ...) @printf(ptr {}{})",
+                    format,
+                    args.concat()
+                ));
+            }
+            ASTNode::If {
+                cond,
+                then_block,
+                else_block,
+                ..
+            } => {
+                let cond = self.expression(cond, symbols)?;
+                let (then_label, end_label) = (self.label(), self.label());
+                let else_label = match else_block {
+                    Some(_) => self.label(),
+                    None => end_label.clone(),
+                };
+                self.emit(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    cond, then_label, else_label
+                ));
+                self.start_block(then_label);
+                self.block(then_block, symbols)?;
+                self.emit(format!("br label %{}", end_label));
+                if let Some(else_block) = else_block {
+                    self.start_block(else_label);
+                    self.block(else_block, symbols)?;
+                    self.emit(format!("br label %{}", end_label));
+                }
+                self.start_block(end_label);
+            }
+            // Like the generated C loop: the end is evaluated before every
+            // iteration, the body may change the counter, and the loop stops
+            // once the counter reaches the largest int
+            ASTNode::For {
+                var,
+                start,
+                end,
+                body,
+                ..
+            } => {
+                let start = self.expression(start, symbols)?;
+                let counter = self.alloca(var, &Type::Int);
+                self.emit(format!("store i32 {}, ptr {}", start, counter));
+                let mut body_symbols = symbols.clone();
+                body_symbols.insert(
+                    var.clone(),
+                    Local {
+                        ty: Type::Int,
+                        ptr: counter.clone(),
+                    },
+                );
+                let cond_label = self.label();
+                let body_label = self.label();
+                let step_label = self.label();
+                let end_label = self.label();
+                self.emit(format!("br label %{}", cond_label));
+                self.start_block(cond_label.clone());
+                let value = self.value(format!("load i32, ptr {}", counter));
+                let end = self.expression(end, &body_symbols)?;
+                let more = self.value(format!("icmp sle i32 {}, {}", value, end));
+                self.emit(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    more, body_label, end_label
+                ));
+                self.start_block(body_label);
+                self.loops.push((step_label.clone(), end_label.clone()));
+                self.statements(body, &mut body_symbols)?;
+                self.loops.pop();
+                self.emit(format!("br label %{}", step_label));
+                self.start_block(step_label);
+                let value = self.value(format!("load i32, ptr {}", counter));
+                let last = self.value(format!("icmp eq i32 {}, {}", value, i32::MAX));
+                let next_label = self.label();
+                self.emit(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    last, end_label, next_label
+                ));
+                self.start_block(next_label);
+                let next = self.value(format!("add i32 {}, 1", value));
+                self.emit(format!("store i32 {}, ptr {}", next, counter));
+                self.emit(format!("br label %{}", cond_label));
+                self.start_block(end_label);
+            }
+            ASTNode::Block(nodes) => self.block(nodes, symbols)?,
+            // Enum values are variant indices, so a `match` is a `switch`
+            ASTNode::Match {
+                value,
+                arms,
+                else_block,
+                ..
+            } => {
+                let Type::Enum(enum_name) = self.checked_type(value, symbols) else {
+                    unreachable!("match value was type checked as an enum");
+                };
+                let value = self.expression(value, symbols)?;
+                let end_label = self.label();
+                let else_label = match else_block {
+                    Some(_) => self.label(),
+                    None => end_label.clone(),
+                };
+                let mut cases = Vec::with_capacity(arms.len());
+                for arm in arms {
+                    let variant = self.definitions.enums[&enum_name]
+                        .iter()
+                        .position(|variant| *variant == arm.variant)
+                        .expect("variant was checked by analyze");
+                    cases.push((variant, self.label()));
+                }
+                self.emit(format!("switch i32 {}, label %{} [", value, else_label));
+                for (variant, label) in &cases {
+                    self.emit(format!("  i32 {}, label %{}", variant, label));
+                }
+                self.emit("]");
+                for (arm, (_, label)) in arms.iter().zip(cases) {
+                    self.start_block(label);
+                    self.block(&arm.body, symbols)?;
+                    self.emit(format!("br label %{}", end_label));
+                }
+                if let Some(else_block) = else_block {
+                    self.start_block(else_label);
+                    self.block(else_block, symbols)?;
+                    self.emit(format!("br label %{}", end_label));
+                }
+                self.start_block(end_label);
+            }
+            ASTNode::Call { .. } => {
+                self.expression(node, symbols)?;
+            }
+            ASTNode::Give { value, .. } => {
+                let value = self.expression(value, symbols)?;
+                self.terminate(format!("ret i32 {}", value));
+            }
+            ASTNode::Input { span, .. } => return Err(unsupported("'input'", *span)),
+            // `exit` flushes what was printed
+            ASTNode::Exit { value, .. } => {
+                let value = self.expression(value, symbols)?;
+                self.declarations.insert(EXIT);
+                self.emit(format!("call void @exit(i32 {})", value));
+                self.terminate("unreachable");
+            }
+            // The message is fixed when compiling, position included
+            ASTNode::Assert {
+                cond,
+                message,
+                span,
+            } => {
+                let cond = self.expression(cond, symbols)?;
+                let (fail_label, ok_label) = (self.label(), self.label());
+                self.emit(format!(
+                    "br i1 {}, label %{}, label %{}",
+                    cond, ok_label, fail_label
+                ));
+                self.start_block(fail_label);
+                let failure = assertion_failure(&self.source, *span, message.as_deref());
+                let len = failure.len() + 1;
+                let failure = self.string(&format!("{}\n", failure));
+                self.declarations.extend([FFLUSH, WRITE, EXIT]);
+                self.emit("call i32 @fflush(ptr null)");
+                self.emit(format!(
+                    "call i64 @write(i32 2, ptr {}, i64 {})",
+                    failure, len
+                ));
+                self.emit(format!("call void @exit(i32 {})", ASSERT_EXIT_CODE));
+                self.emit("unreachable");
+                self.start_block(ok_label);
+            }
+            ASTNode::Break(_) => {
+                let (_, end) = self.loops.last().expect("analyze allows break in loops");
+                let jump = format!("br label %{}", end);
+                self.terminate(jump);
+            }
+            ASTNode::Continue(_) => {
+                let (step, _) = self.loops.last().expect("analyze allows continue in loops");
+                let jump = format!("br label %{}", step);
+                self.terminate(jump);
+            }
+            // Emitted ahead of main by `transpile`
+            ASTNode::Function { .. } | ASTNode::StructDef { .. } | ASTNode::EnumDef { .. } => {}
+            _ => unreachable!("not a statement: {:?}", node),
+        }
+        Ok(())
+    }
+
+    // Emit a function definition around the code `body` generates. Falling
+    // off the end gives 0.
+    fn function(
+        &mut self,
+        signature: String,
+        body: impl FnOnce(&mut Self) -> Result<(), CodegenError>,
+    ) -> Result<String, CodegenError> {
+        self.allocas = CodeWriter::new();
+        self.allocas.indent();
+        self.body = CodeWriter::new();
+        self.body.indent();
+        self.block = "entry".to_string();
+        body(self)?;
+        self.emit("ret i32 0");
+        let allocas = std::mem::take(&mut self.allocas).finish();
+        let body = std::mem::take(&mut self.body).finish();
+        Ok(format!("{} {{\nentry:\n{}{}}}\n", signature, allocas, body))
+    }
+}
+
+// Transpile a checked program into an LLVM IR module. `source` names the
+// program in the messages of failed assertions.
+pub fn transpile(ast: &[ASTNode], source: &Path) -> Result<Generated, CodegenError> {
+    let mut generator = Generator {
+        source: source.to_path_buf(),
+        ..Generator::default()
+    };
+    generator.definitions.add_checked(ast);
+    let mut types = String::new();
+    for node in ast {
+        match node {
+            ASTNode::StructDef { name, .. } => {
+                let fields: Vec<String> = generator.definitions.structs[name]
+                    .iter()
+                    .map(|(_, ty)| ll_type(ty))
+                    .collect();
+                types.push_str(&format!(
+                    "{} = type {{ {} }}\n",
+                    ll_type_name(name),
+                    fields.join(", ")
+                ));
+            }
+            ASTNode::Function { name, params, .. } => {
+                let params = params
+                    .iter()
+                    .map(|param| parameter_type(param).expect("parameter was checked by analyze"))
+                    .collect();
+                generator.functions.insert(name.clone(), params);
+            }
+            _ => {}
+        }
+    }
+
+    // Parameters are assignable, so each is copied to a variable
+    let mut functions = Vec::new();
+    for node in ast {
+        let ASTNode::Function {
+            name, params, body, ..
+        } = node
+        else {
+            continue;
+        };
+        let types = generator.functions[name].clone();
+        let signature: Vec<String> = types
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| format!("{} %arg{}", ll_type(ty), i))
+            .collect();
+        let signature = format!("define i32 {}({})", ll_function(name), signature.join(", "));
+        functions.push(generator.function(signature, |generator| {
+            let mut symbols = SymbolTable::new();
+            for (i, (param, ty)) in params.iter().zip(types).enumerate() {
+                let ptr = generator.alloca(&param.name, &ty);
+                generator.emit(format!("store {} %arg{}, ptr {}", ll_type(&ty), i, ptr));
+                symbols.insert(param.name.clone(), Local { ty, ptr });
+            }
+            generator.statements(body, &mut symbols)
+        })?);
+    }
+
+    let main = generator.function(
+        "define i32 @main(i32 %argc, ptr %argv)".to_string(),
+        |generator| {
+            generator.statements(ast, &mut SymbolTable::new())?;
+            if generator.uses_args {
+                // The entry block's allocas come before its other
+                // instructions, so this runs before anything reads them
+                generator
+                    .allocas
+                    .line("store i32 %argc, ptr @bprt_arg_count");
+                generator.allocas.line("store ptr %argv, ptr @bprt_args");
+            }
+            Ok(())
+        },
+    )?;
+
+    for name in generator.shown_enums.clone() {
+        let variants = generator.definitions.enums[&name].clone();
+        let names: Vec<String> = variants
+            .iter()
+            .map(|variant| format!("ptr {}", generator.string(variant)))
+            .collect();
+        generator.globals.push(format!(
+            "{} = private unnamed_addr constant [{} x ptr] [{}]",
+            ll_variant_names(&name),
+            variants.len(),
+            names.join(", ")
+        ));
+    }
+
+    let mut code = String::new();
+    if !types.is_empty() {
+        code.push_str(&types);
+        code.push('\n');
+    }
+    for global in &generator.globals {
+        code.push_str(global);
+        code.push('\n');
+    }
+    if !generator.globals.is_empty() {
+        code.push('\n');
+    }
+    for function in functions {
+        code.push_str(&function);
+        code.push('\n');
+    }
+    code.push_str(&main);
+    for helper in &generator.helpers {
+        code.push('\n');
+        code.push_str(helper);
+    }
+    if !generator.declarations.is_empty() {
+        code.push('\n');
+    }
+    for declaration in &generator.declarations {
+        code.push_str(declaration);
+        code.push('\n');
+    }
+    Ok(Generated {
+        code,
+        libraries: generator.libraries.into_iter().map(String::from).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::FileId;
+    use crate::parser::parse_source;
+    use crate::semantic::analyze;
+
+    // The IR generated for `input`
+    fn ir(input: &str) -> String {
+        let ast = parse_source(input, FileId::MAIN).unwrap();
+        analyze(&ast).unwrap();
+        transpile(&ast, Path::new("test.bp")).unwrap().code
+    }
+
+    #[test]
+    fn hello_is_a_complete_module() {
+        assert_eq!(
+            ir("show \"hi\";\n"),
+            "@.str.0 = private unnamed_addr constant [4 x i8] c\"hi\\0A\\00\"\n\n\
+             define i32 @main(i32 %argc, ptr %argv) {\n\
+             entry:\n    \
+             call i32 (ptr, ...) @printf(ptr @.str.0)\n    \
+             ret i32 0\n\
+             }\n\n\
+             declare i32 @printf(ptr, ...)\n"
+        );
+    }
+
+    #[test]
+    fn variables_live_in_allocas() {
+        let code = ir("m x = 1;\nx = x + 2;\nshow x;\n");
+        assert!(
+            code.contains(
+                "    %bp_x.1 = alloca i32\n    \
+                 store i32 1, ptr %bp_x.1\n    \
+                 %t2 = load i32, ptr %bp_x.1\n    \
+                 %t3 = add i32 %t2, 2\n    \
+                 store i32 %t3, ptr %bp_x.1\n"
+            ),
+            "{}",
+            code
+        );
+    }
+
+    #[test]
+    fn if_else_branches_to_a_join_block() {
+        let code = ir("m x = 3;\nif x > 2 {\n    show 1;\n} else {\n    show 2;\n}\n");
+        assert!(
+            code.contains(
+                "    %t3 = icmp sgt i32 %t2, 2\n    \
+                 br i1 %t3, label %L4, label %L6\n\
+                 L4:\n    \
+                 call i32 (ptr, ...) @printf(ptr @.str.0, i32 1)\n    \
+                 br label %L5\n\
+                 L6:\n    \
+                 call i32 (ptr, ...) @printf(ptr @.str.0, i32 2)\n    \
+                 br label %L5\n\
+                 L5:\n    \
+                 ret i32 0\n"
+            ),
+            "{}",
+            code
+        );
+    }
+
+    #[test]
+    fn functions_copy_their_parameters() {
+        let code = ir("fn twice(n) {\n    give n * 2;\n}\nshow twice(4);\n");
+        assert!(
+            code.contains(
+                "define i32 @bp_twice(i32 %arg0) {\n\
+                 entry:\n    \
+                 %bp_n.1 = alloca i32\n    \
+                 store i32 %arg0, ptr %bp_n.1\n"
+            ),
+            "{}",
+            code
+        );
+        assert!(code.contains("call i32 @bp_twice(i32 4)"), "{}", code);
+    }
+
+    #[test]
+    fn division_by_a_variable_wraps() {
+        let code = ir("m a = 7;\nm b = 2;\nshow a / b, a % b;\n");
+        assert!(
+            code.contains("call i32 @bprt_wrapping_div(i32 %t3, i32 %t4)"),
+            "{}",
+            code
+        );
+        assert!(code.contains("call i32 @bprt_wrapping_mod("), "{}", code);
+        assert!(
+            code.contains("define private i32 @bprt_wrapping_div(i32 %a, i32 %b)"),
+            "{}",
+            code
+        );
+    }
+
+    #[test]
+    fn division_by_a_literal_is_plain() {
+        let code = ir("m a = 7;\nshow a / 2, a % 3;\n");
+        assert!(code.contains("sdiv i32 %t2, 2"), "{}", code);
+        assert!(code.contains("srem i32 %t4, 3"), "{}", code);
+        assert!(!code.contains("bprt_wrapping"), "{}", code);
+        let code = ir("m a = 7;\nshow a / -1;\n");
+        assert!(code.contains("@bprt_wrapping_div"), "{}", code);
+    }
+
+    #[test]
+    fn unsupported_builtin_is_an_error() {
+        let ast = parse_source("show upper(\"a\");\n", FileId::MAIN).unwrap();
+        analyze(&ast).unwrap();
+        let Err(err) = transpile(&ast, Path::new("test.bp")) else {
+            panic!("upper should be refused");
+        };
+        assert_eq!(err.message, "'upper' is not supported by the LLVM target");
+        assert_eq!((err.span.line, err.span.col), (1, 6));
+    }
+}
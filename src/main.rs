@@ -1,276 +1,388 @@
-use std::fs::File;
-use std::io::{self, Read, Write};
-use std::process::Command;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 
-// Define the different types of tokens
-#[derive(Debug, PartialEq, Clone)]
-enum Token {
-    Keyword(String),
-    Identifier(String),
-    Number(i32),
-    StringLiteral(String),
-    Equals,
-    Semicolon,
-    EndOfFile,
+use bplang::backend::Target;
+use bplang::cache;
+use bplang::codes;
+use bplang::config;
+use bplang::diagnostic::{self, ColorChoice, ErrorFormat};
+use bplang::driver::{self, Emit, Engine, Mode, Options, OPT_LEVELS};
+use bplang::error::CompileError;
+use bplang::format::format_source;
+use bplang::harness;
+use bplang::project;
+use bplang::repl;
+use bplang::watch;
+
+// Source file used when no path is given on the command line
+const DEFAULT_SOURCE: &str = "main.bp";
+
+const USAGE: &str = "\
+usage: bplang [check | build | run] [options] <file.bp | -> [-- <args>...]
+       bplang watch [options] <file.bp> [-- <args>...]
+       bplang repl
+       bplang test [options] [<file.bp | dir>...]
+       bplang fmt [--check] <file.bp>...
+       bplang new <name> [--bin-name <name>]
+       bplang explain <code>
+       bplang clean-cache
+
+options:
+    -o <path>         write the executable to <path>
+    --target <lang>   language to translate the program to: c (default), js,
+                      py, rust or llvm
+    --cc <compiler>   C compiler to use (default: $CC, then cc, gcc or clang)
+    --opt <level>     optimization level for the C compiler (0-3, s or z)
+    --no-opt          do not fold constant expressions before generating C
+    --trap-overflow   stop the program with an error when int arithmetic
+                      overflows, instead of wrapping (C target and engines)
+    --debug           build with debug information
+    --cflag <flag>    pass <flag> to the C compiler, may be repeated
+    --link <name>     link the C library <name> (-l<name>), may be repeated
+    --verbose         print the C compiler command line
+    --timings         print how long each step of the compiler took
+    --deny-warnings   report warnings as errors
+    --error-format <format>
+                      how errors and warnings are written: human (default)
+                      or json, one object per line on stdout
+    --color <when>    color diagnostics: auto (default, when stderr is a
+                      terminal), always or never
+    --engine <name>   how run executes the program: compile (default), tree
+                      to interpret the syntax tree or vm to run it as bytecode
+    --interpret       same as --engine tree
+    --capture         print the program's output once it exits
+    --no-cache        build even if an unchanged program was built before
+    --keep-c          write the generated C next to the source
+    --no-line-directives
+                      leave out the #line directives mapping the generated C
+                      back to the BP source
+    --emit-tokens     print the tokens and stop
+    --emit-ast        print the syntax tree and stop
+    --emit-ast=json   print the syntax tree as JSON and stop
+    --emit-bp         print the optimized program as BP and stop
+    --emit-bytecode   print the bytecode the vm engine runs and stop
+    --emit-c          write the generated C and stop
+    --emit-c=-        print the generated C and stop
+    --emit-llvm       write the program as LLVM IR and stop
+";
+
+// Report a bad command line and exit
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    eprint!("{}", USAGE);
+    process::exit(1);
 }
 
-// Tokenize the BP source code into a list of tokens
-fn tokenize(input: &str) -> Vec<Token> {
-    let mut tokens = Vec::new();
-    let mut chars = input.chars().peekable();
+// The argument following an option that takes a value
+fn usage_value(args: &mut impl Iterator<Item = String>, option: &str) -> String {
+    args.next()
+        .unwrap_or_else(|| usage_error(&format!("{} needs a value", option)))
+}
 
-    while let Some(&ch) = chars.peek() {
-        match ch {
-            '=' => {
-                tokens.push(Token::Equals);
-                chars.next();
-            }
-            ';' => {
-                tokens.push(Token::Semicolon);
-                chars.next();
-            }
-            '"' => {
-                chars.next(); // skip starting quote
-                let mut s = String::new();
-                while let Some(&ch) = chars.peek() {
-                    if ch == '"' {
-                        break;
-                    }
-                    s.push(ch);
-                    chars.next();
-                }
-                chars.next(); // skip ending quote
-                tokens.push(Token::StringLiteral(s));
-            }
-            '0'..='9' => {
-                let mut num = String::new();
-                while let Some(&ch) = chars.peek() {
-                    if ch.is_digit(10) {
-                        num.push(ch);
-                    } else {
-                        break;
-                    }
-                    chars.next();
-                }
-                tokens.push(Token::Number(num.parse::<i32>().unwrap()));
+// `bplang fmt`: rewrite files in the canonical layout, or with `--check`
+// only tell which ones it would change
+fn format_files(args: impl Iterator<Item = String>) -> ! {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            flag if flag.starts_with('-') => usage_error(&format!("unknown option: {}", flag)),
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+    if files.is_empty() {
+        usage_error("fmt needs a source file");
+    }
+    let mut code = 0;
+    for file in &files {
+        let formatted = fs::read_to_string(file)
+            .map_err(CompileError::from)
+            .and_then(|source| Ok((format_source(&source)?, source)));
+        match formatted {
+            Ok((formatted, source)) if formatted == source => {}
+            Ok(_) if check => {
+                println!("would reformat {}", file.display());
+                code = 1;
             }
-            'a'..='z' | 'A'..='Z' => {
-                let mut ident = String::new();
-                while let Some(&ch) = chars.peek() {
-                    if ch.is_alphabetic() {
-                        ident.push(ch);
-                    } else {
-                        break;
-                    }
-                    chars.next();
-                }
-                match ident.as_str() {
-                    "m" | "c" | "show" => tokens.push(Token::Keyword(ident)),
-                    _ => tokens.push(Token::Identifier(ident)),
+            Ok((formatted, _)) => {
+                if let Err(err) = fs::write(file, formatted) {
+                    eprintln!("error: cannot write {}: {}", file.display(), err);
+                    process::exit(1);
                 }
+                println!("formatted {}", file.display());
             }
-            ' ' | '\n' | '\t' => {
-                chars.next(); // skip whitespace
+            Err(err) => {
+                driver::report_compile_error(file, &err, ErrorFormat::Human);
+                process::exit(err.exit_code());
             }
-            _ => panic!("Unexpected character: {:?}", ch),
         }
     }
-
-    tokens.push(Token::EndOfFile);
-    tokens
+    process::exit(code);
 }
 
-// Define the AST
-#[derive(Debug)]
-enum ASTNode {
-    VariableDeclaration { name: String, value: Box<ASTNode> },
-    StringLiteral(String),
-    NumberLiteral(i32),
-    Show(String),
+// `bplang new`: create a project and tell how to run it
+fn new_project(mut args: impl Iterator<Item = String>) -> ! {
+    let mut dir = None;
+    let mut bin_name = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bin-name" => bin_name = Some(usage_value(&mut args, "--bin-name")),
+            flag if flag.starts_with('-') => usage_error(&format!("unknown option: {}", flag)),
+            _ if dir.is_some() => usage_error("more than one project name given"),
+            _ => dir = Some(PathBuf::from(arg)),
+        }
+    }
+    let dir = dir.unwrap_or_else(|| usage_error("new needs a project name"));
+    if let Err(err) = project::create(&dir, bin_name.as_deref()) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+    println!("Created project {}", dir.display());
+    println!();
+    println!("Next steps:");
+    println!("    cd {}", dir.display());
+    println!("    bplang run");
+    process::exit(0);
 }
 
-// Parse tokens into an AST
-fn parse(tokens: &[Token]) -> Vec<ASTNode> {
-    let mut ast = Vec::new();
-    let mut idx = 0;
+// `bplang explain`: describe an error or warning code
+fn explain_code(mut args: impl Iterator<Item = String>) -> ! {
+    let code = args
+        .next()
+        .unwrap_or_else(|| usage_error("explain needs an error code, such as E0010"));
+    if args.next().is_some() {
+        usage_error("explain takes a single error code");
+    }
+    match codes::lookup(&code) {
+        Some(info) => {
+            print!("{}", codes::explain(info));
+            process::exit(0);
+        }
+        None => {
+            eprintln!("error: unknown error code: {}", code);
+            process::exit(1);
+        }
+    }
+}
 
-    while idx < tokens.len() {
-        match &tokens[idx] {
-            Token::Keyword(k) => {
-                match k.as_str() {
-                    "m" | "c" => {
-                        if idx + 1 < tokens.len() {
-                            if let Token::Identifier(name) = &tokens[idx + 1] {
-                                if idx + 2 < tokens.len() {
-                                    if let Token::Equals = &tokens[idx + 2] {
-                                        if idx + 3 < tokens.len() {
-                                            match &tokens[idx + 3] {
-                                                Token::Number(num) => {
-                                                    ast.push(ASTNode::VariableDeclaration {
-                                                        name: name.clone(),
-                                                        value: Box::new(ASTNode::NumberLiteral(
-                                                            *num,
-                                                        )),
-                                                    });
-                                                    idx += 4; // Move past the variable declaration
-                                                }
-                                                Token::StringLiteral(s) => {
-                                                    ast.push(ASTNode::VariableDeclaration {
-                                                        name: name.clone(),
-                                                        value: Box::new(ASTNode::StringLiteral(
-                                                            s.clone(),
-                                                        )),
-                                                    });
-                                                    idx += 4; // Move past the variable declaration
-                                                }
-                                                _ => {
-                                                    println!("Unexpected value after `=` at token index: {}", idx + 3);
-                                                    idx += 1; // Move to the next token
-                                                }
-                                            }
-                                        } else {
-                                            println!("Expected a value after `=` but reached end of tokens.");
-                                            idx += 1;
-                                        }
-                                    } else {
-                                        println!(
-                                            "Expected `=` after identifier `{}` at token index: {}",
-                                            name,
-                                            idx + 1
-                                        );
-                                        idx += 1; // Move to the next token
-                                    }
-                                } else {
-                                    println!(
-                                        "Expected `=` after identifier but reached end of tokens."
-                                    );
-                                    idx += 1;
-                                }
-                            } else {
-                                println!(
-                                    "Expected identifier after keyword `{}` at token index: {}",
-                                    k,
-                                    idx + 1
-                                );
-                                idx += 1;
-                            }
-                        } else {
-                            println!("Expected identifier but reached end of tokens.");
-                            idx += 1;
-                        }
-                    }
-                    "show" => {
-                        if idx + 1 < tokens.len() {
-                            if let Token::StringLiteral(s) = &tokens[idx + 1] {
-                                ast.push(ASTNode::Show(s.clone()));
-                                idx += 2; // Move past the `show` statement
-                            } else {
-                                println!(
-                                    "Expected string literal after `show` at token index: {}",
-                                    idx + 1
-                                );
-                                idx += 1;
-                            }
-                        } else {
-                            println!("Expected string literal but reached end of tokens.");
-                            idx += 1;
-                        }
-                    }
-                    _ => {
-                        println!("Unknown keyword: {} at token index: {}", k, idx);
-                        idx += 1; // Move to the next token
-                    }
-                }
-            }
-            Token::Semicolon => {
-                // Skip semicolons, move to the next token
-                idx += 1;
+fn main() {
+    // The command line: a subcommand, then options and the source file
+    let mut options = Options::default();
+    let mut sources = Vec::new();
+    let mut args = env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("repl") {
+        match repl::run(io::stdin().lock()) {
+            Ok(code) => process::exit(code),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                process::exit(1);
             }
-            Token::EndOfFile => {
-                break; // Exit the loop when end of file token is reached
+        }
+    }
+    if args.peek().map(String::as_str) == Some("clean-cache") {
+        let dir = cache::cache_dir();
+        match cache::clean() {
+            Ok(removed) => {
+                println!("Removed {} cached builds from {}", removed, dir.display());
+                process::exit(0);
             }
-            _ => {
-                println!("Unexpected token: {:?} at index: {}", tokens[idx], idx);
-                idx += 1; // Move to the next token
+            Err(err) => {
+                eprintln!("error: cannot clean {}: {}", dir.display(), err);
+                process::exit(1);
             }
         }
     }
-
-    ast
-}
-
-// Transpile AST into C code and write it to a file
-fn transpile_and_write_c(ast: Vec<ASTNode>, output_file: &str) -> io::Result<()> {
-    let mut c_code = String::from("#include <stdio.h>\n\nint main() {\n");
-
-    for node in ast {
-        match node {
-            ASTNode::VariableDeclaration { name, value } => match *value {
-                ASTNode::NumberLiteral(num) => {
-                    c_code.push_str(&format!("    int {} = {};\n", name, num));
-                }
-                ASTNode::StringLiteral(s) => {
-                    c_code.push_str(&format!("    char {}[] = \"{}\";\n", name, s));
+    if args.peek().map(String::as_str) == Some("fmt") {
+        args.next();
+        format_files(args);
+    }
+    if args.peek().map(String::as_str) == Some("explain") {
+        args.next();
+        explain_code(args);
+    }
+    if args.peek().map(String::as_str) == Some("new") {
+        args.next();
+        new_project(args);
+    }
+    // Without a subcommand the program is built and run. `watch` runs it
+    // again after every change.
+    let mut watching = false;
+    let mut testing = false;
+    if let Some(mode) = args.peek().and_then(|arg| match arg.as_str() {
+        "check" => Some(Mode::Check),
+        "build" => Some(Mode::Build),
+        "run" => Some(Mode::Run),
+        "watch" => {
+            watching = true;
+            Some(Mode::Run)
+        }
+        "test" => {
+            testing = true;
+            Some(Mode::Run)
+        }
+        _ => None,
+    }) {
+        options.mode = mode;
+        args.next();
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--deny-warnings" => options.deny_warnings = true,
+            "--capture" => options.capture = true,
+            "--no-cache" => options.no_cache = true,
+            "--interpret" => options.engine = Engine::Tree,
+            "--engine" => {
+                let name = usage_value(&mut args, "--engine");
+                options.engine = Engine::from_name(&name).unwrap_or_else(|| {
+                    usage_error(&format!(
+                        "unknown engine: {} (expected {})",
+                        name,
+                        Engine::NAMES.join(", ")
+                    ))
+                });
+            }
+            "--color" => {
+                let name = usage_value(&mut args, "--color");
+                let choice = ColorChoice::from_name(&name).unwrap_or_else(|| {
+                    usage_error(&format!(
+                        "unknown color choice: {} (expected {})",
+                        name,
+                        ColorChoice::NAMES.join(", ")
+                    ))
+                });
+                diagnostic::set_color(choice);
+            }
+            "--error-format" => {
+                let name = usage_value(&mut args, "--error-format");
+                options.error_format = ErrorFormat::from_name(&name).unwrap_or_else(|| {
+                    usage_error(&format!(
+                        "unknown error format: {} (expected {})",
+                        name,
+                        ErrorFormat::NAMES.join(", ")
+                    ))
+                });
+            }
+            "--keep-c" => options.keep_c = true,
+            "--verbose" => options.verbose = true,
+            "--timings" => options.timings = true,
+            "--target" => {
+                let name = usage_value(&mut args, "--target");
+                options.target = Target::from_name(&name).unwrap_or_else(|| {
+                    usage_error(&format!(
+                        "unknown target: {} (expected {})",
+                        name,
+                        Target::NAMES.join(", ")
+                    ))
+                });
+            }
+            "--cc" => options.cc = Some(usage_value(&mut args, "--cc")),
+            "--opt" => {
+                let level = usage_value(&mut args, "--opt");
+                if !OPT_LEVELS.contains(&level.as_str()) {
+                    usage_error(&format!("invalid optimization level: {}", level));
                 }
-                _ => {}
-            },
-            ASTNode::Show(s) => {
-                c_code.push_str(&format!("    printf(\"{}\\n\");\n", s));
+                options.opt_level = Some(level);
+            }
+            "--no-opt" => options.no_opt = true,
+            "--trap-overflow" => options.trap_overflow = true,
+            "--no-line-directives" => options.no_line_directives = true,
+            "--debug" => options.debug = true,
+            "--cflag" => options.cflags.push(usage_value(&mut args, "--cflag")),
+            "--link" => options.links.push(usage_value(&mut args, "--link")),
+            "-o" => options.output = Some(PathBuf::from(usage_value(&mut args, "-o"))),
+            "--emit-tokens" => options.emit = Some(Emit::Tokens),
+            "--emit-ast" => options.emit = Some(Emit::Ast),
+            "--emit-ast=json" => options.emit = Some(Emit::AstJson),
+            "--emit-bp" => options.emit = Some(Emit::Source),
+            "--emit-bytecode" => options.emit = Some(Emit::Bytecode),
+            "--emit-c" => options.emit = Some(Emit::CFile),
+            "--emit-c=-" => options.emit = Some(Emit::CStdout),
+            "--emit-llvm" => {
+                options.target = Target::Llvm;
+                options.emit = Some(Emit::CFile);
+            }
+            // Everything after `--` belongs to the program
+            "--" => options.program_args.extend(args.by_ref()),
+            flag if flag.starts_with('-') && flag != driver::STDIN_SOURCE => {
+                usage_error(&format!("unknown option: {}", flag))
             }
-            _ => {} // Handles other unhandled ASTNode variants
+            _ => sources.push(PathBuf::from(arg)),
         }
     }
-
-    c_code.push_str("    return 0;\n}");
-
-    // Write the C code to the output file
-    let mut file = File::create(output_file)?;
-    file.write_all(c_code.as_bytes())?;
-
-    Ok(())
-}
-
-fn main() -> io::Result<()> {
-    // Step 1: Read BP source code from the `main.bp` file
-    let bp_file_path = "main.bp";
-    let mut bp_file = File::open(bp_file_path)?;
-    let mut source_code = String::new();
-    bp_file.read_to_string(&mut source_code)?;
-
-    // Step 2: Tokenize the BP source code
-    let tokens = tokenize(&source_code);
-    //println!("Tokens: {:?}", tokens);
-
-    // Step 3: Parse tokens into AST
-    let ast = parse(&tokens);
-    //println!("AST: {:?}", ast);
-
-    // Step 4: Transpile AST to C code and write to `main.c`
-    transpile_and_write_c(ast, "main.c")?;
-    println!("C code has been generated and written to main.c");
-
-    // Step 5: Compile the generated C code using `gcc`
-    let output = Command::new("gcc")
-        .arg("main.c")
-        .arg("-o")
-        .arg("main") // Output binary file `main`
-        .output()?;
-
-    // Check if the compilation was successful
-    if !output.status.success() {
-        println!("Compilation failed:");
-        io::stderr().write_all(&output.stderr)?;
-        return Err(io::Error::new(io::ErrorKind::Other, "C compilation failed"));
+    if options.engine != Engine::Compile && options.mode != Mode::Run {
+        usage_error("--interpret and --engine can only be used with run");
+    }
+    if options.trap_overflow && options.target != Target::C {
+        usage_error("--trap-overflow only works with the C target");
+    }
+    if !options.program_args.is_empty() && options.mode != Mode::Run {
+        usage_error("program arguments can only be given to run");
+    }
+    if testing {
+        process::exit(harness::run_tests(&sources, &options));
+    }
+    if sources.len() > 1 {
+        usage_error("more than one source file given");
+    }
+    // Without a source file, the project's `bp.toml` names it and supplies
+    // the options the command line leaves out
+    let source = sources.pop().or_else(|| {
+        let path = config::find()?;
+        let config = config::load(&path).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        });
+        let dir = path.parent().expect("a file in a directory");
+        if options.output.is_none() {
+            options.output = config.output.map(|output| dir.join(output));
+        }
+        if options.cc.is_none() {
+            options.cc = config.cc;
+        }
+        if options.cflags.is_empty() {
+            options.cflags = config.cflags;
+        }
+        if options.opt_level.is_none() {
+            options.opt_level = config.opt;
+        }
+        let entry = dir.join(config.entry?);
+        if !entry.is_file() {
+            eprintln!(
+                "error: {}: entry file not found: {}",
+                path.display(),
+                entry.display()
+            );
+            process::exit(1);
+        }
+        Some(entry)
+    });
+    let bp_file_path = source.unwrap_or_else(|| PathBuf::from(DEFAULT_SOURCE));
+    if bp_file_path != Path::new(driver::STDIN_SOURCE) && !bp_file_path.is_file() {
+        // Not a usage mistake, so reported like any other unreadable file
+        let err = CompileError::Io(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("source file not found: {}", bp_file_path.display()),
+        ));
+        driver::report_compile_error(&bp_file_path, &err, options.error_format);
+        process::exit(err.exit_code());
     }
 
-    println!("Compilation successful, running the program...");
-
-    // Step 6: Execute the compiled binary
-    let execution_output = Command::new("./main").output()?;
-
-    // Print the output of the program
-    println!("Program output:");
-    io::stdout().write_all(&execution_output.stdout)?;
+    if watching {
+        if bp_file_path == Path::new(driver::STDIN_SOURCE) {
+            usage_error("watch needs a source file");
+        }
+        process::exit(watch::watch(&bp_file_path, &options));
+    }
 
-    Ok(())
+    match driver::run(&bp_file_path, &options) {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            driver::report_compile_error(&bp_file_path, &err, options.error_format);
+            process::exit(err.exit_code());
+        }
+    }
 }
@@ -0,0 +1,325 @@
+//! Loads the files a program pulls in with `use "other.bp";` and merges
+//! their definitions into it
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::ast::ASTNode;
+use crate::codes;
+use crate::error::CompileError;
+use crate::lexer::{FileId, Span};
+use crate::parser::parse_source;
+use crate::semantic::SemanticError;
+
+// A file loaded with `use`
+#[derive(Debug, Clone)]
+pub struct Module {
+    // Where the file really is, telling two paths to one file apart
+    pub canonical: PathBuf,
+    // The path as the program names it, shown in diagnostics
+    pub path: PathBuf,
+    pub text: String,
+}
+
+// Every file loaded so far in this process. `FileId(n)` is entry `n - 1`,
+// `FileId::MAIN` being the program itself.
+static MODULES: Mutex<Vec<Module>> = Mutex::new(Vec::new());
+
+// Record a loaded file and give its id. Loading the same file again, as a
+// watched program does after a change, keeps the id and takes the new text.
+fn register(module: Module) -> FileId {
+    let mut modules = MODULES.lock().unwrap_or_else(|err| err.into_inner());
+    let index = match modules
+        .iter()
+        .position(|other| other.canonical == module.canonical)
+    {
+        Some(index) => {
+            modules[index] = module;
+            index
+        }
+        None => {
+            modules.push(module);
+            modules.len() - 1
+        }
+    };
+    FileId(index as u32 + 1)
+}
+
+// The loaded file behind `file`; `None` for the program itself
+pub fn module(file: FileId) -> Option<Module> {
+    let index = file.0.checked_sub(1)?;
+    let modules = MODULES.lock().unwrap_or_else(|err| err.into_inner());
+    modules.get(index as usize).cloned()
+}
+
+// The path shown for a span in `file`, the program itself being at `main`
+pub fn source_path(file: FileId, main: &Path) -> PathBuf {
+    match module(file) {
+        Some(module) => module.path,
+        None => main.to_path_buf(),
+    }
+}
+
+// A loading failure, at the `use` statement that asked for the file
+fn error(code: &'static str, message: String, span: Span) -> CompileError {
+    CompileError::Semantic(vec![SemanticError {
+        code,
+        message,
+        span,
+    }])
+}
+
+// What loading a program's files keeps track of
+struct Loader {
+    // The files being loaded, outermost first, to catch a file using itself
+    // through others: canonical path and path as shown
+    stack: Vec<(PathBuf, PathBuf)>,
+    // Files whose definitions are already merged, loaded only once however
+    // many files use them
+    loaded: Vec<Module>,
+    definitions: Vec<ASTNode>,
+}
+
+impl Loader {
+    // Load the files used by the top-level statements in `nodes`, which are
+    // in a file in `dir`
+    fn uses(&mut self, nodes: &[ASTNode], dir: &Path) -> Result<(), CompileError> {
+        for node in nodes {
+            let ASTNode::Use { path, span } = node else {
+                continue;
+            };
+            let shown = dir.join(path);
+            let canonical = fs::canonicalize(&shown).map_err(|err| {
+                error(
+                    codes::IMPORT_NOT_FOUND,
+                    format!("cannot use '{}': {}", shown.display(), err),
+                    *span,
+                )
+            })?;
+            if let Some(start) = self.stack.iter().position(|(c, _)| *c == canonical) {
+                let cycle: Vec<String> = self.stack[start..]
+                    .iter()
+                    .map(|(_, shown)| shown.display().to_string())
+                    .chain([shown.display().to_string()])
+                    .collect();
+                return Err(error(
+                    codes::IMPORT_CYCLE,
+                    format!("files use each other in a cycle: {}", cycle.join(" -> ")),
+                    *span,
+                ));
+            }
+            if self
+                .loaded
+                .iter()
+                .any(|module| module.canonical == canonical)
+            {
+                continue;
+            }
+
+            let text = fs::read_to_string(&shown).map_err(|err| {
+                error(
+                    codes::IMPORT_NOT_FOUND,
+                    format!("cannot use '{}': {}", shown.display(), err),
+                    *span,
+                )
+            })?;
+            let module = Module {
+                canonical: canonical.clone(),
+                path: shown.clone(),
+                text,
+            };
+            let file = register(module.clone());
+            let ast = parse_source(&module.text, file)?;
+
+            // A used file only adds definitions; statements would run at
+            // some arbitrary point of the program using it
+            for node in &ast {
+                if !is_definition(node) {
+                    return Err(error(
+                        codes::STATEMENT_IN_MODULE,
+                        format!(
+                            "'{}' is loaded with 'use', so it may only define \
+                             functions and types",
+                            shown.display()
+                        ),
+                        node.span().unwrap_or(*span),
+                    ));
+                }
+            }
+
+            // The files it uses come first, then its own definitions
+            self.stack.push((canonical, shown.clone()));
+            let module_dir = shown.parent().unwrap_or(Path::new("")).to_path_buf();
+            self.uses(&ast, &module_dir)?;
+            self.stack.pop();
+            self.definitions.extend(
+                ast.into_iter()
+                    .filter(|node| !matches!(node, ASTNode::Use { .. })),
+            );
+            self.loaded.push(module);
+        }
+        Ok(())
+    }
+}
+
+// What a used file may contain
+fn is_definition(node: &ASTNode) -> bool {
+    matches!(
+        node,
+        ASTNode::Function { .. }
+            | ASTNode::ExternFunction { .. }
+            | ASTNode::StructDef { .. }
+            | ASTNode::EnumDef { .. }
+            | ASTNode::CInclude { .. }
+            | ASTNode::Link { .. }
+            | ASTNode::Use { .. }
+    )
+}
+
+// Load every file the program `ast`, read from `source`, uses, directly or
+// through other used files, with paths relative to the file naming them.
+// Returns the program with the definitions of the used files ahead of its
+// own statements and its top-level `use` statements gone, and the files
+// loaded.
+pub fn resolve(
+    ast: Vec<ASTNode>,
+    source: &Path,
+) -> Result<(Vec<ASTNode>, Vec<Module>), CompileError> {
+    let mut loader = Loader {
+        stack: Vec::new(),
+        loaded: Vec::new(),
+        definitions: Vec::new(),
+    };
+    // A program on stdin has no file of its own to use again
+    if let Ok(canonical) = fs::canonicalize(source) {
+        loader.stack.push((canonical, source.to_path_buf()));
+    }
+    let dir = source.parent().unwrap_or(Path::new(""));
+    loader.uses(&ast, dir)?;
+
+    let mut program = loader.definitions;
+    program.extend(
+        ast.into_iter()
+            .filter(|node| !matches!(node, ASTNode::Use { .. })),
+    );
+    Ok((program, loader.loaded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+    // A fresh directory holding `files`, given as name and text
+    fn directory(files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "bplang-module-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        for (name, text) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        dir
+    }
+
+    // The functions of the resolved program at `main` in `dir`, in order,
+    // and the paths of the files it loaded
+    fn resolved(dir: &Path, main: &str) -> (Vec<String>, Vec<PathBuf>) {
+        let source = dir.join(main);
+        let text = fs::read_to_string(&source).unwrap();
+        let (program, modules) = resolve(parse_source(&text, FileId::MAIN).unwrap(), &source)
+            .expect("the program should resolve");
+        let names = program
+            .iter()
+            .map(|node| match node {
+                ASTNode::Function { name, .. } => name.clone(),
+                ASTNode::Use { .. } => panic!("'use' left in the program"),
+                _ => "statement".to_string(),
+            })
+            .collect();
+        let paths = modules
+            .into_iter()
+            .map(|module| module.path.strip_prefix(dir).unwrap().to_path_buf())
+            .collect();
+        (names, paths)
+    }
+
+    fn function(name: &str) -> String {
+        format!("fn {}() {{\n    give 1;\n}}\n", name)
+    }
+
+    #[test]
+    fn used_definitions_come_before_the_program() {
+        let dir = directory(&[
+            ("main.bp", "use \"lib/b.bp\";\nshow in_b();\n"),
+            ("lib/b.bp", &format!("use \"c.bp\";\n{}", function("in_b"))),
+            ("lib/c.bp", &function("in_c")),
+        ]);
+        let (names, paths) = resolved(&dir, "main.bp");
+        assert_eq!(names, ["in_c", "in_b", "statement"]);
+        assert_eq!(paths, [Path::new("lib/c.bp"), Path::new("lib/b.bp")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_used_twice_is_loaded_once() {
+        let dir = directory(&[
+            ("main.bp", "use \"b.bp\";\nuse \"c.bp\";\nshow 1;\n"),
+            ("b.bp", &format!("use \"d.bp\";\n{}", function("in_b"))),
+            ("c.bp", &format!("use \"d.bp\";\n{}", function("in_c"))),
+            ("d.bp", &function("in_d")),
+        ]);
+        let (names, paths) = resolved(&dir, "main.bp");
+        assert_eq!(names, ["in_d", "in_b", "in_c", "statement"]);
+        assert_eq!(paths.len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn spans_in_a_used_file_name_it() {
+        let dir = directory(&[
+            ("main.bp", "use \"lib/b.bp\";\nshow in_b();\n"),
+            ("lib/b.bp", &function("in_b")),
+        ]);
+        let source = dir.join("main.bp");
+        let text = fs::read_to_string(&source).unwrap();
+        let (program, _) = resolve(parse_source(&text, FileId::MAIN).unwrap(), &source).unwrap();
+        let span = program[0].span().unwrap();
+        assert_eq!(source_path(span.file, &source), dir.join("lib/b.bp"));
+        let span = program[1].span().unwrap();
+        assert_eq!(source_path(span.file, &source), source);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cycle_names_every_file_in_it() {
+        let dir = directory(&[
+            ("a.bp", "use \"b.bp\";\nshow 1;\n"),
+            ("b.bp", "use \"c.bp\";\n"),
+            ("c.bp", "use \"b.bp\";\n"),
+        ]);
+        let source = dir.join("a.bp");
+        let text = fs::read_to_string(&source).unwrap();
+        let Err(CompileError::Semantic(errors)) =
+            resolve(parse_source(&text, FileId::MAIN).unwrap(), &source)
+        else {
+            panic!("the cycle should be an error");
+        };
+        assert_eq!(errors[0].code, codes::IMPORT_CYCLE);
+        let b = dir.join("b.bp").display().to_string();
+        let c = dir.join("c.bp").display().to_string();
+        assert_eq!(
+            errors[0].message,
+            format!("files use each other in a cycle: {} -> {} -> {}", b, c, b)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
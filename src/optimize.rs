@@ -0,0 +1,582 @@
+//! Optimizations on a checked program, applied before C code generation
+
+use std::collections::HashSet;
+
+use crate::ast::{ASTNode, BinaryOperator, MatchArm, UnaryOperator};
+use crate::codegen::raw_c_names;
+use crate::codes;
+use crate::interpreter::{integer_arithmetic, overflow_message};
+use crate::lexer::Span;
+use crate::semantic::{SemanticError, Warning};
+use crate::visit::{walk_expression, walk_expression_mut, walk_statement, Visitor, VisitorMut};
+
+// Optimize a program that passed `analyze`: fold its constants, then remove
+// the code that can never run or whose result is never used. Int overflow
+// in constants wraps, as it does when the program runs, or is an error with
+// `trap_overflow`. Returns the optimized program and warnings about the
+// unreachable code removed, in source order.
+pub fn optimize(
+    ast: Vec<ASTNode>,
+    trap_overflow: bool,
+) -> Result<(Vec<ASTNode>, Vec<Warning>), SemanticError> {
+    let ast = fold_constants(ast, trap_overflow)?;
+    let mut warnings = Vec::new();
+    let ast = eliminate_dead_code(ast, &mut warnings);
+    warnings.sort_by_key(|warning| (warning.span.line, warning.span.col));
+    Ok((ast, warnings))
+}
+
+// Fold the constant expressions of a program, and drop the branches of `if`
+// statements whose condition is a constant. Int arithmetic dividing by zero
+// is an error here, rather than a crash of the generated C, and so is
+// overflow if the program would trap it.
+fn fold_constants(
+    mut ast: Vec<ASTNode>,
+    trap_overflow: bool,
+) -> Result<Vec<ASTNode>, SemanticError> {
+    let mut folder = Folder {
+        error: None,
+        trap_overflow,
+    };
+    folder.visit_block(&mut ast);
+    match folder.error {
+        Some(error) => Err(error),
+        None => Ok(ast),
+    }
+}
+
+// Folds constants in place. After the first error, which is the one
+// reported, nothing more is folded.
+struct Folder {
+    error: Option<SemanticError>,
+    trap_overflow: bool,
+}
+
+impl VisitorMut for Folder {
+    fn visit_block(&mut self, nodes: &mut Vec<ASTNode>) {
+        let mut folded = Vec::with_capacity(nodes.len());
+        for mut node in nodes.drain(..) {
+            let ASTNode::If {
+                cond,
+                then_block,
+                else_block,
+                ..
+            } = &mut node
+            else {
+                self.visit_statement(&mut node);
+                folded.push(node);
+                continue;
+            };
+            self.visit_expression(cond);
+            // The branch taken stays a block, keeping its declarations
+            // local; the other one goes without being folded
+            if let ASTNode::BoolLiteral(taken) = **cond {
+                let branch = if taken {
+                    Some(std::mem::take(then_block))
+                } else {
+                    else_block.take()
+                };
+                if let Some(mut branch) = branch {
+                    self.visit_block(&mut branch);
+                    folded.push(ASTNode::Block(branch));
+                }
+                continue;
+            }
+            self.visit_block(then_block);
+            if let Some(else_block) = else_block {
+                self.visit_block(else_block);
+            }
+            folded.push(node);
+        }
+        *nodes = folded;
+    }
+
+    // Bottom-up, replacing operations on literals by their result
+    fn visit_expression(&mut self, node: &mut ASTNode) {
+        walk_expression_mut(self, node);
+        if self.error.is_some()
+            || !matches!(node, ASTNode::BinaryOp { .. } | ASTNode::UnaryOp { .. })
+        {
+            return;
+        }
+        let folded = match std::mem::replace(node, ASTNode::BoolLiteral(false)) {
+            ASTNode::BinaryOp { op, lhs, rhs, span } => {
+                fold_binary(op, *lhs, *rhs, self.trap_overflow, span)
+            }
+            ASTNode::UnaryOp { op, operand, span } => {
+                fold_unary(op, *operand, self.trap_overflow, span)
+            }
+            _ => unreachable!("only operations are folded"),
+        };
+        match folded {
+            Ok(folded) => *node = folded,
+            Err(error) => self.error = Some(error),
+        }
+    }
+}
+
+// The error for constant arithmetic that overflows with `trap_overflow`
+fn overflow_error(operation: &str, long: bool, span: Span) -> SemanticError {
+    SemanticError {
+        code: codes::CONSTANT_ARITHMETIC,
+        message: overflow_message(operation, long),
+        span,
+    }
+}
+
+fn fold_unary(
+    op: UnaryOperator,
+    operand: ASTNode,
+    trap_overflow: bool,
+    span: Span,
+) -> Result<ASTNode, SemanticError> {
+    Ok(match (op, operand) {
+        (UnaryOperator::Neg, ASTNode::NumberLiteral(num)) => match num.overflowing_neg() {
+            (_, true) if trap_overflow => {
+                return Err(overflow_error(&format!("-({})", num), false, span))
+            }
+            (negated, _) => ASTNode::NumberLiteral(negated),
+        },
+        (UnaryOperator::Neg, ASTNode::LongLiteral(num)) => match num.overflowing_neg() {
+            (_, true) if trap_overflow => {
+                return Err(overflow_error(&format!("-({})", num), true, span))
+            }
+            (negated, _) => ASTNode::LongLiteral(negated),
+        },
+        (UnaryOperator::Neg, ASTNode::FloatLiteral(num)) => ASTNode::FloatLiteral(-num),
+        (UnaryOperator::Not, ASTNode::BoolLiteral(b)) => ASTNode::BoolLiteral(!b),
+        (op, operand) => ASTNode::UnaryOp {
+            op,
+            operand: Box::new(operand),
+            span,
+        },
+    })
+}
+
+fn fold_binary(
+    op: BinaryOperator,
+    lhs: ASTNode,
+    rhs: ASTNode,
+    trap_overflow: bool,
+    span: Span,
+) -> Result<ASTNode, SemanticError> {
+    let folded = match (&op, &lhs, &rhs) {
+        // `and` and `or` skip their right side once the left decides
+        (BinaryOperator::And, ASTNode::BoolLiteral(false), _)
+        | (BinaryOperator::Or, ASTNode::BoolLiteral(true), _) => Some(lhs.clone()),
+        (BinaryOperator::And, ASTNode::BoolLiteral(true), _)
+        | (BinaryOperator::Or, ASTNode::BoolLiteral(false), _) => return Ok(rhs),
+        (_, ASTNode::NumberLiteral(_) | ASTNode::LongLiteral(_), _)
+            if matches!(rhs, ASTNode::NumberLiteral(_) | ASTNode::LongLiteral(_)) =>
+        {
+            Some(fold_int(op, &lhs, &rhs, trap_overflow, span)?)
+        }
+        (_, ASTNode::BoolLiteral(a), ASTNode::BoolLiteral(b)) => match op {
+            BinaryOperator::Eq => Some(ASTNode::BoolLiteral(a == b)),
+            BinaryOperator::Ne => Some(ASTNode::BoolLiteral(a != b)),
+            _ => None,
+        },
+        (BinaryOperator::Add, ASTNode::StringLiteral(_), _)
+        | (BinaryOperator::Add, _, ASTNode::StringLiteral(_)) => {
+            match (literal_text(&lhs), literal_text(&rhs)) {
+                (Some(a), Some(b)) => Some(ASTNode::StringLiteral(a + &b)),
+                _ => None,
+            }
+        }
+        // Float arithmetic is left to C; comparisons give the same answer
+        // here
+        _ => match (number(&lhs), number(&rhs)) {
+            (Some(a), Some(b)) => compare(&op, a.partial_cmp(&b)).map(ASTNode::BoolLiteral),
+            _ => None,
+        },
+    };
+    Ok(folded.unwrap_or_else(|| ASTNode::BinaryOp {
+        op,
+        lhs: Box::new(lhs),
+        rhs: Box::new(rhs),
+        span,
+    }))
+}
+
+// Result of an operator on two int or long literals, which the analyzer
+// checked accepts them. The result is a long if either operand is one, and
+// is computed as when the program runs.
+fn fold_int(
+    op: BinaryOperator,
+    lhs: &ASTNode,
+    rhs: &ASTNode,
+    trap_overflow: bool,
+    span: Span,
+) -> Result<ASTNode, SemanticError> {
+    let long = matches!(lhs, ASTNode::LongLiteral(_)) || matches!(rhs, ASTNode::LongLiteral(_));
+    let (a, b) = (integer(lhs), integer(rhs));
+    if matches!(op, BinaryOperator::Div | BinaryOperator::Mod) && b == 0 {
+        return Err(SemanticError {
+            code: codes::CONSTANT_ARITHMETIC,
+            message: format!("division by zero in {} {} {}", a, op, b),
+            span,
+        });
+    }
+    if let Some(ordering) = compare(&op, Some(a.cmp(&b))) {
+        return Ok(ASTNode::BoolLiteral(ordering));
+    }
+    let (result, overflow) = integer_arithmetic(op, a, b, long);
+    if overflow && trap_overflow {
+        return Err(overflow_error(&format!("{} {} {}", a, op, b), long, span));
+    }
+    Ok(if long {
+        ASTNode::LongLiteral(result)
+    } else {
+        ASTNode::NumberLiteral(result as i32)
+    })
+}
+
+// Value of an int or long literal
+fn integer(node: &ASTNode) -> i64 {
+    match node {
+        ASTNode::NumberLiteral(num) => i64::from(*num),
+        ASTNode::LongLiteral(num) => *num,
+        _ => unreachable!("not an int literal: {:?}", node),
+    }
+}
+
+// Result of a comparison operator given how its operands are ordered. NaN
+// orders with nothing, so only `!=` holds for it.
+fn compare(op: &BinaryOperator, ordering: Option<std::cmp::Ordering>) -> Option<bool> {
+    use std::cmp::Ordering::{Equal, Greater, Less};
+    Some(match op {
+        BinaryOperator::Lt => ordering == Some(Less),
+        BinaryOperator::Gt => ordering == Some(Greater),
+        BinaryOperator::Le => matches!(ordering, Some(Less | Equal)),
+        BinaryOperator::Ge => matches!(ordering, Some(Greater | Equal)),
+        BinaryOperator::Eq => ordering == Some(Equal),
+        BinaryOperator::Ne => ordering != Some(Equal),
+        _ => return None,
+    })
+}
+
+// Value of a numeric literal as a double, as C converts it for comparison
+fn number(node: &ASTNode) -> Option<f64> {
+    match node {
+        ASTNode::NumberLiteral(num) => Some(*num as f64),
+        ASTNode::LongLiteral(num) => Some(*num as f64),
+        ASTNode::FloatLiteral(num) => Some(*num),
+        _ => None,
+    }
+}
+
+// Text a literal adds to a string, as the C runtime formats it. Floats are
+// left out: `%g` rounding is not worth reproducing here.
+fn literal_text(node: &ASTNode) -> Option<String> {
+    match node {
+        ASTNode::StringLiteral(s) => Some(s.clone()),
+        ASTNode::NumberLiteral(num) => Some(num.to_string()),
+        ASTNode::LongLiteral(num) => Some(num.to_string()),
+        ASTNode::BoolLiteral(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+// Remove the statements of a function body, or of the top level, that can
+// never run, along with the variables nothing reads. A variable goes only
+// when no value stored in it calls a function, since the call might have
+// effects of its own.
+fn eliminate_dead_code(nodes: Vec<ASTNode>, warnings: &mut Vec<Warning>) -> Vec<ASTNode> {
+    let mut uses = Uses::default();
+    uses.visit_block(&nodes);
+    let mut declared = Declared::default();
+    declared.visit_block(&nodes);
+    let mut unused = declared.names;
+    unused.retain(|name| !uses.reads.contains(name) && !uses.kept.contains(name));
+    prune(nodes, &unused, warnings)
+}
+
+// Records the variables a program reads, and those it stores the result of
+// a call in or reads input into. Function bodies are left to their own
+// pass.
+#[derive(Default)]
+struct Uses {
+    reads: HashSet<String>,
+    kept: HashSet<String>,
+}
+
+impl Visitor for Uses {
+    fn visit_statement(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Function { .. } => return,
+            ASTNode::VariableDeclaration {
+                name,
+                value: Some(value),
+                ..
+            }
+            | ASTNode::Assignment { name, value, .. }
+            | ASTNode::FieldAssignment { name, value, .. }
+                if has_call(value) =>
+            {
+                self.kept.insert(name.clone());
+            }
+            ASTNode::IndexAssignment {
+                name, index, value, ..
+            } if has_call(index) || has_call(value) => {
+                self.kept.insert(name.clone());
+            }
+            ASTNode::Input { name, .. } => {
+                self.kept.insert(name.clone());
+            }
+            // The loop itself reads its variable
+            ASTNode::For { var, .. } => {
+                self.reads.insert(var.clone());
+            }
+            ASTNode::CRaw { code, .. } => self.reads.extend(raw_c_names(code).map(str::to_string)),
+            _ => {}
+        }
+        walk_statement(self, node);
+    }
+
+    fn visit_expression(&mut self, node: &ASTNode) {
+        if let ASTNode::Identifier(name, _)
+        | ASTNode::FieldAccess { name, .. }
+        | ASTNode::Index { name, .. } = node
+        {
+            self.reads.insert(name.clone());
+        }
+        walk_expression(self, node);
+    }
+}
+
+// Records the variables declared in a program, outside function bodies
+#[derive(Default)]
+struct Declared {
+    names: HashSet<String>,
+}
+
+impl Visitor for Declared {
+    fn visit_statement(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::VariableDeclaration { name, .. } => {
+                self.names.insert(name.clone());
+            }
+            ASTNode::Function { .. } => {}
+            node => walk_statement(self, node),
+        }
+    }
+
+    // Expressions declare nothing
+    fn visit_expression(&mut self, _: &ASTNode) {}
+}
+
+// Finds whether evaluating an expression calls a function
+struct CallFinder {
+    found: bool,
+}
+
+impl Visitor for CallFinder {
+    fn visit_expression(&mut self, node: &ASTNode) {
+        if matches!(node, ASTNode::Call { .. }) {
+            self.found = true;
+        } else {
+            walk_expression(self, node);
+        }
+    }
+}
+
+fn has_call(node: &ASTNode) -> bool {
+    let mut finder = CallFinder { found: false };
+    finder.visit_expression(node);
+    finder.found
+}
+
+// The jump a statement always ends with, so nothing after it runs: its
+// keyword and position. An `if` or `match` jumps when all its branches do.
+fn jump(node: &ASTNode) -> Option<(&'static str, Span)> {
+    match node {
+        ASTNode::Break(span) => Some(("break", *span)),
+        ASTNode::Continue(span) => Some(("continue", *span)),
+        ASTNode::Give { span, .. } => Some(("give", *span)),
+        ASTNode::Exit { span, .. } => Some(("exit", *span)),
+        ASTNode::Block(nodes) => nodes.last().and_then(jump),
+        ASTNode::If {
+            then_block,
+            else_block: Some(else_block),
+            span,
+            ..
+        } if always_jumps(then_block) && always_jumps(else_block) => Some(("if", *span)),
+        ASTNode::Match {
+            arms,
+            else_block: Some(else_block),
+            span,
+            ..
+        } if arms.iter().all(|arm| always_jumps(&arm.body)) && always_jumps(else_block) => {
+            Some(("match", *span))
+        }
+        _ => None,
+    }
+}
+
+fn always_jumps(nodes: &[ASTNode]) -> bool {
+    nodes.last().and_then(jump).is_some()
+}
+
+// Drop the declarations of and stores to `unused` variables, and the
+// statements following a jump. Definitions are kept wherever they are, being
+// visible to the whole program.
+fn prune(
+    nodes: Vec<ASTNode>,
+    unused: &HashSet<String>,
+    warnings: &mut Vec<Warning>,
+) -> Vec<ASTNode> {
+    let mut kept = Vec::with_capacity(nodes.len());
+    let mut jumped: Option<(&str, Span)> = None;
+    let mut removed = false;
+    for node in nodes {
+        let definition = matches!(
+            node,
+            ASTNode::Function { .. }
+                | ASTNode::ExternFunction { .. }
+                | ASTNode::StructDef { .. }
+                | ASTNode::EnumDef { .. }
+                | ASTNode::CInclude { .. }
+                | ASTNode::Link { .. }
+        );
+        if jumped.is_some() && !definition {
+            removed = true;
+            continue;
+        }
+        let node = match node {
+            ASTNode::VariableDeclaration { ref name, .. }
+            | ASTNode::Assignment { ref name, .. }
+            | ASTNode::FieldAssignment { ref name, .. }
+            | ASTNode::IndexAssignment { ref name, .. }
+                if unused.contains(name) =>
+            {
+                continue;
+            }
+            ASTNode::If {
+                cond,
+                then_block,
+                else_block,
+                span,
+            } => ASTNode::If {
+                cond,
+                then_block: prune(then_block, unused, warnings),
+                else_block: else_block.map(|nodes| prune(nodes, unused, warnings)),
+                span,
+            },
+            ASTNode::Block(nodes) => ASTNode::Block(prune(nodes, unused, warnings)),
+            ASTNode::For {
+                var,
+                start,
+                end,
+                body,
+                span,
+            } => ASTNode::For {
+                var,
+                start,
+                end,
+                body: prune(body, unused, warnings),
+                span,
+            },
+            ASTNode::Match {
+                value,
+                arms,
+                else_block,
+                span,
+            } => ASTNode::Match {
+                value,
+                arms: arms
+                    .into_iter()
+                    .map(|arm| MatchArm {
+                        body: prune(arm.body, unused, warnings),
+                        ..arm
+                    })
+                    .collect(),
+                else_block: else_block.map(|nodes| prune(nodes, unused, warnings)),
+                span,
+            },
+            // A function body has variables of its own
+            ASTNode::Function {
+                name,
+                params,
+                body,
+                span,
+            } => ASTNode::Function {
+                name,
+                params,
+                body: eliminate_dead_code(body, warnings),
+                span,
+            },
+            node => node,
+        };
+        if jumped.is_none() {
+            jumped = jump(&node);
+        }
+        kept.push(node);
+    }
+    if let (Some((keyword, span)), true) = (jumped, removed) {
+        warnings.push(Warning {
+            code: codes::UNREACHABLE_CODE,
+            message: format!("unreachable code after '{}' is removed", keyword),
+            span,
+        });
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::to_source;
+    use crate::lexer::FileId;
+    use crate::parser::parse_source;
+    use crate::semantic::analyze;
+
+    // `input` optimized and written back as BP
+    fn optimized(input: &str) -> String {
+        let ast = parse_source(input, FileId::MAIN).unwrap();
+        analyze(&ast).unwrap();
+        to_source(&optimize(ast, false).unwrap().0)
+    }
+
+    #[test]
+    fn constants_are_folded() {
+        assert_eq!(
+            optimized("m x = 2 * 3 + 4;\nshow x;"),
+            "m x = 10;\nshow x;\n"
+        );
+        assert_eq!(
+            optimized("show 7 / 2, 7 % 2, -(3 - 5), 1 < 2 and not false;"),
+            "show 3, 1, 2, true;\n"
+        );
+        assert_eq!(optimized("show \"a\" + \"b\";"), "show \"ab\";\n");
+    }
+
+    #[test]
+    fn constant_conditions_keep_the_branch_that_runs() {
+        assert_eq!(
+            optimized("if 1 < 2 { show 1; } else { show 2; }"),
+            "{\n    show 1;\n}\n"
+        );
+        assert_eq!(optimized("if false { show 1; }\nshow 2;"), "show 2;\n");
+    }
+
+    #[test]
+    fn unreachable_code_is_removed_with_a_warning() {
+        let ast = parse_source("exit 0;\nshow 1;", FileId::MAIN).unwrap();
+        analyze(&ast).unwrap();
+        let (ast, warnings) = optimize(ast, false).unwrap();
+        assert_eq!(to_source(&ast), "exit 0;\n");
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, codes::UNREACHABLE_CODE);
+        assert_eq!(
+            warnings[0].message,
+            "unreachable code after 'exit' is removed"
+        );
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        let ast = parse_source("show 1 / 0;", FileId::MAIN).unwrap();
+        let err = optimize(ast, false).unwrap_err();
+        assert_eq!(err.code, codes::CONSTANT_ARITHMETIC);
+    }
+}
@@ -0,0 +1,1622 @@
+//! Builds the syntax tree from the token stream
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::ast::{ASTNode, BinaryOperator, FieldDef, MatchArm, Parameter, UnaryOperator};
+use crate::codes;
+use crate::error::CompileError;
+use crate::lexer::{FileId, LexError, Lexer, Span, SpannedToken, Token, KEYWORDS, OUT_OF_RANGE};
+
+// Error produced when the token stream does not form a valid BP program
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    // Registered in `codes`
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+impl ParseError {
+    fn new(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        ParseError {
+            code,
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Binary operator precedence levels, loosest first. Every level is
+// left-associative, so `a == b == c` parses as `(a == b) == c` like in C.
+// `and`/`or` become C's `&&`/`||` and keep their short-circuit evaluation.
+const PRECEDENCE: &[&[BinaryOperator]] = &[
+    &[BinaryOperator::Or],
+    &[BinaryOperator::And],
+    &[BinaryOperator::Eq, BinaryOperator::Ne],
+    &[
+        BinaryOperator::Lt,
+        BinaryOperator::Gt,
+        BinaryOperator::Le,
+        BinaryOperator::Ge,
+    ],
+    &[BinaryOperator::Add, BinaryOperator::Sub],
+    &[
+        BinaryOperator::Mul,
+        BinaryOperator::Div,
+        BinaryOperator::Mod,
+    ],
+];
+
+// How deeply expressions and blocks may nest. Deeper input is refused
+// rather than running out of stack, here or in the passes after parsing,
+// which all recurse over the tree.
+const MAX_NESTING: usize = 64;
+
+// Split a `show` string into literal text and the variables referenced as
+// `{name}` or `{name.field}`. `{{` and `}}` stand for literal braces. Interpolated variables
+// carry the span of the whole string.
+fn split_interpolation(s: &str, span: Span) -> Result<Vec<ASTNode>, ParseError> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(ch) => name.push(ch),
+                        None => return Err(ParseError::new(
+                            codes::BAD_INTERPOLATION,
+                            "unclosed '{' in interpolated string (write '{{' for a literal brace)",
+                            span,
+                        )),
+                    }
+                }
+                let name = name.trim();
+                let is_identifier = |name: &str| {
+                    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+                        && !KEYWORDS.contains(&name)
+                };
+                // A variable, or a field of a struct variable
+                let value = match name.split_once('.') {
+                    None if is_identifier(name) => ASTNode::Identifier(name.to_string(), span),
+                    Some((var, field)) if is_identifier(var) && is_identifier(field) => {
+                        ASTNode::FieldAccess {
+                            name: var.to_string(),
+                            field: field.to_string(),
+                            span,
+                        }
+                    }
+                    _ => {
+                        return Err(ParseError::new(
+                            codes::BAD_INTERPOLATION,
+                            format!("expected a variable name inside '{{}}', found '{}'", name),
+                            span,
+                        ))
+                    }
+                };
+                if !text.is_empty() {
+                    parts.push(ASTNode::StringLiteral(std::mem::take(&mut text)));
+                }
+                parts.push(value);
+            }
+            _ => text.push(ch),
+        }
+    }
+
+    if !text.is_empty() || parts.is_empty() {
+        parts.push(ASTNode::StringLiteral(text));
+    }
+    Ok(parts)
+}
+
+// Recursive-descent parser pulling tokens from a stream as it goes. Each
+// `parse_*` method consumes the construct it is named after and leaves the
+// stream just past it.
+struct Parser<'src, I> {
+    tokens: I,
+    // Tokens read from the stream but not consumed yet: the current one and
+    // at most one after it
+    lookahead: VecDeque<SpannedToken<'src>>,
+    // The last token consumed, which a missing `;` is reported just past
+    previous: Option<Span>,
+    // Where the stream ends, for the `EndOfFile` read past it
+    end: Span,
+    // The error that cut the stream short, which reads as ending there
+    lex_error: Option<LexError>,
+    // Number of loops enclosing the statement being parsed
+    loop_depth: usize,
+    // Number of expressions and blocks enclosing the one being parsed
+    nesting: usize,
+    // Set while parsing an expression followed by a block, where `name {`
+    // starts the block rather than a struct literal
+    no_struct_literal: bool,
+    // Errors recorded so far; parsing resumes after each one
+    errors: Vec<ParseError>,
+}
+
+impl<'src, I> Parser<'src, I>
+where
+    I: Iterator<Item = Result<SpannedToken<'src>, LexError>>,
+{
+    fn new(tokens: I) -> Self {
+        Parser {
+            tokens,
+            lookahead: VecDeque::with_capacity(2),
+            previous: None,
+            end: Span {
+                line: 1,
+                col: 1,
+                len: 0,
+                file: FileId::MAIN,
+            },
+            lex_error: None,
+            loop_depth: 0,
+            nesting: 0,
+            no_struct_literal: false,
+            errors: Vec::new(),
+        }
+    }
+
+    // Token `offset` positions ahead, reading it from the stream if needed.
+    // Past the end of the stream, or a lexical error, every token is
+    // `EndOfFile`.
+    fn peek_at(&mut self, offset: usize) -> &SpannedToken<'src> {
+        while self.lookahead.len() <= offset {
+            let token = match self.tokens.next() {
+                Some(Ok(token)) if self.lex_error.is_none() => token,
+                Some(Err(err)) if self.lex_error.is_none() => {
+                    self.lex_error = Some(err);
+                    continue;
+                }
+                _ => SpannedToken {
+                    token: Token::EndOfFile,
+                    span: self.end,
+                },
+            };
+            self.end = match token.token {
+                Token::EndOfFile => token.span,
+                _ => Span {
+                    col: token.span.col + token.span.len,
+                    len: 0,
+                    ..token.span
+                },
+            };
+            self.lookahead.push_back(token);
+        }
+        &self.lookahead[offset]
+    }
+
+    fn peek(&mut self) -> &SpannedToken<'src> {
+        self.peek_at(0)
+    }
+
+    // Consume the current token and give it. `EndOfFile` stays current
+    // once reached.
+    fn advance(&mut self) -> SpannedToken<'src> {
+        let current = match self.peek().token {
+            Token::EndOfFile => self.peek().clone(),
+            _ => self.lookahead.pop_front().expect("peek read a token"),
+        };
+        self.previous = Some(current.span);
+        current
+    }
+
+    fn check(&mut self, token: &Token<'_>) -> bool {
+        &self.peek().token == token
+    }
+
+    fn check_keyword(&mut self, keyword: &str) -> bool {
+        matches!(&self.peek().token, Token::Keyword(k) if *k == keyword)
+    }
+
+    // Consume `token`, or fail with `message` at the current token
+    fn expect(
+        &mut self,
+        token: Token<'_>,
+        message: &str,
+    ) -> Result<SpannedToken<'src>, ParseError> {
+        if self.check(&token) {
+            Ok(self.advance())
+        } else {
+            Err(ParseError::new(
+                codes::EXPECTED_SYNTAX,
+                message,
+                self.peek().span,
+            ))
+        }
+    }
+
+    // Consume an identifier, or fail with `message` at the current token
+    fn expect_identifier(&mut self, message: &str) -> Result<(String, Span), ParseError> {
+        let current = self.peek().clone();
+        match &current.token {
+            Token::Identifier(name) => {
+                self.advance();
+                Ok((name.to_string(), current.span))
+            }
+            _ => Err(ParseError::new(
+                codes::EXPECTED_SYNTAX,
+                message,
+                current.span,
+            )),
+        }
+    }
+
+    // Binary operators follow `PRECEDENCE`; the prefix operators `-` and
+    // `not` bind tighter than any binary operator, and parentheses group a
+    // sub-expression.
+    fn parse_expression(&mut self) -> Result<ASTNode, ParseError> {
+        self.nested(|parser| parser.parse_binary(0))
+    }
+
+    // Run `parse` one level of nesting deeper, refusing to go past
+    // `MAX_NESTING`
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.nesting == MAX_NESTING {
+            return Err(ParseError::new(
+                codes::NESTED_TOO_DEEPLY,
+                format!("nested more than {} deep", MAX_NESTING),
+                self.peek().span,
+            ));
+        }
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    // Parse a chain of operators from precedence `level` and tighter
+    fn parse_binary(&mut self, level: usize) -> Result<ASTNode, ParseError> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.parse_unary();
+        };
+
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(op) =
+            BinaryOperator::from_token(&self.peek().token).filter(|op| operators.contains(op))
+        {
+            let span = self.advance().span;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = ASTNode::BinaryOp {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                span,
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<ASTNode, ParseError> {
+        let op = match &self.peek().token {
+            Token::Minus => UnaryOperator::Neg,
+            Token::Keyword(k) if *k == "not" => UnaryOperator::Not,
+            _ => return self.parse_primary(),
+        };
+        // The smallest int and long are written as negated literals whose
+        // digits alone are too large for the type
+        if op == UnaryOperator::Neg {
+            let smallest = match self.peek_at(1).token {
+                Token::Number(num) if num == 1 << 31 => Some(ASTNode::NumberLiteral(i32::MIN)),
+                Token::Number(num) if num == 1 << 63 => Some(ASTNode::LongLiteral(i64::MIN)),
+                _ => None,
+            };
+            if let Some(smallest) = smallest {
+                self.advance();
+                self.advance();
+                return Ok(smallest);
+            }
+        }
+        let span = self.advance().span;
+        let operand = self.nested(Self::parse_unary)?;
+        Ok(ASTNode::UnaryOp {
+            op,
+            operand: Box::new(operand),
+            span,
+        })
+    }
+
+    // Parse comma-separated call arguments up to and including the closing
+    // `)`. `open` is the span reported if the parenthesis is never closed.
+    fn parse_arguments(&mut self, open: Span) -> Result<Vec<ASTNode>, ParseError> {
+        let mut args = Vec::new();
+        if !self.check(&Token::RParen) {
+            loop {
+                args.push(self.parse_expression()?);
+                if !self.check(&Token::Comma) {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        if !self.check(&Token::RParen) {
+            return Err(ParseError::new(
+                codes::UNBALANCED_DELIMITER,
+                "unmatched '(' in call",
+                open,
+            ));
+        }
+        self.advance();
+        Ok(args)
+    }
+
+    // Parse `[index]` after an array name. `name` is the span reported if
+    // the bracket is never closed.
+    fn parse_index(&mut self, name: Span) -> Result<ASTNode, ParseError> {
+        self.advance(); // `[`
+        let index = self.parse_expression()?;
+        if !self.check(&Token::RBracket) {
+            return Err(ParseError::new(
+                codes::UNBALANCED_DELIMITER,
+                "unmatched '[' in index",
+                name,
+            ));
+        }
+        self.advance();
+        Ok(index)
+    }
+
+    fn parse_primary(&mut self) -> Result<ASTNode, ParseError> {
+        let current = self.peek().clone();
+        let node = match &current.token {
+            // A literal too large for an int is a long
+            Token::Number(num) => match (i32::try_from(*num), i64::try_from(*num)) {
+                (Ok(num), _) => ASTNode::NumberLiteral(num),
+                (_, Ok(num)) => ASTNode::LongLiteral(num),
+                _ => {
+                    return Err(ParseError::new(
+                        codes::INTEGER_OUT_OF_RANGE,
+                        OUT_OF_RANGE,
+                        current.span,
+                    ))
+                }
+            },
+            Token::Float(num) => ASTNode::FloatLiteral(*num),
+            Token::Keyword(k) if *k == "true" || *k == "false" => {
+                ASTNode::BoolLiteral(*k == "true")
+            }
+            Token::StringLiteral(s) => ASTNode::StringLiteral(s.to_string()),
+            Token::Identifier(name) if self.peek_at(1).token == Token::LParen => {
+                self.advance(); // name
+                self.advance(); // `(`
+                let args = self.parse_arguments(current.span)?;
+                return Ok(ASTNode::Call {
+                    name: name.to_string(),
+                    args,
+                    span: current.span,
+                });
+            }
+            Token::Identifier(name) if self.peek_at(1).token == Token::LBracket => {
+                self.advance(); // name
+                let index = self.parse_index(current.span)?;
+                return Ok(ASTNode::Index {
+                    name: name.to_string(),
+                    index: Box::new(index),
+                    span: current.span,
+                });
+            }
+            Token::Identifier(name) if self.peek_at(1).token == Token::Dot => {
+                self.advance(); // name
+                self.advance(); // `.`
+                let (field, _) = self.expect_identifier("expected field name after '.'")?;
+                return Ok(ASTNode::FieldAccess {
+                    name: name.to_string(),
+                    field,
+                    span: current.span,
+                });
+            }
+            Token::Identifier(name)
+                if self.peek_at(1).token == Token::LBrace && !self.no_struct_literal =>
+            {
+                self.advance(); // name
+                self.advance(); // `{`
+                return self.parse_struct_literal(name, current.span);
+            }
+            Token::Identifier(name) => ASTNode::Identifier(name.to_string(), current.span),
+            Token::LBracket => {
+                self.advance();
+                let mut elements = Vec::new();
+                if !self.check(&Token::RBracket) {
+                    loop {
+                        elements.push(self.parse_expression()?);
+                        if !self.check(&Token::Comma) {
+                            break;
+                        }
+                        self.advance();
+                    }
+                }
+                if !self.check(&Token::RBracket) {
+                    return Err(ParseError::new(
+                        codes::UNBALANCED_DELIMITER,
+                        "unmatched '[' in array literal",
+                        current.span,
+                    ));
+                }
+                self.advance();
+                return Ok(ASTNode::ArrayLiteral(elements, current.span));
+            }
+            Token::LParen => {
+                self.advance();
+                let inner = self.parse_expression()?;
+                if !self.check(&Token::RParen) {
+                    return Err(ParseError::new(
+                        codes::UNBALANCED_DELIMITER,
+                        "unmatched '('",
+                        current.span,
+                    ));
+                }
+                self.advance();
+                return Ok(inner);
+            }
+            _ => {
+                return Err(ParseError::new(
+                    codes::EXPECTED_SYNTAX,
+                    "expected an expression",
+                    current.span,
+                ))
+            }
+        };
+        self.advance();
+        Ok(node)
+    }
+
+    // Parse `field = value, ...` up to and including the closing `}` of a
+    // struct literal. A trailing comma is allowed.
+    fn parse_struct_literal(&mut self, name: &str, span: Span) -> Result<ASTNode, ParseError> {
+        let mut fields = Vec::new();
+        while !self.check(&Token::RBrace) {
+            if self.check(&Token::EndOfFile) {
+                return Err(ParseError::new(
+                    codes::UNBALANCED_DELIMITER,
+                    "unmatched '{' in struct literal",
+                    span,
+                ));
+            }
+            let (field, _) = self.expect_identifier("expected field name in struct literal")?;
+            self.expect(
+                Token::Equals,
+                &format!("expected '=' after field '{}'", field),
+            )?;
+            fields.push((field, self.parse_expression()?));
+            if !self.check(&Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        if !self.check(&Token::RBrace) {
+            return Err(ParseError::new(
+                codes::EXPECTED_SYNTAX,
+                "expected ',' or '}' after struct field",
+                self.peek().span,
+            ));
+        }
+        self.advance();
+        Ok(ASTNode::StructInit {
+            name: name.to_string(),
+            fields,
+            span,
+        })
+    }
+
+    // Parse an expression directly followed by a block, as in `if` and
+    // `for` headers
+    fn parse_header_expression(&mut self) -> Result<ASTNode, ParseError> {
+        let outer = std::mem::replace(&mut self.no_struct_literal, true);
+        let expr = self.parse_expression();
+        self.no_struct_literal = outer;
+        expr
+    }
+
+    // Parse one statement. Statements other than `if`, `for`, `fn`, `struct`,
+    // `enum`, `match` and blocks end with a `;`, including the last one in the file.
+    fn parse_statement(&mut self) -> Result<ASTNode, ParseError> {
+        let current = self.peek().clone();
+        let (statement, what) = match &current.token {
+            Token::Identifier(name) if self.peek_at(1).token == Token::Equals => {
+                self.advance(); // name
+                self.advance(); // `=`
+                let value = self.parse_expression()?;
+                let statement = ASTNode::Assignment {
+                    name: name.to_string(),
+                    value: Box::new(value),
+                    span: current.span,
+                };
+                (statement, format!("assignment to '{}'", name))
+            }
+            Token::Identifier(name) if self.peek_at(1).token == Token::LBracket => {
+                self.advance(); // name
+                let index = self.parse_index(current.span)?;
+                self.expect(
+                    Token::Equals,
+                    &format!("expected '=' after index of '{}'", name),
+                )?;
+                let value = self.parse_expression()?;
+                let statement = ASTNode::IndexAssignment {
+                    name: name.to_string(),
+                    index: Box::new(index),
+                    value: Box::new(value),
+                    span: current.span,
+                };
+                (statement, format!("assignment to an element of '{}'", name))
+            }
+            Token::Identifier(name) if self.peek_at(1).token == Token::Dot => {
+                self.advance(); // name
+                self.advance(); // `.`
+                let (field, _) = self.expect_identifier("expected field name after '.'")?;
+                self.expect(
+                    Token::Equals,
+                    &format!("expected '=' after field '{}.{}'", name, field),
+                )?;
+                let value = self.parse_expression()?;
+                let what = format!("assignment to '{}.{}'", name, field);
+                let statement = ASTNode::FieldAssignment {
+                    name: name.to_string(),
+                    field,
+                    value: Box::new(value),
+                    span: current.span,
+                };
+                (statement, what)
+            }
+            // A call used as a statement, for its side effects
+            Token::Identifier(name) if self.peek_at(1).token == Token::LParen => {
+                (self.parse_expression()?, format!("call to '{}'", name))
+            }
+            Token::Keyword(k) => match *k {
+                "if" => return self.parse_if(),
+                "for" => return self.parse_for(),
+                "fn" => return self.parse_function(),
+                "struct" => return self.parse_struct(),
+                "enum" => return self.parse_enum(),
+                "match" => return self.parse_match(),
+                "m" | "c" => {
+                    let statement = self.parse_declaration()?;
+                    let ASTNode::VariableDeclaration { name, .. } = &statement else {
+                        unreachable!("parse_declaration returns a declaration");
+                    };
+                    let what = format!("declaration of '{}'", name);
+                    (statement, what)
+                }
+                "show" => (self.parse_show()?, "'show' statement".to_string()),
+                "extern" => (self.parse_extern()?, "'extern' declaration".to_string()),
+                "give" => {
+                    self.advance();
+                    let value = self.parse_expression()?;
+                    let statement = ASTNode::Give {
+                        value: Box::new(value),
+                        span: current.span,
+                    };
+                    (statement, "'give' statement".to_string())
+                }
+                "exit" => {
+                    self.advance();
+                    let value = self.parse_expression()?;
+                    let statement = ASTNode::Exit {
+                        value: Box::new(value),
+                        span: current.span,
+                    };
+                    (statement, "'exit' statement".to_string())
+                }
+                "assert" => {
+                    self.advance();
+                    let cond = self.parse_expression()?;
+                    let mut message = None;
+                    if self.check(&Token::Comma) {
+                        self.advance();
+                        let current = self.peek().clone();
+                        let Token::StringLiteral(text) = &current.token else {
+                            return Err(ParseError::new(
+                                codes::EXPECTED_SYNTAX,
+                                "expected a string literal message after ',' in 'assert'",
+                                current.span,
+                            ));
+                        };
+                        self.advance();
+                        message = Some(text.to_string());
+                    }
+                    let statement = ASTNode::Assert {
+                        cond: Box::new(cond),
+                        message,
+                        span: current.span,
+                    };
+                    (statement, "'assert' statement".to_string())
+                }
+                "input" => {
+                    self.advance();
+                    let (name, _) =
+                        self.expect_identifier("expected variable name after 'input'")?;
+                    let statement = ASTNode::Input {
+                        name,
+                        span: current.span,
+                    };
+                    (statement, "'input' statement".to_string())
+                }
+                // The C is kept as written, for the C target to copy out.
+                // Library names go to the C compiler.
+                "craw" | "cinclude" | "link" | "use" => {
+                    self.advance();
+                    let payload = self.peek().clone();
+                    let Token::StringLiteral(text) = &payload.token else {
+                        return Err(ParseError::new(
+                            codes::EXPECTED_SYNTAX,
+                            format!("expected a string literal after '{}'", k),
+                            payload.span,
+                        ));
+                    };
+                    self.advance();
+                    let statement = match *k {
+                        "craw" => ASTNode::CRaw {
+                            code: text.to_string(),
+                            span: current.span,
+                        },
+                        "cinclude" => ASTNode::CInclude {
+                            header: text.to_string(),
+                            span: current.span,
+                        },
+                        "link" => ASTNode::Link {
+                            library: text.to_string(),
+                            span: current.span,
+                        },
+                        _ => ASTNode::Use {
+                            path: text.to_string(),
+                            span: current.span,
+                        },
+                    };
+                    (statement, format!("'{}' statement", k))
+                }
+                "break" | "continue" => {
+                    // Checked here so the user gets a positioned error instead
+                    // of the C compiler's complaint about the generated code
+                    if self.loop_depth == 0 {
+                        return Err(ParseError::new(
+                            codes::OUTSIDE_LOOP,
+                            format!("'{}' outside of a loop", k),
+                            current.span,
+                        ));
+                    }
+                    self.advance();
+                    let statement = if *k == "break" {
+                        ASTNode::Break(current.span)
+                    } else {
+                        ASTNode::Continue(current.span)
+                    };
+                    (statement, format!("'{}'", k))
+                }
+                _ => {
+                    return Err(ParseError::new(
+                        codes::UNEXPECTED_TOKEN,
+                        format!("unexpected keyword '{}'", k),
+                        current.span,
+                    ))
+                }
+            },
+            Token::LBrace => return Ok(ASTNode::Block(self.parse_block()?)),
+            Token::RParen => {
+                return Err(ParseError::new(
+                    codes::UNBALANCED_DELIMITER,
+                    "unmatched ')'",
+                    current.span,
+                ))
+            }
+            token => {
+                return Err(ParseError::new(
+                    codes::UNEXPECTED_TOKEN,
+                    format!("unexpected token {}", token),
+                    current.span,
+                ))
+            }
+        };
+
+        self.expect_semicolon(&what);
+        Ok(statement)
+    }
+
+    // Consume the `;` ending a statement. A missing one is recorded without
+    // failing the statement, since everything before it parsed fine. The
+    // error points just past the statement's last token, and the tokens up
+    // to the next `;` are skipped so they are not taken for a statement.
+    fn expect_semicolon(&mut self, what: &str) {
+        if self.check(&Token::Semicolon) {
+            self.advance();
+            return;
+        }
+        let last = self.previous.unwrap_or(self.end);
+        self.errors.push(ParseError::new(
+            codes::EXPECTED_SYNTAX,
+            format!("expected ';' after {}", what),
+            Span {
+                line: last.line,
+                col: last.col + last.len,
+                len: 1,
+                file: last.file,
+            },
+        ));
+        self.synchronize();
+    }
+
+    // An optional `: type` after the name `what`
+    fn parse_annotation(&mut self, what: &str) -> Result<Option<String>, ParseError> {
+        if !self.check(&Token::Colon) {
+            return Ok(None);
+        }
+        self.advance();
+        let (ty, _) = self.expect_identifier(&format!("expected a type after ':' in {}", what))?;
+        Ok(Some(ty))
+    }
+
+    // `m name = value` or `c name = value`, with an optional `: type` after
+    // the name. An annotated declaration may leave out `= value`.
+    fn parse_declaration(&mut self) -> Result<ASTNode, ParseError> {
+        let Token::Keyword(k) = self.advance().token else {
+            unreachable!("declarations start with a keyword");
+        };
+        let mutable = k == "m";
+        let (name, span) =
+            self.expect_identifier(&format!("expected identifier after keyword '{}'", k))?;
+        let ty = self.parse_annotation(&format!("declaration of '{}'", name))?;
+        let value = if ty.is_some() && !self.check(&Token::Equals) {
+            None
+        } else {
+            let message = if ty.is_some() {
+                format!("expected '=' or ';' after the type of '{}'", name)
+            } else {
+                format!("expected '=' or ':' after identifier '{}'", name)
+            };
+            self.expect(Token::Equals, &message)?;
+            Some(Box::new(self.parse_expression()?))
+        };
+        Ok(ASTNode::VariableDeclaration {
+            name,
+            mutable,
+            ty,
+            value,
+            span,
+        })
+    }
+
+    // One or more comma-separated expressions, printed on one line
+    fn parse_show(&mut self) -> Result<ASTNode, ParseError> {
+        let span = self.peek().span;
+        let mut values = Vec::new();
+        loop {
+            self.advance(); // `show` or `,`
+            let value_span = self.peek().span;
+            match self.parse_expression()? {
+                ASTNode::StringLiteral(s) => values.extend(split_interpolation(&s, value_span)?),
+                value => values.push(value),
+            }
+            if !self.check(&Token::Comma) {
+                break;
+            }
+        }
+        Ok(ASTNode::Show(values, span))
+    }
+
+    fn parse_if(&mut self) -> Result<ASTNode, ParseError> {
+        let span = self.advance().span; // `if`
+        let cond = self.parse_header_expression()?;
+        let then_block = self.parse_block()?;
+        let else_block = if self.check_keyword("else") {
+            self.advance();
+            if self.check_keyword("if") {
+                // `else if` is an `if` nested as the only statement of the
+                // else block
+                Some(vec![self.nested(Self::parse_if)?])
+            } else {
+                Some(self.parse_block()?)
+            }
+        } else {
+            None
+        };
+        Ok(ASTNode::If {
+            cond: Box::new(cond),
+            then_block,
+            else_block,
+            span,
+        })
+    }
+
+    // `for i = start to end { ... }`, counting up with `end` included
+    fn parse_for(&mut self) -> Result<ASTNode, ParseError> {
+        self.advance(); // `for`
+        let (var, span) = self.expect_identifier("expected loop variable after 'for'")?;
+        self.expect(
+            Token::Equals,
+            &format!("expected '=' after loop variable '{}'", var),
+        )?;
+        let start = self.parse_expression()?;
+        if !self.check_keyword("to") {
+            return Err(ParseError::new(
+                codes::EXPECTED_SYNTAX,
+                "expected 'to' after the loop start",
+                self.peek().span,
+            ));
+        }
+        self.advance();
+        let end = self.parse_header_expression()?;
+
+        self.loop_depth += 1;
+        let body = self.parse_block();
+        self.loop_depth -= 1;
+
+        Ok(ASTNode::For {
+            var,
+            start: Box::new(start),
+            end: Box::new(end),
+            body: body?,
+            span,
+        })
+    }
+
+    // `fn name(a, b: str) { ... }`; parameters without a type hold ints
+    fn parse_function(&mut self) -> Result<ASTNode, ParseError> {
+        self.advance(); // `fn`
+        let (name, span) = self.expect_identifier("expected function name after 'fn'")?;
+        let params = self.parse_parameters(&name)?;
+
+        // Loops around the definition do not extend into the body
+        let outer_loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.parse_block();
+        self.loop_depth = outer_loop_depth;
+
+        Ok(ASTNode::Function {
+            name,
+            params,
+            body: body?,
+            span,
+        })
+    }
+
+    // The parenthesized parameter list of function `name`, each parameter
+    // with an optional `: type`
+    fn parse_parameters(&mut self, name: &str) -> Result<Vec<Parameter>, ParseError> {
+        self.expect(
+            Token::LParen,
+            &format!("expected '(' after function name '{}'", name),
+        )?;
+        let mut params = Vec::new();
+        if !self.check(&Token::RParen) {
+            loop {
+                let (param, param_span) = self.expect_identifier("expected parameter name")?;
+                let ty = self.parse_annotation(&format!("parameter '{}'", param))?;
+                params.push(Parameter {
+                    name: param,
+                    ty,
+                    span: param_span,
+                });
+                if !self.check(&Token::Comma) {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        self.expect(Token::RParen, "expected ',' or ')' after parameter")?;
+        Ok(params)
+    }
+
+    // `extern fn name(params);` or `extern fn name(params): type;`, without
+    // the `;`
+    fn parse_extern(&mut self) -> Result<ASTNode, ParseError> {
+        self.advance(); // `extern`
+        self.expect(Token::Keyword("fn"), "expected 'fn' after 'extern'")?;
+        let (name, span) = self.expect_identifier("expected function name after 'extern fn'")?;
+        let params = self.parse_parameters(&name)?;
+        let result = self.parse_annotation(&format!("the result of '{}'", name))?;
+        Ok(ASTNode::ExternFunction {
+            name,
+            params,
+            result,
+            span,
+        })
+    }
+
+    // `struct Name { a; b: float; }`; each field ends with `;` and may name
+    // its type after a `:`
+    fn parse_struct(&mut self) -> Result<ASTNode, ParseError> {
+        self.advance(); // `struct`
+        let (name, span) = self.expect_identifier("expected struct name after 'struct'")?;
+        let open = self.expect(
+            Token::LBrace,
+            &format!("expected '{{' after struct name '{}'", name),
+        )?;
+        let mut fields = Vec::new();
+        while !self.check(&Token::RBrace) {
+            if self.check(&Token::EndOfFile) {
+                return Err(ParseError::new(
+                    codes::UNBALANCED_DELIMITER,
+                    "unclosed '{'",
+                    open.span,
+                ));
+            }
+            let (field, field_span) = self.expect_identifier("expected field name")?;
+            let ty = self.parse_annotation(&format!("field '{}'", field))?;
+            self.expect(
+                Token::Semicolon,
+                &format!("expected ';' after field '{}'", field),
+            )?;
+            fields.push(FieldDef {
+                name: field,
+                ty,
+                span: field_span,
+            });
+        }
+        self.advance();
+        Ok(ASTNode::StructDef { name, fields, span })
+    }
+
+    // `enum Name { A, B, C }`, with an optional trailing comma
+    fn parse_enum(&mut self) -> Result<ASTNode, ParseError> {
+        self.advance(); // `enum`
+        let (name, span) = self.expect_identifier("expected enum name after 'enum'")?;
+        let open = self.expect(
+            Token::LBrace,
+            &format!("expected '{{' after enum name '{}'", name),
+        )?;
+        let mut variants = Vec::new();
+        while !self.check(&Token::RBrace) {
+            if self.check(&Token::EndOfFile) {
+                return Err(ParseError::new(
+                    codes::UNBALANCED_DELIMITER,
+                    "unclosed '{'",
+                    open.span,
+                ));
+            }
+            let (variant, _) = self.expect_identifier("expected variant name")?;
+            variants.push(variant);
+            if !self.check(&Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(Token::RBrace, "expected ',' or '}' after variant")?;
+        Ok(ASTNode::EnumDef {
+            name,
+            variants,
+            span,
+        })
+    }
+
+    // `match value { A { ... } B { ... } else { ... } }`; the `else` arm
+    // comes last
+    fn parse_match(&mut self) -> Result<ASTNode, ParseError> {
+        let span = self.advance().span; // `match`
+        let value = self.parse_header_expression()?;
+        let open = self.expect(Token::LBrace, "expected '{' after the 'match' value")?;
+        let mut arms = Vec::new();
+        let mut else_block = None;
+        while !self.check(&Token::RBrace) {
+            if self.check(&Token::EndOfFile) {
+                return Err(ParseError::new(
+                    codes::UNBALANCED_DELIMITER,
+                    "unclosed '{'",
+                    open.span,
+                ));
+            }
+            if else_block.is_some() {
+                return Err(ParseError::new(
+                    codes::ELSE_ARM_NOT_LAST,
+                    "the 'else' arm must be the last one in a 'match'",
+                    self.peek().span,
+                ));
+            }
+            if self.check_keyword("else") {
+                self.advance();
+                else_block = Some(self.parse_block()?);
+                continue;
+            }
+            let (variant, arm_span) =
+                self.expect_identifier("expected a variant name or 'else' in 'match'")?;
+            let body = self.parse_block()?;
+            arms.push(MatchArm {
+                variant,
+                body,
+                span: arm_span,
+            });
+        }
+        self.advance();
+        Ok(ASTNode::Match {
+            value: Box::new(value),
+            arms,
+            else_block,
+            span,
+        })
+    }
+
+    // Parse statements up to the end of the file, or up to the closing `}`
+    // when `in_block` is set. The closing brace itself is left for the caller.
+    // A statement that fails to parse is recorded in `errors` and skipped.
+    fn parse_statements(&mut self, in_block: bool) -> Vec<ASTNode> {
+        let mut statements = Vec::new();
+
+        loop {
+            let current = self.peek().clone();
+            match &current.token {
+                Token::Semicolon => {
+                    // Skip semicolons, move to the next token
+                    self.advance();
+                }
+                Token::EndOfFile => break,
+                Token::RBrace if in_block => break,
+                Token::RBrace => {
+                    self.errors.push(ParseError::new(
+                        codes::UNBALANCED_DELIMITER,
+                        "unmatched '}'",
+                        current.span,
+                    ));
+                    self.advance();
+                }
+                _ => match self.parse_statement() {
+                    Ok(statement) => statements.push(statement),
+                    Err(err) => {
+                        self.errors.push(err);
+                        self.synchronize();
+                    }
+                },
+            }
+        }
+
+        statements
+    }
+
+    // Panic-mode recovery: skip to just past the next `;`, or past a whole
+    // braced block, so parsing can resume at the following statement. A `}`
+    // closing an enclosing block is left in place.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek().token {
+                Token::EndOfFile => break,
+                Token::Semicolon if depth == 0 => {
+                    self.advance();
+                    break;
+                }
+                Token::LBrace => depth += 1,
+                Token::RBrace if depth == 0 => break,
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.advance();
+                        // An `else` belongs to the `if` being skipped
+                        if !self.check_keyword("else") {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    // Parse a brace-delimited block of statements
+    fn parse_block(&mut self) -> Result<Vec<ASTNode>, ParseError> {
+        self.nested(|parser| {
+            let open = parser.expect(Token::LBrace, "expected '{'")?;
+            let statements = parser.parse_statements(true);
+            if !parser.check(&Token::RBrace) {
+                return Err(ParseError::new(
+                    codes::UNBALANCED_DELIMITER,
+                    "unclosed '{'",
+                    open.span,
+                ));
+            }
+            parser.advance();
+            Ok(statements)
+        })
+    }
+}
+
+// Parse tokens into an AST, reporting every syntax error found. There is
+// no AST at all unless every statement parsed: after an error the parser
+// only goes on to find more errors, and what it recovered is dropped, so no
+// later stage ever sees a partly parsed program.
+pub fn parse(tokens: &[SpannedToken<'_>]) -> Result<Vec<ASTNode>, Vec<ParseError>> {
+    let mut parser = Parser::new(tokens.iter().cloned().map(Ok));
+    let ast = parser.parse_statements(false);
+    if parser.errors.is_empty() {
+        Ok(ast)
+    } else {
+        Err(parser.errors)
+    }
+}
+
+// Parse the source of `file` as it is tokenized, without holding all of
+// its tokens at once. A lexical error is reported alone, as the syntax
+// errors before it may only be the program being cut short.
+pub fn parse_source(input: &str, file: FileId) -> Result<Vec<ASTNode>, CompileError> {
+    let mut parser = Parser::new(Lexer::for_file(input, file));
+    let ast = parser.parse_statements(false);
+    if let Some(err) = parser.lex_error {
+        return Err(CompileError::Lex(err));
+    }
+    if parser.errors.is_empty() {
+        Ok(ast)
+    } else {
+        Err(CompileError::Parse(parser.errors))
+    }
+}
+
+// Parse tokens holding a single expression, optionally followed by `;`, as
+// typed at the REPL
+pub fn parse_expression(tokens: &[SpannedToken<'_>]) -> Result<ASTNode, ParseError> {
+    let mut parser = Parser::new(tokens.iter().cloned().map(Ok));
+    let expr = parser.parse_expression()?;
+    if !parser.errors.is_empty() {
+        return Err(parser.errors.swap_remove(0));
+    }
+    if parser.check(&Token::Semicolon) {
+        parser.advance();
+    }
+    parser.expect(Token::EndOfFile, "expected the end of the expression")?;
+    Ok(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    // Parse `input`, which must not parse, into its errors
+    fn errors(input: &str) -> Vec<ParseError> {
+        parse(&tokenize(input).unwrap()).expect_err("the input should not parse")
+    }
+
+    // `line:col` and message of the first error parsing `input`
+    fn first_error(input: &str) -> (usize, usize, String) {
+        let err = errors(input).swap_remove(0);
+        (err.span.line, err.span.col, err.message)
+    }
+
+    // An expression written back with every operation in parentheses
+    fn grouped(node: &ASTNode) -> String {
+        match node {
+            ASTNode::BinaryOp { op, lhs, rhs, .. } => {
+                format!("({} {} {})", grouped(lhs), op, grouped(rhs))
+            }
+            ASTNode::UnaryOp { op, operand, .. } => format!("({} {})", op, grouped(operand)),
+            ASTNode::NumberLiteral(num) => num.to_string(),
+            ASTNode::Identifier(name, _) => name.clone(),
+            other => panic!("unexpected node {:?}", other),
+        }
+    }
+
+    fn expression(input: &str) -> String {
+        grouped(&parse_expression(&tokenize(input).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(expression("2 + 3 * 4"), "(2 + (3 * 4))");
+        assert_eq!(expression("2 * 3 + 4"), "((2 * 3) + 4)");
+        assert_eq!(expression("w - x / y % z"), "(w - ((x / y) % z))");
+        assert_eq!(expression("(2 + 3) * 4"), "((2 + 3) * 4)");
+    }
+
+    #[test]
+    fn arithmetic_is_left_associative() {
+        assert_eq!(expression("10 - 3 - 2"), "((10 - 3) - 2)");
+        assert_eq!(expression("100 / 10 / 5"), "((100 / 10) / 5)");
+        assert_eq!(expression("7 % 4 * 3"), "((7 % 4) * 3)");
+    }
+
+    #[test]
+    fn comparisons_bind_looser_than_arithmetic() {
+        assert_eq!(expression("1 + 2 < 3 * 4"), "((1 + 2) < (3 * 4))");
+        assert_eq!(expression("x <= y - 1"), "(x <= (y - 1))");
+        assert_eq!(expression("x < y == y > x"), "((x < y) == (y > x))");
+    }
+
+    #[test]
+    fn equality_chains_to_the_left() {
+        assert_eq!(expression("x == y == z"), "((x == y) == z)");
+        assert_eq!(expression("x != y == z"), "((x != y) == z)");
+    }
+
+    #[test]
+    fn declaration_value_is_a_whole_expression() {
+        let ast = parse(&tokenize("m x = 1 + 2 * 3;").unwrap()).unwrap();
+        match &ast[..] {
+            [ASTNode::VariableDeclaration {
+                value: Some(value), ..
+            }] => assert_eq!(grouped(value), "(1 + (2 * 3))"),
+            other => panic!("expected one declaration, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn errors_point_at_their_line_and_column() {
+        assert_eq!(
+            first_error("show 1;\nm x 5;"),
+            (2, 5, "expected '=' or ':' after identifier 'x'".to_string())
+        );
+        assert_eq!(
+            first_error("show 1;\n\nm y = (1 + ;"),
+            (3, 12, "expected an expression".to_string())
+        );
+        assert_eq!(
+            first_error("m a = 1;\n  a = ;"),
+            (2, 7, "expected an expression".to_string())
+        );
+    }
+
+    #[test]
+    fn unclosed_block_points_at_its_brace() {
+        let err = errors("m a = 1;\nif a { show a;\n").swap_remove(0);
+        assert_eq!((err.span.line, err.span.col), (2, 6));
+        assert_eq!(err.code, codes::UNBALANCED_DELIMITER);
+    }
+
+    #[test]
+    fn every_broken_statement_is_reported() {
+        let lines: Vec<usize> = errors("m = 1;\nshow 2;\nm y = ;\nm z = 1 2;\nshow 3 +;\nshow )")
+            .iter()
+            .map(|err| err.span.line)
+            .collect();
+        assert_eq!(lines, [1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn unexpected_tokens_are_shown_as_written() {
+        let messages: Vec<String> = errors("2;\n+ 1;\ne10;")
+            .into_iter()
+            .map(|err| err.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "unexpected token number 2",
+                "unexpected token punct +",
+                "unexpected token identifier e10",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_semicolon_is_one_error() {
+        let found = errors("m y = 1 2;\nshow y;");
+        assert_eq!(found.len(), 1, "{:?}", found);
+        assert_eq!(found[0].message, "expected ';' after declaration of 'y'");
+        assert_eq!((found[0].span.line, found[0].span.col), (1, 8));
+    }
+
+    // The syntax tree of `input`, as `--emit-ast` prints it
+    fn tree(input: &str) -> String {
+        crate::ast::ast_tree(&parse(&tokenize(input).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn declarations() {
+        assert_eq!(
+            tree("m x = 1;\nc y: str = \"a\";\nm z: float;"),
+            "VariableDeclaration m x @1:3\n  Number 1\n\
+             VariableDeclaration c y: str @2:3\n  String \"a\"\n\
+             VariableDeclaration m z: float @3:3\n"
+        );
+    }
+
+    #[test]
+    fn array_and_struct_values() {
+        assert_eq!(
+            tree("m a = [1, 2];\nm p = P { x = 1 };"),
+            "VariableDeclaration m a @1:3\n  Array\n    Number 1\n    Number 2\n\
+             VariableDeclaration m p @2:3\n  StructInit P\n    x =\n      Number 1\n"
+        );
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(
+            tree("x = 3;\narr[0] = 4;\np.x = 5;"),
+            "Assignment x @1:1\n  Number 3\n\
+             IndexAssignment arr @2:1\n  Number 0\n  Number 4\n\
+             FieldAssignment p.x @3:1\n  Number 5\n"
+        );
+    }
+
+    #[test]
+    fn show_with_interpolation() {
+        assert_eq!(
+            tree("show \"x is {x}!\", 1;"),
+            "Show @1:1\n  String \"x is \"\n  Identifier x\n  String \"!\"\n  Number 1\n"
+        );
+    }
+
+    #[test]
+    fn if_else_chain() {
+        assert_eq!(
+            tree("if a { show 1; } else if b { show 2; } else { show 3; }"),
+            "If @1:1\n  Identifier a\n  then\n    Show @1:8\n      Number 1\n  else\n\
+             \x20   If @1:23\n      Identifier b\n      then\n        Show @1:30\n          Number 2\n\
+             \x20     else\n        Show @1:47\n          Number 3\n"
+        );
+    }
+
+    #[test]
+    fn for_loop_with_break_and_continue() {
+        assert_eq!(
+            tree("for i = 1 to n { break; continue; }"),
+            "For i @1:5\n  Number 1\n  Identifier n\n  body\n    Break @1:18\n    Continue @1:25\n"
+        );
+    }
+
+    #[test]
+    fn block() {
+        assert_eq!(
+            tree("{ m z = 1; }"),
+            "Block\n  VariableDeclaration m z @1:5\n    Number 1\n"
+        );
+    }
+
+    #[test]
+    fn function_give_and_call() {
+        assert_eq!(
+            tree("fn add(a, b: int) { give a + b; }\nadd(1, 2);"),
+            "Function add(a, b: int) @1:4\n  Give @1:21\n    BinaryOp +\n      Identifier a\n      Identifier b\n\
+             Call add\n  Number 1\n  Number 2\n"
+        );
+    }
+
+    #[test]
+    fn struct_and_enum_definitions() {
+        assert_eq!(
+            tree("struct P { x; name: str; }\nenum Color { Red, Green }"),
+            "StructDef P @1:8\n  field x\n  field name: str\nEnumDef Color { Red, Green } @2:6\n"
+        );
+    }
+
+    #[test]
+    fn match_with_else() {
+        assert_eq!(
+            tree("match col { Red { show 1; } else { show 2; } }"),
+            "Match @1:1\n  Identifier col\n  Red\n    Show @1:19\n      Number 1\n  else\n    Show @1:36\n      Number 2\n"
+        );
+    }
+
+    #[test]
+    fn input_assert_and_exit() {
+        assert_eq!(
+            tree("input x;\nassert x > 0, \"positive\";\nexit 0;"),
+            "Input x @1:1\n\
+             Assert @2:1\n  BinaryOp >\n    Identifier x\n    Number 0\n  message: \"positive\"\n\
+             Exit @3:1\n  Number 0\n"
+        );
+    }
+
+    #[test]
+    fn c_interop_and_use() {
+        assert_eq!(
+            tree(
+                "use \"lib.bp\";\ncinclude \"ctype.h\";\nlink \"m\";\n\
+                 extern fn isdigit(ch): bool;\ncraw \"int z;\";"
+            ),
+            "Use \"lib.bp\" @1:1\nCInclude \"ctype.h\" @2:1\nLink \"m\" @3:1\n\
+             ExternFunction isdigit(ch): bool @4:11\nCRaw \"int z;\" @5:1\n"
+        );
+    }
+
+    #[test]
+    fn broken_statement_gives_no_tree() {
+        for input in [
+            "m x = ;",
+            "show 1",
+            "if x { show 1;",
+            "fn f( { }",
+            "x = 1 +;",
+        ] {
+            assert!(
+                parse(&tokenize(input).unwrap()).is_err(),
+                "{:?} parsed",
+                input
+            );
+        }
+    }
+
+    // Pieces of BP that lex on their own, to build random programs from
+    const FRAGMENTS: &[&str] = &[
+        "m",
+        "c",
+        "x",
+        "y",
+        "f",
+        "Point",
+        "=",
+        "+",
+        "-",
+        "*",
+        "/",
+        "%",
+        "(",
+        ")",
+        "{",
+        "}",
+        "[",
+        "]",
+        ";",
+        ",",
+        ".",
+        ":",
+        "<",
+        "<=",
+        "==",
+        "!=",
+        ">",
+        "show",
+        "if",
+        "else",
+        "for",
+        "to",
+        "break",
+        "continue",
+        "fn",
+        "give",
+        "true",
+        "false",
+        "and",
+        "or",
+        "not",
+        "exit",
+        "input",
+        "assert",
+        "struct",
+        "enum",
+        "match",
+        "craw",
+        "cinclude",
+        "extern",
+        "link",
+        "use",
+        "int",
+        "str",
+        "0",
+        "1",
+        "2147483648",
+        "1.5",
+        "\"s\"",
+        "\"{x}\"",
+    ];
+
+    // Whole statements, so some random programs parse and others break
+    // only in places
+    const STATEMENTS: &[&str] = &[
+        "m x = 1;",
+        "show x, \"a\";",
+        "x = (x + 1) * 2;",
+        "if x < 2 { show 1; } else { show 2; }",
+        "for i = 1 to 3 { break; }",
+        "fn f(a, b: str) { give a; }",
+        "struct P { x; y: str; }",
+        "enum E { A, B }",
+        "match e { A { } else { } }",
+        "assert x == 1, \"no\";",
+        "{ m y = [1, 2]; y[0] = 3; }",
+    ];
+
+    // Openers that nest, after what puts them where they can start, each
+    // repeated far past `MAX_NESTING`, so some random programs would run
+    // out of stack without the limit
+    const OPENERS: &[(&str, &str)] = &[
+        ("show", "("),
+        ("show", "-"),
+        ("show", "not"),
+        ("show", "["),
+        ("show", "f("),
+        ("show", "x["),
+        ("", "{"),
+        ("", "if x {"),
+        ("if x {}", "else if x {}"),
+    ];
+
+    fn deep_runs() -> Vec<String> {
+        OPENERS
+            .iter()
+            .map(|(start, opener)| format!("{} {}", start, format!("{} ", opener).repeat(200)))
+            .collect()
+    }
+
+    // xorshift64, so every run tries the same programs
+    fn next(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn random_tokens_never_panic_or_parse_with_errors() {
+        let deep = deep_runs();
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..5000 {
+            let len = next(&mut state) % 24;
+            let input: Vec<&str> = (0..len)
+                .map(|_| {
+                    let pick = next(&mut state);
+                    if pick % 64 == 1 {
+                        return deep[(pick / 64 % deep.len() as u64) as usize].as_str();
+                    }
+                    let pool = if pick.is_multiple_of(4) {
+                        FRAGMENTS
+                    } else {
+                        STATEMENTS
+                    };
+                    pool[(pick / 4 % pool.len() as u64) as usize]
+                })
+                .collect();
+            let input = input.join(" ");
+            let tokens = tokenize(&input).unwrap();
+
+            let mut parser = Parser::new(tokens.iter().cloned().map(Ok));
+            parser.parse_statements(false);
+            match parse(&tokens) {
+                Ok(_) => assert!(parser.errors.is_empty(), "{:?} parsed with errors", input),
+                Err(errors) => {
+                    assert!(!errors.is_empty(), "{:?} failed without errors", input);
+                    assert_eq!(errors.len(), parser.errors.len(), "{:?}", input);
+                    for err in errors {
+                        assert!(
+                            tokens.iter().any(|token| token.span.line == err.span.line
+                                && token.span.col <= err.span.col),
+                            "{:?}: {} points past the input",
+                            input,
+                            err
+                        );
+                    }
+                }
+            }
+            assert_eq!(
+                parse_source(&input, FileId::MAIN).is_ok(),
+                parser.errors.is_empty(),
+                "{:?}",
+                input
+            );
+            let _ = parse_expression(&tokens);
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error_not_a_crash() {
+        for run in deep_runs() {
+            let input = format!("show 1;\n{}", run);
+            let found = errors(&input);
+            assert_eq!(found[0].code, codes::NESTED_TOO_DEEPLY, "{:.40}", input);
+            assert_eq!(found[0].span.line, 2, "{:.40}", input);
+            let _ = parse_expression(&tokenize(&run).unwrap());
+        }
+    }
+
+    #[test]
+    fn nesting_up_to_the_limit_parses() {
+        let parens = format!("show {}1{};", "(".repeat(63), ")".repeat(63));
+        assert!(parse(&tokenize(&parens).unwrap()).is_ok());
+        let blocks = format!("{}show 1;{}", "{".repeat(63), "}".repeat(63));
+        assert!(parse(&tokenize(&blocks).unwrap()).is_ok());
+
+        let (line, col, message) = first_error(&format!("show {}1;", "-".repeat(64)));
+        assert_eq!((line, col), (1, 70));
+        assert_eq!(message, "nested more than 64 deep");
+    }
+}
@@ -0,0 +1,75 @@
+//! `bplang new`: creates a project ready for `bplang run`
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::CONFIG_FILE;
+
+// Where the program's source goes, relative to the project directory
+const ENTRY: &str = "src/main.bp";
+
+// Directory the executable is built into, ignored by git
+const BIN_DIR: &str = "bin";
+
+// `s` as the body of a quoted TOML string
+fn escape_toml(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// `s` as the body of a BP string given to `show`, where braces interpolate
+fn escape_bp(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('{', "{{")
+        .replace('}', "}}")
+}
+
+// Create a project at `dir`, named after its last component, whose
+// executable is called `bin_name` or else the project's name. An existing
+// directory is only used when empty.
+pub fn create(dir: &Path, bin_name: Option<&str>) -> io::Result<()> {
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid project path: {}", dir.display()),
+            )
+        })?;
+    let bin_name = bin_name.unwrap_or(&name);
+    if bin_name.is_empty() || bin_name.contains(['/', '\\']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid executable name: '{}'", bin_name),
+        ));
+    }
+    if dir.exists() && (!dir.is_dir() || fs::read_dir(dir)?.next().is_some()) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists and is not empty", dir.display()),
+        ));
+    }
+
+    fs::create_dir_all(
+        dir.join(ENTRY)
+            .parent()
+            .expect("the entry is in a directory"),
+    )?;
+    fs::write(
+        dir.join(CONFIG_FILE),
+        format!(
+            "entry = \"{}\"\noutput = \"{}/{}\"\n",
+            ENTRY,
+            BIN_DIR,
+            escape_toml(bin_name)
+        ),
+    )?;
+    fs::write(
+        dir.join(ENTRY),
+        format!("show \"Hello from {}\";\n", escape_bp(&name)),
+    )?;
+    fs::write(dir.join(".gitignore"), format!("/{}/\n", BIN_DIR))?;
+    Ok(())
+}
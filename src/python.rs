@@ -0,0 +1,810 @@
+//! Translates a checked program into Python 3
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::ast::{ASTNode, BinaryOperator, UnaryOperator};
+use crate::backend::Generated;
+use crate::error::{assertion_failure, ASSERT_EXIT_CODE};
+use crate::semantic::{builtin, expression_type, parameter_type, Definitions, Type};
+use crate::writer::{unparenthesized, CodeWriter};
+
+// Python identifier for a BP variable, parameter or function. The prefix
+// keeps user names clear of Python keywords and builtins.
+fn py_name(name: &str) -> String {
+    format!("bp_{}", name)
+}
+
+// Runtime support, written ahead of the program only when it is used.
+// Helper names start with `bprt_`, which no mangled user name can. Errors
+// are reported like the C runtime does, after what was printed so far.
+const FAIL_RUNTIME: &str = r#"def bprt_fail(message):
+    sys.stdout.flush()
+    print("error: " + message, file=sys.stderr)
+    sys.exit(1)
+"#;
+
+// Python raises on a zero divisor, where C gives an infinity or a NaN
+const FLOAT_DIV_RUNTIME: &str = r#"def bprt_float_div(a, b):
+    if b == 0:
+        if a == 0 or a != a:
+            return math.nan
+        return math.copysign(math.inf, a) * math.copysign(1.0, b)
+    return a / b
+"#;
+
+// `pow` and `sqrt` give an infinity or a NaN where Python's raise
+const MATH_RUNTIME: &str = r#"def bprt_pow(a, b):
+    try:
+        return math.pow(a, b)
+    except OverflowError:
+        return math.inf
+    except ValueError:
+        return math.nan
+
+
+def bprt_sqrt(x):
+    return math.sqrt(x) if x >= 0 else math.nan
+"#;
+
+// Arrays are bounds checked, like in the interpreter: Python would count a
+// negative index from the end
+const INDEX_RUNTIME: &str = r#"def bprt_index(array, index, name):
+    if index < 0 or index >= len(array):
+        bprt_fail("index %d is out of range for '%s' of length %d" % (index, name, len(array)))
+    return index
+"#;
+
+// `substr` counts UTF-8 bytes, like the C helper
+const SUBSTR_RUNTIME: &str = r#"def bprt_substr(s, start, length):
+    data = s.encode()
+    start = min(max(start, 0), len(data))
+    length = min(max(length, 0), len(data) - start)
+    return data[start:start + length].decode(errors="replace")
+"#;
+
+// Parsing text as an int for `input` and `to_int`: the whole text must be
+// a decimal number, optionally surrounded by blanks. Gives the int, or
+// the reason the text is not one.
+const PARSE_INT_RUNTIME: &str = r#"BPRT_NOT_AN_INT = "not an int"
+BPRT_OUT_OF_RANGE = "out of range"
+
+
+def bprt_parse_int(text):
+    if not re.fullmatch(r"[ \t]*[+-]?[0-9]+[ \t]*", text):
+        return BPRT_NOT_AN_INT
+    value = int(text.strip(" \t"))
+    if value < -2147483648 or value > 2147483647:
+        return BPRT_OUT_OF_RANGE
+    return value
+"#;
+
+const TO_INT_RUNTIME: &str = r#"def bprt_to_int(s):
+    value = bprt_parse_int(s)
+    if value == BPRT_NOT_AN_INT:
+        bprt_fail("to_int: '%s' is not an integer" % s)
+    if value == BPRT_OUT_OF_RANGE:
+        bprt_fail("to_int: '%s' is out of range" % s)
+    return value
+"#;
+
+// Runtime support for `input`: one line from stdin without its line ending
+const INPUT_RUNTIME: &str = r#"def bprt_input_line():
+    sys.stdout.flush()
+    line = sys.stdin.readline()
+    if not line:
+        bprt_fail("unexpected end of input")
+    return line.rstrip("\r\n")
+
+
+def bprt_input_int():
+    line = bprt_input_line()
+    value = bprt_parse_int(line)
+    if value == BPRT_NOT_AN_INT:
+        bprt_fail("input is not an integer: '%s'" % line)
+    if value == BPRT_OUT_OF_RANGE:
+        bprt_fail("input integer out of range: '%s'" % line)
+    return value
+"#;
+
+// `random` and `seed`: the 64-bit linear congruential generator of the C
+// runtime, so a seeded program gives the same numbers
+const RANDOM_RUNTIME: &str = r#"BPRT_MASK = (1 << 64) - 1
+bprt_rng_state = 0
+
+
+# Mix the seed, so nearby seeds start far apart
+def bprt_seed(seed):
+    global bprt_rng_state
+    z = (seed + 0x9E3779B97F4A7C15) & BPRT_MASK
+    z = ((z ^ (z >> 30)) * 0xBF58476D1CE4E5B9) & BPRT_MASK
+    z = ((z ^ (z >> 27)) * 0x94D049BB133111EB) & BPRT_MASK
+    bprt_rng_state = z ^ (z >> 31)
+
+
+def bprt_random(n):
+    global bprt_rng_state
+    if n <= 0:
+        bprt_fail("random: the bound must be positive, not %d" % n)
+    bprt_rng_state = (bprt_rng_state * 6364136223846793005 + 1442695040888963407) & BPRT_MASK
+    return (bprt_rng_state >> 33) % n
+"#;
+
+// `read_file` and `write_file`, which stop the program when they fail.
+// Files are handled as bytes, like in C.
+const FILE_RUNTIME: &str = r#"def bprt_file_error(name, path, err):
+    bprt_fail("%s: cannot access '%s': %s" % (name, path, err.strerror))
+
+
+def bprt_read_file(path):
+    try:
+        with open(path, "rb") as file:
+            return file.read().decode(errors="replace")
+    except OSError as err:
+        bprt_file_error("read_file", path, err)
+
+
+def bprt_write_file(path, content):
+    try:
+        with open(path, "wb") as file:
+            file.write(content.encode())
+    except OSError as err:
+        bprt_file_error("write_file", path, err)
+"#;
+
+// `arg`: the script path or an argument after it, as C's `argv`. Arguments
+// out of range are "".
+const ARG_RUNTIME: &str = r#"def bprt_arg(i):
+    return sys.argv[i] if 0 <= i < len(sys.argv) else ""
+"#;
+
+// `sleep_ms` shows what was printed so far before pausing. Computed
+// negative durations do not sleep.
+const SLEEP_RUNTIME: &str = r#"def bprt_sleep_ms(ms):
+    sys.stdout.flush()
+    if ms > 0:
+        time.sleep(ms / 1000)
+"#;
+
+// A variable in scope at the current point of code generation
+#[derive(Clone)]
+struct Variable {
+    ty: Type,
+    // Its Python name, which differs from `py_name` when it shadows another
+    // variable
+    py_name: String,
+}
+
+// Variables known at the current point of code generation, by BP name
+type SymbolTable = HashMap<String, Variable>;
+
+// Python name of the variable `name`, which analyze made sure is in scope
+fn variable<'a>(symbols: &'a SymbolTable, name: &str) -> &'a str {
+    &symbols
+        .get(name)
+        .expect("variable was checked by analyze")
+        .py_name
+}
+
+// Escape a string so it can be placed between double quotes in Python
+fn escape_py(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            ch if ch.is_ascii_control() => escaped.push_str(&format!("\\x{:02x}", ch as u32)),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+// Value a variable declared without one starts with; analyze made sure it
+// is assigned before it is read
+fn zero(ty: &Type) -> &'static str {
+    match ty {
+        Type::Int => "0",
+        Type::Float => "0.0",
+        Type::Bool => "False",
+        Type::Str => "\"\"",
+        _ => unreachable!("only scalars are declared without a value: {:?}", ty),
+    }
+}
+
+// Emits Python for statements and expressions, remembering the modules and
+// runtime helpers the emitted code relies on so only those are written
+// ahead of it
+#[derive(Default)]
+struct Generator {
+    imports: BTreeSet<&'static str>,
+    helpers: Vec<&'static str>,
+    // Whether the program calls `random`, and whether it seeds it itself
+    uses_random: bool,
+    seeded: bool,
+    // Whether the program divides ints, which raises on a zero divisor
+    divides: bool,
+    // The BP source, named by failed assertions
+    source: PathBuf,
+    // The structs and enums the program defines
+    definitions: Definitions,
+    // Variables renamed so far because they shadow another
+    shadow_count: usize,
+    // Counters of the `for` loops around the statement being emitted,
+    // innermost last, which a `continue` must step
+    loops: Vec<String>,
+}
+
+impl Generator {
+    // Type of an expression the analyzer has already accepted
+    fn checked_type(&self, node: &ASTNode, symbols: &SymbolTable) -> Type {
+        expression_type(
+            node,
+            &|name| symbols.get(name).map(|variable| variable.ty.clone()),
+            &self.definitions,
+        )
+        .expect("expression was type checked by analyze")
+    }
+
+    // Add runtime helpers, once each, along with the modules they import
+    fn require(&mut self, helpers: &[&'static str], imports: &[&'static str]) {
+        self.imports.extend(imports);
+        for helper in helpers {
+            if !self.helpers.contains(helper) {
+                self.helpers.push(helper);
+            }
+        }
+    }
+
+    // Record a variable declared in the current block and give its Python
+    // name. Python variables belong to the whole function, so a variable
+    // shadowing an outer one gets a name of its own, numbered so it cannot
+    // be any `bp_` name.
+    fn declare(&mut self, name: &str, ty: Type, symbols: &mut SymbolTable) -> String {
+        let py_name = if symbols.contains_key(name) {
+            self.shadow_count += 1;
+            format!("bp{}_{}", self.shadow_count, name)
+        } else {
+            py_name(name)
+        };
+        symbols.insert(
+            name.to_string(),
+            Variable {
+                ty,
+                py_name: py_name.clone(),
+            },
+        );
+        py_name
+    }
+
+    // Operand of a string `+` or `show`, converted to a string as `show`
+    // prints it
+    fn string_operand(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        let value = self.expression(node, symbols);
+        match self.checked_type(node, symbols) {
+            // Enum values are the names of their variants
+            Type::Str | Type::Enum(_) => value,
+            Type::Int => format!("str({})", value),
+            // Python's `%g` is C's
+            Type::Float => format!("(\"%g\" % {})", value),
+            Type::Bool => format!("(\"true\" if {} else \"false\")", value),
+            Type::Long => unreachable!("longs are rejected by reject_long"),
+            Type::Array(_) | Type::Struct(_) => unreachable!("aggregates cannot be concatenated"),
+        }
+    }
+
+    // Element `index` of the array `name`. Indices known when compiling were
+    // checked by analyze.
+    fn element(&mut self, name: &str, index: &ASTNode, symbols: &SymbolTable) -> String {
+        let array = variable(symbols, name);
+        if let ASTNode::NumberLiteral(index) = index {
+            return format!("{}[{}]", array, index);
+        }
+        self.require(&[FAIL_RUNTIME, INDEX_RUNTIME], &["sys"]);
+        format!(
+            "{}[bprt_index({}, {}, \"{}\")]",
+            array,
+            array,
+            self.expression(index, symbols),
+            name
+        )
+    }
+
+    // Translate an expression node into the equivalent Python expression.
+    // Operations are parenthesized so Python re-derives the BP grouping.
+    fn expression(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        match node {
+            // Negative literals come from folding
+            ASTNode::NumberLiteral(num) if *num < 0 => format!("({})", num),
+            ASTNode::NumberLiteral(num) => num.to_string(),
+            ASTNode::FloatLiteral(num) if num.is_sign_negative() => format!("({:?})", num),
+            ASTNode::FloatLiteral(num) => format!("{:?}", num),
+            ASTNode::BoolLiteral(true) => "True".to_string(),
+            ASTNode::BoolLiteral(false) => "False".to_string(),
+            ASTNode::StringLiteral(s) => format!("\"{}\"", escape_py(s)),
+            ASTNode::Identifier(name, _) => variable(symbols, name).to_string(),
+            ASTNode::Index { name, index, .. } => self.element(name, index, symbols),
+            ASTNode::ArrayLiteral(elements, _) => {
+                let elements: Vec<String> = elements
+                    .iter()
+                    .map(|element| self.expression(element, symbols))
+                    .collect();
+                format!("[{}]", elements.join(", "))
+            }
+            // Structs are dicts keyed by field name
+            ASTNode::FieldAccess { name, field, .. } if symbols.contains_key(name) => {
+                format!("{}[\"{}\"]", variable(symbols, name), field)
+            }
+            // Not a variable, so an enum variant, which is its name
+            ASTNode::FieldAccess { field, .. } => format!("\"{}\"", field),
+            ASTNode::StructInit { fields, .. } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| {
+                        format!("\"{}\": {}", field, self.expression(value, symbols))
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            ASTNode::BinaryOp { op, lhs, rhs, .. } => {
+                let ty = self.checked_type(node, symbols);
+                if ty == Type::Str {
+                    return format!(
+                        "({} + {})",
+                        self.string_operand(lhs, symbols),
+                        self.string_operand(rhs, symbols)
+                    );
+                }
+                let lhs = self.expression(lhs, symbols);
+                let rhs = self.expression(rhs, symbols);
+                let symbol = match (op, ty) {
+                    // Floor division: the same as C's for non-negative
+                    // operands only
+                    (BinaryOperator::Div, Type::Int) => {
+                        self.divides = true;
+                        "//"
+                    }
+                    (BinaryOperator::Mod, _) => {
+                        self.divides = true;
+                        "%"
+                    }
+                    (BinaryOperator::Div, _) => {
+                        self.require(&[FLOAT_DIV_RUNTIME], &["math"]);
+                        return format!("bprt_float_div({}, {})", lhs, rhs);
+                    }
+                    (BinaryOperator::And, _) => "and",
+                    (BinaryOperator::Or, _) => "or",
+                    (op, _) => op.c_symbol(),
+                };
+                format!("({} {} {})", lhs, symbol, rhs)
+            }
+            ASTNode::UnaryOp { op, operand, .. } => {
+                let value = self.expression(operand, symbols);
+                match op {
+                    UnaryOperator::Neg => format!("(-{})", value),
+                    UnaryOperator::Not => format!("(not {})", value),
+                }
+            }
+            ASTNode::Call { name, args, .. } if name == "len" => {
+                match self.checked_type(&args[0], symbols) {
+                    // Array lengths are known at compile time
+                    Type::Array(len) => len.to_string(),
+                    // Strings are measured in bytes, like in C
+                    _ => format!("len({}.encode())", self.expression(&args[0], symbols)),
+                }
+            }
+            // The other builtins call runtime helpers or Python's own
+            ASTNode::Call { name, args, .. } if builtin(name).is_some() => {
+                let arg_values: Vec<String> = args
+                    .iter()
+                    .map(|arg| self.expression(arg, symbols))
+                    .collect();
+                let function = match name.as_str() {
+                    // `bytes` only changes the case of ASCII letters, like
+                    // the C locale
+                    "upper" | "lower" => {
+                        return format!("{}.encode().{}().decode()", arg_values[0], name);
+                    }
+                    "substr" => {
+                        self.require(&[SUBSTR_RUNTIME], &[]);
+                        "bprt_substr"
+                    }
+                    "to_str" => "str",
+                    "to_int" => {
+                        self.require(
+                            &[FAIL_RUNTIME, PARSE_INT_RUNTIME, TO_INT_RUNTIME],
+                            &["re", "sys"],
+                        );
+                        "bprt_to_int"
+                    }
+                    "abs" | "min" | "max" => name.as_str(),
+                    "pow" | "sqrt" => {
+                        self.require(&[MATH_RUNTIME], &["math"]);
+                        if name == "pow" {
+                            "bprt_pow"
+                        } else {
+                            "bprt_sqrt"
+                        }
+                    }
+                    "random" | "seed" => {
+                        self.require(&[FAIL_RUNTIME, RANDOM_RUNTIME], &["sys"]);
+                        if name == "seed" {
+                            self.seeded = true;
+                            "bprt_seed"
+                        } else {
+                            self.uses_random = true;
+                            "bprt_random"
+                        }
+                    }
+                    "env" => {
+                        self.imports.insert("os");
+                        return format!("os.environ.get({}, \"\")", arg_values[0]);
+                    }
+                    "read_file" | "write_file" => {
+                        self.require(&[FAIL_RUNTIME, FILE_RUNTIME], &["sys"]);
+                        if name == "read_file" {
+                            "bprt_read_file"
+                        } else {
+                            "bprt_write_file"
+                        }
+                    }
+                    "argc" => {
+                        self.imports.insert("sys");
+                        return "len(sys.argv)".to_string();
+                    }
+                    "arg" => {
+                        self.require(&[ARG_RUNTIME], &["sys"]);
+                        "bprt_arg"
+                    }
+                    "sleep_ms" => {
+                        self.require(&[SLEEP_RUNTIME], &["sys", "time"]);
+                        "bprt_sleep_ms"
+                    }
+                    _ => unreachable!("builtin without Python translation: {}", name),
+                };
+                format!("{}({})", function, arg_values.join(", "))
+            }
+            ASTNode::Call { name, args, .. } => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| self.expression(arg, symbols))
+                    .collect();
+                format!("{}({})", py_name(name), args.join(", "))
+            }
+            _ => unreachable!("not an expression: {:?}", node),
+        }
+    }
+
+    // Emit `nodes` one level deeper, as the body of a compound statement.
+    // Python needs a statement there even when BP has none.
+    fn body(&mut self, nodes: &[ASTNode], symbols: &mut SymbolTable, out: &mut CodeWriter) {
+        out.indent();
+        let start = out.line_count();
+        self.statements(nodes, symbols, out);
+        if out.line_count() == start {
+            out.line("pass");
+        }
+        out.dedent();
+    }
+
+    // Emit an `if` and its branches. An `else` holding only another `if`
+    // becomes an `elif`.
+    fn if_chain(
+        &mut self,
+        keyword: &str,
+        cond: &ASTNode,
+        then_block: &[ASTNode],
+        else_block: Option<&[ASTNode]>,
+        symbols: &SymbolTable,
+        out: &mut CodeWriter,
+    ) {
+        let cond = unparenthesized(self.expression(cond, symbols));
+        out.line(format!("{} {}:", keyword, cond));
+        // Blocks get their own copy of the symbols, matching BP scoping
+        self.body(then_block, &mut symbols.clone(), out);
+        match else_block {
+            Some(
+                [ASTNode::If {
+                    cond,
+                    then_block,
+                    else_block,
+                    ..
+                }],
+            ) => self.if_chain(
+                "elif",
+                cond,
+                then_block,
+                else_block.as_deref(),
+                symbols,
+                out,
+            ),
+            Some(else_block) => {
+                out.line("else:");
+                self.body(else_block, &mut symbols.clone(), out);
+            }
+            None => {}
+        }
+    }
+
+    // Write the Python translation of `nodes` to `out`. Variables declared
+    // here are recorded in `symbols`.
+    fn statements(&mut self, nodes: &[ASTNode], symbols: &mut SymbolTable, out: &mut CodeWriter) {
+        for node in nodes {
+            match node {
+                // `c` constants are only enforced by analyze
+                ASTNode::VariableDeclaration {
+                    name, ty, value, ..
+                } => {
+                    let declared = ty
+                        .as_ref()
+                        .map(|ty| Type::from_name(ty).expect("annotation was checked by analyze"));
+                    let Some(value) = value else {
+                        let ty = declared.expect("the parser requires a value or a type");
+                        let value = zero(&ty);
+                        let py_var = self.declare(name, ty, symbols);
+                        out.line(format!("{} = {}", py_var, value));
+                        continue;
+                    };
+                    let value_type = match value.as_ref() {
+                        ASTNode::ArrayLiteral(elements, _) => Type::Array(elements.len()),
+                        ASTNode::StructInit { name, .. } => Type::Struct(name.clone()),
+                        value => match declared {
+                            Some(declared) => declared,
+                            None => self.checked_type(value, symbols),
+                        },
+                    };
+                    // The value may read the variable being shadowed
+                    let value_code = unparenthesized(self.expression(value, symbols));
+                    let py_var = self.declare(name, value_type, symbols);
+                    out.line(format!("{} = {}", py_var, value_code));
+                }
+                ASTNode::Assignment { name, value, .. } => {
+                    let value = unparenthesized(self.expression(value, symbols));
+                    out.line(format!("{} = {}", variable(symbols, name), value));
+                }
+                ASTNode::FieldAssignment {
+                    name, field, value, ..
+                } => {
+                    let value = unparenthesized(self.expression(value, symbols));
+                    out.line(format!(
+                        "{}[\"{}\"] = {}",
+                        variable(symbols, name),
+                        field,
+                        value
+                    ));
+                }
+                ASTNode::IndexAssignment {
+                    name, index, value, ..
+                } => {
+                    let target = self.element(name, index, symbols);
+                    let value = unparenthesized(self.expression(value, symbols));
+                    out.line(format!("{} = {}", target, value));
+                }
+                ASTNode::Show(values, _) => {
+                    let parts: Vec<String> = values
+                        .iter()
+                        .map(|value| self.string_operand(value, symbols))
+                        .collect();
+                    out.line(format!("print({})", unparenthesized(parts.join(" + "))));
+                }
+                ASTNode::If {
+                    cond,
+                    then_block,
+                    else_block,
+                    ..
+                } => self.if_chain("if", cond, then_block, else_block.as_deref(), symbols, out),
+                // A `while`, since the end is evaluated before every
+                // iteration and the body may change the counter. Every
+                // `continue` steps the counter itself.
+                ASTNode::For {
+                    var,
+                    start,
+                    end,
+                    body,
+                    ..
+                } => {
+                    let start = unparenthesized(self.expression(start, symbols));
+                    let mut body_symbols = symbols.clone();
+                    let py_var = self.declare(var, Type::Int, &mut body_symbols);
+                    let end = unparenthesized(self.expression(end, &body_symbols));
+                    out.line(format!("{} = {}", py_var, start));
+                    out.line(format!("while {} <= {}:", py_var, end));
+                    self.loops.push(py_var.clone());
+                    out.indent();
+                    self.statements(body, &mut body_symbols, out);
+                    out.line(format!("{} += 1", py_var));
+                    out.dedent();
+                    self.loops.pop();
+                }
+                // Python has no blocks of its own; names declared in one
+                // were made distinct by `declare`
+                ASTNode::Block(nodes) => self.statements(nodes, &mut symbols.clone(), out),
+                // An `if` chain on the variant name
+                ASTNode::Match {
+                    value,
+                    arms,
+                    else_block,
+                    ..
+                } => {
+                    let subject = match value.as_ref() {
+                        ASTNode::Identifier(..) | ASTNode::FieldAccess { .. } => {
+                            self.expression(value, symbols)
+                        }
+                        // Evaluated once. A match in an arm may reuse the
+                        // name, as the arm was already chosen.
+                        value => {
+                            let value = unparenthesized(self.expression(value, symbols));
+                            out.line(format!("bprt_match = {}", value));
+                            "bprt_match".to_string()
+                        }
+                    };
+                    let mut keyword = "if";
+                    for arm in arms {
+                        out.line(format!("{} {} == \"{}\":", keyword, subject, arm.variant));
+                        self.body(&arm.body, &mut symbols.clone(), out);
+                        keyword = "elif";
+                    }
+                    match else_block {
+                        Some(else_block) if arms.is_empty() => {
+                            self.statements(else_block, &mut symbols.clone(), out)
+                        }
+                        Some(else_block) => {
+                            out.line("else:");
+                            self.body(else_block, &mut symbols.clone(), out);
+                        }
+                        None => {}
+                    }
+                }
+                ASTNode::Call { .. } => {
+                    let call = self.expression(node, symbols);
+                    out.line(call);
+                }
+                ASTNode::Give { value, .. } => {
+                    let value = unparenthesized(self.expression(value, symbols));
+                    out.line(format!("return {}", value));
+                }
+                ASTNode::Input { name, .. } => {
+                    let helper = match symbols.get(name).map(|variable| &variable.ty) {
+                        Some(Type::Int) => "bprt_input_int",
+                        Some(Type::Str) => "bprt_input_line",
+                        ty => unreachable!("input into {:?} was type checked", ty),
+                    };
+                    self.require(
+                        &[FAIL_RUNTIME, PARSE_INT_RUNTIME, INPUT_RUNTIME],
+                        &["re", "sys"],
+                    );
+                    out.line(format!("{} = {}()", variable(symbols, name), helper));
+                }
+                // The message is fixed when compiling, position included
+                ASTNode::Assert {
+                    cond,
+                    message,
+                    span,
+                } => {
+                    let failure = assertion_failure(&self.source, *span, message.as_deref());
+                    self.imports.insert("sys");
+                    out.line(format!("if not {}:", self.expression(cond, symbols)));
+                    out.indent();
+                    out.line("sys.stdout.flush()");
+                    out.line(format!(
+                        "print(\"{}\", file=sys.stderr)",
+                        escape_py(&failure)
+                    ));
+                    out.line(format!("sys.exit({})", ASSERT_EXIT_CODE));
+                    out.dedent();
+                }
+                ASTNode::Exit { value, .. } => {
+                    self.imports.insert("sys");
+                    let value = unparenthesized(self.expression(value, symbols));
+                    out.line(format!("sys.exit({})", value));
+                }
+                ASTNode::Break(_) => out.line("break"),
+                ASTNode::Continue(_) => {
+                    let counter = self.loops.last().expect("analyze allows continue in loops");
+                    out.line(format!("{} += 1", counter));
+                    out.line("continue");
+                }
+                // Emitted ahead of the top-level statements by `transpile`;
+                // structs and enums need no declaration
+                ASTNode::Function { .. } | ASTNode::StructDef { .. } | ASTNode::EnumDef { .. } => {}
+                _ => {} // Handles other unhandled ASTNode variants
+            }
+        }
+    }
+}
+
+// Transpile a checked program into a Python 3 script. `source` names the
+// program in the messages of failed assertions.
+pub fn transpile(ast: &[ASTNode], source: &Path) -> Generated {
+    let mut generator = Generator {
+        source: source.to_path_buf(),
+        ..Generator::default()
+    };
+    generator.definitions.add_checked(ast);
+    let mut out = CodeWriter::new();
+
+    // Functions are looked up when called, so they can call each other in
+    // any order
+    for node in ast {
+        if let ASTNode::Function {
+            name, params, body, ..
+        } = node
+        {
+            let mut symbols = SymbolTable::new();
+            let names: Vec<String> = params
+                .iter()
+                .map(|param| {
+                    let ty = parameter_type(param).expect("parameter was checked by analyze");
+                    generator.declare(&param.name, ty, &mut symbols)
+                })
+                .collect();
+            out.line(format!("def {}({}):", py_name(name), names.join(", ")));
+            out.indent();
+            generator.statements(body, &mut symbols, &mut out);
+            // Falling off the end of a function gives 0
+            out.line("return 0");
+            out.dedent();
+            out.blank();
+            out.blank();
+        }
+    }
+
+    // The top-level statements become a function of their own, so their
+    // variables are locals like those of other functions
+    let mut main = CodeWriter::new();
+    main.indent();
+    generator.statements(ast, &mut SymbolTable::new(), &mut main);
+    out.line("def bprt_main():");
+    out.indent();
+    // Unseeded programs get different numbers on every run
+    if generator.uses_random && !generator.seeded {
+        generator.imports.insert("time");
+        out.line("bprt_seed(int(time.time()))");
+    } else if main.line_count() == 0 {
+        out.line("pass");
+    }
+    out.dedent();
+    let mut code = out.finish() + &main.finish();
+
+    // Dividing by zero stops the program like in the interpreter
+    let mut entry = CodeWriter::new();
+    entry.blank();
+    entry.blank();
+    if generator.divides {
+        generator.require(&[FAIL_RUNTIME], &["sys"]);
+        entry.line("try:");
+        entry.indent();
+        entry.line("bprt_main()");
+        entry.dedent();
+        entry.line("except ZeroDivisionError:");
+        entry.indent();
+        entry.line("bprt_fail(\"division by zero\")");
+        entry.dedent();
+    } else {
+        entry.line("bprt_main()");
+    }
+    code.push_str(&entry.finish());
+
+    // Only now is it known which modules and helpers the code needs
+    // Top-level definitions are two blank lines apart, as PEP 8 has it
+    let mut prelude = String::new();
+    for module in &generator.imports {
+        prelude.push_str(&format!("import {}\n", module));
+    }
+    for helper in &generator.helpers {
+        if !prelude.is_empty() {
+            prelude.push_str("\n\n");
+        }
+        prelude.push_str(helper);
+    }
+    if !prelude.is_empty() {
+        prelude.push_str("\n\n");
+    }
+    Generated {
+        code: prelude + &code,
+        libraries: Vec::new(),
+    }
+}
@@ -0,0 +1,211 @@
+//! Interactive read-eval-print loop on top of the interpreter
+
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::ast::{ast_tree, ASTNode};
+use crate::backend::reject_inline_c;
+use crate::codes;
+use crate::diagnostic::Severity;
+use crate::driver::report;
+use crate::error::assertion_failure;
+use crate::interpreter::{InterpretError, Interpreter};
+use crate::lexer::{tokenize, LexErrorKind, SpannedToken, Token};
+use crate::parser::{parse, parse_expression, ParseError};
+use crate::semantic::Analyzer;
+
+// Name used for the input in diagnostics
+const INPUT_NAME: &str = "<repl>";
+
+// One complete piece of input: statements to run, or a bare expression
+// whose value is echoed
+enum Input {
+    Statements(Vec<ASTNode>),
+    Expression(ASTNode),
+}
+
+// Statements first; if they do not parse, the input may be a bare
+// expression. The statement errors are the ones worth reporting.
+fn parse_input(tokens: &[SpannedToken]) -> Result<Input, Vec<ParseError>> {
+    match parse(tokens) {
+        Ok(ast) => Ok(Input::Statements(ast)),
+        Err(errors) => parse_expression(tokens)
+            .map(Input::Expression)
+            .map_err(|_| errors),
+    }
+}
+
+// Whether the input stops inside a block or block comment, so another line
+// should be read before evaluating it
+fn needs_more(source: &str) -> bool {
+    match tokenize(source) {
+        Ok(tokens) => {
+            let depth = tokens.iter().fold(0i32, |depth, token| match token.token {
+                Token::LBrace => depth + 1,
+                Token::RBrace => depth - 1,
+                _ => depth,
+            });
+            depth > 0
+        }
+        Err(err) => err.kind == LexErrorKind::UnterminatedBlockComment,
+    }
+}
+
+fn prompt(text: &str) -> io::Result<()> {
+    print!("{}", text);
+    io::stdout().flush()
+}
+
+// Run the REPL until end of input. `show` output appears as soon as a
+// statement runs; variables and functions persist between inputs.
+// `:tokens` and `:ast` dump the previous input, `:quit` leaves. Returns the
+// code given to `exit`, or 0.
+pub fn run(input: impl BufRead) -> io::Result<i32> {
+    let path = Path::new(INPUT_NAME);
+    let mut analyzer = Analyzer::new();
+    // `input` statements read the lines that follow them
+    let mut interpreter = Interpreter::new(input, io::stdout());
+    let mut last = String::new();
+
+    loop {
+        prompt("> ")?;
+        let mut source = String::new();
+        if interpreter.input().read_line(&mut source)? == 0 {
+            println!();
+            return Ok(0);
+        }
+        match source.trim() {
+            "" => continue,
+            ":quit" => return Ok(0),
+            ":tokens" => {
+                if let Ok(tokens) = tokenize(&last) {
+                    for token in &tokens {
+                        println!("{}", token);
+                    }
+                }
+                continue;
+            }
+            ":ast" => {
+                match tokenize(&last).map(|tokens| parse_input(&tokens)) {
+                    Ok(Ok(Input::Statements(ast))) => print!("{}", ast_tree(&ast)),
+                    Ok(Ok(Input::Expression(expr))) => print!("{}", ast_tree(&[expr])),
+                    _ => {}
+                }
+                continue;
+            }
+            _ => {}
+        }
+        while needs_more(&source) {
+            prompt("... ")?;
+            if interpreter.input().read_line(&mut source)? == 0 {
+                break;
+            }
+        }
+        last = source.clone();
+
+        let tokens = match tokenize(&source) {
+            Ok(tokens) => tokens,
+            Err(err) => {
+                report(
+                    path,
+                    Some(&source),
+                    Severity::Error,
+                    err.code(),
+                    err.span,
+                    &err,
+                );
+                continue;
+            }
+        };
+        let result = match parse_input(&tokens) {
+            Ok(Input::Statements(ast)) => match analyzer.check_input(&ast) {
+                Ok(()) => {
+                    let used = ast.iter().find_map(|node| match node {
+                        ASTNode::Use { span, .. } => Some(*span),
+                        _ => None,
+                    });
+                    if let Some(span) = used {
+                        report(
+                            path,
+                            Some(&source),
+                            Severity::Error,
+                            codes::UNSUPPORTED_BY_TARGET,
+                            span,
+                            &"'use' only works in a program file, not the REPL",
+                        );
+                        continue;
+                    }
+                    if let Err(err) = reject_inline_c(&ast, "the REPL") {
+                        report(
+                            path,
+                            Some(&source),
+                            Severity::Error,
+                            codes::UNSUPPORTED_BY_TARGET,
+                            err.span,
+                            &err,
+                        );
+                        continue;
+                    }
+                    interpreter.run(&ast)
+                }
+                Err(err) => {
+                    report(
+                        path,
+                        Some(&source),
+                        Severity::Error,
+                        err.code,
+                        err.span,
+                        &err,
+                    );
+                    continue;
+                }
+            },
+            Ok(Input::Expression(expr)) => match analyzer.check_expression(&expr) {
+                Ok(_) => interpreter
+                    .evaluate(&expr)
+                    .map(|value| println!("{}", value)),
+                Err(err) => {
+                    report(
+                        path,
+                        Some(&source),
+                        Severity::Error,
+                        err.code,
+                        err.span,
+                        &err,
+                    );
+                    continue;
+                }
+            },
+            Err(errors) => {
+                for err in &errors {
+                    report(
+                        path,
+                        Some(&source),
+                        Severity::Error,
+                        err.code,
+                        err.span,
+                        err,
+                    );
+                }
+                continue;
+            }
+        };
+        match result {
+            Ok(()) => {}
+            Err(InterpretError::Runtime(err)) => report(
+                path,
+                Some(&source),
+                Severity::Error,
+                codes::RUNTIME_ERROR,
+                err.span,
+                &err,
+            ),
+            Err(InterpretError::Io(err)) => return Err(err),
+            Err(InterpretError::Exit(code)) => return Ok(code),
+            // Like a runtime error, a failed assertion only ends the input
+            Err(InterpretError::Assert { message, span }) => {
+                eprintln!("{}", assertion_failure(path, span, message.as_deref()))
+            }
+        }
+    }
+}
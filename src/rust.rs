@@ -0,0 +1,990 @@
+//! Translates a checked program into a Rust `main.rs`
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{ASTNode, BinaryOperator, UnaryOperator};
+use crate::backend::Generated;
+use crate::error::{assertion_failure, ASSERT_EXIT_CODE};
+use crate::semantic::{builtin, expression_type, parameter_type, Definitions, Returns, Type};
+use crate::writer::{unparenthesized, CodeWriter};
+
+// Rust identifier for a BP variable, parameter, function or field. The
+// prefix keeps user names clear of Rust keywords, which raw identifiers
+// could not do for all of them (`self`, `crate`).
+fn rs_name(name: &str) -> String {
+    format!("bp_{}", name)
+}
+
+// Rust name of a struct or enum type
+fn rs_type_name(name: &str) -> String {
+    format!("bpt_{}", name)
+}
+
+// Rust path of a variant of enum `name`. Variants get a prefix too, as BP
+// allows `Self` as a variant name.
+fn rs_variant(name: &str, variant: &str) -> String {
+    format!("{}::bpv_{}", rs_type_name(name), variant)
+}
+
+// Rust type holding a value of type `ty`
+fn rs_type(ty: &Type) -> String {
+    match ty {
+        Type::Int => "i32".to_string(),
+        Type::Float => "f64".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Str => "String".to_string(),
+        Type::Array(len) => format!("[i32; {}]", len),
+        Type::Struct(name) | Type::Enum(name) => rs_type_name(name),
+        Type::Long => unreachable!("longs are rejected by reject_long"),
+    }
+}
+
+// Value a variable declared without one starts with. analyze made sure it
+// is assigned before it is read, but Rust's own check is stricter.
+fn zero(ty: &Type) -> &'static str {
+    match ty {
+        Type::Int => "0",
+        Type::Float => "0.0",
+        Type::Bool => "false",
+        Type::Str => "String::new()",
+        _ => unreachable!("only scalars are declared without a value: {:?}", ty),
+    }
+}
+
+// Step the counter of a `for` loop, which ends at the largest int rather
+// than wrapping past it
+fn step(counter: &str, out: &mut CodeWriter) {
+    out.line(format!("if {} == i32::MAX {{", counter));
+    out.indent();
+    out.line("break;");
+    out.dedent();
+    out.line("}");
+    out.line(format!("{} += 1;", counter));
+}
+
+// Runtime support, written ahead of the program only when it is used.
+// Helper names start with `bprt_`, which no mangled user name can. Errors
+// are reported like the C runtime does.
+const FAIL_RUNTIME: &str = r#"fn bprt_fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
+"#;
+
+// Int division wraps like the interpreter's, and stops the program on a
+// zero divisor rather than panicking
+const DIV_RUNTIME: &str = r#"fn bprt_div(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        bprt_fail("division by zero");
+    }
+    a.wrapping_div(b)
+}
+
+fn bprt_rem(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        bprt_fail("division by zero");
+    }
+    a.wrapping_rem(b)
+}
+"#;
+
+// Arrays are bounds checked with the interpreter's message
+const INDEX_RUNTIME: &str = r#"fn bprt_index(index: i32, len: usize, name: &str) -> usize {
+    if index < 0 || index as usize >= len {
+        bprt_fail(&format!(
+            "index {} is out of range for '{}' of length {}",
+            index, name, len
+        ));
+    }
+    index as usize
+}
+"#;
+
+// Floats are shown like C's `printf("%g")`: six significant digits, trailing
+// zeros removed, and an exponent when it is below -4 or at least 6
+const FLOAT_RUNTIME: &str = r#"fn bprt_strip_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+fn bprt_float_str(num: f64) -> String {
+    if num.is_nan() {
+        return if num.is_sign_negative() { "-nan" } else { "nan" }.to_string();
+    }
+    if num.is_infinite() {
+        return if num < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+    if num == 0.0 {
+        return if num.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+    // The exponent after rounding to the precision decides the style
+    let scientific = format!("{:.5e}", num);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if !(-4..6).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", bprt_strip_zeros(mantissa), sign, exponent.unsigned_abs())
+    } else {
+        bprt_strip_zeros(&format!("{:.*}", (5 - exponent) as usize, num)).to_string()
+    }
+}
+"#;
+
+// `substr`: at most `len` bytes from `start`, clamped to the string
+const SUBSTR_RUNTIME: &str = r#"fn bprt_substr(s: &str, start: i32, len: i32) -> String {
+    let from = start.clamp(0, s.len() as i32) as usize;
+    let count = len.clamp(0, (s.len() - from) as i32) as usize;
+    String::from_utf8_lossy(&s.as_bytes()[from..from + count]).into_owned()
+}
+"#;
+
+// Parsing text as an int for `input` and `to_int`: the whole text must be
+// a decimal number, optionally surrounded by blanks
+const PARSE_INT_RUNTIME: &str = r#"enum BprtParseError {
+    NotAnInt,
+    OutOfRange,
+}
+
+fn bprt_parse_int(text: &str) -> Result<i32, BprtParseError> {
+    use std::num::IntErrorKind;
+    text.trim_matches([' ', '\t'])
+        .parse()
+        .map_err(|err: std::num::ParseIntError| match err.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => BprtParseError::OutOfRange,
+            _ => BprtParseError::NotAnInt,
+        })
+}
+"#;
+
+const TO_INT_RUNTIME: &str = r#"fn bprt_to_int(s: &str) -> i32 {
+    match bprt_parse_int(s) {
+        Ok(value) => value,
+        Err(BprtParseError::NotAnInt) => bprt_fail(&format!("to_int: '{}' is not an integer", s)),
+        Err(BprtParseError::OutOfRange) => bprt_fail(&format!("to_int: '{}' is out of range", s)),
+    }
+}
+"#;
+
+// Runtime support for `input`: one line from stdin without its line ending
+const INPUT_RUNTIME: &str = r#"fn bprt_input_line() -> String {
+    let mut line = String::new();
+    match std::io::stdin().read_line(&mut line) {
+        Ok(0) => bprt_fail("unexpected end of input"),
+        Ok(_) => {}
+        Err(err) => bprt_fail(&format!("cannot read input: {}", err)),
+    }
+    while line.ends_with('\n') || line.ends_with('\r') {
+        line.pop();
+    }
+    line
+}
+
+fn bprt_input_int() -> i32 {
+    let line = bprt_input_line();
+    match bprt_parse_int(&line) {
+        Ok(value) => value,
+        Err(BprtParseError::NotAnInt) => bprt_fail(&format!("input is not an integer: '{}'", line)),
+        Err(BprtParseError::OutOfRange) => {
+            bprt_fail(&format!("input integer out of range: '{}'", line))
+        }
+    }
+}
+"#;
+
+// `random` and `seed`: the 64-bit linear congruential generator of the C
+// runtime, so a seeded program gives the same numbers
+const RANDOM_RUNTIME: &str = r#"static BPRT_RNG_STATE: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+// Mix the seed, so nearby seeds start far apart
+fn bprt_seed(seed: i32) {
+    let mut z = (seed as u64).wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    BPRT_RNG_STATE.store(z ^ (z >> 31), std::sync::atomic::Ordering::Relaxed);
+}
+
+fn bprt_random(n: i32) -> i32 {
+    if n <= 0 {
+        bprt_fail(&format!("random: the bound must be positive, not {}", n));
+    }
+    let state = BPRT_RNG_STATE
+        .load(std::sync::atomic::Ordering::Relaxed)
+        .wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407);
+    BPRT_RNG_STATE.store(state, std::sync::atomic::Ordering::Relaxed);
+    ((state >> 33) % n as u64) as i32
+}
+"#;
+
+// `read_file` and `write_file`, which stop the program when they fail
+const FILE_RUNTIME: &str = r#"fn bprt_file_error(name: &str, path: &str, err: std::io::Error) -> ! {
+    // Without the "(os error 2)" Rust adds, like strerror
+    let reason = err.to_string();
+    let reason = reason.split(" (os error").next().unwrap_or_default();
+    bprt_fail(&format!("{}: cannot access '{}': {}", name, path, reason))
+}
+
+fn bprt_read_file(path: &str) -> String {
+    match std::fs::read(path) {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        Err(err) => bprt_file_error("read_file", path, err),
+    }
+}
+
+fn bprt_write_file(path: &str, content: &str) {
+    if let Err(err) = std::fs::write(path, content) {
+        bprt_file_error("write_file", path, err);
+    }
+}
+"#;
+
+// `arg`: the program name or an argument, as C's `argv`. Arguments out of
+// range are "".
+const ARG_RUNTIME: &str = r#"fn bprt_arg(i: i32) -> String {
+    if i < 0 {
+        return String::new();
+    }
+    std::env::args().nth(i as usize).unwrap_or_default()
+}
+"#;
+
+// `sleep_ms`. Computed negative durations do not sleep.
+const SLEEP_RUNTIME: &str = r#"fn bprt_sleep_ms(ms: i32) {
+    if ms > 0 {
+        std::thread::sleep(std::time::Duration::from_millis(ms as u64));
+    }
+}
+"#;
+
+// Variable types known at the current point of code generation. Rust
+// scopes `let` like BP scopes declarations, so names need no renaming.
+type SymbolTable = HashMap<String, Type>;
+
+// Escape a string so it can be placed between double quotes in Rust
+fn escape_rs(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            ch if ch.is_ascii_control() => escaped.push_str(&format!("\\x{:02x}", ch as u32)),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+// Escape a string for use as literal text in a `format!` string
+fn escape_format(s: &str) -> String {
+    escape_rs(s).replace('{', "{{").replace('}', "}}")
+}
+
+// Emits Rust for statements and expressions, remembering the runtime
+// helpers the emitted code relies on so only those are written ahead of it
+#[derive(Default)]
+struct Generator {
+    helpers: Vec<&'static str>,
+    // Whether the program calls `random`, and whether it seeds it itself
+    uses_random: bool,
+    seeded: bool,
+    // The BP source, named by failed assertions
+    source: PathBuf,
+    // The structs and enums the program defines
+    definitions: Definitions,
+    // The parameter types of every function, for converting arguments
+    functions: HashMap<String, Vec<Type>>,
+    // Counters of the `for` loops around the statement being emitted,
+    // innermost last, which a `continue` must step
+    loops: Vec<String>,
+}
+
+impl Generator {
+    // Type of an expression the analyzer has already accepted
+    fn checked_type(&self, node: &ASTNode, symbols: &SymbolTable) -> Type {
+        expression_type(node, &|name| symbols.get(name).cloned(), &self.definitions)
+            .expect("expression was type checked by analyze")
+    }
+
+    // Type of a field of the struct `name`
+    fn field_type(&self, name: &str, field: &str) -> Type {
+        self.definitions.structs[name]
+            .iter()
+            .find(|(other, _)| other == field)
+            .map(|(_, ty)| ty.clone())
+            .expect("field was checked by analyze")
+    }
+
+    // Add runtime helpers, once each, after the ones they use
+    fn require(&mut self, helpers: &[&'static str]) {
+        for helper in helpers {
+            if !self.helpers.contains(helper) {
+                self.helpers.push(helper);
+            }
+        }
+    }
+
+    // An expression converted to the type `ty` of the place it is stored
+    // in. Rust never widens an int to a float by itself.
+    fn converted(&mut self, node: &ASTNode, ty: &Type, symbols: &SymbolTable) -> String {
+        if *ty != Type::Float || self.checked_type(node, symbols) != Type::Int {
+            return self.expression(node, symbols);
+        }
+        match node {
+            ASTNode::NumberLiteral(num) if *num < 0 => format!("({:?})", *num as f64),
+            ASTNode::NumberLiteral(num) => format!("{:?}", *num as f64),
+            node => format!("({} as f64)", self.expression(node, symbols)),
+        }
+    }
+
+    // A string expression that methods can be called on without taking a
+    // copy: variables and fields as they are, literals as `&str`s
+    fn receiver(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        match node {
+            ASTNode::StringLiteral(s) => format!("\"{}\"", escape_rs(s)),
+            ASTNode::Identifier(name, _) => rs_name(name),
+            ASTNode::FieldAccess { name, field, .. } if symbols.contains_key(name) => {
+                format!("{}.{}", rs_name(name), rs_name(field))
+            }
+            node => self.expression(node, symbols),
+        }
+    }
+
+    // A string expression borrowed as a `&str`
+    fn borrowed(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        match node {
+            ASTNode::StringLiteral(s) => format!("\"{}\"", escape_rs(s)),
+            node => format!("&{}", self.receiver(node, symbols)),
+        }
+    }
+
+    // Format string and arguments showing `values` one after another, as
+    // `show` and string `+` do. Literal text goes into the format string.
+    fn format_parts(&mut self, values: &[&ASTNode], symbols: &SymbolTable) -> String {
+        let mut format = String::new();
+        let mut arguments = String::new();
+        for value in values {
+            if let ASTNode::StringLiteral(s) = value {
+                format.push_str(&escape_format(s));
+                continue;
+            }
+            format.push_str("{}");
+            let argument = match self.checked_type(value, symbols) {
+                Type::Str => self.receiver(value, symbols),
+                Type::Float => {
+                    self.require(&[FLOAT_RUNTIME]);
+                    format!("bprt_float_str({})", self.expression(value, symbols))
+                }
+                // Bools show as `true` and `false`, enums by their Display
+                _ => self.expression(value, symbols),
+            };
+            arguments.push_str(&format!(", {}", argument));
+        }
+        format!("\"{}\"{}", format, arguments)
+    }
+
+    // The operands of a chain of string `+`, which are shown one after
+    // another
+    fn concatenated<'a>(
+        &self,
+        node: &'a ASTNode,
+        symbols: &SymbolTable,
+        parts: &mut Vec<&'a ASTNode>,
+    ) {
+        match node {
+            ASTNode::BinaryOp {
+                op: BinaryOperator::Add,
+                lhs,
+                rhs,
+                ..
+            } if self.checked_type(node, symbols) == Type::Str => {
+                self.concatenated(lhs, symbols, parts);
+                self.concatenated(rhs, symbols, parts);
+            }
+            node => parts.push(node),
+        }
+    }
+
+    // Element `index` of the array `name`. Indices known when compiling were
+    // checked by analyze.
+    fn element(&mut self, name: &str, index: &ASTNode, symbols: &SymbolTable) -> String {
+        if let ASTNode::NumberLiteral(index) = index {
+            return format!("{}[{}]", rs_name(name), index);
+        }
+        self.require(&[FAIL_RUNTIME, INDEX_RUNTIME]);
+        format!(
+            "{}[bprt_index({}, {}.len(), \"{}\")]",
+            rs_name(name),
+            self.expression(index, symbols),
+            rs_name(name),
+            name
+        )
+    }
+
+    // Translate an expression node into the equivalent Rust expression,
+    // giving an owned value: string variables are cloned. Int arithmetic
+    // wraps like the interpreter's, and operations are parenthesized so
+    // Rust re-derives the BP grouping.
+    fn expression(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        match node {
+            ASTNode::NumberLiteral(i32::MIN) => "i32::MIN".to_string(),
+            ASTNode::NumberLiteral(num) if *num < 0 => format!("({})", num),
+            ASTNode::NumberLiteral(num) => num.to_string(),
+            ASTNode::FloatLiteral(num) if num.is_sign_negative() => format!("({:?})", num),
+            ASTNode::FloatLiteral(num) => format!("{:?}", num),
+            ASTNode::BoolLiteral(b) => b.to_string(),
+            ASTNode::StringLiteral(s) => format!("String::from(\"{}\")", escape_rs(s)),
+            ASTNode::Identifier(name, _) if symbols.get(name) == Some(&Type::Str) => {
+                format!("{}.clone()", rs_name(name))
+            }
+            ASTNode::Identifier(name, _) => rs_name(name),
+            ASTNode::Index { name, index, .. } => self.element(name, index, symbols),
+            ASTNode::ArrayLiteral(elements, _) => {
+                let elements: Vec<String> = elements
+                    .iter()
+                    .map(|element| self.expression(element, symbols))
+                    .collect();
+                format!("[{}]", elements.join(", "))
+            }
+            ASTNode::FieldAccess { name, field, .. } if symbols.contains_key(name) => {
+                let place = format!("{}.{}", rs_name(name), rs_name(field));
+                if self.checked_type(node, symbols) == Type::Str {
+                    format!("{}.clone()", place)
+                } else {
+                    place
+                }
+            }
+            // Not a variable, so an enum variant
+            ASTNode::FieldAccess { name, field, .. } => rs_variant(name, field),
+            ASTNode::StructInit { name, fields, .. } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(field, value)| {
+                        let ty = self.field_type(name, field);
+                        format!(
+                            "{}: {}",
+                            rs_name(field),
+                            self.converted(value, &ty, symbols)
+                        )
+                    })
+                    .collect();
+                format!("{} {{ {} }}", rs_type_name(name), fields.join(", "))
+            }
+            ASTNode::BinaryOp { op, lhs, rhs, .. } => {
+                let ty = self.checked_type(node, symbols);
+                if ty == Type::Str {
+                    let mut parts = Vec::new();
+                    self.concatenated(node, symbols, &mut parts);
+                    return format!("format!({})", self.format_parts(&parts, symbols));
+                }
+                // Mixed int and float operands are compared or combined as
+                // floats
+                let lhs_type = self.checked_type(lhs, symbols);
+                let rhs_type = self.checked_type(rhs, symbols);
+                let operands = if lhs_type == Type::Float || rhs_type == Type::Float {
+                    Type::Float
+                } else {
+                    lhs_type
+                };
+                let lhs = self.converted(lhs, &operands, symbols);
+                let rhs = self.converted(rhs, &operands, symbols);
+                match (op, operands) {
+                    (BinaryOperator::Add, Type::Int) => {
+                        format!("i32::wrapping_add({}, {})", lhs, rhs)
+                    }
+                    (BinaryOperator::Sub, Type::Int) => {
+                        format!("i32::wrapping_sub({}, {})", lhs, rhs)
+                    }
+                    (BinaryOperator::Mul, Type::Int) => {
+                        format!("i32::wrapping_mul({}, {})", lhs, rhs)
+                    }
+                    (BinaryOperator::Div | BinaryOperator::Mod, Type::Int) => {
+                        self.require(&[FAIL_RUNTIME, DIV_RUNTIME]);
+                        let helper = if *op == BinaryOperator::Div {
+                            "bprt_div"
+                        } else {
+                            "bprt_rem"
+                        };
+                        format!("{}({}, {})", helper, lhs, rhs)
+                    }
+                    (op, _) => format!("({} {} {})", lhs, op.c_symbol(), rhs),
+                }
+            }
+            ASTNode::UnaryOp { op, operand, .. } => {
+                let value = self.expression(operand, symbols);
+                match (op, self.checked_type(node, symbols)) {
+                    (UnaryOperator::Neg, Type::Int) => format!("i32::wrapping_neg({})", value),
+                    (op, _) => format!("({}{})", op.c_symbol(), value),
+                }
+            }
+            ASTNode::Call { name, args, .. } if name == "len" => {
+                match self.checked_type(&args[0], symbols) {
+                    // Array lengths are known at compile time
+                    Type::Array(len) => len.to_string(),
+                    // Strings are measured in bytes, as in C
+                    _ => format!("({}.len() as i32)", self.receiver(&args[0], symbols)),
+                }
+            }
+            // The other builtins call runtime helpers or the standard library
+            ASTNode::Call { name, args, .. } if builtin(name).is_some() => {
+                let result = match builtin(name).map(|builtin| &builtin.result) {
+                    Some(Returns::Nothing) => None,
+                    _ => Some(self.checked_type(node, symbols)),
+                };
+                match name.as_str() {
+                    // Only ASCII letters change, as in the C locale
+                    "upper" => format!("{}.to_ascii_uppercase()", self.receiver(&args[0], symbols)),
+                    "lower" => format!("{}.to_ascii_lowercase()", self.receiver(&args[0], symbols)),
+                    "substr" => {
+                        self.require(&[SUBSTR_RUNTIME]);
+                        format!(
+                            "bprt_substr({}, {}, {})",
+                            self.borrowed(&args[0], symbols),
+                            self.expression(&args[1], symbols),
+                            self.expression(&args[2], symbols)
+                        )
+                    }
+                    "to_str" => format!("{}.to_string()", self.expression(&args[0], symbols)),
+                    "to_int" => {
+                        self.require(&[FAIL_RUNTIME, PARSE_INT_RUNTIME, TO_INT_RUNTIME]);
+                        format!("bprt_to_int({})", self.borrowed(&args[0], symbols))
+                    }
+                    // Ints stay ints, and the smallest int is its own
+                    // absolute value, as in the interpreter
+                    "abs" | "min" | "max" | "pow" | "sqrt" => {
+                        let ty = result.expect("math builtins give a value");
+                        let args: Vec<String> = args
+                            .iter()
+                            .map(|arg| self.converted(arg, &ty, symbols))
+                            .collect();
+                        let function = match name.as_str() {
+                            "abs" if ty == Type::Int => "i32::wrapping_abs",
+                            "pow" => "f64::powf",
+                            name => {
+                                return format!("{}::{}({})", rs_type(&ty), name, args.join(", "))
+                            }
+                        };
+                        format!("{}({})", function, args.join(", "))
+                    }
+                    "random" | "seed" => {
+                        self.require(&[FAIL_RUNTIME, RANDOM_RUNTIME]);
+                        let function = if name == "seed" {
+                            self.seeded = true;
+                            "bprt_seed"
+                        } else {
+                            self.uses_random = true;
+                            "bprt_random"
+                        };
+                        format!("{}({})", function, self.expression(&args[0], symbols))
+                    }
+                    "env" => format!(
+                        "std::env::var({}).unwrap_or_default()",
+                        self.borrowed(&args[0], symbols)
+                    ),
+                    "read_file" => {
+                        self.require(&[FAIL_RUNTIME, FILE_RUNTIME]);
+                        format!("bprt_read_file({})", self.borrowed(&args[0], symbols))
+                    }
+                    "write_file" => {
+                        self.require(&[FAIL_RUNTIME, FILE_RUNTIME]);
+                        format!(
+                            "bprt_write_file({}, {})",
+                            self.borrowed(&args[0], symbols),
+                            self.borrowed(&args[1], symbols)
+                        )
+                    }
+                    "argc" => "(std::env::args().count() as i32)".to_string(),
+                    "arg" => {
+                        self.require(&[ARG_RUNTIME]);
+                        format!("bprt_arg({})", self.expression(&args[0], symbols))
+                    }
+                    "sleep_ms" => {
+                        self.require(&[SLEEP_RUNTIME]);
+                        format!("bprt_sleep_ms({})", self.expression(&args[0], symbols))
+                    }
+                    _ => unreachable!("builtin without Rust translation: {}", name),
+                }
+            }
+            ASTNode::Call { name, args, .. } => {
+                let params = self.functions[name].clone();
+                let args: Vec<String> = args
+                    .iter()
+                    .zip(&params)
+                    .map(|(arg, ty)| self.converted(arg, ty, symbols))
+                    .collect();
+                format!("{}({})", rs_name(name), args.join(", "))
+            }
+            _ => unreachable!("not an expression: {:?}", node),
+        }
+    }
+
+    // A condition, without the parentheses Rust does not want around it
+    fn condition(&mut self, node: &ASTNode, symbols: &SymbolTable) -> String {
+        unparenthesized(self.expression(node, symbols))
+    }
+
+    // Emit `nodes` one level deeper, as the body of a braced block. Blocks
+    // get their own copy of the symbols, as Rust scopes them.
+    fn block(&mut self, nodes: &[ASTNode], symbols: &SymbolTable, out: &mut CodeWriter) {
+        out.indent();
+        self.statements(nodes, &mut symbols.clone(), out);
+        out.dedent();
+    }
+
+    // Write the Rust translation of `nodes` to `out`. Variables declared
+    // here are recorded in `symbols`.
+    fn statements(&mut self, nodes: &[ASTNode], symbols: &mut SymbolTable, out: &mut CodeWriter) {
+        for node in nodes {
+            match node {
+                ASTNode::VariableDeclaration {
+                    name,
+                    mutable,
+                    ty,
+                    value,
+                    ..
+                } => {
+                    let declared = ty
+                        .as_ref()
+                        .map(|ty| Type::from_name(ty).expect("annotation was checked by analyze"));
+                    // It may be assigned more than once, whatever BP says
+                    let Some(value) = value else {
+                        let ty = declared.expect("the parser requires a value or a type");
+                        out.line(format!(
+                            "let mut {}: {} = {};",
+                            rs_name(name),
+                            rs_type(&ty),
+                            zero(&ty)
+                        ));
+                        symbols.insert(name.clone(), ty);
+                        continue;
+                    };
+                    let value_type = match value.as_ref() {
+                        ASTNode::ArrayLiteral(elements, _) => Type::Array(elements.len()),
+                        ASTNode::StructInit { name, .. } => Type::Struct(name.clone()),
+                        value => match declared {
+                            Some(declared) => declared,
+                            None => self.checked_type(value, symbols),
+                        },
+                    };
+                    // The value may read the variable being shadowed, which
+                    // Rust allows
+                    let value_code = unparenthesized(self.converted(value, &value_type, symbols));
+                    let keyword = if *mutable { "let mut" } else { "let" };
+                    out.line(format!(
+                        "{} {}: {} = {};",
+                        keyword,
+                        rs_name(name),
+                        rs_type(&value_type),
+                        value_code
+                    ));
+                    symbols.insert(name.clone(), value_type);
+                }
+                ASTNode::Assignment { name, value, .. } => {
+                    let ty = symbols[name].clone();
+                    let value = unparenthesized(self.converted(value, &ty, symbols));
+                    out.line(format!("{} = {};", rs_name(name), value));
+                }
+                ASTNode::FieldAssignment {
+                    name, field, value, ..
+                } => {
+                    let Some(Type::Struct(struct_name)) = symbols.get(name) else {
+                        unreachable!("field assignment to '{}' was type checked", name);
+                    };
+                    let ty = self.field_type(&struct_name.clone(), field);
+                    let value = unparenthesized(self.converted(value, &ty, symbols));
+                    out.line(format!("{}.{} = {};", rs_name(name), rs_name(field), value));
+                }
+                ASTNode::IndexAssignment {
+                    name, index, value, ..
+                } => {
+                    let target = self.element(name, index, symbols);
+                    let value = unparenthesized(self.expression(value, symbols));
+                    out.line(format!("{} = {};", target, value));
+                }
+                ASTNode::Show(values, _) => {
+                    let values: Vec<&ASTNode> = values.iter().collect();
+                    out.line(format!(
+                        "println!({});",
+                        self.format_parts(&values, symbols)
+                    ));
+                }
+                ASTNode::If {
+                    cond,
+                    then_block,
+                    else_block,
+                    ..
+                } => {
+                    out.line(format!("if {} {{", self.condition(cond, symbols)));
+                    self.block(then_block, symbols, out);
+                    // `else if` chains stay flat
+                    let mut else_block = else_block.as_deref();
+                    while let Some(
+                        [ASTNode::If {
+                            cond,
+                            then_block,
+                            else_block: next,
+                            ..
+                        }],
+                    ) = else_block
+                    {
+                        out.line(format!("}} else if {} {{", self.condition(cond, symbols)));
+                        self.block(then_block, symbols, out);
+                        else_block = next.as_deref();
+                    }
+                    if let Some(else_block) = else_block {
+                        out.line("} else {");
+                        self.block(else_block, symbols, out);
+                    }
+                    out.line("}");
+                }
+                // A `while`, since the end is evaluated before every
+                // iteration and the body may change the counter, in a block
+                // of its own so the counter goes out of scope after it.
+                // Every `continue` steps the counter itself, and the loop
+                // stops once it reaches the largest int.
+                ASTNode::For {
+                    var,
+                    start,
+                    end,
+                    body,
+                    ..
+                } => {
+                    let start = unparenthesized(self.expression(start, symbols));
+                    let mut body_symbols = symbols.clone();
+                    body_symbols.insert(var.clone(), Type::Int);
+                    let end = unparenthesized(self.expression(end, &body_symbols));
+                    let counter = rs_name(var);
+                    out.line("{");
+                    out.indent();
+                    out.line(format!("let mut {}: i32 = {};", counter, start));
+                    out.line(format!("while {} <= {} {{", counter, end));
+                    out.indent();
+                    self.loops.push(counter.clone());
+                    self.statements(body, &mut body_symbols, out);
+                    self.loops.pop();
+                    step(&counter, out);
+                    out.dedent();
+                    out.line("}");
+                    out.dedent();
+                    out.line("}");
+                }
+                ASTNode::Block(nodes) => {
+                    out.line("{");
+                    self.block(nodes, symbols, out);
+                    out.line("}");
+                }
+                ASTNode::Match {
+                    value,
+                    arms,
+                    else_block,
+                    ..
+                } => {
+                    let Type::Enum(enum_name) = self.checked_type(value, symbols) else {
+                        unreachable!("match value was type checked as an enum");
+                    };
+                    out.line(format!("match {} {{", self.condition(value, symbols)));
+                    out.indent();
+                    for arm in arms {
+                        out.line(format!("{} => {{", rs_variant(&enum_name, &arm.variant)));
+                        self.block(&arm.body, symbols, out);
+                        out.line("}");
+                    }
+                    // Rust wants every variant handled
+                    match else_block {
+                        Some(else_block) => {
+                            out.line("_ => {");
+                            self.block(else_block, symbols, out);
+                            out.line("}");
+                        }
+                        None if arms.len() < self.definitions.enums[&enum_name].len() => {
+                            out.line("_ => {}");
+                        }
+                        None => {}
+                    }
+                    out.dedent();
+                    out.line("}");
+                }
+                ASTNode::Call { .. } => {
+                    let call = self.expression(node, symbols);
+                    out.line(format!("{};", call));
+                }
+                ASTNode::Give { value, .. } => {
+                    let value = unparenthesized(self.expression(value, symbols));
+                    out.line(format!("return {};", value));
+                }
+                ASTNode::Input { name, .. } => {
+                    let helper = match symbols.get(name) {
+                        Some(Type::Int) => "bprt_input_int",
+                        Some(Type::Str) => "bprt_input_line",
+                        ty => unreachable!("input into {:?} was type checked", ty),
+                    };
+                    self.require(&[FAIL_RUNTIME, PARSE_INT_RUNTIME, INPUT_RUNTIME]);
+                    out.line(format!("{} = {}();", rs_name(name), helper));
+                }
+                // The message is fixed when compiling, position included
+                ASTNode::Assert {
+                    cond,
+                    message,
+                    span,
+                } => {
+                    let failure = assertion_failure(&self.source, *span, message.as_deref());
+                    out.line(format!("if !{} {{", self.expression(cond, symbols)));
+                    out.indent();
+                    out.line(format!("eprintln!(\"{}\");", escape_format(&failure)));
+                    out.line(format!("std::process::exit({});", ASSERT_EXIT_CODE));
+                    out.dedent();
+                    out.line("}");
+                }
+                ASTNode::Exit { value, .. } => {
+                    let value = unparenthesized(self.expression(value, symbols));
+                    out.line(format!("std::process::exit({});", value));
+                }
+                ASTNode::Break(_) => out.line("break;"),
+                ASTNode::Continue(_) => {
+                    let counter = self.loops.last().expect("analyze allows continue in loops");
+                    step(counter, out);
+                    out.line("continue;");
+                }
+                // Emitted ahead of main by `transpile`
+                ASTNode::Function { .. } | ASTNode::StructDef { .. } | ASTNode::EnumDef { .. } => {}
+                _ => {} // Handles other unhandled ASTNode variants
+            }
+        }
+    }
+}
+
+// Transpile a checked program into a complete Rust program. `source` names
+// the program in the messages of failed assertions.
+pub fn transpile(ast: &[ASTNode], source: &Path) -> Generated {
+    let mut generator = Generator {
+        source: source.to_path_buf(),
+        ..Generator::default()
+    };
+    generator.definitions.add_checked(ast);
+    let mut out = CodeWriter::new();
+
+    for node in ast {
+        match node {
+            ASTNode::StructDef { name, .. } => {
+                out.line(format!("struct {} {{", rs_type_name(name)));
+                out.indent();
+                for (field, ty) in &generator.definitions.structs[name] {
+                    out.line(format!("{}: {},", rs_name(field), rs_type(ty)));
+                }
+                out.dedent();
+                out.line("}");
+                out.blank();
+            }
+            // Enum values are shown by variant name
+            ASTNode::EnumDef { name, variants, .. } => {
+                out.line("#[derive(Clone, Copy, PartialEq)]");
+                out.line(format!("enum {} {{", rs_type_name(name)));
+                out.indent();
+                for variant in variants {
+                    out.line(format!("bpv_{},", variant));
+                }
+                out.dedent();
+                out.line("}");
+                out.blank();
+                out.line(format!(
+                    "impl std::fmt::Display for {} {{",
+                    rs_type_name(name)
+                ));
+                out.indent();
+                out.line("fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {");
+                out.indent();
+                out.line("f.write_str(match self {");
+                out.indent();
+                for variant in variants {
+                    out.line(format!("{} => \"{}\",", rs_variant(name, variant), variant));
+                }
+                out.dedent();
+                out.line("})");
+                out.dedent();
+                out.line("}");
+                out.dedent();
+                out.line("}");
+                out.blank();
+            }
+            ASTNode::Function { name, params, .. } => {
+                let params = params
+                    .iter()
+                    .map(|param| parameter_type(param).expect("parameter was checked by analyze"))
+                    .collect();
+                generator.functions.insert(name.clone(), params);
+            }
+            _ => {}
+        }
+    }
+
+    // Parameters are assignable in BP, so they are all `mut`
+    for node in ast {
+        if let ASTNode::Function {
+            name, params, body, ..
+        } = node
+        {
+            let mut symbols = SymbolTable::new();
+            let params: Vec<String> = params
+                .iter()
+                .map(|param| {
+                    let ty = parameter_type(param).expect("parameter was checked by analyze");
+                    let param_code = format!("mut {}: {}", rs_name(&param.name), rs_type(&ty));
+                    symbols.insert(param.name.clone(), ty);
+                    param_code
+                })
+                .collect();
+            out.line(format!(
+                "fn {}({}) -> i32 {{",
+                rs_name(name),
+                params.join(", ")
+            ));
+            out.indent();
+            generator.statements(body, &mut symbols, &mut out);
+            // Falling off the end of a function gives 0
+            out.line("return 0;");
+            out.dedent();
+            out.line("}");
+            out.blank();
+        }
+    }
+
+    let mut main = CodeWriter::new();
+    main.indent();
+    generator.statements(ast, &mut SymbolTable::new(), &mut main);
+    out.line("fn main() {");
+    // Unseeded programs get different numbers on every run
+    if generator.uses_random && !generator.seeded {
+        out.indent();
+        out.line("bprt_seed(");
+        out.indent();
+        out.line("std::time::SystemTime::now()");
+        out.indent();
+        out.line(".duration_since(std::time::UNIX_EPOCH)");
+        out.line(".map_or(0, |elapsed| elapsed.as_secs() as i32),");
+        out.dedent();
+        out.dedent();
+        out.line(");");
+        out.dedent();
+    }
+    let mut code = out.finish() + &main.finish();
+    code.push_str("}\n");
+
+    // Only now is it known which helpers the code needs. The generated code
+    // keeps BP's names and may leave values unused, which is no reason for
+    // warnings.
+    let mut prelude = String::from("#![allow(warnings)]\n\n");
+    for helper in &generator.helpers {
+        prelude.push_str(helper);
+        prelude.push('\n');
+    }
+    Generated {
+        code: prelude + &code,
+        libraries: Vec::new(),
+    }
+}
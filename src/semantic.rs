@@ -0,0 +1,1877 @@
+//! Name resolution and type checking on the parsed program
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{ASTNode, BinaryOperator, FieldDef, Parameter, UnaryOperator};
+use crate::codegen::raw_c_names;
+use crate::codes;
+use crate::lexer::Span;
+use crate::visit::{walk_block, walk_expression, walk_statement, Visitor};
+
+// Error found by the semantic checks on an otherwise well-formed AST
+#[derive(Debug, PartialEq)]
+pub struct SemanticError {
+    // Registered in `codes`
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SemanticError {}
+
+// Suspicious but valid code found by the semantic checks. Warnings do not
+// stop compilation unless `--deny-warnings` is given.
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    // Registered in `codes`
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// Static type of a BP value. Arrays hold ints and know their length;
+// structs and enums are known by name.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+    Int,
+    // A 64-bit int
+    Long,
+    Float,
+    Bool,
+    Str,
+    Array(usize),
+    Struct(String),
+    Enum(String),
+}
+
+impl Type {
+    // Type written in an annotation
+    pub(crate) fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Int),
+            "long" => Some(Type::Long),
+            "float" => Some(Type::Float),
+            "bool" => Some(Type::Bool),
+            "str" => Some(Type::Str),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Long | Type::Float)
+    }
+
+    // Whether a value of type `from` may be stored where this type is
+    // expected. An int converts to a long or a float, as in C; nothing else
+    // converts implicitly.
+    pub(crate) fn widens(&self, from: &Type) -> bool {
+        *from == Type::Int && matches!(self, Type::Long | Type::Float)
+    }
+
+    // Type of arithmetic on two numbers: a float if either is one, else a
+    // long if either is one, following C's usual arithmetic conversions
+    fn promoted(&self, other: &Type) -> Type {
+        if *self == Type::Float || *other == Type::Float {
+            Type::Float
+        } else if *self == Type::Long || *other == Type::Long {
+            Type::Long
+        } else {
+            Type::Int
+        }
+    }
+
+    // Whether values of this type are whole aggregates, which cannot be
+    // shown, compared or concatenated
+    fn is_aggregate(&self) -> bool {
+        matches!(self, Type::Array(_) | Type::Struct(_))
+    }
+
+    // Type name preceded by an indefinite article, for diagnostics
+    fn with_article(&self) -> String {
+        match self {
+            Type::Int | Type::Array(_) => format!("an {}", self),
+            Type::Struct(name) => format!("struct {}", name),
+            Type::Enum(name) => format!("enum {}", name),
+            _ => format!("a {}", self),
+        }
+    }
+
+    pub(crate) fn c_type(&self) -> &'static str {
+        match self {
+            Type::Int => "int",
+            Type::Long => "long long",
+            Type::Float => "double",
+            Type::Bool => "bool",
+            Type::Str => "const char *",
+            Type::Array(_) => unreachable!("arrays are declared by emit_statements"),
+            Type::Struct(_) | Type::Enum(_) => {
+                unreachable!("defined types are named by the code generator")
+            }
+        }
+    }
+
+    // printf conversion used to show a value of this type. Bools are
+    // shown as `true`/`false` through `show_argument`.
+    pub(crate) fn printf_format(&self) -> &'static str {
+        match self {
+            Type::Int => "%d",
+            Type::Long => "%lld",
+            Type::Float => "%g",
+            // Enums are shown by variant name
+            Type::Bool | Type::Str | Type::Enum(_) => "%s",
+            Type::Array(_) | Type::Struct(_) => unreachable!("aggregates cannot be shown"),
+        }
+    }
+}
+
+// The structs and enums a program defines: the fields of each struct and
+// the variants of each enum, in declaration order. The C functions it
+// declares are kept too, since their calls are typed by their result.
+#[derive(Default)]
+pub(crate) struct Definitions {
+    pub structs: HashMap<String, Vec<(String, Type)>>,
+    pub enums: HashMap<String, Vec<String>>,
+    // Result types of the functions declared with `extern fn`
+    pub externs: HashMap<String, Type>,
+}
+
+impl Definitions {
+    // Record the definitions among `nodes`, which passed `analyze`
+    pub(crate) fn add_checked(&mut self, nodes: &[ASTNode]) {
+        for node in nodes {
+            match node {
+                ASTNode::StructDef { name, fields, span } => {
+                    let fields =
+                        struct_fields(name, fields, *span).expect("struct was checked by analyze");
+                    self.structs.insert(name.clone(), fields);
+                }
+                ASTNode::EnumDef { name, variants, .. } => {
+                    self.enums.insert(name.clone(), variants.clone());
+                }
+                ASTNode::ExternFunction {
+                    name, result, span, ..
+                } => {
+                    let ty = extern_result(name, result.as_deref(), *span)
+                        .expect("extern was checked by analyze");
+                    self.externs.insert(name.clone(), ty);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// Fields of a struct definition with their types. Fields without an
+// annotation hold ints.
+pub(crate) fn struct_fields(
+    name: &str,
+    fields: &[FieldDef],
+    span: Span,
+) -> Result<Vec<(String, Type)>, SemanticError> {
+    // C does not allow a struct without members
+    if fields.is_empty() {
+        return Err(SemanticError {
+            code: codes::EMPTY_DEFINITION,
+            message: format!("struct '{}' needs at least one field", name),
+            span,
+        });
+    }
+    let mut typed: Vec<(String, Type)> = Vec::with_capacity(fields.len());
+    for field in fields {
+        if typed.iter().any(|(other, _)| *other == field.name) {
+            return Err(SemanticError {
+                code: codes::DUPLICATE_DEFINITION,
+                message: format!("duplicate field '{}' in struct '{}'", field.name, name),
+                span: field.span,
+            });
+        }
+        let ty = match &field.ty {
+            None => Type::Int,
+            Some(ty) => annotation_type(ty, &format!("field '{}'", field.name), field.span)?,
+        };
+        typed.push((field.name.clone(), ty));
+    }
+    Ok(typed)
+}
+
+// Type named by the annotation `ty` of `what`
+fn annotation_type(ty: &str, what: &str, span: Span) -> Result<Type, SemanticError> {
+    Type::from_name(ty).ok_or_else(|| SemanticError {
+        code: codes::UNKNOWN_TYPE,
+        message: format!(
+            "unknown type '{}' for {} (expected int, long, float, bool or str)",
+            ty, what
+        ),
+        span,
+    })
+}
+
+// Type of a function parameter, an int unless annotated
+pub(crate) fn parameter_type(param: &Parameter) -> Result<Type, SemanticError> {
+    match &param.ty {
+        None => Ok(Type::Int),
+        Some(ty) => annotation_type(ty, &format!("parameter '{}'", param.name), param.span),
+    }
+}
+
+// Result type of the extern function `name`, an int unless annotated
+pub(crate) fn extern_result(
+    name: &str,
+    result: Option<&str>,
+    span: Span,
+) -> Result<Type, SemanticError> {
+    match result {
+        None => Ok(Type::Int),
+        Some(ty) => annotation_type(ty, &format!("the result of '{}'", name), span),
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Type::Int => "int",
+            Type::Long => "long",
+            Type::Float => "float",
+            Type::Bool => "bool",
+            Type::Str => "string",
+            Type::Array(len) => return write!(f, "int[{}]", len),
+            Type::Struct(name) | Type::Enum(name) => name,
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Value of an index written as a literal, which can be range checked before
+// the program runs
+fn constant_index(index: &ASTNode) -> Option<i64> {
+    match index {
+        ASTNode::NumberLiteral(num) => Some(*num as i64),
+        ASTNode::UnaryOp {
+            op: UnaryOperator::Neg,
+            operand,
+            ..
+        } => constant_index(operand).map(|num| -num),
+        _ => None,
+    }
+}
+
+// Check indexing `name`, of type `array`, with an index of type `index_ty`.
+// Indices only known at run time are not bounds checked.
+fn check_index(
+    name: &str,
+    array: &Type,
+    index: &ASTNode,
+    index_ty: Type,
+    span: Span,
+) -> Result<(), SemanticError> {
+    let &Type::Array(len) = array else {
+        return Err(SemanticError {
+            code: codes::TYPE_MISMATCH,
+            message: format!("cannot index '{}', which is {}", name, array.with_article()),
+            span,
+        });
+    };
+    if index_ty != Type::Int {
+        return Err(SemanticError {
+            code: codes::TYPE_MISMATCH,
+            message: format!(
+                "type mismatch: the index of '{}' must be an int but is {}",
+                name,
+                index_ty.with_article()
+            ),
+            span,
+        });
+    }
+    match constant_index(index) {
+        Some(position) if position < 0 || position >= len as i64 => Err(SemanticError {
+            code: codes::OUT_OF_RANGE,
+            message: format!(
+                "index {} is out of range for '{}' of length {}",
+                position, name, len
+            ),
+            span,
+        }),
+        _ => Ok(()),
+    }
+}
+
+// Type of field `field` of variable `name`, which has type `ty`
+fn field_type(
+    name: &str,
+    ty: &Type,
+    field: &str,
+    definitions: &Definitions,
+    span: Span,
+) -> Result<Type, SemanticError> {
+    let Type::Struct(struct_name) = ty else {
+        return Err(SemanticError {
+            code: codes::TYPE_MISMATCH,
+            message: format!(
+                "cannot access field '{}' of '{}', which is {}",
+                field,
+                name,
+                ty.with_article()
+            ),
+            span,
+        });
+    };
+    definitions.structs[struct_name]
+        .iter()
+        .find(|(other, _)| other == field)
+        .map(|(_, ty)| ty.clone())
+        .ok_or_else(|| SemanticError {
+            code: codes::NO_SUCH_MEMBER,
+            message: format!("struct '{}' has no field '{}'", struct_name, field),
+            span,
+        })
+}
+
+// Infer the type of an expression, given the types of the variables in
+// scope and the structs and enums defined. Arithmetic involving a float yields a
+// float, else involving a long a long, following C's usual arithmetic
+// conversions, and `+` with a string
+// operand concatenates. Call arguments are checked by the analyzer; calls
+// to user functions yield ints.
+pub(crate) fn expression_type(
+    node: &ASTNode,
+    variables: &dyn Fn(&str) -> Option<Type>,
+    definitions: &Definitions,
+) -> Result<Type, SemanticError> {
+    match node {
+        ASTNode::NumberLiteral(_) => Ok(Type::Int),
+        ASTNode::LongLiteral(_) => Ok(Type::Long),
+        ASTNode::FloatLiteral(_) => Ok(Type::Float),
+        ASTNode::BoolLiteral(_) => Ok(Type::Bool),
+        ASTNode::StringLiteral(_) => Ok(Type::Str),
+        ASTNode::Identifier(name, span) => variables(name).ok_or_else(|| SemanticError {
+            code: codes::UNDECLARED_VARIABLE,
+            message: format!("use of undeclared variable '{}'", name),
+            span: *span,
+        }),
+        ASTNode::ArrayLiteral(_, span) => Err(SemanticError {
+            code: codes::MISPLACED_LITERAL,
+            message: "an array literal can only initialize a variable".to_string(),
+            span: *span,
+        }),
+        ASTNode::Index { name, index, span } => {
+            let array = variables(name).ok_or_else(|| SemanticError {
+                code: codes::UNDECLARED_VARIABLE,
+                message: format!("use of undeclared variable '{}'", name),
+                span: *span,
+            })?;
+            let index_ty = expression_type(index, variables, definitions)?;
+            check_index(name, &array, index, index_ty, *span)?;
+            Ok(Type::Int)
+        }
+        ASTNode::StructInit { span, .. } => Err(SemanticError {
+            code: codes::MISPLACED_LITERAL,
+            message: "a struct literal can only initialize a variable".to_string(),
+            span: *span,
+        }),
+        // `name.field` names an enum variant when no variable is called
+        // `name`
+        ASTNode::FieldAccess { name, field, span } => match variables(name) {
+            Some(ty) => field_type(name, &ty, field, definitions, *span),
+            None => match definitions.enums.get(name) {
+                Some(variants) if variants.contains(field) => Ok(Type::Enum(name.clone())),
+                Some(_) => Err(SemanticError {
+                    code: codes::NO_SUCH_MEMBER,
+                    message: format!("enum '{}' has no variant '{}'", name, field),
+                    span: *span,
+                }),
+                None => Err(SemanticError {
+                    code: codes::UNDECLARED_VARIABLE,
+                    message: format!("use of undeclared variable '{}'", name),
+                    span: *span,
+                }),
+            },
+        },
+        ASTNode::BinaryOp { op, lhs, rhs, span } => {
+            let lhs = expression_type(lhs, variables, definitions)?;
+            let rhs = expression_type(rhs, variables, definitions)?;
+            let result = match op {
+                // Adding to a string appends the other value as `show`
+                // would print it
+                BinaryOperator::Add
+                    if (lhs == Type::Str || rhs == Type::Str)
+                        && !lhs.is_aggregate()
+                        && !rhs.is_aggregate() =>
+                {
+                    Some(Type::Str)
+                }
+                BinaryOperator::Add
+                | BinaryOperator::Sub
+                | BinaryOperator::Mul
+                | BinaryOperator::Div
+                    if lhs.is_numeric() && rhs.is_numeric() =>
+                {
+                    Some(lhs.promoted(&rhs))
+                }
+                BinaryOperator::Mod
+                    if matches!(lhs, Type::Int | Type::Long)
+                        && matches!(rhs, Type::Int | Type::Long) =>
+                {
+                    Some(lhs.promoted(&rhs))
+                }
+                BinaryOperator::Lt
+                | BinaryOperator::Gt
+                | BinaryOperator::Le
+                | BinaryOperator::Ge
+                    if lhs.is_numeric() && rhs.is_numeric() =>
+                {
+                    Some(Type::Bool)
+                }
+                // Strings are not comparable: C would compare the pointers
+                BinaryOperator::Eq | BinaryOperator::Ne
+                    if (lhs.is_numeric() && rhs.is_numeric())
+                        || (lhs == Type::Bool && rhs == Type::Bool)
+                        || (matches!(lhs, Type::Enum(_)) && lhs == rhs) =>
+                {
+                    Some(Type::Bool)
+                }
+                BinaryOperator::And | BinaryOperator::Or
+                    if lhs == Type::Bool && rhs == Type::Bool =>
+                {
+                    Some(Type::Bool)
+                }
+                _ => None,
+            };
+            result.ok_or_else(|| SemanticError {
+                code: codes::TYPE_MISMATCH,
+                message: format!("operator '{}' cannot be applied to {} and {}", op, lhs, rhs),
+                span: *span,
+            })
+        }
+        ASTNode::UnaryOp { op, operand, span } => {
+            let operand = expression_type(operand, variables, definitions)?;
+            match op {
+                UnaryOperator::Neg if operand.is_numeric() => Ok(operand),
+                UnaryOperator::Not if operand == Type::Bool => Ok(Type::Bool),
+                _ => Err(SemanticError {
+                    code: codes::TYPE_MISMATCH,
+                    message: format!("operator '{}' cannot be applied to {}", op, operand),
+                    span: *span,
+                }),
+            }
+        }
+        ASTNode::Call { name, args, span } => match builtin(name).map(|builtin| &builtin.result) {
+            None => Ok(definitions.externs.get(name).cloned().unwrap_or(Type::Int)),
+            Some(Returns::Nothing) => Err(SemanticError {
+                code: codes::NO_VALUE,
+                message: format!("function '{}' does not give a value", name),
+                span: *span,
+            }),
+            Some(Returns::Is(ty)) => Ok(ty.clone()),
+            Some(Returns::Widened) => {
+                let mut result = Type::Int;
+                for arg in args {
+                    if expression_type(arg, variables, definitions)? == Type::Float {
+                        result = Type::Float;
+                    }
+                }
+                Ok(result)
+            }
+        },
+        _ => unreachable!("not an expression: {:?}", node),
+    }
+}
+
+// Type a builtin function accepts for one of its parameters
+#[derive(Debug, Clone)]
+pub(crate) enum Param {
+    Is(Type),
+    // Anything with a length: an array or a string
+    Sized,
+    // An int or a float
+    Numeric,
+}
+
+impl Param {
+    fn accepts(&self, ty: &Type) -> bool {
+        match self {
+            Param::Is(expected) => ty == expected,
+            Param::Sized => matches!(ty, Type::Array(_) | Type::Str),
+            Param::Numeric => matches!(ty, Type::Int | Type::Float),
+        }
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Param::Is(ty) => write!(f, "{}", ty.with_article()),
+            Param::Sized => write!(f, "an array or a string"),
+            Param::Numeric => write!(f, "an int or a float"),
+        }
+    }
+}
+
+// Result type of a builtin function
+#[derive(Debug, Clone)]
+pub(crate) enum Returns {
+    Is(Type),
+    // A float if any argument is one, else an int, like arithmetic
+    Widened,
+    // Called as a statement only
+    Nothing,
+}
+
+// A function provided by the language rather than defined with `fn`
+pub(crate) struct Builtin {
+    pub name: &'static str,
+    pub params: &'static [Param],
+    pub result: Returns,
+}
+
+pub(crate) const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "len",
+        params: &[Param::Sized],
+        result: Returns::Is(Type::Int),
+    },
+    Builtin {
+        name: "upper",
+        params: &[Param::Is(Type::Str)],
+        result: Returns::Is(Type::Str),
+    },
+    Builtin {
+        name: "lower",
+        params: &[Param::Is(Type::Str)],
+        result: Returns::Is(Type::Str),
+    },
+    Builtin {
+        name: "substr",
+        params: &[
+            Param::Is(Type::Str),
+            Param::Is(Type::Int),
+            Param::Is(Type::Int),
+        ],
+        result: Returns::Is(Type::Str),
+    },
+    Builtin {
+        name: "to_str",
+        params: &[Param::Is(Type::Int)],
+        result: Returns::Is(Type::Str),
+    },
+    Builtin {
+        name: "to_int",
+        params: &[Param::Is(Type::Str)],
+        result: Returns::Is(Type::Int),
+    },
+    Builtin {
+        name: "abs",
+        params: &[Param::Numeric],
+        result: Returns::Widened,
+    },
+    Builtin {
+        name: "min",
+        params: &[Param::Numeric, Param::Numeric],
+        result: Returns::Widened,
+    },
+    Builtin {
+        name: "max",
+        params: &[Param::Numeric, Param::Numeric],
+        result: Returns::Widened,
+    },
+    Builtin {
+        name: "pow",
+        params: &[Param::Numeric, Param::Numeric],
+        result: Returns::Is(Type::Float),
+    },
+    Builtin {
+        name: "sqrt",
+        params: &[Param::Numeric],
+        result: Returns::Is(Type::Float),
+    },
+    Builtin {
+        name: "random",
+        params: &[Param::Is(Type::Int)],
+        result: Returns::Is(Type::Int),
+    },
+    Builtin {
+        name: "seed",
+        params: &[Param::Is(Type::Int)],
+        result: Returns::Nothing,
+    },
+    Builtin {
+        name: "env",
+        params: &[Param::Is(Type::Str)],
+        result: Returns::Is(Type::Str),
+    },
+    Builtin {
+        name: "read_file",
+        params: &[Param::Is(Type::Str)],
+        result: Returns::Is(Type::Str),
+    },
+    Builtin {
+        name: "write_file",
+        params: &[Param::Is(Type::Str), Param::Is(Type::Str)],
+        result: Returns::Nothing,
+    },
+    Builtin {
+        name: "argc",
+        params: &[],
+        result: Returns::Is(Type::Int),
+    },
+    Builtin {
+        name: "arg",
+        params: &[Param::Is(Type::Int)],
+        result: Returns::Is(Type::Str),
+    },
+    Builtin {
+        name: "sleep_ms",
+        params: &[Param::Is(Type::Int)],
+        result: Returns::Nothing,
+    },
+];
+
+pub(crate) fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+// What the semantic checks know about a declared variable. `assigned` is
+// whether the variable surely holds a value at the statement being checked,
+// which only a declaration without one leaves unset.
+struct Binding {
+    mutable: bool,
+    ty: Type,
+    span: Span,
+    assigned: Cell<bool>,
+}
+
+// What the semantic checks know about a defined function
+struct FunctionInfo {
+    params: Vec<Type>,
+    span: Span,
+}
+
+// Walks the AST with a stack of block scopes, checking rules the parser
+// cannot see on its own
+pub struct Analyzer {
+    scopes: Vec<HashMap<String, Binding>>,
+    functions: HashMap<String, FunctionInfo>,
+    definitions: Definitions,
+    // Where each struct and enum is defined, for duplicate definitions
+    type_spans: HashMap<String, Span>,
+    in_function: bool,
+    warnings: Vec<Warning>,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        Analyzer {
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+            definitions: Definitions::default(),
+            type_spans: HashMap::new(),
+            in_function: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    // Record every top-level function up front, so calls may appear before
+    // the definition. Extern functions are recorded the same way.
+    fn collect_functions(&mut self, nodes: &[ASTNode]) -> Result<(), SemanticError> {
+        for node in nodes {
+            if let ASTNode::Function {
+                name, params, span, ..
+            }
+            | ASTNode::ExternFunction {
+                name, params, span, ..
+            } = node
+            {
+                if builtin(name).is_some() {
+                    return Err(SemanticError {
+                        code: codes::DUPLICATE_DEFINITION,
+                        message: format!(
+                            "function '{}' is a builtin and cannot be redefined",
+                            name
+                        ),
+                        span: *span,
+                    });
+                }
+                if let Some(previous) = self.functions.get(name) {
+                    return Err(SemanticError {
+                        code: codes::DUPLICATE_DEFINITION,
+                        message: format!(
+                            "function '{}' is already defined at line {}:{}",
+                            name, previous.span.line, previous.span.col
+                        ),
+                        span: *span,
+                    });
+                }
+                let params = params
+                    .iter()
+                    .map(parameter_type)
+                    .collect::<Result<_, _>>()?;
+                if let ASTNode::ExternFunction { result, .. } = node {
+                    let ty = extern_result(name, result.as_deref(), *span)?;
+                    self.definitions.externs.insert(name.clone(), ty);
+                }
+                self.functions.insert(
+                    name.clone(),
+                    FunctionInfo {
+                        params,
+                        span: *span,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Record every top-level struct and enum up front, so they may be used
+    // before their definition like in the generated C, where all typedefs
+    // come first
+    fn collect_types(&mut self, nodes: &[ASTNode]) -> Result<(), SemanticError> {
+        for node in nodes {
+            let (name, span) = match node {
+                ASTNode::StructDef { name, span, .. } | ASTNode::EnumDef { name, span, .. } => {
+                    (name, *span)
+                }
+                _ => continue,
+            };
+            if let Some(previous) = self.type_spans.get(name) {
+                return Err(SemanticError {
+                    code: codes::DUPLICATE_DEFINITION,
+                    message: format!(
+                        "type '{}' is already defined at line {}:{}",
+                        name, previous.line, previous.col
+                    ),
+                    span,
+                });
+            }
+            match node {
+                ASTNode::StructDef { fields, .. } => {
+                    let fields = struct_fields(name, fields, span)?;
+                    self.definitions.structs.insert(name.clone(), fields);
+                }
+                ASTNode::EnumDef { variants, .. } => {
+                    // C does not allow an empty enum either
+                    if variants.is_empty() {
+                        return Err(SemanticError {
+                            code: codes::EMPTY_DEFINITION,
+                            message: format!("enum '{}' needs at least one variant", name),
+                            span,
+                        });
+                    }
+                    for (position, variant) in variants.iter().enumerate() {
+                        if variants[..position].contains(variant) {
+                            return Err(SemanticError {
+                                code: codes::DUPLICATE_DEFINITION,
+                                message: format!(
+                                    "duplicate variant '{}' in enum '{}'",
+                                    variant, name
+                                ),
+                                span,
+                            });
+                        }
+                    }
+                    self.definitions
+                        .enums
+                        .insert(name.clone(), variants.clone());
+                }
+                _ => unreachable!("only type definitions get here"),
+            }
+            self.type_spans.insert(name.clone(), span);
+        }
+        Ok(())
+    }
+
+    // Check that every call in the expression targets a defined function with
+    // arguments of the right count and types, and that every variable read
+    // has been assigned, then infer the expression's type
+    pub fn check_expression(&self, node: &ASTNode) -> Result<Type, SemanticError> {
+        self.check_calls(node)?;
+        self.check_assigned(node)?;
+        expression_type(
+            node,
+            &|name| self.lookup(name).map(|binding| binding.ty.clone()),
+            &self.definitions,
+        )
+    }
+
+    fn check_calls(&self, node: &ASTNode) -> Result<(), SemanticError> {
+        match node {
+            ASTNode::Call { name, args, span } if builtin(name).is_some() => {
+                self.check_builtin(name, args, *span)
+            }
+            ASTNode::Call { name, args, span } => {
+                let Some(function) = self.functions.get(name) else {
+                    return Err(SemanticError {
+                        code: codes::UNDEFINED_FUNCTION,
+                        message: format!("call to undefined function '{}'", name),
+                        span: *span,
+                    });
+                };
+                if function.params.len() != args.len() {
+                    return Err(SemanticError {
+                        code: codes::ARGUMENT_COUNT,
+                        message: format!(
+                            "function '{}' takes {} argument(s) but {} were given",
+                            name,
+                            function.params.len(),
+                            args.len()
+                        ),
+                        span: *span,
+                    });
+                }
+                for (position, (param, arg)) in function.params.iter().zip(args).enumerate() {
+                    let ty = self.check_expression(arg)?;
+                    let widening = param.widens(&ty);
+                    if !widening {
+                        self.expect_type(
+                            ty,
+                            param.clone(),
+                            &format!("argument {} of '{}'", position + 1, name),
+                            *span,
+                        )?;
+                    }
+                }
+                Ok(())
+            }
+            ASTNode::BinaryOp { lhs, rhs, .. } => {
+                self.check_calls(lhs)?;
+                self.check_calls(rhs)
+            }
+            ASTNode::UnaryOp { operand, .. } => self.check_calls(operand),
+            ASTNode::Index { index, .. } => self.check_calls(index),
+            ASTNode::ArrayLiteral(elements, _) => elements
+                .iter()
+                .try_for_each(|element| self.check_calls(element)),
+            ASTNode::StructInit { fields, .. } => fields
+                .iter()
+                .try_for_each(|(_, value)| self.check_calls(value)),
+            _ => Ok(()),
+        }
+    }
+
+    // Fail when the expression reads a variable declared without a value
+    // before it is assigned one
+    fn check_assigned(&self, node: &ASTNode) -> Result<(), SemanticError> {
+        match node {
+            ASTNode::Identifier(name, span) => match self.lookup(name) {
+                Some(binding) if !binding.assigned.get() => Err(SemanticError {
+                    code: codes::USED_BEFORE_ASSIGNED,
+                    message: format!("variable '{}' is used before it is assigned", name),
+                    span: *span,
+                }),
+                _ => Ok(()),
+            },
+            ASTNode::BinaryOp { lhs, rhs, .. } => {
+                self.check_assigned(lhs)?;
+                self.check_assigned(rhs)
+            }
+            ASTNode::UnaryOp { operand, .. } => self.check_assigned(operand),
+            ASTNode::Index { index, .. } => self.check_assigned(index),
+            ASTNode::Call { args, .. } | ASTNode::ArrayLiteral(args, _) => {
+                args.iter().try_for_each(|arg| self.check_assigned(arg))
+            }
+            ASTNode::StructInit { fields, .. } => fields
+                .iter()
+                .try_for_each(|(_, value)| self.check_assigned(value)),
+            _ => Ok(()),
+        }
+    }
+
+    // Check the arguments of a call to a builtin function
+    fn check_builtin(&self, name: &str, args: &[ASTNode], span: Span) -> Result<(), SemanticError> {
+        let builtin = builtin(name).expect("called for builtins only");
+        if builtin.params.len() != args.len() {
+            return Err(SemanticError {
+                code: codes::ARGUMENT_COUNT,
+                message: format!(
+                    "function '{}' takes {} argument(s) but {} were given",
+                    name,
+                    builtin.params.len(),
+                    args.len()
+                ),
+                span,
+            });
+        }
+        for (position, (param, arg)) in builtin.params.iter().zip(args).enumerate() {
+            let ty = self.check_expression(arg)?;
+            if !param.accepts(&ty) {
+                return Err(SemanticError {
+                    code: codes::TYPE_MISMATCH,
+                    message: format!(
+                        "type mismatch: argument {} of '{}' must be {} but is {}",
+                        position + 1,
+                        name,
+                        param,
+                        ty.with_article()
+                    ),
+                    span,
+                });
+            }
+        }
+        // Constant positions are checked up front, like array indices
+        if name == "substr" {
+            for (arg, what) in args[1..].iter().zip(["start", "length"]) {
+                match constant_index(arg) {
+                    Some(value) if value < 0 => {
+                        return Err(SemanticError {
+                            code: codes::OUT_OF_RANGE,
+                            message: format!("the {} of 'substr' cannot be negative", what),
+                            span,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if name == "sleep_ms" && constant_index(&args[0]).is_some_and(|value| value < 0) {
+            return Err(SemanticError {
+                code: codes::OUT_OF_RANGE,
+                message: "the duration of 'sleep_ms' cannot be negative".to_string(),
+                span,
+            });
+        }
+        Ok(())
+    }
+
+    // Fail unless `ty` is `expected`; `what` names the value in the message
+    fn expect_type(
+        &self,
+        ty: Type,
+        expected: Type,
+        what: &str,
+        span: Span,
+    ) -> Result<(), SemanticError> {
+        if ty == expected {
+            return Ok(());
+        }
+        Err(SemanticError {
+            code: codes::TYPE_MISMATCH,
+            message: format!(
+                "type mismatch: {} must be {} but is {}",
+                what,
+                expected.with_article(),
+                ty.with_article()
+            ),
+            span,
+        })
+    }
+
+    // Type of an array literal initializing a variable: every element is an
+    // int, and C does not allow an empty array
+    fn check_array_literal(&self, elements: &[ASTNode], span: Span) -> Result<Type, SemanticError> {
+        if elements.is_empty() {
+            return Err(SemanticError {
+                code: codes::EMPTY_DEFINITION,
+                message: "an array needs at least one element".to_string(),
+                span,
+            });
+        }
+        for (position, element) in elements.iter().enumerate() {
+            let ty = self.check_expression(element)?;
+            self.expect_type(
+                ty,
+                Type::Int,
+                &format!("array element {}", position + 1),
+                span,
+            )?;
+        }
+        Ok(Type::Array(elements.len()))
+    }
+
+    // Type of a struct literal initializing a variable: every field of the
+    // struct is given once, with a value of its type
+    fn check_struct_literal(
+        &self,
+        name: &str,
+        fields: &[(String, ASTNode)],
+        span: Span,
+    ) -> Result<Type, SemanticError> {
+        let Some(definition) = self.definitions.structs.get(name) else {
+            return Err(SemanticError {
+                code: codes::UNKNOWN_TYPE,
+                message: format!("use of undefined struct '{}'", name),
+                span,
+            });
+        };
+        for (position, (field, value)) in fields.iter().enumerate() {
+            let Some((_, expected)) = definition.iter().find(|(other, _)| other == field) else {
+                return Err(SemanticError {
+                    code: codes::NO_SUCH_MEMBER,
+                    message: format!("struct '{}' has no field '{}'", name, field),
+                    span,
+                });
+            };
+            if fields[..position].iter().any(|(other, _)| other == field) {
+                return Err(SemanticError {
+                    code: codes::DUPLICATE_DEFINITION,
+                    message: format!("field '{}' of '{}' is given more than once", field, name),
+                    span,
+                });
+            }
+            let ty = self.check_expression(value)?;
+            let widening = expected.widens(&ty);
+            if !widening {
+                self.expect_type(
+                    ty,
+                    expected.clone(),
+                    &format!("field '{}.{}'", name, field),
+                    span,
+                )?;
+            }
+        }
+        if let Some((missing, _)) = definition
+            .iter()
+            .find(|(field, _)| !fields.iter().any(|(given, _)| given == field))
+        {
+            return Err(SemanticError {
+                code: codes::MISSING_FIELD,
+                message: format!(
+                    "missing field '{}' in literal of struct '{}'",
+                    missing, name
+                ),
+                span,
+            });
+        }
+        Ok(Type::Struct(name.to_string()))
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // Declare `name` in the innermost scope, warning when it hides a
+    // variable of an enclosing block
+    fn declare(&mut self, name: &str, binding: Binding) {
+        if let Some(outer) = self.lookup(name) {
+            self.warnings.push(Warning {
+                code: codes::SHADOWED_VARIABLE,
+                message: format!(
+                    "'{}' shadows the variable declared at line {}:{}",
+                    name, outer.span.line, outer.span.col
+                ),
+                span: binding.span,
+            });
+        }
+        self.scopes
+            .last_mut()
+            .expect("analyzer always has a scope")
+            .insert(name.to_string(), binding);
+    }
+
+    // Declare a variable of type `ty` after checking it is not already
+    // declared in the innermost scope. Redeclaring there is an error
+    // whatever the keywords; shadowing from an inner block is allowed.
+    fn declare_checked(
+        &mut self,
+        name: &str,
+        mutable: bool,
+        ty: Type,
+        assigned: bool,
+        span: Span,
+    ) -> Result<(), SemanticError> {
+        if let Some(previous) = self.scopes.last().and_then(|scope| scope.get(name)) {
+            return Err(SemanticError {
+                code: codes::DUPLICATE_DEFINITION,
+                message: format!(
+                    "duplicate declaration of '{}'; first declared at line {}:{}",
+                    name, previous.span.line, previous.span.col
+                ),
+                span,
+            });
+        }
+        self.declare(
+            name,
+            Binding {
+                mutable,
+                ty,
+                span,
+                assigned: Cell::new(assigned),
+            },
+        );
+        Ok(())
+    }
+
+    fn check_block(&mut self, nodes: &[ASTNode]) -> Result<(), SemanticError> {
+        self.scopes.push(HashMap::new());
+        let result = self.check_statements(nodes);
+        self.scopes.pop();
+        result
+    }
+
+    // Variables in scope that are not assigned yet, by scope depth and name
+    fn unassigned(&self) -> Vec<(usize, String)> {
+        let mut unassigned = Vec::new();
+        for (depth, scope) in self.scopes.iter().enumerate() {
+            for (name, binding) in scope {
+                if !binding.assigned.get() {
+                    unassigned.push((depth, name.clone()));
+                }
+            }
+        }
+        unassigned
+    }
+
+    fn set_assigned(&self, variables: &[(usize, String)], assigned: bool) {
+        for (depth, name) in variables {
+            self.scopes[*depth][name].assigned.set(assigned);
+        }
+    }
+
+    // Check blocks of which at most one runs, like the branches of an `if`.
+    // A variable counts as assigned after them when every block that does
+    // not jump away assigns it, and one of the blocks always runs.
+    fn check_branches(
+        &mut self,
+        blocks: &[&[ASTNode]],
+        exhaustive: bool,
+    ) -> Result<(), SemanticError> {
+        let pending = self.unassigned();
+        let mut assigned = vec![exhaustive; pending.len()];
+        for block in blocks {
+            self.check_block(block)?;
+            let jumps = matches!(
+                block.last(),
+                Some(
+                    ASTNode::Break(_)
+                        | ASTNode::Continue(_)
+                        | ASTNode::Give { .. }
+                        | ASTNode::Exit { .. }
+                )
+            );
+            if !jumps {
+                for (assigned, (depth, name)) in assigned.iter_mut().zip(&pending) {
+                    *assigned &= self.scopes[*depth][name].assigned.get();
+                }
+            }
+            self.set_assigned(&pending, false);
+        }
+        for (assigned, variable) in assigned.iter().zip(&pending) {
+            self.set_assigned(std::slice::from_ref(variable), *assigned);
+        }
+        Ok(())
+    }
+
+    fn check_statements(&mut self, nodes: &[ASTNode]) -> Result<(), SemanticError> {
+        for node in nodes {
+            match node {
+                ASTNode::VariableDeclaration {
+                    name,
+                    mutable,
+                    ty: annotation,
+                    value,
+                    span,
+                } => {
+                    let declared = annotation
+                        .as_ref()
+                        .map(|ty| annotation_type(ty, &format!("'{}'", name), *span))
+                        .transpose()?;
+                    let Some(value) = value else {
+                        if !*mutable {
+                            return Err(SemanticError {
+                                code: codes::MISSING_INITIALIZER,
+                                message: format!("constant '{}' needs a value", name),
+                                span: *span,
+                            });
+                        }
+                        let ty = declared.expect("the parser requires a value or a type");
+                        self.declare_checked(name, *mutable, ty, false, *span)?;
+                        continue;
+                    };
+                    let ty = match value.as_ref() {
+                        ASTNode::ArrayLiteral(elements, span) => {
+                            self.check_array_literal(elements, *span)?
+                        }
+                        ASTNode::StructInit {
+                            name: struct_name,
+                            fields,
+                            span,
+                        } => self.check_struct_literal(struct_name, fields, *span)?,
+                        value => self.check_expression(value)?,
+                    };
+                    if let ASTNode::Identifier(..) = value.as_ref() {
+                        let literal = match ty {
+                            Type::Array(_) => Some("array"),
+                            Type::Struct(_) => Some("struct"),
+                            _ => None,
+                        };
+                        if let Some(literal) = literal {
+                            return Err(SemanticError {
+                                code: codes::MISSING_INITIALIZER,
+                                message: format!(
+                                    "{} '{}' must be initialized with {} {} literal",
+                                    literal,
+                                    name,
+                                    if literal == "array" { "an" } else { "a" },
+                                    literal
+                                ),
+                                span: *span,
+                            });
+                        }
+                    }
+                    // An annotation states the type the value must have; an
+                    // int may initialize a long or a float
+                    let ty = match declared {
+                        Some(declared) if declared.widens(&ty) => declared,
+                        Some(declared) if declared != ty => {
+                            return Err(SemanticError {
+                                code: codes::TYPE_MISMATCH,
+                                message: format!(
+                                    "type mismatch: variable '{}' has type {} but is initialized with {}",
+                                    name,
+                                    declared,
+                                    ty.with_article()
+                                ),
+                                span: *span,
+                            });
+                        }
+                        _ => ty,
+                    };
+                    self.declare_checked(name, *mutable, ty, true, *span)?;
+                }
+                ASTNode::Assignment { name, value, span } => {
+                    let ty = self.check_expression(value)?;
+                    if let Some(binding) = self.lookup(name) {
+                        binding.assigned.set(true);
+                    }
+                    let Some(binding) = self.lookup(name) else {
+                        return Err(SemanticError {
+                            code: codes::UNDECLARED_VARIABLE,
+                            message: format!("assignment to undeclared variable '{}'", name),
+                            span: *span,
+                        });
+                    };
+                    if !binding.mutable {
+                        return Err(SemanticError {
+                            code: codes::ASSIGN_TO_CONSTANT,
+                            message: format!(
+                                "cannot assign to constant '{}' declared at line {}:{}",
+                                name, binding.span.line, binding.span.col
+                            ),
+                            span: *span,
+                        });
+                    }
+                    if let Type::Array(_) = binding.ty {
+                        return Err(SemanticError {
+                            code: codes::WHOLE_VALUE_ASSIGNED,
+                            message: format!(
+                                "cannot assign to array '{}'; assign to its elements instead",
+                                name
+                            ),
+                            span: *span,
+                        });
+                    }
+                    if let Type::Struct(_) = binding.ty {
+                        return Err(SemanticError {
+                            code: codes::WHOLE_VALUE_ASSIGNED,
+                            message: format!(
+                                "cannot assign to struct '{}'; assign to its fields instead",
+                                name
+                            ),
+                            span: *span,
+                        });
+                    }
+                    let widening = binding.ty.widens(&ty);
+                    if ty != binding.ty && !widening {
+                        return Err(SemanticError {
+                            code: codes::TYPE_MISMATCH,
+                            message: format!(
+                                "type mismatch: variable '{}' has type {} but is assigned {}",
+                                name,
+                                binding.ty,
+                                ty.with_article()
+                            ),
+                            span: *span,
+                        });
+                    }
+                }
+                ASTNode::If {
+                    cond,
+                    then_block,
+                    else_block,
+                    span,
+                } => {
+                    let ty = self.check_expression(cond)?;
+                    self.expect_type(ty, Type::Bool, "the 'if' condition", *span)?;
+                    match else_block {
+                        Some(else_block) => self.check_branches(&[then_block, else_block], true)?,
+                        None => self.check_branches(&[then_block], false)?,
+                    }
+                }
+                ASTNode::Block(nodes) => self.check_block(nodes)?,
+                ASTNode::Match {
+                    value,
+                    arms,
+                    else_block,
+                    span,
+                } => {
+                    let ty = self.check_expression(value)?;
+                    let Type::Enum(enum_name) = ty else {
+                        return Err(SemanticError {
+                            code: codes::TYPE_MISMATCH,
+                            message: format!(
+                                "type mismatch: the 'match' value must be an enum but is {}",
+                                ty.with_article()
+                            ),
+                            span: *span,
+                        });
+                    };
+                    let variants = self.definitions.enums[&enum_name].clone();
+                    for (position, arm) in arms.iter().enumerate() {
+                        if !variants.contains(&arm.variant) {
+                            return Err(SemanticError {
+                                code: codes::NO_SUCH_MEMBER,
+                                message: format!(
+                                    "enum '{}' has no variant '{}'",
+                                    enum_name, arm.variant
+                                ),
+                                span: arm.span,
+                            });
+                        }
+                        if arms[..position]
+                            .iter()
+                            .any(|other| other.variant == arm.variant)
+                        {
+                            return Err(SemanticError {
+                                code: codes::DUPLICATE_DEFINITION,
+                                message: format!(
+                                    "variant '{}' is matched more than once",
+                                    arm.variant
+                                ),
+                                span: arm.span,
+                            });
+                        }
+                    }
+                    let missing: Vec<&str> = variants
+                        .iter()
+                        .filter(|variant| !arms.iter().any(|arm| arm.variant == **variant))
+                        .map(String::as_str)
+                        .collect();
+                    if else_block.is_none() && !missing.is_empty() {
+                        self.warnings.push(Warning {
+                            code: codes::NON_EXHAUSTIVE_MATCH,
+                            message: format!(
+                                "'match' on enum '{}' does not handle {} and has no 'else'",
+                                enum_name,
+                                missing.join(", ")
+                            ),
+                            span: *span,
+                        });
+                    }
+                    let mut blocks: Vec<&[ASTNode]> =
+                        arms.iter().map(|arm| arm.body.as_slice()).collect();
+                    blocks.extend(else_block.as_deref());
+                    self.check_branches(&blocks, else_block.is_some() || missing.is_empty())?;
+                }
+                ASTNode::FieldAssignment {
+                    name,
+                    field,
+                    value,
+                    span,
+                } => {
+                    let ty = self.check_expression(value)?;
+                    let Some(binding) = self.lookup(name) else {
+                        return Err(SemanticError {
+                            code: codes::UNDECLARED_VARIABLE,
+                            message: format!("assignment to undeclared variable '{}'", name),
+                            span: *span,
+                        });
+                    };
+                    let field_ty = field_type(name, &binding.ty, field, &self.definitions, *span)?;
+                    if !binding.mutable {
+                        return Err(SemanticError {
+                            code: codes::ASSIGN_TO_CONSTANT,
+                            message: format!(
+                                "cannot assign to a field of constant '{}' declared at line {}:{}",
+                                name, binding.span.line, binding.span.col
+                            ),
+                            span: *span,
+                        });
+                    }
+                    let widening = field_ty.widens(&ty);
+                    if !widening {
+                        self.expect_type(
+                            ty,
+                            field_ty,
+                            &format!("field '{}.{}'", name, field),
+                            *span,
+                        )?;
+                    }
+                }
+                // Collected up front by `collect_types`
+                ASTNode::StructDef { name, span, .. } | ASTNode::EnumDef { name, span, .. }
+                    if self.scopes.len() > 1 || self.in_function =>
+                {
+                    return Err(SemanticError {
+                        code: codes::NOT_AT_TOP_LEVEL,
+                        message: format!("type '{}' must be defined at the top level", name),
+                        span: *span,
+                    });
+                }
+                ASTNode::IndexAssignment {
+                    name,
+                    index,
+                    value,
+                    span,
+                } => {
+                    let index_ty = self.check_expression(index)?;
+                    let ty = self.check_expression(value)?;
+                    let Some(binding) = self.lookup(name) else {
+                        return Err(SemanticError {
+                            code: codes::UNDECLARED_VARIABLE,
+                            message: format!("assignment to undeclared variable '{}'", name),
+                            span: *span,
+                        });
+                    };
+                    check_index(name, &binding.ty, index, index_ty, *span)?;
+                    if !binding.mutable {
+                        return Err(SemanticError {
+                            code: codes::ASSIGN_TO_CONSTANT,
+                            message: format!(
+                                "cannot assign to an element of constant '{}' declared at line {}:{}",
+                                name, binding.span.line, binding.span.col
+                            ),
+                            span: *span,
+                        });
+                    }
+                    self.expect_type(ty, Type::Int, "an array element", *span)?;
+                }
+                ASTNode::Show(values, _) => {
+                    for value in values {
+                        // Only a variable can have an array type here
+                        let ty = self.check_expression(value)?;
+                        if let ASTNode::Identifier(name, span) = value {
+                            let parts = match ty {
+                                Type::Array(_) => Some(("array", "elements")),
+                                Type::Struct(_) => Some(("struct", "fields")),
+                                _ => None,
+                            };
+                            if let Some((what, parts)) = parts {
+                                return Err(SemanticError {
+                                    code: codes::TYPE_MISMATCH,
+                                    message: format!(
+                                        "{} '{}' cannot be shown; show its {} instead",
+                                        what, name, parts
+                                    ),
+                                    span: *span,
+                                });
+                            }
+                        }
+                    }
+                }
+                ASTNode::For {
+                    var,
+                    start,
+                    end,
+                    body,
+                    span,
+                } => {
+                    let ty = self.check_expression(start)?;
+                    self.expect_type(ty, Type::Int, "the loop start", *span)?;
+                    let ty = self.check_expression(end)?;
+                    self.expect_type(ty, Type::Int, "the loop end", *span)?;
+                    // The body may not run at all, so what it assigns stays
+                    // unassigned after the loop
+                    let pending = self.unassigned();
+                    self.scopes.push(HashMap::new());
+                    self.declare(
+                        var,
+                        Binding {
+                            mutable: true,
+                            ty: Type::Int,
+                            span: *span,
+                            assigned: Cell::new(true),
+                        },
+                    );
+                    let result = self.check_block(body);
+                    self.scopes.pop();
+                    result?;
+                    self.set_assigned(&pending, false);
+                }
+                ASTNode::Function {
+                    name,
+                    params,
+                    body,
+                    span,
+                } => {
+                    if self.scopes.len() > 1 {
+                        return Err(SemanticError {
+                            code: codes::NOT_AT_TOP_LEVEL,
+                            message: format!(
+                                "function '{}' must be defined at the top level",
+                                name
+                            ),
+                            span: *span,
+                        });
+                    }
+                    // The body only sees its parameters, not the variables
+                    // of the enclosing program
+                    let mut parameters = HashMap::new();
+                    for param in params {
+                        let binding = Binding {
+                            mutable: true,
+                            ty: parameter_type(param)?,
+                            span: param.span,
+                            assigned: Cell::new(true),
+                        };
+                        if parameters.insert(param.name.clone(), binding).is_some() {
+                            return Err(SemanticError {
+                                code: codes::DUPLICATE_DEFINITION,
+                                message: format!(
+                                    "duplicate parameter '{}' in function '{}'",
+                                    param.name, name
+                                ),
+                                span: param.span,
+                            });
+                        }
+                    }
+                    // Parameters share the body's scope, as in C
+                    let outer = std::mem::replace(&mut self.scopes, vec![parameters]);
+                    self.in_function = true;
+                    let result = self.check_statements(body);
+                    self.in_function = false;
+                    self.scopes = outer;
+                    result?;
+                }
+                ASTNode::Give { value, span } => {
+                    if !self.in_function {
+                        return Err(SemanticError {
+                            code: codes::GIVE_OUTSIDE_FUNCTION,
+                            message: "'give' outside of a function".to_string(),
+                            span: *span,
+                        });
+                    }
+                    let ty = self.check_expression(value)?;
+                    self.expect_type(ty, Type::Int, "the 'give' value", *span)?;
+                }
+                ASTNode::Input { name, span } => {
+                    let Some(binding) = self.lookup(name) else {
+                        return Err(SemanticError {
+                            code: codes::UNDECLARED_VARIABLE,
+                            message: format!("input into undeclared variable '{}'", name),
+                            span: *span,
+                        });
+                    };
+                    if !binding.mutable {
+                        return Err(SemanticError {
+                            code: codes::ASSIGN_TO_CONSTANT,
+                            message: format!(
+                                "cannot read input into constant '{}' declared at line {}:{}",
+                                name, binding.span.line, binding.span.col
+                            ),
+                            span: *span,
+                        });
+                    }
+                    binding.assigned.set(true);
+                    if !matches!(binding.ty, Type::Int | Type::Str) {
+                        return Err(SemanticError {
+                            code: codes::TYPE_MISMATCH,
+                            message: format!(
+                                "type mismatch: 'input' reads an int or a string, but '{}' has type {}",
+                                name, binding.ty
+                            ),
+                            span: *span,
+                        });
+                    }
+                }
+                ASTNode::Exit { value, span } => {
+                    let ty = self.check_expression(value)?;
+                    self.expect_type(ty, Type::Int, "the exit code", *span)?;
+                }
+                ASTNode::Assert { cond, span, .. } => {
+                    let ty = self.check_expression(cond)?;
+                    self.expect_type(ty, Type::Bool, "an assert condition", *span)?;
+                }
+                // The result is discarded, so builtins giving nothing are
+                // fine here
+                call @ ASTNode::Call { .. } => {
+                    self.check_calls(call)?;
+                }
+                ASTNode::ExternFunction { name, span, .. }
+                    if self.scopes.len() > 1 || self.in_function =>
+                {
+                    return Err(SemanticError {
+                        code: codes::NOT_AT_TOP_LEVEL,
+                        message: format!(
+                            "extern function '{}' must be declared at the top level",
+                            name
+                        ),
+                        span: *span,
+                    });
+                }
+                ASTNode::CInclude { span, .. } if self.scopes.len() > 1 || self.in_function => {
+                    return Err(SemanticError {
+                        code: codes::NOT_AT_TOP_LEVEL,
+                        message: "'cinclude' must be at the top level".to_string(),
+                        span: *span,
+                    });
+                }
+                ASTNode::Link { span, .. } if self.scopes.len() > 1 || self.in_function => {
+                    return Err(SemanticError {
+                        code: codes::NOT_AT_TOP_LEVEL,
+                        message: "'link' must be at the top level".to_string(),
+                        span: *span,
+                    });
+                }
+                ASTNode::Use { span, .. } if self.scopes.len() > 1 || self.in_function => {
+                    return Err(SemanticError {
+                        code: codes::NOT_AT_TOP_LEVEL,
+                        message: "'use' must be at the top level".to_string(),
+                        span: *span,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // Check one more piece of a program typed at the REPL. Names declared by
+    // earlier pieces stay visible, and unused variables are not reported.
+    pub fn check_input(&mut self, ast: &[ASTNode]) -> Result<(), SemanticError> {
+        self.collect_types(ast)?;
+        self.collect_functions(ast)?;
+        self.check_statements(ast)
+    }
+}
+
+// Finds the variables that are declared but never read, in scopes matching
+// the analyzer's: each block has its own, a `for` variable one around its
+// body, and a function's parameters share its body's, which cannot see the
+// program's variables. Assignments are not reads.
+#[derive(Default)]
+struct UnusedVariables {
+    // Each variable's declaration and whether it was read
+    scopes: Vec<HashMap<String, (Span, bool)>>,
+    warnings: Vec<Warning>,
+}
+
+impl UnusedVariables {
+    fn read(&mut self, name: &str) {
+        if let Some((_, read)) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+        {
+            *read = true;
+        }
+    }
+
+    // Warn about the variables of a finished scope that were never read.
+    // Names starting with `_` are exempt.
+    fn close_scope(&mut self, scope: HashMap<String, (Span, bool)>) {
+        for (name, (span, read)) in scope {
+            if !read && !name.starts_with('_') {
+                self.warnings.push(Warning {
+                    code: codes::UNUSED_VARIABLE,
+                    message: format!("unused variable '{}'", name),
+                    span,
+                });
+            }
+        }
+    }
+}
+
+impl Visitor for UnusedVariables {
+    fn visit_block(&mut self, nodes: &[ASTNode]) {
+        self.scopes.push(HashMap::new());
+        walk_block(self, nodes);
+        let scope = self.scopes.pop().expect("block scope was pushed");
+        self.close_scope(scope);
+    }
+
+    fn visit_statement(&mut self, node: &ASTNode) {
+        match node {
+            // The value is read before the name it declares exists
+            ASTNode::VariableDeclaration { name, span, .. } => {
+                walk_statement(self, node);
+                self.scopes
+                    .last_mut()
+                    .expect("a block scope is open")
+                    .insert(name.clone(), (*span, false));
+            }
+            ASTNode::For {
+                var,
+                start,
+                end,
+                body,
+                span,
+            } => {
+                self.visit_expression(start);
+                self.visit_expression(end);
+                self.scopes
+                    .push(HashMap::from([(var.clone(), (*span, true))]));
+                self.visit_block(body);
+                self.scopes.pop();
+            }
+            ASTNode::Function { params, body, .. } => {
+                let parameters = params
+                    .iter()
+                    .map(|param| (param.name.clone(), (param.span, true)))
+                    .collect();
+                let outer = std::mem::replace(&mut self.scopes, vec![parameters]);
+                walk_block(self, body);
+                let scope = self.scopes.pop().expect("the body's scope");
+                self.scopes = outer;
+                self.close_scope(scope);
+            }
+            // Raw C is the C compiler's to check. Only the variables it
+            // names are noted, so they are not reported as unused.
+            ASTNode::CRaw { code, .. } => {
+                for name in raw_c_names(code) {
+                    self.read(name);
+                }
+            }
+            _ => walk_statement(self, node),
+        }
+    }
+
+    fn visit_expression(&mut self, node: &ASTNode) {
+        match node {
+            ASTNode::Identifier(name, _)
+            | ASTNode::Index { name, .. }
+            | ASTNode::FieldAccess { name, .. } => self.read(name),
+            _ => {}
+        }
+        walk_expression(self, node);
+    }
+}
+
+// Run the semantic checks over a parsed program, returning its warnings in
+// source order
+pub fn analyze(ast: &[ASTNode]) -> Result<Vec<Warning>, SemanticError> {
+    let mut analyzer = Analyzer::new();
+    analyzer.collect_types(ast)?;
+    analyzer.collect_functions(ast)?;
+    analyzer.check_statements(ast)?;
+    let mut unused = UnusedVariables::default();
+    unused.visit_block(ast);
+    let mut warnings = analyzer.warnings;
+    warnings.extend(unused.warnings);
+    warnings.sort_by_key(|warning| (warning.span.line, warning.span.col));
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::FileId;
+    use crate::parser::parse_source;
+
+    fn check(input: &str) -> Result<Vec<Warning>, SemanticError> {
+        analyze(&parse_source(input, FileId::MAIN).unwrap())
+    }
+
+    #[test]
+    fn accepted_programs() {
+        let programs = [
+            "m x = 5;\nx = 6;\nshow x;",
+            "m s = \"a\" + 1;\nshow s;",
+            "m f: float = 1;\nshow f * 2.5;",
+            "m big: long = 2;\nshow big + 1;",
+            "m b = 1 < 2 and not false;\nshow b;",
+            "m name: str;\nname = \"bp\";\nshow name;",
+            "fn twice(n) { give n * 2; }\nshow twice(4);",
+            "fn greet(name: str) { show \"hi \" + name; }\ngreet(\"bp\");",
+            "m a = [1, 2, 3];\na[0] = len(a);\nshow a[0];",
+            "struct P { x; }\nm p = P { x = 1 };\np.x = 2;\nshow p.x;",
+            "enum C { Red, Blue }\nm col = C.Red;\nmatch col { Red { show 1; } Blue { show 2; } }",
+        ];
+        for program in programs {
+            if let Err(err) = check(program) {
+                panic!("{:?} was rejected: {}", program, err);
+            }
+        }
+    }
+
+    #[test]
+    fn rejected_programs() {
+        let programs = [
+            (
+                "m x = 5;\nx = \"hello\";",
+                "type mismatch: variable 'x' has type int but is assigned a string",
+                2,
+            ),
+            (
+                "m b = true;\nshow b + 1;",
+                "operator '+' cannot be applied to bool and int",
+                2,
+            ),
+            (
+                "m s: str = 5;",
+                "type mismatch: variable 's' has type string but is initialized with an int",
+                1,
+            ),
+            (
+                "if 1 { show 1; }",
+                "type mismatch: the 'if' condition must be a bool but is an int",
+                1,
+            ),
+            (
+                "fn f(a) { give a; }\nshow f(\"x\");",
+                "type mismatch: argument 1 of 'f' must be an int but is a string",
+                2,
+            ),
+            (
+                "m f: float = 1;\nm i: int = f;",
+                "type mismatch: variable 'i' has type int but is initialized with a float",
+                2,
+            ),
+            (
+                "show \"a\" < \"b\";",
+                "operator '<' cannot be applied to string and string",
+                1,
+            ),
+            (
+                "m x = 1.5 % 2.0;",
+                "operator '%' cannot be applied to float and float",
+                1,
+            ),
+            ("show q;", "use of undeclared variable 'q'", 1),
+            (
+                "c k = 1;\nk = 2;",
+                "cannot assign to constant 'k' declared at line 1:3",
+                2,
+            ),
+            (
+                "m x = 1;\nm x = 2;",
+                "duplicate declaration of 'x'; first declared at line 1:3",
+                2,
+            ),
+        ];
+        for (program, message, line) in programs {
+            match check(program) {
+                Ok(_) => panic!("{:?} was accepted", program),
+                Err(err) => {
+                    assert_eq!(err.message, message, "{:?}", program);
+                    assert_eq!(err.span.line, line, "{:?}", program);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unused_variable_is_a_warning() {
+        let warnings = check("m used = 1;\nm unused = 2;\nshow used;").unwrap();
+        let messages: Vec<&str> = warnings.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(messages, ["unused variable 'unused'"]);
+        assert_eq!(warnings[0].code, codes::UNUSED_VARIABLE);
+    }
+}
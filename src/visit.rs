@@ -0,0 +1,462 @@
+//! Walking the syntax tree. A pass implements `Visitor` to read a program,
+//! or `VisitorMut` to change it in place, overriding only the methods for
+//! what it cares about; the defaults visit every child node.
+
+use crate::ast::ASTNode;
+
+// A pass reading a program. Each method's default walks into the children of
+// what it is given, so an override that still wants them visited calls the
+// matching `walk_*` function.
+pub trait Visitor {
+    // A list of statements: the program, or the body of a block, branch,
+    // loop or function
+    fn visit_block(&mut self, nodes: &[ASTNode]) {
+        walk_block(self, nodes);
+    }
+
+    fn visit_statement(&mut self, node: &ASTNode) {
+        walk_statement(self, node);
+    }
+
+    fn visit_expression(&mut self, node: &ASTNode) {
+        walk_expression(self, node);
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, nodes: &[ASTNode]) {
+    for node in nodes {
+        visitor.visit_statement(node);
+    }
+}
+
+// Visit the expressions and blocks inside a statement. Every kind of node is
+// listed, so a new one fails to compile until it is walked. An expression
+// used as a statement, a call, goes to `visit_expression`.
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, node: &ASTNode) {
+    match node {
+        ASTNode::VariableDeclaration { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expression(value);
+            }
+        }
+        ASTNode::Assignment { value, .. } | ASTNode::FieldAssignment { value, .. } => {
+            visitor.visit_expression(value);
+        }
+        ASTNode::IndexAssignment { index, value, .. } => {
+            visitor.visit_expression(index);
+            visitor.visit_expression(value);
+        }
+        ASTNode::Show(values, _) => {
+            for value in values {
+                visitor.visit_expression(value);
+            }
+        }
+        ASTNode::If {
+            cond,
+            then_block,
+            else_block,
+            ..
+        } => {
+            visitor.visit_expression(cond);
+            visitor.visit_block(then_block);
+            if let Some(else_block) = else_block {
+                visitor.visit_block(else_block);
+            }
+        }
+        ASTNode::For {
+            start, end, body, ..
+        } => {
+            visitor.visit_expression(start);
+            visitor.visit_expression(end);
+            visitor.visit_block(body);
+        }
+        ASTNode::Block(nodes) | ASTNode::Function { body: nodes, .. } => {
+            visitor.visit_block(nodes);
+        }
+        ASTNode::Match {
+            value,
+            arms,
+            else_block,
+            ..
+        } => {
+            visitor.visit_expression(value);
+            for arm in arms {
+                visitor.visit_block(&arm.body);
+            }
+            if let Some(else_block) = else_block {
+                visitor.visit_block(else_block);
+            }
+        }
+        ASTNode::Give { value, .. } | ASTNode::Exit { value, .. } => {
+            visitor.visit_expression(value);
+        }
+        ASTNode::Assert { cond, .. } => visitor.visit_expression(cond),
+        ASTNode::Break(_)
+        | ASTNode::Continue(_)
+        | ASTNode::ExternFunction { .. }
+        | ASTNode::StructDef { .. }
+        | ASTNode::EnumDef { .. }
+        | ASTNode::Input { .. }
+        | ASTNode::CRaw { .. }
+        | ASTNode::CInclude { .. }
+        | ASTNode::Link { .. }
+        | ASTNode::Use { .. } => {}
+        ASTNode::StringLiteral(_)
+        | ASTNode::NumberLiteral(_)
+        | ASTNode::LongLiteral(_)
+        | ASTNode::FloatLiteral(_)
+        | ASTNode::BoolLiteral(_)
+        | ASTNode::Identifier(..)
+        | ASTNode::ArrayLiteral(..)
+        | ASTNode::Index { .. }
+        | ASTNode::StructInit { .. }
+        | ASTNode::FieldAccess { .. }
+        | ASTNode::BinaryOp { .. }
+        | ASTNode::UnaryOp { .. }
+        | ASTNode::Call { .. } => visitor.visit_expression(node),
+    }
+}
+
+// Visit the operands of an expression. A statement found where an
+// expression belongs, which the parser never makes, goes to
+// `visit_statement`.
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, node: &ASTNode) {
+    match node {
+        ASTNode::StringLiteral(_)
+        | ASTNode::NumberLiteral(_)
+        | ASTNode::LongLiteral(_)
+        | ASTNode::FloatLiteral(_)
+        | ASTNode::BoolLiteral(_)
+        | ASTNode::Identifier(..)
+        | ASTNode::FieldAccess { .. } => {}
+        ASTNode::ArrayLiteral(elements, _) | ASTNode::Call { args: elements, .. } => {
+            for element in elements {
+                visitor.visit_expression(element);
+            }
+        }
+        ASTNode::Index { index, .. } => visitor.visit_expression(index),
+        ASTNode::StructInit { fields, .. } => {
+            for (_, value) in fields {
+                visitor.visit_expression(value);
+            }
+        }
+        ASTNode::BinaryOp { lhs, rhs, .. } => {
+            visitor.visit_expression(lhs);
+            visitor.visit_expression(rhs);
+        }
+        ASTNode::UnaryOp { operand, .. } => visitor.visit_expression(operand),
+        ASTNode::VariableDeclaration { .. }
+        | ASTNode::Assignment { .. }
+        | ASTNode::IndexAssignment { .. }
+        | ASTNode::FieldAssignment { .. }
+        | ASTNode::Show(..)
+        | ASTNode::If { .. }
+        | ASTNode::For { .. }
+        | ASTNode::Block(_)
+        | ASTNode::Break(_)
+        | ASTNode::Continue(_)
+        | ASTNode::Function { .. }
+        | ASTNode::ExternFunction { .. }
+        | ASTNode::StructDef { .. }
+        | ASTNode::EnumDef { .. }
+        | ASTNode::Match { .. }
+        | ASTNode::Give { .. }
+        | ASTNode::Exit { .. }
+        | ASTNode::Assert { .. }
+        | ASTNode::Input { .. }
+        | ASTNode::CRaw { .. }
+        | ASTNode::CInclude { .. }
+        | ASTNode::Link { .. }
+        | ASTNode::Use { .. } => visitor.visit_statement(node),
+    }
+}
+
+// A pass changing a program in place. Blocks come as the vector itself, so
+// a pass may add, remove or replace statements.
+pub trait VisitorMut {
+    fn visit_block(&mut self, nodes: &mut Vec<ASTNode>) {
+        walk_block_mut(self, nodes);
+    }
+
+    fn visit_statement(&mut self, node: &mut ASTNode) {
+        walk_statement_mut(self, node);
+    }
+
+    fn visit_expression(&mut self, node: &mut ASTNode) {
+        walk_expression_mut(self, node);
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, nodes: &mut [ASTNode]) {
+    for node in nodes {
+        visitor.visit_statement(node);
+    }
+}
+
+// `walk_statement` for a pass changing the program
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut ASTNode) {
+    match node {
+        ASTNode::VariableDeclaration { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expression(value);
+            }
+        }
+        ASTNode::Assignment { value, .. } | ASTNode::FieldAssignment { value, .. } => {
+            visitor.visit_expression(value);
+        }
+        ASTNode::IndexAssignment { index, value, .. } => {
+            visitor.visit_expression(index);
+            visitor.visit_expression(value);
+        }
+        ASTNode::Show(values, _) => {
+            for value in values {
+                visitor.visit_expression(value);
+            }
+        }
+        ASTNode::If {
+            cond,
+            then_block,
+            else_block,
+            ..
+        } => {
+            visitor.visit_expression(cond);
+            visitor.visit_block(then_block);
+            if let Some(else_block) = else_block {
+                visitor.visit_block(else_block);
+            }
+        }
+        ASTNode::For {
+            start, end, body, ..
+        } => {
+            visitor.visit_expression(start);
+            visitor.visit_expression(end);
+            visitor.visit_block(body);
+        }
+        ASTNode::Block(nodes) | ASTNode::Function { body: nodes, .. } => {
+            visitor.visit_block(nodes);
+        }
+        ASTNode::Match {
+            value,
+            arms,
+            else_block,
+            ..
+        } => {
+            visitor.visit_expression(value);
+            for arm in arms {
+                visitor.visit_block(&mut arm.body);
+            }
+            if let Some(else_block) = else_block {
+                visitor.visit_block(else_block);
+            }
+        }
+        ASTNode::Give { value, .. } | ASTNode::Exit { value, .. } => {
+            visitor.visit_expression(value);
+        }
+        ASTNode::Assert { cond, .. } => visitor.visit_expression(cond),
+        ASTNode::Break(_)
+        | ASTNode::Continue(_)
+        | ASTNode::ExternFunction { .. }
+        | ASTNode::StructDef { .. }
+        | ASTNode::EnumDef { .. }
+        | ASTNode::Input { .. }
+        | ASTNode::CRaw { .. }
+        | ASTNode::CInclude { .. }
+        | ASTNode::Link { .. }
+        | ASTNode::Use { .. } => {}
+        ASTNode::StringLiteral(_)
+        | ASTNode::NumberLiteral(_)
+        | ASTNode::LongLiteral(_)
+        | ASTNode::FloatLiteral(_)
+        | ASTNode::BoolLiteral(_)
+        | ASTNode::Identifier(..)
+        | ASTNode::ArrayLiteral(..)
+        | ASTNode::Index { .. }
+        | ASTNode::StructInit { .. }
+        | ASTNode::FieldAccess { .. }
+        | ASTNode::BinaryOp { .. }
+        | ASTNode::UnaryOp { .. }
+        | ASTNode::Call { .. } => visitor.visit_expression(node),
+    }
+}
+
+// `walk_expression` for a pass changing the program
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut ASTNode) {
+    match node {
+        ASTNode::StringLiteral(_)
+        | ASTNode::NumberLiteral(_)
+        | ASTNode::LongLiteral(_)
+        | ASTNode::FloatLiteral(_)
+        | ASTNode::BoolLiteral(_)
+        | ASTNode::Identifier(..)
+        | ASTNode::FieldAccess { .. } => {}
+        ASTNode::ArrayLiteral(elements, _) | ASTNode::Call { args: elements, .. } => {
+            for element in elements {
+                visitor.visit_expression(element);
+            }
+        }
+        ASTNode::Index { index, .. } => visitor.visit_expression(index),
+        ASTNode::StructInit { fields, .. } => {
+            for (_, value) in fields {
+                visitor.visit_expression(value);
+            }
+        }
+        ASTNode::BinaryOp { lhs, rhs, .. } => {
+            visitor.visit_expression(lhs);
+            visitor.visit_expression(rhs);
+        }
+        ASTNode::UnaryOp { operand, .. } => visitor.visit_expression(operand),
+        ASTNode::VariableDeclaration { .. }
+        | ASTNode::Assignment { .. }
+        | ASTNode::IndexAssignment { .. }
+        | ASTNode::FieldAssignment { .. }
+        | ASTNode::Show(..)
+        | ASTNode::If { .. }
+        | ASTNode::For { .. }
+        | ASTNode::Block(_)
+        | ASTNode::Break(_)
+        | ASTNode::Continue(_)
+        | ASTNode::Function { .. }
+        | ASTNode::ExternFunction { .. }
+        | ASTNode::StructDef { .. }
+        | ASTNode::EnumDef { .. }
+        | ASTNode::Match { .. }
+        | ASTNode::Give { .. }
+        | ASTNode::Exit { .. }
+        | ASTNode::Assert { .. }
+        | ASTNode::Input { .. }
+        | ASTNode::CRaw { .. }
+        | ASTNode::CInclude { .. }
+        | ASTNode::Link { .. }
+        | ASTNode::Use { .. } => visitor.visit_statement(node),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::to_source;
+    use crate::lexer::FileId;
+    use crate::parser::parse_source;
+
+    const PROGRAM: &str = "\
+fn twice(n) {
+    give n * 2;
+}
+
+m x = twice(1 + 2);
+for i = 1 to x {
+    if i > 2 {
+        show i, x;
+    } else {
+        x = x - 1;
+    }
+}
+";
+
+    // Counts what it is shown, and the names read
+    #[derive(Default)]
+    struct Counter {
+        blocks: usize,
+        statements: usize,
+        expressions: usize,
+        names: Vec<String>,
+    }
+
+    impl Visitor for Counter {
+        fn visit_block(&mut self, nodes: &[ASTNode]) {
+            self.blocks += 1;
+            walk_block(self, nodes);
+        }
+
+        fn visit_statement(&mut self, node: &ASTNode) {
+            self.statements += 1;
+            walk_statement(self, node);
+        }
+
+        fn visit_expression(&mut self, node: &ASTNode) {
+            self.expressions += 1;
+            if let ASTNode::Identifier(name, _) = node {
+                self.names.push(name.clone());
+            }
+            walk_expression(self, node);
+        }
+    }
+
+    // Renames a variable wherever it is declared, assigned or read
+    struct Rename {
+        from: &'static str,
+        to: &'static str,
+    }
+
+    impl Rename {
+        fn rename(&self, name: &mut String) {
+            if name == self.from {
+                *name = self.to.to_string();
+            }
+        }
+    }
+
+    impl VisitorMut for Rename {
+        fn visit_statement(&mut self, node: &mut ASTNode) {
+            match node {
+                ASTNode::VariableDeclaration { name, .. }
+                | ASTNode::Assignment { name, .. }
+                | ASTNode::Input { name, .. }
+                | ASTNode::For { var: name, .. } => self.rename(name),
+                _ => {}
+            }
+            walk_statement_mut(self, node);
+        }
+
+        fn visit_expression(&mut self, node: &mut ASTNode) {
+            if let ASTNode::Identifier(name, _) = node {
+                self.rename(name);
+            }
+            walk_expression_mut(self, node);
+        }
+    }
+
+    #[test]
+    fn counting_visitor_sees_every_node() {
+        let ast = parse_source(PROGRAM, FileId::MAIN).unwrap();
+        let mut counter = Counter::default();
+        counter.visit_block(&ast);
+        // The program, the function body, the loop body and both branches
+        assert_eq!(counter.blocks, 5);
+        // fn, m, for, give, if, show, assignment
+        assert_eq!(counter.statements, 7);
+        // n * 2 (3), twice(1 + 2) (4), 1 and x (2), i > 2 (3), i and x (2),
+        // x - 1 (3)
+        assert_eq!(counter.expressions, 17);
+        assert_eq!(counter.names, ["n", "x", "i", "i", "x", "x"]);
+    }
+
+    #[test]
+    fn renaming_transformer_changes_the_tree() {
+        let mut ast = parse_source(PROGRAM, FileId::MAIN).unwrap();
+        let mut rename = Rename {
+            from: "x",
+            to: "limit",
+        };
+        rename.visit_block(&mut ast);
+        assert_eq!(to_source(&ast), PROGRAM.replace('x', "limit"));
+    }
+
+    #[test]
+    fn transformer_may_replace_statements() {
+        struct DropShows;
+        impl VisitorMut for DropShows {
+            fn visit_block(&mut self, nodes: &mut Vec<ASTNode>) {
+                nodes.retain(|node| !matches!(node, ASTNode::Show(..)));
+                walk_block_mut(self, nodes);
+            }
+        }
+        let mut ast = parse_source(
+            "show 1;\nif true {\n    show 2;\n}\nm y = 3;\n",
+            FileId::MAIN,
+        )
+        .unwrap();
+        DropShows.visit_block(&mut ast);
+        assert_eq!(to_source(&ast), "if true {}\nm y = 3;\n");
+    }
+}
@@ -0,0 +1,726 @@
+//! Runs bytecode from `bytecode` on a stack machine, with the same results
+//! as the tree-walking interpreter
+
+use std::io::{BufRead, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bytecode::{Op, Program};
+use crate::interpreter::{
+    binary_op, call_builtin, input_value, negate, runtime_error, seeded_state, store, typed,
+    InterpretError, Value,
+};
+use crate::lexer::Span;
+use crate::semantic::BUILTINS;
+
+// Calls nested deeper than this stop the program instead of exhausting
+// memory
+const MAX_FRAMES: usize = 1 << 18;
+
+// A running function: which one, the next instruction and its locals
+struct Frame {
+    function: usize,
+    pc: usize,
+    locals: Vec<Value>,
+}
+
+// Executes a program's instructions. Values are pushed on a stack shared by
+// all calls; each call gets a frame holding its locals.
+pub struct Vm<'p, R: BufRead, W: Write> {
+    program: &'p Program,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    input: R,
+    out: W,
+    // State of `random`, the same generator the C runtime uses
+    rng_state: u64,
+    // What `argc` and `arg` see, starting with the program name
+    args: Vec<String>,
+    // Stop with an error when int arithmetic overflows, instead of wrapping
+    trap_overflow: bool,
+}
+
+impl<'p, R: BufRead, W: Write> Vm<'p, R, W> {
+    // A machine ready to run `program`'s top-level code, reading `input`
+    // statements from `input` and writing what the program shows to `out`
+    pub fn new(program: &'p Program, args: Vec<String>, input: R, out: W) -> Self {
+        let main = &program.functions[0];
+        Vm {
+            program,
+            stack: Vec::new(),
+            frames: vec![Frame {
+                function: 0,
+                pc: 0,
+                locals: vec![Value::Int(0); main.locals.len()],
+            }],
+            input,
+            out,
+            // Unseeded programs get different numbers on every run
+            rng_state: seeded_state(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.as_secs() as i32),
+            ),
+            args,
+            trap_overflow: false,
+        }
+    }
+
+    // Make int overflow an error, as `--trap-overflow` does
+    pub fn with_trap_overflow(mut self, trap_overflow: bool) -> Self {
+        self.trap_overflow = trap_overflow;
+        self
+    }
+
+    // Run until the top-level code returns
+    pub fn run(&mut self) -> Result<(), InterpretError> {
+        let result = self.execute();
+        self.out.flush()?;
+        result
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("bytecode keeps the stack balanced")
+    }
+
+    // The top `count` values, in the order they were pushed
+    fn pop_many(&mut self, count: usize) -> Vec<Value> {
+        self.stack.split_off(self.stack.len() - count)
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("a function is running")
+    }
+
+    // The element of the array in local `slot` at `index`, checked like
+    // in the interpreter
+    fn element(&mut self, slot: u32, index: i32, span: Span) -> Result<&mut i32, InterpretError> {
+        let frame = self.frames.last_mut().expect("a function is running");
+        let name = &self.program.functions[frame.function].locals[slot as usize];
+        let Value::Array(elements) = &mut frame.locals[slot as usize] else {
+            unreachable!("type checked as an array: {}", name);
+        };
+        let len = elements.len();
+        usize::try_from(index)
+            .ok()
+            .and_then(|position| elements.get_mut(position))
+            .ok_or_else(|| {
+                runtime_error(
+                    format!(
+                        "index {} is out of range for '{}' of length {}",
+                        index, name, len
+                    ),
+                    span,
+                )
+            })
+    }
+
+    // Field `field` of the struct in local `slot`
+    fn field(&mut self, slot: u32, field: u32) -> &mut Value {
+        let Value::Struct { fields, .. } = &mut self.frame().locals[slot as usize] else {
+            unreachable!("type checked as a struct");
+        };
+        &mut fields[field as usize].1
+    }
+
+    // Builtins needing the machine's state; the others are shared with the
+    // interpreter
+    fn builtin(&mut self, index: u32, span: Span) -> Result<Value, InterpretError> {
+        let builtin = &BUILTINS[index as usize];
+        let args = self.pop_many(builtin.params.len());
+        match (builtin.name, args.as_slice()) {
+            ("seed", [seed]) => {
+                self.rng_state = seeded_state(seed.as_int());
+                Ok(Value::Int(0))
+            }
+            ("random", [bound]) => {
+                let bound = bound.as_int();
+                if bound <= 0 {
+                    return Err(runtime_error(
+                        format!("random: the bound must be positive, not {}", bound),
+                        span,
+                    ));
+                }
+                self.rng_state = self
+                    .rng_state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                Ok(Value::Int(((self.rng_state >> 33) % bound as u64) as i32))
+            }
+            ("argc", []) => Ok(Value::Int(self.args.len() as i32)),
+            // Out of range gives "", as in the C runtime
+            ("arg", [i]) => {
+                let arg = usize::try_from(i.as_int())
+                    .ok()
+                    .and_then(|i| self.args.get(i));
+                Ok(Value::Str(arg.cloned().unwrap_or_default()))
+            }
+            (name, args) => call_builtin(name, args, span),
+        }
+    }
+
+    fn execute(&mut self) -> Result<(), InterpretError> {
+        let program = self.program;
+        loop {
+            let frame = self.frames.last_mut().expect("a function is running");
+            let function = &program.functions[frame.function];
+            let op = function.code[frame.pc];
+            let span = function.spans[frame.pc];
+            frame.pc += 1;
+            match op {
+                Op::Int(num) => self.stack.push(Value::Int(num)),
+                Op::Const(index) => self.stack.push(program.constants[index as usize].clone()),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Dup => {
+                    let top = self
+                        .stack
+                        .last()
+                        .expect("bytecode keeps the stack balanced");
+                    self.stack.push(top.clone());
+                }
+                Op::Load(slot) => {
+                    let value = self.frame().locals[slot as usize].clone();
+                    self.stack.push(value);
+                }
+                Op::Init(slot) => {
+                    let value = self.pop();
+                    self.frame().locals[slot as usize] = value;
+                }
+                Op::Store(slot) => {
+                    let value = self.pop();
+                    store(&mut self.frame().locals[slot as usize], value);
+                }
+                Op::LoadIndex(slot) => {
+                    let index = self.pop().as_int();
+                    let element = *self.element(slot, index, span)?;
+                    self.stack.push(Value::Int(element));
+                }
+                Op::StoreIndex(slot) => {
+                    let value = self.pop().as_int();
+                    let index = self.pop().as_int();
+                    *self.element(slot, index, span)? = value;
+                }
+                Op::LoadField(slot, field) => {
+                    let value = self.field(slot, field).clone();
+                    self.stack.push(value);
+                }
+                Op::StoreField(slot, field) => {
+                    let value = self.pop();
+                    store(self.field(slot, field), value);
+                }
+                Op::Array(len) => {
+                    let elements = self.pop_many(len as usize);
+                    let elements = elements.iter().map(Value::as_int).collect();
+                    self.stack.push(Value::Array(elements));
+                }
+                Op::Struct(index) => {
+                    let layout = &program.structs[index as usize];
+                    let given = self.pop_many(layout.fields.len());
+                    let fields = layout
+                        .fields
+                        .iter()
+                        .map(|(field, position, ty)| {
+                            (field.clone(), typed(ty, given[*position].clone()))
+                        })
+                        .collect();
+                    self.stack.push(Value::Struct {
+                        name: layout.name.clone(),
+                        fields,
+                    });
+                }
+                Op::ToLong => {
+                    if let Value::Int(num) = self.pop() {
+                        self.stack.push(Value::Long(i64::from(num)));
+                    }
+                }
+                Op::ToFloat => {
+                    if let Value::Int(num) = self.pop() {
+                        self.stack.push(Value::Float(num as f64));
+                    }
+                }
+                Op::Neg => {
+                    let value = negate(self.pop(), self.trap_overflow, span)?;
+                    self.stack.push(value);
+                }
+                Op::Not => {
+                    let value = self.pop().as_bool();
+                    self.stack.push(Value::Bool(!value));
+                }
+                Op::Jump(target) => self.frame().pc = target as usize,
+                Op::JumpIfFalse(target) => {
+                    if !self.pop().as_bool() {
+                        self.frame().pc = target as usize;
+                    }
+                }
+                Op::JumpIfTrue(target) => {
+                    if self.pop().as_bool() {
+                        self.frame().pc = target as usize;
+                    }
+                }
+                Op::Call(id) => {
+                    if self.frames.len() == MAX_FRAMES {
+                        return Err(runtime_error(
+                            "too many nested function calls".to_string(),
+                            span,
+                        ));
+                    }
+                    let callee = &program.functions[id as usize];
+                    let args = self.pop_many(callee.params.len());
+                    let mut locals = vec![Value::Int(0); callee.locals.len()];
+                    for (slot, (ty, arg)) in callee.params.iter().zip(args).enumerate() {
+                        locals[slot] = typed(ty, arg);
+                    }
+                    self.frames.push(Frame {
+                        function: id as usize,
+                        pc: 0,
+                        locals,
+                    });
+                }
+                Op::Builtin(index) => {
+                    let value = self.builtin(index, span)?;
+                    self.stack.push(value);
+                }
+                // Functions give ints; the top-level code's result is unused
+                Op::Return => {
+                    let value = self.pop();
+                    self.frames.pop();
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(value);
+                }
+                Op::Print(count) => {
+                    let mut line = String::new();
+                    for value in self.pop_many(count as usize) {
+                        line.push_str(&value.to_string());
+                    }
+                    writeln!(self.out, "{}", line)?;
+                }
+                Op::Input(slot) => {
+                    // Prompts shown before the read must be visible
+                    self.out.flush()?;
+                    let mut line = String::new();
+                    if self.input.read_line(&mut line)? == 0 {
+                        return Err(runtime_error("unexpected end of input".to_string(), span));
+                    }
+                    let local = &mut self.frame().locals[slot as usize];
+                    let value = input_value(&line, local, span)?;
+                    store(local, value);
+                }
+                Op::Assert(message) => {
+                    if self.pop() != Value::Bool(true) {
+                        let message =
+                            message.map(|index| program.constants[index as usize].to_string());
+                        return Err(InterpretError::Assert { message, span });
+                    }
+                }
+                Op::Exit => return Err(InterpretError::Exit(self.pop().as_int())),
+                op => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let op = op
+                        .operator()
+                        .expect("every other instruction is handled above");
+                    let value = binary_op(op, &lhs, &rhs, self.trap_overflow, span)?;
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+}
+
+// Run a program compiled by `bytecode::compile`, reading its input from
+// `input` and writing what it shows to `out`, with int overflow an error if
+// `trap_overflow`. Returns the program's exit code.
+pub fn execute(
+    program: &Program,
+    args: Vec<String>,
+    trap_overflow: bool,
+    input: impl BufRead,
+    out: impl Write,
+) -> Result<i32, InterpretError> {
+    let mut vm = Vm::new(program, args, input, out).with_trap_overflow(trap_overflow);
+    match vm.run() {
+        Ok(()) => Ok(0),
+        Err(InterpretError::Exit(code)) => Ok(code),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::{Function, StructLayout};
+    use crate::lexer::FileId;
+    use crate::semantic::Type;
+
+    const SPAN: Span = Span {
+        line: 1,
+        col: 1,
+        len: 0,
+        file: FileId::MAIN,
+    };
+
+    // A function with `locals` running `code`
+    fn function(params: Vec<Type>, locals: &[&str], code: Vec<Op>) -> Function {
+        Function {
+            name: "f".to_string(),
+            params,
+            locals: locals.iter().map(|local| local.to_string()).collect(),
+            spans: vec![SPAN; code.len()],
+            code,
+        }
+    }
+
+    fn program(code: Vec<Op>, locals: &[&str], constants: Vec<Value>) -> Program {
+        Program {
+            functions: vec![function(Vec::new(), locals, code)],
+            constants,
+            structs: Vec::new(),
+        }
+    }
+
+    // What running `program` with `input` shows, and its exit code
+    fn run_with(
+        program: &Program,
+        input: &str,
+        trap: bool,
+    ) -> Result<(String, i32), InterpretError> {
+        let mut out = Vec::new();
+        let args = vec!["prog".to_string(), "first".to_string()];
+        let code = execute(program, args, trap, input.as_bytes(), &mut out)?;
+        Ok((String::from_utf8(out).unwrap(), code))
+    }
+
+    // What top-level `code` shows, which must end the program itself
+    fn shows(code: Vec<Op>, locals: &[&str], constants: Vec<Value>) -> String {
+        run_with(&program(code, locals, constants), "", false)
+            .unwrap()
+            .0
+    }
+
+    // The message of the runtime error `code` stops with
+    fn fails(code: Vec<Op>, trap: bool) -> String {
+        match run_with(&program(code, &["a"], Vec::new()), "", trap) {
+            Err(InterpretError::Runtime(err)) => err.message,
+            other => panic!(
+                "expected a runtime error, got {:?}",
+                other.map(|(out, _)| out)
+            ),
+        }
+    }
+
+    // Print the top value, then end the program
+    fn end(mut code: Vec<Op>) -> Vec<Op> {
+        code.extend([Op::Print(1), Op::Int(0), Op::Return]);
+        code
+    }
+
+    fn builtin(name: &str) -> Op {
+        Op::Builtin(BUILTINS.iter().position(|b| b.name == name).unwrap() as u32)
+    }
+
+    #[test]
+    fn int_and_const_push_values() {
+        let constants = vec![Value::Str("hi".to_string()), Value::Float(1.5)];
+        let code = vec![
+            Op::Int(-4),
+            Op::Const(0),
+            Op::Const(1),
+            Op::Print(3),
+            Op::Int(0),
+            Op::Return,
+        ];
+        assert_eq!(shows(code, &[], constants), "-4hi1.5\n");
+    }
+
+    #[test]
+    fn pop_and_dup() {
+        let code = end(vec![Op::Int(1), Op::Int(2), Op::Pop, Op::Dup, Op::Add]);
+        assert_eq!(shows(code, &[], Vec::new()), "2\n");
+    }
+
+    #[test]
+    fn init_store_and_load_locals() {
+        let code = end(vec![
+            Op::Int(5),
+            Op::Init(0),
+            Op::Load(0),
+            Op::Int(2),
+            Op::Mul,
+            Op::Store(0),
+            Op::Load(0),
+        ]);
+        assert_eq!(shows(code, &["x"], Vec::new()), "10\n");
+    }
+
+    #[test]
+    fn store_widens_ints_into_longs() {
+        let code = end(vec![
+            Op::Const(0),
+            Op::Init(0),
+            Op::Int(i32::MAX),
+            Op::Store(0),
+            Op::Load(0),
+            Op::Int(1),
+            Op::ToLong,
+            Op::Add,
+        ]);
+        assert_eq!(shows(code, &["big"], vec![Value::Long(0)]), "2147483648\n");
+    }
+
+    #[test]
+    fn array_elements() {
+        let code = end(vec![
+            Op::Int(1),
+            Op::Int(2),
+            Op::Int(3),
+            Op::Array(3),
+            Op::Init(0),
+            Op::Int(1),
+            Op::Int(9),
+            Op::StoreIndex(0),
+            Op::Int(1),
+            Op::LoadIndex(0),
+            Op::Int(2),
+            Op::LoadIndex(0),
+            Op::Add,
+        ]);
+        assert_eq!(shows(code, &["nums"], Vec::new()), "12\n");
+    }
+
+    #[test]
+    fn array_index_out_of_range_stops() {
+        let code = vec![
+            Op::Int(7),
+            Op::Array(1),
+            Op::Init(0),
+            Op::Int(1),
+            Op::LoadIndex(0),
+        ];
+        assert_eq!(
+            fails(code, false),
+            "index 1 is out of range for 'a' of length 1"
+        );
+    }
+
+    #[test]
+    fn struct_fields() {
+        // Written as `Point { label = "p", x = 4 }`, defined with x first
+        let code = vec![
+            Op::Const(0),
+            Op::Int(4),
+            Op::Struct(0),
+            Op::Init(0),
+            Op::Int(7),
+            Op::StoreField(0, 0),
+            Op::LoadField(0, 1),
+            Op::LoadField(0, 0),
+            Op::Print(2),
+            Op::Int(0),
+            Op::Return,
+        ];
+        let mut program = program(code, &["p"], vec![Value::Str("p".to_string())]);
+        program.structs.push(StructLayout {
+            name: "Point".to_string(),
+            fields: vec![
+                ("x".to_string(), 1, Type::Int),
+                ("label".to_string(), 0, Type::Str),
+            ],
+        });
+        assert_eq!(run_with(&program, "", false).unwrap().0, "p7\n");
+    }
+
+    #[test]
+    fn to_long_and_to_float_convert_ints() {
+        let long = end(vec![
+            Op::Int(i32::MAX),
+            Op::ToLong,
+            Op::Int(2),
+            Op::ToLong,
+            Op::Mul,
+        ]);
+        assert_eq!(shows(long, &[], Vec::new()), "4294967294\n");
+        let float = end(vec![
+            Op::Int(1),
+            Op::ToFloat,
+            Op::Int(4),
+            Op::ToFloat,
+            Op::Div,
+        ]);
+        assert_eq!(shows(float, &[], Vec::new()), "0.25\n");
+    }
+
+    #[test]
+    fn arithmetic() {
+        for (op, expected) in [
+            (Op::Add, "-5\n"),
+            (Op::Sub, "-9\n"),
+            (Op::Mul, "-14\n"),
+            (Op::Div, "-3\n"),
+            (Op::Mod, "-1\n"),
+        ] {
+            let code = end(vec![Op::Int(-7), Op::Int(2), op]);
+            assert_eq!(shows(code, &[], Vec::new()), expected, "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn arithmetic_wraps_unless_trapped() {
+        let code = vec![Op::Int(i32::MAX), Op::Int(1), Op::Add];
+        assert_eq!(shows(end(code.clone()), &[], Vec::new()), "-2147483648\n");
+        assert_eq!(
+            fails(code, true),
+            "integer overflow: 2147483647 + 1 does not fit in an int"
+        );
+    }
+
+    #[test]
+    fn division_by_zero_stops() {
+        assert_eq!(
+            fails(vec![Op::Int(1), Op::Int(0), Op::Div], false),
+            "division by zero"
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        for (op, expected) in [
+            (Op::Eq, "false\n"),
+            (Op::Ne, "true\n"),
+            (Op::Lt, "true\n"),
+            (Op::Gt, "false\n"),
+            (Op::Le, "true\n"),
+            (Op::Ge, "false\n"),
+        ] {
+            let code = end(vec![Op::Int(1), Op::Int(2), op]);
+            assert_eq!(shows(code, &[], Vec::new()), expected, "{:?}", op);
+        }
+    }
+
+    #[test]
+    fn neg_and_not() {
+        assert_eq!(
+            shows(end(vec![Op::Int(3), Op::Neg]), &[], Vec::new()),
+            "-3\n"
+        );
+        let not = end(vec![Op::Int(1), Op::Int(1), Op::Eq, Op::Not]);
+        assert_eq!(shows(not, &[], Vec::new()), "false\n");
+    }
+
+    #[test]
+    fn jumps() {
+        // Each skipped value would end up on top if its jump went wrong
+        let code = vec![
+            Op::Jump(2),
+            Op::Int(10),
+            Op::Int(1),
+            Op::Int(2),
+            Op::Lt,
+            Op::JumpIfFalse(7),
+            Op::Int(20),
+            Op::Int(1),
+            Op::Int(2),
+            Op::Gt,
+            Op::JumpIfFalse(12),
+            Op::Int(30),
+            Op::Int(1),
+            Op::Int(1),
+            Op::Eq,
+            Op::JumpIfTrue(17),
+            Op::Int(40),
+            Op::Print(1),
+            Op::Int(0),
+            Op::Return,
+        ];
+        assert_eq!(shows(code, &[], Vec::new()), "20\n");
+    }
+
+    #[test]
+    fn call_and_return() {
+        let mut program = program(end(vec![Op::Int(41), Op::Call(1)]), &[], Vec::new());
+        program.functions.push(function(
+            vec![Type::Int],
+            &["n"],
+            vec![Op::Load(0), Op::Int(1), Op::Add, Op::Return],
+        ));
+        assert_eq!(run_with(&program, "", false).unwrap().0, "42\n");
+    }
+
+    #[test]
+    fn builtins() {
+        let upper = end(vec![Op::Const(0), builtin("upper")]);
+        assert_eq!(
+            shows(upper, &[], vec![Value::Str("abc".to_string())]),
+            "ABC\n"
+        );
+        let args = vec![
+            Op::Int(1),
+            builtin("arg"),
+            builtin("argc"),
+            Op::Print(2),
+            Op::Int(0),
+            Op::Return,
+        ];
+        assert_eq!(shows(args, &[], Vec::new()), "first2\n");
+    }
+
+    #[test]
+    fn input_reads_a_line_into_a_local() {
+        let program = program(end(vec![Op::Input(0), Op::Load(0)]), &["n"], Vec::new());
+        assert_eq!(run_with(&program, "42\n", false).unwrap().0, "42\n");
+        match run_with(&program, "", false) {
+            Err(InterpretError::Runtime(err)) => assert_eq!(err.message, "unexpected end of input"),
+            other => panic!(
+                "expected a runtime error, got {:?}",
+                other.map(|(out, _)| out)
+            ),
+        }
+    }
+
+    #[test]
+    fn assert_stops_when_false() {
+        let passes = vec![
+            Op::Int(1),
+            Op::Int(1),
+            Op::Eq,
+            Op::Assert(None),
+            Op::Int(0),
+            Op::Return,
+        ];
+        assert_eq!(shows(passes, &[], Vec::new()), "");
+        let program = program(
+            vec![
+                Op::Int(1),
+                Op::Int(2),
+                Op::Eq,
+                Op::Assert(Some(0)),
+                Op::Int(0),
+                Op::Return,
+            ],
+            &[],
+            vec![Value::Str("no match".to_string())],
+        );
+        match run_with(&program, "", false) {
+            Err(InterpretError::Assert { message, .. }) => {
+                assert_eq!(message.as_deref(), Some("no match"))
+            }
+            other => panic!("expected an assertion, got {:?}", other.map(|(out, _)| out)),
+        }
+    }
+
+    #[test]
+    fn exit_ends_with_its_code() {
+        let code = vec![
+            Op::Int(3),
+            Op::Exit,
+            Op::Int(1),
+            Op::Print(1),
+            Op::Int(0),
+            Op::Return,
+        ];
+        let program = program(code, &[], Vec::new());
+        assert_eq!(run_with(&program, "", false).unwrap(), (String::new(), 3));
+    }
+}
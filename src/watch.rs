@@ -0,0 +1,148 @@
+//! `bplang watch`: runs a program again every time its source changes
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::driver::{self, Engine, Options};
+use crate::lexer::FileId;
+use crate::module;
+use crate::parser::parse_source;
+
+// How often the watched files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// How long a changed file must stay the same before it is rebuilt, so an
+// editor saving in several writes triggers a single run
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+// Ctrl-C stops the watch between runs. A built program, in the same
+// process group, gets the signal too and stops by itself. A program run by
+// an engine runs in this process, so for its run Ctrl-C goes back to ending
+// the process.
+#[cfg(unix)]
+mod interrupt {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+    const SIGINT: i32 = 2;
+
+    // The handler that ends the process
+    const SIG_DFL: usize = 0;
+
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+    }
+
+    extern "C" fn on_interrupt(_: i32) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        // Safe: the handler only stores to an atomic
+        unsafe {
+            signal(SIGINT, on_interrupt as extern "C" fn(i32) as usize);
+        }
+    }
+
+    pub fn restore() {
+        // Safe: the default handler is always valid
+        unsafe {
+            signal(SIGINT, SIG_DFL);
+        }
+    }
+
+    pub fn interrupted() -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+}
+
+// Elsewhere Ctrl-C keeps its default of ending the process
+#[cfg(not(unix))]
+mod interrupt {
+    pub fn install() {}
+
+    pub fn restore() {}
+
+    pub fn interrupted() -> bool {
+        false
+    }
+}
+
+// What a file's last change looks like: its modification time and size,
+// since a time alone may be too coarse to tell quick saves apart. `None`
+// while the file is missing, as when an editor replaces it.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+// Files whose changes trigger a run: the program's source and the files it
+// uses, as far as they can be loaded
+fn watched_files(source: &Path) -> Vec<PathBuf> {
+    let mut files = vec![source.to_path_buf()];
+    let used = fs::read_to_string(source).ok().and_then(|text| {
+        let ast = parse_source(&text, FileId::MAIN).ok()?;
+        module::resolve(ast, source).ok()
+    });
+    if let Some((_, modules)) = used {
+        files.extend(modules.into_iter().map(|module| module.path));
+    }
+    files
+}
+
+fn stamps(files: &[PathBuf]) -> Vec<Stamp> {
+    files.iter().map(|file| stamp(file)).collect()
+}
+
+// Build and run the program at `source` now and after every change, until
+// interrupted. Errors are reported and the watch goes on. Returns the exit
+// code for `bplang` itself.
+pub fn watch(source: &Path, options: &Options) -> i32 {
+    interrupt::install();
+    let mut last = None;
+    loop {
+        let files = watched_files(source);
+        let mut current = stamps(&files);
+        if last.as_ref() != Some(&current) && current.iter().all(Option::is_some) {
+            // Wait for the writes to stop
+            loop {
+                thread::sleep(SETTLE_TIME);
+                let settled = stamps(&files);
+                if settled == current {
+                    break;
+                }
+                current = settled;
+            }
+            println!("---- running {} ----", source.display());
+            let in_process = options.engine != Engine::Compile;
+            if in_process {
+                interrupt::restore();
+            }
+            let result = driver::run(source, options);
+            if in_process {
+                interrupt::install();
+            }
+            if interrupt::interrupted() {
+                return 0;
+            }
+            match result {
+                Ok(code) => println!("---- exited with code {} ----", code),
+                Err(err) => {
+                    driver::report_compile_error(source, &err, options.error_format);
+                    println!("---- failed with code {} ----", err.exit_code());
+                }
+            }
+            println!("Watching {} for changes (Ctrl-C to stop)", source.display());
+            last = Some(current);
+        }
+        if interrupt::interrupted() {
+            return 0;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
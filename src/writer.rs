@@ -0,0 +1,155 @@
+//! Builds generated source code line by line, keeping track of indentation
+
+// Text indenting one level
+const INDENT: &str = "    ";
+
+// Generated code under construction. Every `line` is written at the current
+// depth, which `indent` and `dedent` move in and out.
+#[derive(Default)]
+pub struct CodeWriter {
+    code: String,
+    depth: usize,
+    lines: usize,
+}
+
+impl CodeWriter {
+    pub fn new() -> Self {
+        CodeWriter::default()
+    }
+
+    // Write one line at the current depth. Empty lines get no indentation.
+    pub fn line(&mut self, text: impl AsRef<str>) {
+        let text = text.as_ref();
+        if !text.is_empty() {
+            for _ in 0..self.depth {
+                self.code.push_str(INDENT);
+            }
+            self.code.push_str(text);
+        }
+        self.code.push('\n');
+        self.lines += 1;
+    }
+
+    // Write a line at the left margin whatever the depth, as C wants its
+    // preprocessor directives
+    pub fn directive(&mut self, text: impl AsRef<str>) {
+        self.code.push_str(text.as_ref());
+        self.code.push('\n');
+        self.lines += 1;
+    }
+
+    // Write an empty line
+    pub fn blank(&mut self) {
+        self.line("");
+    }
+
+    pub fn indent(&mut self) {
+        self.depth += 1;
+    }
+
+    pub fn dedent(&mut self) {
+        self.depth = self
+            .depth
+            .checked_sub(1)
+            .expect("dedent matches an earlier indent");
+    }
+
+    // Add code written by another writer, as it stands, for parts that are
+    // generated before what has to precede them is known
+    pub fn append(&mut self, other: CodeWriter) {
+        self.code.push_str(&other.code);
+        self.lines += other.lines;
+    }
+
+    // Number of lines written so far, to tell whether a block came out empty
+    pub fn line_count(&self) -> usize {
+        self.lines
+    }
+
+    pub fn finish(self) -> String {
+        self.code
+    }
+}
+
+// An expression without the parentheses around the whole of it, for places
+// where the language needs none, like a condition or the value of an
+// assignment. String literals in the expression are skipped over.
+pub fn unparenthesized(expr: String) -> String {
+    if !expr.starts_with('(') {
+        return expr;
+    }
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, ch) in expr.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => {
+                depth -= 1;
+                // The opening parenthesis closes before the end
+                if depth == 0 && i != expr.len() - 1 {
+                    return expr;
+                }
+            }
+            _ => {}
+        }
+    }
+    expr[1..expr.len() - 1].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_follow_the_depth() {
+        let mut out = CodeWriter::new();
+        out.line("int main(void) {");
+        out.indent();
+        out.line("if (x) {");
+        out.indent();
+        out.directive("#line 3 \"a.bp\"");
+        out.line("f();");
+        out.dedent();
+        out.blank();
+        out.line("}");
+        out.dedent();
+        out.line("}");
+        assert_eq!(out.line_count(), 7);
+        assert_eq!(
+            out.finish(),
+            "int main(void) {\n    if (x) {\n#line 3 \"a.bp\"\n        f();\n\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn append_keeps_the_other_writer_as_written() {
+        let mut inner = CodeWriter::new();
+        inner.indent();
+        inner.line("b;");
+        let mut out = CodeWriter::new();
+        out.line("a;");
+        out.append(inner);
+        assert_eq!(out.line_count(), 2);
+        assert_eq!(out.finish(), "a;\n    b;\n");
+    }
+
+    #[test]
+    fn unparenthesized_removes_only_enclosing_parentheses() {
+        let cases = [
+            ("(a + b)", "a + b"),
+            ("((a + b) * c)", "(a + b) * c"),
+            ("(a + b) * (c + d)", "(a + b) * (c + d)"),
+            ("f(x)", "f(x)"),
+            ("x", "x"),
+            ("(\")\" + s)", "\")\" + s"),
+            ("(\"\\\"(\" + s)", "\"\\\"(\" + s"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(unparenthesized(expr.to_string()), expected, "{}", expr);
+        }
+    }
+}
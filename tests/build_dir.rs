@@ -0,0 +1,85 @@
+//! Intermediate files go to a fresh temporary directory of their own,
+//! removed afterwards, and never next to the user's files
+
+mod common;
+
+use std::fs;
+
+use common::{program_output, stderr, stdout, Scratch};
+
+// The names in `dir`, sorted
+fn names(dir: &std::path::Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn run_leaves_no_files_behind() {
+    let scratch = Scratch::new("build-dir-run");
+    scratch.write("hello.bp", "show \"hi\";\n");
+    fs::create_dir(scratch.path("tmp")).unwrap();
+    let output = scratch
+        .bplang()
+        .env("TMPDIR", scratch.path("tmp"))
+        .args(["run", "hello.bp"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "hi\n");
+    assert_eq!(names(&scratch.dir), [".cache", "hello.bp", "tmp"]);
+    assert!(names(&scratch.path("tmp")).is_empty());
+}
+
+#[test]
+fn build_writes_only_the_binary() {
+    let scratch = Scratch::new("build-dir-build");
+    scratch.write("main.bp", "show 1;\n");
+    fs::create_dir(scratch.path("tmp")).unwrap();
+    let output = scratch
+        .bplang()
+        .env("TMPDIR", scratch.path("tmp"))
+        .args(["build", "--no-cache", "main.bp"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(names(&scratch.dir), ["main", "main.bp", "tmp"]);
+    assert!(names(&scratch.path("tmp")).is_empty());
+}
+
+#[test]
+fn failed_build_keeps_the_generated_c() {
+    let scratch = Scratch::new("build-dir-failed");
+    scratch.write("broken.bp", "craw \"this is not C\";\n");
+    fs::create_dir(scratch.path("tmp")).unwrap();
+    let output = scratch
+        .bplang()
+        .env("TMPDIR", scratch.path("tmp"))
+        .args(["build", "broken.bp"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(5), "{}", stdout(&output));
+    let kept = names(&scratch.path("tmp"));
+    assert_eq!(kept.len(), 1, "{:?}", kept);
+    let code = scratch.path("tmp").join(&kept[0]).join("broken.c");
+    assert!(code.is_file());
+    assert!(
+        stderr(&output).contains(&code.display().to_string()),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn errors_after_the_last_statement_name_the_generated_c() {
+    let scratch = Scratch::new("build-dir-epilogue");
+    scratch.write("e.bp", "craw \"if (1) {\";\n");
+    let output = scratch.run(&["build", "e.bp"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    assert!(stderr(&output).contains("e.c:"), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("e.bp:2"), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("e.bp:3"), "{}", stderr(&output));
+}
@@ -0,0 +1,82 @@
+//! Builtins reading the program's surroundings, checked on the compiled
+//! program and both engines
+
+mod common;
+
+use common::{program_output, stderr, Scratch};
+
+const ENGINES: &[&str] = &["compile", "tree", "vm"];
+
+// Run `program` on every engine with `BP_TEST_VALUE` set, checking that it
+// prints `expected`
+fn check_env(program: &str, expected: &str) {
+    let scratch = Scratch::new("env");
+    scratch.write("env.bp", program);
+    for engine in ENGINES {
+        let output = scratch
+            .bplang()
+            .env("BP_TEST_VALUE", "hello world")
+            .env_remove("BP_TEST_UNSET")
+            .args(["run", "--engine", engine, "env.bp"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}: {}", engine, stderr(&output));
+        assert_eq!(program_output(&output), expected, "{}", engine);
+    }
+}
+
+#[test]
+fn env_alone() {
+    check_env("show env(\"BP_TEST_VALUE\");\n", "hello world\n");
+}
+
+#[test]
+fn env_with_other_strings() {
+    check_env(
+        "m copy = env(\"BP_TEST_VALUE\");\n\
+         show \"[\", env(\"BP_TEST_UNSET\"), \"] \", len(copy), \" \", copy;\n\
+         show \"got \" + env(\"BP_TEST_VALUE\");\n",
+        "[] 11 hello world\ngot hello world\n",
+    );
+}
+
+// Run `program` on every engine with `args` after `--`, checking that it
+// prints `expected`
+fn check_args(program: &str, args: &[&str], expected: &str) {
+    let scratch = Scratch::new("args");
+    scratch.write("prog.bp", program);
+    for engine in ENGINES {
+        let mut command = vec!["run", "--engine", engine, "prog.bp", "--"];
+        command.extend_from_slice(args);
+        let output = scratch.run(&command);
+        assert!(output.status.success(), "{}: {}", engine, stderr(&output));
+        assert_eq!(program_output(&output), expected, "{}", engine);
+    }
+}
+
+#[test]
+fn args_after_double_dash_reach_the_program() {
+    check_args(
+        "show arg(1), \" \", arg(2);\n",
+        &["alpha", "beta"],
+        "alpha beta\n",
+    );
+}
+
+#[test]
+fn argc_counts_the_program_name() {
+    check_args(
+        "show argc();\nfor i = 1 to argc() - 1 { show i, \": \", arg(i); }\n",
+        &["one", "two words", "--three"],
+        "4\n1: one\n2: two words\n3: --three\n",
+    );
+}
+
+#[test]
+fn arg_out_of_range_is_empty() {
+    check_args(
+        "m last = arg(5);\nshow \"[\", last, \"][\", arg(-1), \"]\";\nshow \"x\" + arg(1);\n",
+        &["only"],
+        "[][]\nxonly\n",
+    );
+}
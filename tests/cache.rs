@@ -0,0 +1,87 @@
+//! The build cache of `bplang run`: which runs reuse a binary and which
+//! build one, seen through a C compiler that logs every call
+
+#![cfg(unix)]
+
+mod common;
+
+use common::{cc_calls, logging_cc, program_output, stderr, Scratch};
+
+fn compiles(scratch: &Scratch) -> usize {
+    cc_calls(scratch).len()
+}
+
+#[test]
+fn second_run_reuses_the_build() {
+    let scratch = Scratch::new("cache-reuse");
+    let cc = logging_cc(&scratch);
+    scratch.write("prog.bp", "show 6 * 7;\n");
+    for _ in 0..2 {
+        let output = scratch.run(&["run", "--cc", &cc, "prog.bp"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(program_output(&output), "42\n");
+    }
+    assert_eq!(compiles(&scratch), 1);
+
+    let output = scratch.run(&["run", "--cc", &cc, "--no-cache", "prog.bp"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(compiles(&scratch), 2);
+}
+
+#[test]
+fn changed_source_is_built_again() {
+    let scratch = Scratch::new("cache-change");
+    let cc = logging_cc(&scratch);
+    scratch.write("prog.bp", "show 1;\n");
+    scratch.run(&["run", "--cc", &cc, "prog.bp"]);
+    scratch.write("prog.bp", "show 2;\n");
+    let output = scratch.run(&["run", "--cc", &cc, "prog.bp"]);
+    assert_eq!(program_output(&output), "2\n");
+    assert_eq!(compiles(&scratch), 2);
+}
+
+#[test]
+fn warnings_are_reported_on_a_cache_hit() {
+    let scratch = Scratch::new("cache-warnings");
+    let cc = logging_cc(&scratch);
+    scratch.write("prog.bp", "m x = 1;\nshow 2;\n");
+    for _ in 0..2 {
+        let output = scratch.run(&["run", "--cc", &cc, "prog.bp"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(
+            stderr(&output).contains("unused variable 'x'"),
+            "{}",
+            stderr(&output)
+        );
+    }
+    assert_eq!(compiles(&scratch), 1);
+}
+
+#[test]
+fn deny_warnings_is_not_skipped_by_a_cached_build() {
+    let scratch = Scratch::new("cache-deny");
+    let cc = logging_cc(&scratch);
+    scratch.write("prog.bp", "m x = 1;\nshow 2;\n");
+    let output = scratch.run(&["run", "--cc", &cc, "prog.bp"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let output = scratch.run(&["run", "--cc", &cc, "--deny-warnings", "prog.bp"]);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "");
+    assert_eq!(compiles(&scratch), 1);
+}
+
+#[test]
+fn same_text_in_another_file_names_that_file() {
+    let scratch = Scratch::new("cache-path");
+    let cc = logging_cc(&scratch);
+    let program = "show 1;\nassert 1 == 2;\n";
+    scratch.write("w1.bp", program);
+    scratch.write("w2.bp", program);
+    let first = scratch.run(&["run", "--cc", &cc, "w1.bp"]);
+    assert!(stderr(&first).contains("w1.bp:2"), "{}", stderr(&first));
+    let second = scratch.run(&["run", "--cc", &cc, "w2.bp"]);
+    assert!(stderr(&second).contains("w2.bp:2"), "{}", stderr(&second));
+    assert!(!stderr(&second).contains("w1.bp"), "{}", stderr(&second));
+    assert_eq!(compiles(&scratch), 2);
+}
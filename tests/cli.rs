@@ -0,0 +1,146 @@
+//! The command line: which file is compiled, where the results go and how
+//! mistakes are reported
+
+mod common;
+
+use std::io::Write;
+use std::process::{Output, Stdio};
+
+use common::{program_output, stderr, stdout, Scratch};
+
+// Run `bplang` with `args` and `source` piped to its stdin
+fn piped(scratch: &Scratch, args: &[&str], source: &str) -> Output {
+    let mut child = scratch
+        .bplang()
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run bplang");
+    child
+        .stdin
+        .take()
+        .expect("piped")
+        .write_all(source.as_bytes())
+        .expect("write the source");
+    child.wait_with_output().expect("wait for bplang")
+}
+
+#[test]
+fn missing_source_file_is_a_one_line_error() {
+    let scratch = Scratch::new("missing");
+    let output = scratch.run(&["build", "nope.bp"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output),
+        "error[E0082]: source file not found: nope.bp\n"
+    );
+}
+
+#[test]
+fn missing_source_file_follows_error_format() {
+    let scratch = Scratch::new("missing-json");
+    let output = scratch.run(&["build", "--error-format", "json", "nope.bp"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output), "");
+    let json = stdout(&output);
+    assert!(json.contains(r#""code":"E0082""#), "{}", json);
+    assert!(json.contains(r#""file":"nope.bp""#), "{}", json);
+}
+
+#[test]
+fn bad_option_still_prints_usage() {
+    let scratch = Scratch::new("usage");
+    let output = scratch.run(&["build", "--no-such-option", "prog.bp"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("usage: bplang"));
+}
+
+#[test]
+fn file_in_subdirectory_builds_next_to_source() {
+    let scratch = Scratch::new("subdir");
+    scratch.write("sub/hello.bp", "show \"hi\";\n");
+    let output = scratch.run(&["build", "--keep-c", "sub/hello.bp"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(scratch.path("sub/hello").is_file());
+    assert!(scratch.path("sub/hello.c").is_file());
+    assert!(!scratch.path("hello").exists());
+    assert!(!scratch.path("main.c").exists());
+}
+
+#[test]
+fn main_bp_is_the_default_source() {
+    let scratch = Scratch::new("default");
+    scratch.write("main.bp", "show 1 + 2;\n");
+    let output = scratch.run(&["run"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "3\n");
+}
+
+#[test]
+fn dash_reads_the_program_from_stdin() {
+    let scratch = Scratch::new("stdin");
+    let output = piped(&scratch, &["run", "-"], "m x = 4;\nshow x * 2;\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "8\n");
+
+    let output = piped(&scratch, &["build", "-"], "show 5;\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(scratch.path("bp_out").is_file());
+
+    let output = piped(&scratch, &["run", "-"], "show 1;\nshow 1 +;\n");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(
+        stderr(&output).starts_with("error[E0020] at <stdin>:2:9: expected an expression"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn output_goes_where_o_says() {
+    let scratch = Scratch::new("output");
+    scratch.write("hello.bp", "show \"hi\";\n");
+
+    let output = scratch.run(&["build", "-o", "bin/hello", "hello.bp"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Built bin/hello\n");
+    assert!(scratch.path("bin/hello").is_file());
+    assert!(!scratch.path("hello").exists());
+
+    // Directories on the way are created, and the program runs from there
+    let output = scratch.run(&["run", "-o", "no/such/dir/hi", "hello.bp"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "hi\n");
+    assert!(scratch.path("no/such/dir/hi").is_file());
+    assert!(!scratch.path("hello").exists());
+
+    // Without `-o`, the binary is named after the source
+    let output = scratch.run(&["build", "hello.bp"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "Built ./hello\n");
+    assert!(scratch.path("hello").is_file());
+}
+
+#[test]
+fn new_project_runs_as_created() {
+    let scratch = Scratch::new("new");
+    let output = scratch.run(&["new", "demo"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).starts_with("Created project demo\n"),
+        "{}",
+        stdout(&output)
+    );
+
+    let output = scratch
+        .bplang()
+        .current_dir(scratch.path("demo"))
+        .arg("run")
+        .output()
+        .expect("run bplang");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "Hello from demo\n");
+    assert!(scratch.path("demo/bin/demo").is_file());
+}
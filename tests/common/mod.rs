@@ -0,0 +1,188 @@
+//! Helpers shared by the integration tests: a scratch directory per test
+//! and the `bplang` binary run inside it
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Distinguishes the directories of tests running at the same time
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+// A directory of its own for one test, removed when the test ends
+pub struct Scratch {
+    pub dir: PathBuf,
+}
+
+impl Scratch {
+    pub fn new(name: &str) -> Scratch {
+        let dir = env::temp_dir().join(format!(
+            "bplang-test-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create the scratch directory");
+        Scratch { dir }
+    }
+
+    // Write `contents` to `name`, relative to the directory, creating the
+    // directories on the way
+    pub fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.dir.join(name);
+        fs::create_dir_all(path.parent().expect("a file in a directory"))
+            .expect("create the file's directory");
+        fs::write(&path, contents).expect("write the file");
+        path
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    // `bplang` run in the directory, with a build cache of its own so tests
+    // neither share builds nor fill the user's cache
+    pub fn bplang(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_bplang"));
+        command
+            .current_dir(&self.dir)
+            .env("XDG_CACHE_HOME", self.dir.join(".cache"))
+            .env_remove("CC");
+        command
+    }
+
+    // Run `bplang` with `args` and wait for it
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.bplang().args(args).output().expect("run bplang")
+    }
+
+    // Run `bplang` with `args`, failing the test if it takes longer than
+    // `limit`, as a program that never ends would
+    pub fn run_within(&self, args: &[&str], limit: Duration) -> Output {
+        let mut child = self
+            .bplang()
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("run bplang");
+        // Read while waiting, so a full pipe cannot stall the child
+        let mut stdout = child.stdout.take().expect("piped");
+        let mut stderr = child.stderr.take().expect("piped");
+        let out = thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = stdout.read_to_end(&mut buf);
+            buf
+        });
+        let err = thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = stderr.read_to_end(&mut buf);
+            buf
+        });
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().expect("wait for bplang") {
+                break status;
+            }
+            if started.elapsed() > limit {
+                let _ = child.kill();
+                let _ = child.wait();
+                panic!(
+                    "bplang {} did not finish within {:?}",
+                    args.join(" "),
+                    limit
+                );
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        Output {
+            status,
+            stdout: out.join().expect("stdout reader"),
+            stderr: err.join().expect("stderr reader"),
+        }
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+// What a program printed, without the messages `run` adds before it
+pub fn program_output(output: &Output) -> String {
+    let stdout = stdout(output);
+    match stdout.split_once("Program output:\n") {
+        Some((_, printed)) => printed.to_string(),
+        None => stdout,
+    }
+}
+
+// A C compiler that notes the arguments of each call as a line of
+// `cc.log` and leaves the work to `cc`
+#[cfg(unix)]
+pub fn logging_cc(scratch: &Scratch) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    let log = scratch.path("cc.log");
+    let stub = scratch.write(
+        "logging-cc",
+        &format!(
+            "#!/bin/sh\necho \"$@\" >> '{}'\nexec cc \"$@\"\n",
+            log.display()
+        ),
+    );
+    fs::set_permissions(&stub, fs::Permissions::from_mode(0o755)).expect("make the stub runnable");
+    stub.display().to_string()
+}
+
+// The arguments of each call `logging_cc` logged, split on spaces
+pub fn cc_calls(scratch: &Scratch) -> Vec<Vec<String>> {
+    fs::read_to_string(scratch.path("cc.log"))
+        .map(|log| {
+            log.lines()
+                .map(|line| line.split(' ').map(String::from).collect())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// The repository's own directory, holding `examples/` and `tests/`
+pub fn repo() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+// The programs in `tests/` and `examples/`, by file name, with their source
+pub fn fixtures() -> Vec<(String, String)> {
+    let mut fixtures = Vec::new();
+    for dir in ["tests", "examples"] {
+        for entry in fs::read_dir(repo().join(dir)).expect("list the fixtures") {
+            let path = entry.expect("a directory entry").path();
+            if path.extension().is_none_or(|ext| ext != "bp") {
+                continue;
+            }
+            let source = fs::read_to_string(&path).expect("read the fixture");
+            let name = path.file_name().expect("a file name");
+            fixtures.push((name.to_string_lossy().into_owned(), source));
+        }
+    }
+    fixtures.sort();
+    assert!(fixtures.len() > 10, "too few fixtures: {}", fixtures.len());
+    fixtures
+}
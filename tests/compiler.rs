@@ -0,0 +1,223 @@
+//! Choosing the C compiler, the flags it is given, and what is reported
+//! when it is missing or fails, seen through stand-in compilers that log
+//! their calls
+
+#![cfg(unix)]
+
+mod common;
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use common::{cc_calls, logging_cc, stderr, stdout, Scratch};
+
+// The real `cc`, which the stand-ins hand the work to
+fn real_cc() -> PathBuf {
+    let path = env::var_os("PATH").expect("PATH is set");
+    env::split_paths(&path)
+        .map(|dir| dir.join("cc"))
+        .find(|cc| cc.is_file())
+        .expect("cc in PATH")
+}
+
+fn executable(path: &Path, script: &str) {
+    fs::write(path, script).expect("write the script");
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).expect("make it runnable");
+}
+
+// A `bin` directory of compilers called `names`, each noting its name in
+// `compilers.log` and compiling with the real `cc` and the real PATH
+fn fake_compilers(scratch: &Scratch, names: &[&str]) -> PathBuf {
+    let bin = scratch.path("bin");
+    fs::create_dir_all(&bin).expect("create bin");
+    let log = scratch.path("compilers.log");
+    let path = env::var("PATH").expect("PATH is set");
+    for name in names {
+        executable(
+            &bin.join(name),
+            &format!(
+                "#!/bin/sh\necho {} >> '{}'\nPATH='{}' exec '{}' \"$@\"\n",
+                name,
+                log.display(),
+                path,
+                real_cc().display()
+            ),
+        );
+    }
+    bin
+}
+
+// The compilers that ran, in order
+fn used(scratch: &Scratch) -> Vec<String> {
+    fs::read_to_string(scratch.path("compilers.log"))
+        .map(|log| log.lines().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+// `bplang build` with only `bin` in PATH
+fn build(scratch: &Scratch, bin: &Path, args: &[&str]) -> Output {
+    scratch.write("prog.bp", "show 1;\n");
+    scratch
+        .bplang()
+        .env("PATH", bin)
+        .arg("build")
+        .args(args)
+        .arg("prog.bp")
+        .output()
+        .expect("run bplang")
+}
+
+#[test]
+fn path_is_probed_for_cc_then_gcc_then_clang() {
+    for (present, expected) in [
+        (&["cc", "gcc", "clang"][..], "cc"),
+        (&["gcc", "clang"][..], "gcc"),
+        (&["clang"][..], "clang"),
+    ] {
+        let scratch = Scratch::new("compiler-probe");
+        let bin = fake_compilers(&scratch, present);
+        let output = build(&scratch, &bin, &[]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(used(&scratch), [expected], "with {:?}", present);
+    }
+}
+
+#[test]
+fn cc_variable_comes_before_path_and_option_before_both() {
+    let scratch = Scratch::new("compiler-choice");
+    let bin = fake_compilers(&scratch, &["cc", "gcc", "clang"]);
+    scratch.write("prog.bp", "show 1;\n");
+    for args in [
+        &["build", "prog.bp"][..],
+        &["build", "--cc", "gcc", "prog.bp"],
+    ] {
+        let output = scratch
+            .bplang()
+            .env("PATH", &bin)
+            .env("CC", "clang")
+            .args(args)
+            .output()
+            .expect("run bplang");
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+    assert_eq!(used(&scratch), ["clang", "gcc"]);
+}
+
+#[test]
+fn no_compiler_names_what_was_tried() {
+    let scratch = Scratch::new("compiler-none");
+    let bin = fake_compilers(&scratch, &[]);
+    let output = build(&scratch, &bin, &[]);
+    assert_eq!(output.status.code(), Some(6), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output),
+        "error[E0081]: no C compiler found; tried cc, gcc, clang in PATH. \
+         Pass --cc <compiler> or set CC\n"
+    );
+}
+
+#[test]
+fn verbose_shows_the_compiler_and_command() {
+    let scratch = Scratch::new("compiler-verbose");
+    let bin = fake_compilers(&scratch, &["gcc"]);
+    let output = build(&scratch, &bin, &["--verbose", "--opt", "1"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("Using C compiler gcc\nRunning gcc "),
+        "{}",
+        stdout(&output)
+    );
+    assert!(
+        stdout(&output).contains(" -fwrapv -O1 -o ./prog\n"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn flags_arrive_in_order_before_the_output() {
+    let scratch = Scratch::new("compiler-flags");
+    let cc = logging_cc(&scratch);
+    scratch.write("prog.bp", "show 1;\n");
+    let output = scratch.run(&[
+        "build",
+        "--cc",
+        &cc,
+        "--opt",
+        "2",
+        "--cflag",
+        "-DFIRST=1",
+        "--debug",
+        "--cflag",
+        "-Wall",
+        "prog.bp",
+    ]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let calls = cc_calls(&scratch);
+    assert_eq!(calls.len(), 1, "{:?}", calls);
+    let args = &calls[0];
+    assert!(args[0].ends_with("prog.c"), "{:?}", args);
+    assert_eq!(
+        args[1..],
+        ["-fwrapv", "-O2", "-g", "-DFIRST=1", "-Wall", "-o", "./prog"]
+    );
+}
+
+#[test]
+fn missing_compiler_path_is_named() {
+    let scratch = Scratch::new("compiler-missing");
+    scratch.write("prog.bp", "show 1;\n");
+    let output = scratch.run(&["run", "--cc", "/nonexistent", "prog.bp"]);
+    assert_eq!(output.status.code(), Some(6), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output),
+        "error[E0081]: C compiler '/nonexistent' not found; pass --cc <compiler>\n"
+    );
+    assert_eq!(stdout(&output), "");
+}
+
+#[test]
+fn missing_compiler_name_is_looked_for_in_path() {
+    let scratch = Scratch::new("compiler-missing-name");
+    scratch.write("prog.bp", "show 1;\n");
+    let output = scratch.run(&["run", "--cc", "no-such-cc", "prog.bp"]);
+    assert_eq!(output.status.code(), Some(6), "{}", stderr(&output));
+    assert_eq!(
+        stderr(&output),
+        "error[E0081]: C compiler 'no-such-cc' not found in PATH; \
+         install no-such-cc or pass --cc <compiler>\n"
+    );
+}
+
+#[test]
+fn failing_compiler_shows_its_command_and_output() {
+    let scratch = Scratch::new("compiler-fails");
+    let broken = scratch.path("broken-cc");
+    executable(
+        &broken,
+        "#!/bin/sh\necho 'broken-cc: out of luck' >&2\nexit 1\n",
+    );
+    scratch.write("prog.bp", "show 1;\n");
+    let output = scratch.run(&["build", "--cc", &broken.display().to_string(), "prog.bp"]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    let lines: Vec<String> = stderr(&output).lines().map(String::from).collect();
+    assert_eq!(
+        lines[0],
+        "error[E0080]: C compilation failed (exit status: 1)"
+    );
+    assert!(
+        lines[1].starts_with(&format!("  command: {} ", broken.display())),
+        "{:?}",
+        lines
+    );
+    assert!(lines[1].ends_with(" -fwrapv -o ./prog"), "{:?}", lines);
+    assert!(
+        lines[2].starts_with("  generated C: ") && lines[2].ends_with("prog.c"),
+        "{:?}",
+        lines
+    );
+    assert_eq!(lines[3], "broken-cc: out of luck");
+}
@@ -0,0 +1,97 @@
+//! `bplang test` over the programs in `tests/`, with every engine, and over
+//! programs written to fail
+
+mod common;
+
+use common::{repo, stderr, stdout, Scratch};
+
+fn check(options: &[&str]) {
+    let scratch = Scratch::new("corpus");
+    let corpus = repo().join("tests");
+    let corpus = corpus.to_str().expect("a UTF-8 path");
+    let mut args = vec!["test"];
+    args.extend_from_slice(options);
+    args.push(corpus);
+    let output = scratch.run(&args);
+    assert!(
+        output.status.success(),
+        "{:?}:\n{}{}",
+        options,
+        stdout(&output),
+        stderr(&output)
+    );
+    assert!(
+        stdout(&output).contains("test result: ok."),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn compiled() {
+    check(&[]);
+}
+
+#[test]
+fn tree_engine() {
+    check(&["--engine", "tree"]);
+}
+
+#[test]
+fn vm_engine() {
+    check(&["--engine", "vm"]);
+}
+
+#[test]
+fn failures_are_listed_and_fail_the_run() {
+    let scratch = Scratch::new("corpus-failures");
+    scratch.write("tests/good.bp", "show 1;\n//! expect: 1\n");
+    scratch.write("tests/wrong.bp", "show 1;\n//! expect: 2\n");
+    scratch.write("tests/short.bp", "show 1;\n//! expect: 1\n//! expect: 2\n");
+    scratch.write(
+        "tests/compiles.bp",
+        "show 1;\n//! expect-error: undeclared\n",
+    );
+    scratch.write("tests/exits.bp", "exit 3;\n");
+    scratch.write("tests/notes.txt", "not a test\n");
+    let output = scratch.run(&["test"]);
+    assert_eq!(output.status.code(), Some(1));
+    let report = stdout(&output);
+    assert!(report.starts_with("running 5 tests\n"), "{}", report);
+    for (test, result) in [
+        ("compiles", "FAILED"),
+        ("exits", "FAILED"),
+        ("good", "ok"),
+        ("short", "FAILED"),
+        ("wrong", "FAILED"),
+    ] {
+        let line = format!("test tests/{}.bp ... {}\n", test, result);
+        assert!(report.contains(&line), "{}", report);
+    }
+    for why in [
+        "line 1: expected '2', got '1'",
+        "line 2: expected '2', got nothing",
+        "expected an error containing 'undeclared', but the program compiled",
+        "the program exited with code 3",
+    ] {
+        assert!(report.contains(why), "{}", report);
+    }
+    assert!(
+        report.ends_with("test result: FAILED. 1 passed; 4 failed\n"),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn expected_compile_errors_pass() {
+    let scratch = Scratch::new("corpus-errors");
+    scratch.write(
+        "bad.bp",
+        "show nope;\n//! expect-error: undeclared variable 'nope'\n",
+    );
+    let output = scratch.run(&["test", "bad.bp"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stderr(&output), "");
+    assert!(stdout(&output).ends_with("test result: ok. 1 passed; 0 failed\n"));
+}
@@ -0,0 +1,85 @@
+//! Every program of the corpus and the examples gives the same output and
+//! exit code however it is run as it does compiled to C
+
+mod common;
+
+use std::process::Output;
+
+use common::{fixtures, program_output, stderr, Scratch};
+
+fn run(scratch: &Scratch, name: &str, options: &[&str]) -> Output {
+    let mut args = vec!["run"];
+    args.extend_from_slice(options);
+    args.push(name);
+    scratch.run(&args)
+}
+
+// Run every fixture but those in `skip` with `options`, comparing it with
+// the compiled program
+fn check(options: &[&str], skip: &[&str]) {
+    let scratch = Scratch::new("differential");
+    for (name, source) in &fixtures() {
+        if source.contains("//! expect-error:") || skip.contains(&name.as_str()) {
+            continue;
+        }
+        scratch.write(name, source);
+        let compiled = run(&scratch, name, &[]);
+        assert!(compiled.status.success(), "{}: {}", name, stderr(&compiled));
+        let other = run(&scratch, name, options);
+        assert_eq!(
+            other.status.code(),
+            compiled.status.code(),
+            "{} with {:?}: {}",
+            name,
+            options,
+            stderr(&other)
+        );
+        assert_eq!(
+            program_output(&other),
+            program_output(&compiled),
+            "{} with {:?}",
+            name,
+            options
+        );
+    }
+}
+
+#[test]
+fn tree_engine_matches_compiled() {
+    check(&["--engine", "tree"], &[]);
+}
+
+// Longs only work compiled to C and with the engines
+const LONGS: &[&str] = &["long.bp"];
+
+#[test]
+fn js_target_matches_compiled() {
+    check(&["--target", "js"], LONGS);
+}
+
+// Python's ints never wrap around, and its `//` and `%` round toward
+// negative infinity
+const PYTHON_INTS: &[&str] = &["long.bp", "negative_division.bp", "overflow.bp"];
+
+#[test]
+fn py_target_matches_compiled() {
+    check(&["--target", "py"], PYTHON_INTS);
+}
+
+#[test]
+fn rust_target_matches_compiled() {
+    check(&["--target", "rust"], LONGS);
+}
+
+#[test]
+fn vm_engine_matches_compiled() {
+    check(&["--engine", "vm"], &[]);
+}
+
+// The LLVM target has neither longs nor `upper` and `lower`
+const LLVM_UNSUPPORTED: &[&str] = &["long.bp", "strings.bp"];
+
+#[test]
+fn llvm_target_matches_compiled() {
+    check(&["--target", "llvm"], LLVM_UNSUPPORTED);
+}
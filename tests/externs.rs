@@ -0,0 +1,109 @@
+//! C library functions declared with `extern fn`: called directly when
+//! compiled, `puts` and `putchar` run by the tree engine, and the rest
+//! refused before anything runs
+
+mod common;
+
+use common::{program_output, stderr, Scratch};
+
+const LIBC: &str = "\
+extern fn puts(s: str);
+extern fn putchar(ch);
+extern fn atoi(s: str): int;
+
+show atoi(\"7\") * 6;
+puts(\"from puts\");
+putchar(65);
+putchar(10);
+";
+
+const CTYPE: &str = "\
+cinclude \"ctype.h\";
+extern fn isdigit(ch): bool;
+
+show \"before\";
+show isdigit(55);
+";
+
+fn run(program: &str, options: &[&str]) -> std::process::Output {
+    let scratch = Scratch::new("externs");
+    scratch.write("ext.bp", program);
+    let mut args = vec!["run"];
+    args.extend_from_slice(options);
+    args.push("ext.bp");
+    scratch.run(&args)
+}
+
+#[test]
+fn compiled_program_calls_the_c_library() {
+    let output = run(LIBC, &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "42\nfrom puts\nA\n");
+    let output = run(CTYPE, &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "before\ntrue\n");
+}
+
+#[test]
+fn tree_engine_runs_puts_and_putchar() {
+    let program = "extern fn puts(s: str);\nextern fn putchar(ch);\n\
+                   puts(\"hi\");\nputchar(66);\nputchar(10);\n";
+    let output = run(program, &["--engine", "tree"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "hi\nB\n");
+}
+
+#[test]
+fn tree_engine_refuses_other_externs_before_running() {
+    let program = "extern fn atoi(s: str): int;\nshow \"before\";\nshow atoi(\"7\");\n";
+    let output = run(program, &["--engine", "tree"]);
+    assert_eq!(output.status.code(), Some(8), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "");
+    assert!(
+        stderr(&output).starts_with(
+            "error[E0070] at ext.bp:1:11: extern function 'atoi' only works with the C target, \
+             not the tree engine\n"
+        ),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn tree_engine_refuses_puts_declared_differently() {
+    let program = "extern fn puts(n);\nshow \"before\";\nputs(1);\n";
+    let output = run(program, &["--engine", "tree"]);
+    assert_eq!(output.status.code(), Some(8), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "");
+}
+
+#[test]
+fn vm_engine_refuses_every_extern() {
+    let program = "extern fn puts(s: str);\nputs(\"hi\");\n";
+    let output = run(program, &["--engine", "vm"]);
+    assert_eq!(output.status.code(), Some(8), "{}", stderr(&output));
+    assert!(
+        stderr(&output)
+            .contains("extern function 'puts' only works with the C target, not the vm engine"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn test_runner_refuses_externs_like_run() {
+    let scratch = Scratch::new("externs-test");
+    scratch.write(
+        "ext.bp",
+        "extern fn atoi(s: str): int;\nshow atoi(\"7\");\n//! expect-error: extern function 'atoi'\n",
+    );
+    for engine in ["tree", "vm"] {
+        let output = scratch.run(&["test", "--engine", engine, "ext.bp"]);
+        assert!(
+            output.status.success(),
+            "{}: {}",
+            engine,
+            common::stdout(&output)
+        );
+    }
+}
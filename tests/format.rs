@@ -0,0 +1,150 @@
+//! `bplang fmt` over the corpus and the examples: formatting twice gives
+//! what formatting once does, and the text printed from a tree parses back
+//! to the same tree
+
+mod common;
+
+use bplang::ast::{ast_tree, ASTNode};
+use bplang::format::{format_source, to_source};
+use bplang::lexer::FileId;
+use bplang::parser::parse_source;
+
+use common::fixtures;
+
+#[test]
+fn formatting_is_idempotent() {
+    let mut formatted_any = false;
+    for (name, source) in fixtures() {
+        // Programs that do not parse are left alone
+        let Ok(once) = format_source(&source) else {
+            continue;
+        };
+        formatted_any = true;
+        let twice = format_source(&once).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        assert_eq!(twice, once, "{}", name);
+    }
+    assert!(formatted_any);
+}
+
+#[test]
+fn indentation_is_restored() {
+    for (name, source) in fixtures() {
+        let Ok(formatted) = format_source(&source) else {
+            continue;
+        };
+        let flat: String = source
+            .lines()
+            .map(|line| format!("{}\n", line.trim_start()))
+            .collect();
+        let reformatted = format_source(&flat).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        assert_eq!(reformatted, formatted, "{}", name);
+    }
+}
+
+#[test]
+fn messy_program_is_laid_out_once_and_for_all() {
+    let messy = "\
+// totals
+m   x=1+2*3 ;   // seven
+fn   add( a,b ){give a+b;}
+if x>3{show   \"big\" ;}else{show \"small\";}
+
+
+enum Light{Red,Green}
+m l=Light.Red;
+match l{Red{show 1;}else{show 2;}}
+";
+    let expected = "\
+// totals
+m x = 1 + 2 * 3; // seven
+fn add(a, b) {
+    give a + b;
+}
+if x > 3 {
+    show \"big\";
+} else {
+    show \"small\";
+}
+
+enum Light { Red, Green }
+m l = Light.Red;
+match l {
+    Red {
+        show 1;
+    }
+    else {
+        show 2;
+    }
+}
+";
+    let once = format_source(messy).unwrap();
+    assert_eq!(once, expected);
+    assert_eq!(format_source(&once).unwrap(), once);
+}
+
+// The tree of `ast` without source positions, which printing moves
+fn shape(ast: &[ASTNode]) -> String {
+    ast_tree(ast)
+        .split(' ')
+        .filter(|word| !word.starts_with('@'))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Print `source`'s tree and parse the text again, checking the trees match
+fn round_trip(name: &str, source: &str) -> String {
+    let ast = parse_source(source, FileId::MAIN).unwrap_or_else(|err| panic!("{}: {}", name, err));
+    let printed = to_source(&ast);
+    let reparsed = parse_source(&printed, FileId::MAIN)
+        .unwrap_or_else(|err| panic!("{} printed as\n{}\ndoes not parse: {}", name, printed, err));
+    assert_eq!(
+        shape(&reparsed),
+        shape(&ast),
+        "{} printed as\n{}",
+        name,
+        printed
+    );
+    assert_eq!(to_source(&reparsed), printed, "{}", name);
+    printed
+}
+
+#[test]
+fn printed_fixtures_parse_back_to_the_same_tree() {
+    let mut printed_any = false;
+    for (name, source) in fixtures() {
+        if parse_source(&source, FileId::MAIN).is_ok() {
+            round_trip(&name, &source);
+            printed_any = true;
+        }
+    }
+    assert!(printed_any);
+}
+
+#[test]
+fn parentheses_are_kept_only_where_needed() {
+    assert_eq!(
+        round_trip(
+            "grouping",
+            "show (1 - 2) - 3, 1 - (2 - 3), (1 * 2) + 3, (1 + 2) * 3;"
+        ),
+        "show 1 - 2 - 3, 1 - (2 - 3), 1 * 2 + 3, (1 + 2) * 3;\n"
+    );
+    assert_eq!(
+        round_trip(
+            "negation",
+            "m x = 1;\nshow -(x + 1), -(-x), not (x > 1 or x < 0);"
+        ),
+        "m x = 1;\nshow -(x + 1), --x, not (x > 1 or x < 0);\n"
+    );
+}
+
+#[test]
+fn strings_are_escaped_again() {
+    assert_eq!(
+        round_trip(
+            "escapes",
+            "show \"tab\\t quote\\\" slash\\\\ nul\\0 cr\\r nl\\n\";"
+        ),
+        "show \"tab\\t quote\\\" slash\\\\ nul\\0 cr\\r nl\\n\";\n"
+    );
+}
@@ -0,0 +1,80 @@
+//! C libraries named with `link` directives and `--link`: linked by
+//! `bplang run` and `bplang test` alike, after the file that uses them
+
+mod common;
+
+use common::{program_output, stderr, stdout, Scratch};
+
+// `cbrt` is in the math library, which `cc` does not link by default. The
+// argument is a variable so the compiler cannot work the call out itself.
+const CBRT: &str = "\
+cinclude \"math.h\";
+extern fn cbrt(x: float): float;
+m x = 27.0;
+show cbrt(x);
+//! expect: 3
+";
+
+fn with_link(program: &str) -> String {
+    format!("link \"m\";\n{}", program)
+}
+
+#[test]
+fn run_links_the_directive() {
+    let scratch = Scratch::new("link-run");
+    scratch.write("t1.bp", &with_link(CBRT));
+    let output = scratch.run(&["run", "t1.bp"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "3\n");
+}
+
+#[test]
+fn test_links_the_directive() {
+    let scratch = Scratch::new("link-test");
+    scratch.write("t1.bp", &with_link(CBRT));
+    let output = scratch.run(&["test", "t1.bp"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(
+        stdout(&output).contains("test t1.bp ... ok"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn test_links_the_option() {
+    let scratch = Scratch::new("link-option");
+    scratch.write("t2.bp", CBRT);
+    let output = scratch.run(&["test", "--link", "m", "t2.bp"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(
+        stdout(&output).contains("test t2.bp ... ok"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn libraries_follow_the_source_file() {
+    use common::{cc_calls, logging_cc};
+
+    let scratch = Scratch::new("link-order");
+    let cc = logging_cc(&scratch);
+    scratch.write("t1.bp", &with_link(CBRT));
+    for command in ["run", "test"] {
+        let output = scratch.run(&[command, "--cc", &cc, "t1.bp"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+    let calls = cc_calls(&scratch);
+    assert_eq!(calls.len(), 2, "{:?}", calls);
+    for args in calls {
+        let source = args.iter().position(|arg| arg.ends_with("t1.c"));
+        let library = args.iter().position(|arg| arg == "-lm");
+        assert!(
+            source.is_some() && library > source,
+            "-lm should follow the source file: {:?}",
+            args
+        );
+    }
+}
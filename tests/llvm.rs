@@ -0,0 +1,53 @@
+//! The LLVM IR `--emit-llvm` writes for every fixture the LLVM target
+//! supports, compared with the snapshots in `tests/snapshots/llvm`. Run
+//! with `BPLANG_BLESS=1` to write the snapshots instead.
+
+mod common;
+
+use std::env;
+use std::fs;
+
+use common::{fixtures, repo, stderr, Scratch};
+
+// The LLVM target has neither longs nor `upper` and `lower`
+const UNSUPPORTED: &[&str] = &["long.bp", "strings.bp"];
+
+#[test]
+fn ir_matches_the_snapshots() {
+    let bless = env::var_os("BPLANG_BLESS").is_some();
+    let snapshots = repo().join("tests/snapshots/llvm");
+    let scratch = Scratch::new("llvm-snapshots");
+    let mut checked = 0;
+    for (name, source) in &fixtures() {
+        if source.contains("//! expect-error:") || UNSUPPORTED.contains(&name.as_str()) {
+            continue;
+        }
+        scratch.write(name, source);
+        let output = scratch.run(&["build", "--emit-llvm", name]);
+        assert!(output.status.success(), "{}: {}", name, stderr(&output));
+        let file = name.replace(".bp", ".ll");
+        let ir = fs::read_to_string(scratch.path(&file)).expect("read the IR");
+        let snapshot = snapshots.join(&file);
+        if bless {
+            fs::create_dir_all(&snapshots).expect("create the snapshot directory");
+            fs::write(&snapshot, &ir).expect("write the snapshot");
+        } else {
+            let expected = fs::read_to_string(&snapshot).unwrap_or_else(|_| {
+                panic!(
+                    "no snapshot {}; run with BPLANG_BLESS=1",
+                    snapshot.display()
+                )
+            });
+            assert!(
+                ir == expected,
+                "{} differs from {}; run with BPLANG_BLESS=1 if the change is \
+                 intended\n{}",
+                file,
+                snapshot.display(),
+                ir
+            );
+        }
+        checked += 1;
+    }
+    assert!(checked > 10, "too few fixtures: {}", checked);
+}
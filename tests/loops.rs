@@ -0,0 +1,106 @@
+//! `for` loops count through an inclusive range, the same with every engine
+//! and target, up to the largest int
+
+mod common;
+
+use std::time::Duration;
+
+use common::{program_output, stderr, Scratch};
+
+// Ends at the largest int, which the counter must not step past
+const EDGE: &str = "\
+m top = 2147483647;
+for i = 2147483646 to top {
+    show i;
+}
+for i = 2147483645 to 2147483647 {
+    if i == 2147483646 {
+        continue;
+    }
+    show i;
+}
+m n = 0;
+for i = 1 to top {
+    n = n + 1;
+    if i == 3 {
+        i = 2147483646;
+    }
+}
+show n;
+for i = 3 to 1 {
+    show \"never\";
+}
+for i = -2 to 0 {
+    show i;
+}
+";
+
+const EXPECTED: &str = "2147483646\n2147483647\n2147483645\n2147483647\n4\n-2\n-1\n0\n";
+
+fn check(options: &[&str]) {
+    let scratch = Scratch::new("loops");
+    scratch.write("edge.bp", EDGE);
+    let mut args = vec!["run"];
+    args.extend_from_slice(options);
+    args.push("edge.bp");
+    let output = scratch.run_within(&args, Duration::from_secs(60));
+    assert!(
+        output.status.success(),
+        "{:?}: {}",
+        options,
+        stderr(&output)
+    );
+    assert_eq!(program_output(&output), EXPECTED, "{:?}", options);
+}
+
+#[test]
+fn compiled() {
+    check(&[]);
+}
+
+#[test]
+fn tree_engine() {
+    check(&["--engine", "tree"]);
+}
+
+#[test]
+fn vm_engine() {
+    check(&["--engine", "vm"]);
+}
+
+#[test]
+fn js_target() {
+    check(&["--target", "js"]);
+}
+
+#[test]
+fn py_target() {
+    check(&["--target", "py"]);
+}
+
+#[test]
+fn rust_target() {
+    check(&["--target", "rust"]);
+}
+
+#[test]
+fn llvm_target() {
+    check(&["--target", "llvm"]);
+}
+
+// The counter stops at the largest int rather than stepping past it, so
+// trapping on overflow finds nothing to trap
+#[test]
+fn compiled_trapping_overflow() {
+    check(&["--trap-overflow"]);
+}
+
+#[test]
+fn tree_engine_trapping_overflow() {
+    check(&["--engine", "tree", "--trap-overflow"]);
+}
+
+#[test]
+fn vm_engine_trapping_overflow() {
+    check(&["--engine", "vm", "--trap-overflow"]);
+}
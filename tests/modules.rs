@@ -0,0 +1,85 @@
+//! Programs split over files with `use`: definitions shared between them,
+//! and used files that are missing, use each other or hold statements
+
+mod common;
+
+use common::{program_output, stderr, Scratch};
+
+const MATH: &str = "\
+fn square(n) {
+    give n * n;
+}
+";
+
+#[test]
+fn two_files_share_a_function() {
+    let scratch = Scratch::new("modules-share");
+    scratch.write("lib/math.bp", MATH);
+    scratch.write(
+        "main.bp",
+        "use \"lib/math.bp\";\n\nshow square(7);\nshow square(square(2));\n",
+    );
+    for engine in ["compile", "tree", "vm"] {
+        let output = scratch.run(&["run", "--engine", engine, "main.bp"]);
+        assert!(output.status.success(), "{}: {}", engine, stderr(&output));
+        assert_eq!(program_output(&output), "49\n16\n", "{}", engine);
+    }
+}
+
+#[test]
+fn missing_file_is_reported_at_the_use() {
+    let scratch = Scratch::new("modules-missing");
+    scratch.write("main.bp", "use \"lib/nope.bp\";\nshow 1;\n");
+    let output = scratch.run(&["run", "main.bp"]);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+    assert!(
+        stderr(&output).starts_with("error[E0050] at main.bp:1:1: cannot use 'lib/nope.bp'"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(program_output(&output), "");
+}
+
+#[test]
+fn files_using_each_other_are_a_cycle() {
+    let scratch = Scratch::new("modules-cycle");
+    scratch.write("a.bp", "use \"b.bp\";\nshow 1;\n");
+    scratch.write("b.bp", "use \"a.bp\";\n\nfn f() {\n    give 1;\n}\n");
+    let output = scratch.run(&["run", "a.bp"]);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+    assert!(
+        stderr(&output).starts_with(
+            "error[E0051] at b.bp:1:1: files use each other in a cycle: a.bp -> b.bp -> a.bp\n"
+        ),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn errors_in_a_used_file_point_into_it() {
+    let scratch = Scratch::new("modules-error");
+    scratch.write("lib/bad.bp", "fn broken() {\n    give \"s\" + 1;\n}\n");
+    scratch.write("main.bp", "use \"lib/bad.bp\";\nshow 1;\n");
+    let output = scratch.run(&["run", "main.bp"]);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+    assert!(
+        stderr(&output).starts_with("error[E0030] at lib/bad.bp:2:5:"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn used_file_may_only_define() {
+    let scratch = Scratch::new("modules-statement");
+    scratch.write("lib/math.bp", &format!("{}show 1;\n", MATH));
+    scratch.write("main.bp", "use \"lib/math.bp\";\nshow square(2);\n");
+    let output = scratch.run(&["run", "main.bp"]);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+    assert!(
+        stderr(&output).starts_with("error[E0052] at lib/math.bp:4:1:"),
+        "{}",
+        stderr(&output)
+    );
+}
@@ -0,0 +1,79 @@
+//! `2147483647 + 1`: wrapping around by default, stopping the program with
+//! `--trap-overflow`, compiled and interpreted alike
+
+mod common;
+
+use common::{program_output, stderr, Scratch};
+
+// The sum is only known when the program runs
+const RUNTIME: &str = "m x = 2147483647;\nshow x + 1;\n";
+
+// The sum is known while compiling
+const LITERAL: &str = "show 2147483647 + 1;\n";
+
+const MESSAGE: &str = "integer overflow: 2147483647 + 1 does not fit in an int";
+
+fn run(program: &str, options: &[&str]) -> std::process::Output {
+    let scratch = Scratch::new("overflow");
+    scratch.write("over.bp", program);
+    let mut args = vec!["run"];
+    args.extend_from_slice(options);
+    args.push("over.bp");
+    scratch.run(&args)
+}
+
+#[test]
+fn wraps_around_by_default() {
+    for program in [RUNTIME, LITERAL] {
+        for engine in ["compile", "tree", "vm"] {
+            let output = run(program, &["--engine", engine]);
+            assert!(output.status.success(), "{}: {}", engine, stderr(&output));
+            assert_eq!(program_output(&output), "-2147483648\n", "{}", engine);
+        }
+    }
+}
+
+#[test]
+fn compiled_program_traps_when_it_runs() {
+    let output = run(RUNTIME, &["--trap-overflow"]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert_eq!(program_output(&output), "");
+    assert_eq!(
+        stderr(&output),
+        format!("error at over.bp:2: {}\n", MESSAGE)
+    );
+}
+
+#[test]
+fn compiler_traps_a_literal_sum() {
+    let output = run(LITERAL, &["--trap-overflow"]);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+    assert!(
+        stderr(&output).starts_with(&format!("error[E0043] at over.bp:1:17: {}\n", MESSAGE)),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn engines_trap_when_they_run() {
+    for (program, at) in [(RUNTIME, "over.bp:2:8"), (LITERAL, "over.bp:1:17")] {
+        for engine in ["tree", "vm"] {
+            let output = run(program, &["--engine", engine, "--trap-overflow"]);
+            assert_eq!(
+                output.status.code(),
+                Some(7),
+                "{}: {}",
+                engine,
+                stderr(&output)
+            );
+            assert_eq!(program_output(&output), "", "{}", engine);
+            assert!(
+                stderr(&output).starts_with(&format!("error[E0060] at {}: {}\n", at, MESSAGE)),
+                "{}: {}",
+                engine,
+                stderr(&output)
+            );
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! `bplang repl` driven through piped stdin: the transcript it prints, the
+//! errors it reports without ending the session, and how it ends
+
+mod common;
+
+use std::io::Write;
+use std::process::{Output, Stdio};
+
+use common::{stderr, stdout, Scratch};
+
+// Run a session with `input` typed in, then end of input
+fn session(input: &str) -> Output {
+    let scratch = Scratch::new("repl");
+    let mut child = scratch
+        .bplang()
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run bplang repl");
+    child
+        .stdin
+        .take()
+        .expect("piped")
+        .write_all(input.as_bytes())
+        .expect("write the input");
+    child.wait_with_output().expect("wait for bplang repl")
+}
+
+#[test]
+fn statements_persist_and_expressions_echo() {
+    let output = session("m x = 2;\nx * 21\nx = x + 1;\nx\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "> > 42\n> > 3\n> \n");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn unclosed_brace_reads_more_lines() {
+    let output = session("fn twice(n) {\n    give n * 2;\n}\nshow twice(4);\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "> ... ... > 8\n> \n");
+}
+
+#[test]
+fn errors_do_not_end_the_session() {
+    let output = session("m x = 5;\nshow y;\nshow x +;\nshow 1 / (x - 5);\nshow x;\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "> > > > > 5\n> \n");
+    let errors = stderr(&output);
+    let places: Vec<&str> = errors
+        .lines()
+        .filter(|line| line.starts_with("error"))
+        .map(|line| line.split(": ").next().unwrap_or(line))
+        .collect();
+    assert_eq!(
+        places,
+        [
+            "error[E0010] at <repl>:1:6",
+            "error[E0020] at <repl>:1:9",
+            "error[E0060] at <repl>:1:8",
+        ],
+        "{}",
+        errors
+    );
+}
+
+#[test]
+fn tokens_and_ast_dump_the_previous_input() {
+    let output = session("m x = 1;\n:tokens\n:ast\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "> > 1:1 keyword m\n1:3 identifier x\n1:5 punct =\n1:7 number 1\n\
+         1:8 punct ;\n2:1 end of file\n\
+         > VariableDeclaration m x @1:3\n  Number 1\n> \n"
+    );
+}
+
+#[test]
+fn end_of_input_leaves_with_code_zero() {
+    let output = session("");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "> \n");
+
+    let output = session(":quit\nshow 1;\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "> ");
+}
+
+#[test]
+fn exit_leaves_with_its_code() {
+    let output = session("show 1;\nexit 3;\nshow 2;\n");
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "> 1\n> ");
+}
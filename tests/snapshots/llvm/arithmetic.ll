@@ -0,0 +1,65 @@
+@.str.0 = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.str.1 = private unnamed_addr constant [7 x i8] c"%d %d\0A\00"
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %bp_a.1 = alloca i32
+    %bp_b.2 = alloca i32
+    store i32 7, ptr %bp_a.1
+    store i32 3, ptr %bp_b.2
+    %t3 = load i32, ptr %bp_a.1
+    %t4 = load i32, ptr %bp_b.2
+    %t5 = mul i32 %t4, 2
+    %t6 = add i32 %t3, %t5
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t6)
+    %t7 = load i32, ptr %bp_a.1
+    %t8 = load i32, ptr %bp_b.2
+    %t9 = add i32 %t7, %t8
+    %t10 = mul i32 %t9, 2
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t10)
+    %t11 = load i32, ptr %bp_a.1
+    %t12 = load i32, ptr %bp_b.2
+    %t13 = sub i32 %t11, %t12
+    %t14 = sub i32 %t13, 1
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t14)
+    %t15 = load i32, ptr %bp_a.1
+    %t16 = load i32, ptr %bp_b.2
+    %t17 = call i32 @bprt_wrapping_div(i32 %t15, i32 %t16)
+    %t18 = load i32, ptr %bp_a.1
+    %t19 = load i32, ptr %bp_b.2
+    %t20 = call i32 @bprt_wrapping_mod(i32 %t18, i32 %t19)
+    call i32 (ptr, ...) @printf(ptr @.str.1, i32 %t17, i32 %t20)
+    %t21 = load i32, ptr %bp_a.1
+    %t22 = sub i32 %t21, 10
+    %t23 = sub i32 0, %t22
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t23)
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 280)
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 1000)
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 100)
+    ret i32 0
+}
+
+define private i32 @bprt_wrapping_div(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %negate, label %divide
+negate:
+    %negated = sub i32 0, %a
+    ret i32 %negated
+divide:
+    %quotient = sdiv i32 %a, %b
+    ret i32 %quotient
+}
+
+define private i32 @bprt_wrapping_mod(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %zero, label %divide
+zero:
+    ret i32 0
+divide:
+    %remainder = srem i32 %a, %b
+    ret i32 %remainder
+}
+
+declare i32 @printf(ptr, ...)
@@ -0,0 +1,59 @@
+@.str.0 = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %bp_nums.1 = alloca [6 x i32]
+    %bp_sum.9 = alloca i32
+    %bp_i.10 = alloca i32
+    %t2 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 0
+    store i32 4, ptr %t2
+    %t3 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 1
+    store i32 8, ptr %t3
+    %t4 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 2
+    store i32 15, ptr %t4
+    %t5 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 3
+    store i32 16, ptr %t5
+    %t6 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 4
+    store i32 23, ptr %t6
+    %t7 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 5
+    store i32 42, ptr %t7
+    %t8 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 0
+    store i32 5, ptr %t8
+    store i32 0, ptr %bp_sum.9
+    store i32 0, ptr %bp_i.10
+    br label %L11
+L11:
+    %t15 = load i32, ptr %bp_i.10
+    %t16 = sub i32 6, 1
+    %t17 = icmp sle i32 %t15, %t16
+    br i1 %t17, label %L12, label %L14
+L12:
+    %t18 = load i32, ptr %bp_sum.9
+    %t19 = load i32, ptr %bp_i.10
+    %t20 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 %t19
+    %t21 = load i32, ptr %t20
+    %t22 = add i32 %t18, %t21
+    store i32 %t22, ptr %bp_sum.9
+    br label %L13
+L13:
+    %t23 = load i32, ptr %bp_i.10
+    %t24 = icmp eq i32 %t23, 2147483647
+    br i1 %t24, label %L14, label %L25
+L25:
+    %t26 = add i32 %t23, 1
+    store i32 %t26, ptr %bp_i.10
+    br label %L11
+L14:
+    %t27 = load i32, ptr %bp_sum.9
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t27)
+    %t28 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 0
+    %t29 = load i32, ptr %t28
+    %t30 = sub i32 6, 1
+    %t31 = getelementptr [6 x i32], ptr %bp_nums.1, i32 0, i32 %t30
+    %t32 = load i32, ptr %t31
+    %t33 = add i32 %t29, %t32
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t33)
+    ret i32 0
+}
+
+declare i32 @printf(ptr, ...)
@@ -0,0 +1,107 @@
+@.str.0 = private unnamed_addr constant [11 x i8] c"evaluated\0A\00"
+@.str.1 = private unnamed_addr constant [5 x i8] c"true\00"
+@.str.2 = private unnamed_addr constant [6 x i8] c"false\00"
+@.str.3 = private unnamed_addr constant [13 x i8] c"%s %s %s %s\0A\00"
+@.str.4 = private unnamed_addr constant [4 x i8] c"%s\0A\00"
+
+define i32 @bp_loud(i32 %arg0) {
+entry:
+    %bp_value.1 = alloca i32
+    store i32 %arg0, ptr %bp_value.1
+    call i32 (ptr, ...) @printf(ptr @.str.0)
+    %t2 = load i32, ptr %bp_value.1
+    ret i32 %t2
+L3:
+    ret i32 0
+}
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %bp_x.4 = alloca i32
+    %bp_count.37 = alloca i32
+    %bp_safe.38 = alloca i1
+    store i32 5, ptr %bp_x.4
+    %t5 = load i32, ptr %bp_x.4
+    %t6 = icmp sgt i32 %t5, 3
+    %t7 = select i1 %t6, ptr @.str.1, ptr @.str.2
+    %t8 = load i32, ptr %bp_x.4
+    %t9 = icmp sle i32 %t8, 3
+    %t10 = select i1 %t9, ptr @.str.1, ptr @.str.2
+    %t11 = load i32, ptr %bp_x.4
+    %t12 = icmp eq i32 %t11, 5
+    %t13 = select i1 %t12, ptr @.str.1, ptr @.str.2
+    %t14 = load i32, ptr %bp_x.4
+    %t15 = icmp ne i32 %t14, 5
+    %t16 = select i1 %t15, ptr @.str.1, ptr @.str.2
+    call i32 (ptr, ...) @printf(ptr @.str.3, ptr %t7, ptr %t10, ptr %t13, ptr %t16)
+    %t17 = select i1 true, ptr @.str.1, ptr @.str.2
+    call i32 (ptr, ...) @printf(ptr @.str.4, ptr %t17)
+    %t18 = load i32, ptr %bp_x.4
+    %t19 = icmp sgt i32 %t18, 3
+    %t20 = xor i1 %t19, true
+    br i1 %t20, label %L22, label %L21
+L21:
+    %t23 = load i32, ptr %bp_x.4
+    %t24 = icmp eq i32 %t23, 5
+    br i1 %t24, label %L25, label %L26
+L25:
+    %t27 = load i32, ptr %bp_x.4
+    %t28 = icmp slt i32 %t27, 10
+    br label %L26
+L26:
+    %t29 = phi i1 [ false, %L21 ], [ %t28, %L25 ]
+    br label %L22
+L22:
+    %t30 = phi i1 [ true, %entry ], [ %t29, %L26 ]
+    %t31 = select i1 %t30, ptr @.str.1, ptr @.str.2
+    call i32 (ptr, ...) @printf(ptr @.str.4, ptr %t31)
+    %t32 = select i1 false, ptr @.str.1, ptr @.str.2
+    call i32 (ptr, ...) @printf(ptr @.str.4, ptr %t32)
+    %t33 = select i1 true, ptr @.str.1, ptr @.str.2
+    call i32 (ptr, ...) @printf(ptr @.str.4, ptr %t33)
+    %t34 = call i32 @bp_loud(i32 0)
+    %t35 = icmp eq i32 %t34, 1
+    %t36 = select i1 %t35, ptr @.str.1, ptr @.str.2
+    call i32 (ptr, ...) @printf(ptr @.str.4, ptr %t36)
+    store i32 0, ptr %bp_count.37
+    %t39 = load i32, ptr %bp_count.37
+    %t40 = icmp ne i32 %t39, 0
+    br i1 %t40, label %L41, label %L42
+L41:
+    %t43 = load i32, ptr %bp_count.37
+    %t44 = call i32 @bprt_wrapping_div(i32 10, i32 %t43)
+    %t45 = icmp sgt i32 %t44, 2
+    br label %L42
+L42:
+    %t46 = phi i1 [ false, %L22 ], [ %t45, %L41 ]
+    store i1 %t46, ptr %bp_safe.38
+    %t47 = load i1, ptr %bp_safe.38
+    %t48 = select i1 %t47, ptr @.str.1, ptr @.str.2
+    call i32 (ptr, ...) @printf(ptr @.str.4, ptr %t48)
+    ret i32 0
+}
+
+define private i32 @bprt_wrapping_div(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %negate, label %divide
+negate:
+    %negated = sub i32 0, %a
+    ret i32 %negated
+divide:
+    %quotient = sdiv i32 %a, %b
+    ret i32 %quotient
+}
+
+define private i32 @bprt_wrapping_mod(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %zero, label %divide
+zero:
+    ret i32 0
+divide:
+    %remainder = srem i32 %a, %b
+    ret i32 %remainder
+}
+
+declare i32 @printf(ptr, ...)
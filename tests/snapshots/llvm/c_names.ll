@@ -0,0 +1,36 @@
+@.str.0 = private unnamed_addr constant [2 x i8] c"c\00"
+@.str.1 = private unnamed_addr constant [10 x i8] c"%s %d %d\0A\00"
+
+define i32 @bp_printf(i32 %arg0) {
+entry:
+    %bp_main.1 = alloca i32
+    store i32 %arg0, ptr %bp_main.1
+    %t2 = load i32, ptr %bp_main.1
+    %t3 = mul i32 %t2, 2
+    ret i32 %t3
+L4:
+    ret i32 0
+}
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %bp_int.5 = alloca i32
+    %bp_char.6 = alloca ptr
+    %bp_main.7 = alloca i32
+    store i32 5, ptr %bp_int.5
+    store ptr @.str.0, ptr %bp_char.6
+    %t8 = load i32, ptr %bp_int.5
+    %t9 = call i32 @bp_printf(i32 %t8)
+    store i32 %t9, ptr %bp_main.7
+    %t10 = load i32, ptr %bp_int.5
+    %t11 = load i32, ptr %bp_main.7
+    %t12 = add i32 %t10, %t11
+    store i32 %t12, ptr %bp_int.5
+    %t13 = load ptr, ptr %bp_char.6
+    %t14 = load i32, ptr %bp_int.5
+    %t15 = load i32, ptr %bp_main.7
+    call i32 (ptr, ...) @printf(ptr @.str.1, ptr %t13, i32 %t14, i32 %t15)
+    ret i32 0
+}
+
+declare i32 @printf(ptr, ...)
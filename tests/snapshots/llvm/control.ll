@@ -0,0 +1,177 @@
+@.str.0 = private unnamed_addr constant [11 x i8] c"%d is big\0A\00"
+@.str.1 = private unnamed_addr constant [14 x i8] c"%d is medium\0A\00"
+@.str.2 = private unnamed_addr constant [13 x i8] c"%d is small\0A\00"
+@.str.3 = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.str.4 = private unnamed_addr constant [9 x i8] c"pass %d\0A\00"
+@.str.5 = private unnamed_addr constant [7 x i8] c"never\0A\00"
+
+define i32 @bp_size(i32 %arg0) {
+entry:
+    %bp_n.1 = alloca i32
+    store i32 %arg0, ptr %bp_n.1
+    %t2 = load i32, ptr %bp_n.1
+    %t3 = icmp sgt i32 %t2, 10
+    br i1 %t3, label %L4, label %L6
+L4:
+    %t7 = load i32, ptr %bp_n.1
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t7)
+    br label %L5
+L6:
+    %t8 = load i32, ptr %bp_n.1
+    %t9 = icmp sgt i32 %t8, 5
+    br i1 %t9, label %L10, label %L12
+L10:
+    %t13 = load i32, ptr %bp_n.1
+    call i32 (ptr, ...) @printf(ptr @.str.1, i32 %t13)
+    br label %L11
+L12:
+    %t14 = load i32, ptr %bp_n.1
+    call i32 (ptr, ...) @printf(ptr @.str.2, i32 %t14)
+    br label %L11
+L11:
+    br label %L5
+L5:
+    ret i32 0
+}
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %bp_i.18 = alloca i32
+    %bp_pairs.41 = alloca i32
+    %bp_i.42 = alloca i32
+    %bp_j.50 = alloca i32
+    %bp_stop.73 = alloca i32
+    %bp_i.74 = alloca i32
+    %bp_i.87 = alloca i32
+    %t15 = call i32 @bp_size(i32 20)
+    %t16 = call i32 @bp_size(i32 7)
+    %t17 = call i32 @bp_size(i32 1)
+    store i32 1, ptr %bp_i.18
+    br label %L19
+L19:
+    %t23 = load i32, ptr %bp_i.18
+    %t24 = icmp sle i32 %t23, 10
+    br i1 %t24, label %L20, label %L22
+L20:
+    %t25 = load i32, ptr %bp_i.18
+    %t26 = srem i32 %t25, 2
+    %t27 = icmp eq i32 %t26, 0
+    br i1 %t27, label %L28, label %L29
+L28:
+    br label %L21
+L30:
+    br label %L29
+L29:
+    %t31 = load i32, ptr %bp_i.18
+    %t32 = icmp sgt i32 %t31, 7
+    br i1 %t32, label %L33, label %L34
+L33:
+    br label %L22
+L35:
+    br label %L34
+L34:
+    %t36 = load i32, ptr %bp_i.18
+    call i32 (ptr, ...) @printf(ptr @.str.3, i32 %t36)
+    br label %L21
+L21:
+    %t37 = load i32, ptr %bp_i.18
+    %t38 = icmp eq i32 %t37, 2147483647
+    br i1 %t38, label %L22, label %L39
+L39:
+    %t40 = add i32 %t37, 1
+    store i32 %t40, ptr %bp_i.18
+    br label %L19
+L22:
+    store i32 0, ptr %bp_pairs.41
+    store i32 1, ptr %bp_i.42
+    br label %L43
+L43:
+    %t47 = load i32, ptr %bp_i.42
+    %t48 = icmp sle i32 %t47, 4
+    br i1 %t48, label %L44, label %L46
+L44:
+    %t49 = load i32, ptr %bp_i.42
+    store i32 %t49, ptr %bp_j.50
+    br label %L51
+L51:
+    %t55 = load i32, ptr %bp_j.50
+    %t56 = icmp sle i32 %t55, 4
+    br i1 %t56, label %L52, label %L54
+L52:
+    %t57 = load i32, ptr %bp_j.50
+    %t58 = icmp eq i32 %t57, 3
+    br i1 %t58, label %L59, label %L60
+L59:
+    br label %L54
+L61:
+    br label %L60
+L60:
+    %t62 = load i32, ptr %bp_pairs.41
+    %t63 = add i32 %t62, 1
+    store i32 %t63, ptr %bp_pairs.41
+    br label %L53
+L53:
+    %t64 = load i32, ptr %bp_j.50
+    %t65 = icmp eq i32 %t64, 2147483647
+    br i1 %t65, label %L54, label %L66
+L66:
+    %t67 = add i32 %t64, 1
+    store i32 %t67, ptr %bp_j.50
+    br label %L51
+L54:
+    br label %L45
+L45:
+    %t68 = load i32, ptr %bp_i.42
+    %t69 = icmp eq i32 %t68, 2147483647
+    br i1 %t69, label %L46, label %L70
+L70:
+    %t71 = add i32 %t68, 1
+    store i32 %t71, ptr %bp_i.42
+    br label %L43
+L46:
+    %t72 = load i32, ptr %bp_pairs.41
+    call i32 (ptr, ...) @printf(ptr @.str.3, i32 %t72)
+    store i32 3, ptr %bp_stop.73
+    store i32 1, ptr %bp_i.74
+    br label %L75
+L75:
+    %t79 = load i32, ptr %bp_i.74
+    %t80 = load i32, ptr %bp_stop.73
+    %t81 = icmp sle i32 %t79, %t80
+    br i1 %t81, label %L76, label %L78
+L76:
+    store i32 2, ptr %bp_stop.73
+    %t82 = load i32, ptr %bp_i.74
+    call i32 (ptr, ...) @printf(ptr @.str.4, i32 %t82)
+    br label %L77
+L77:
+    %t83 = load i32, ptr %bp_i.74
+    %t84 = icmp eq i32 %t83, 2147483647
+    br i1 %t84, label %L78, label %L85
+L85:
+    %t86 = add i32 %t83, 1
+    store i32 %t86, ptr %bp_i.74
+    br label %L75
+L78:
+    store i32 5, ptr %bp_i.87
+    br label %L88
+L88:
+    %t92 = load i32, ptr %bp_i.87
+    %t93 = icmp sle i32 %t92, 1
+    br i1 %t93, label %L89, label %L91
+L89:
+    call i32 (ptr, ...) @printf(ptr @.str.5)
+    br label %L90
+L90:
+    %t94 = load i32, ptr %bp_i.87
+    %t95 = icmp eq i32 %t94, 2147483647
+    br i1 %t95, label %L91, label %L96
+L96:
+    %t97 = add i32 %t94, 1
+    store i32 %t97, ptr %bp_i.87
+    br label %L88
+L91:
+    ret i32 0
+}
+
+declare i32 @printf(ptr, ...)
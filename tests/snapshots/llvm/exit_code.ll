@@ -0,0 +1,23 @@
+@.str.0 = private unnamed_addr constant [11 x i8] c"finishing\0A\00"
+@.str.1 = private unnamed_addr constant [7 x i8] c"start\0A\00"
+@.str.2 = private unnamed_addr constant [13 x i8] c"not reached\0A\00"
+
+define i32 @bp_finish() {
+entry:
+    call i32 (ptr, ...) @printf(ptr @.str.0)
+    call void @exit(i32 0)
+    unreachable
+L1:
+    ret i32 0
+}
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    call i32 (ptr, ...) @printf(ptr @.str.1)
+    %t2 = call i32 @bp_finish()
+    call i32 (ptr, ...) @printf(ptr @.str.2)
+    ret i32 0
+}
+
+declare i32 @printf(ptr, ...)
+declare void @exit(i32)
@@ -0,0 +1,156 @@
+@.str.0 = private unnamed_addr constant [2 x i8] c" \00"
+@.str.1 = private unnamed_addr constant [4 x i8] c"%s\0A\00"
+@.str.2 = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.str.3 = private unnamed_addr constant [7 x i8] c"apples\00"
+
+define i32 @bp_add(i32 %arg0, i32 %arg1) {
+entry:
+    %bp_a.1 = alloca i32
+    %bp_b.2 = alloca i32
+    store i32 %arg0, ptr %bp_a.1
+    store i32 %arg1, ptr %bp_b.2
+    %t3 = load i32, ptr %bp_a.1
+    %t4 = load i32, ptr %bp_b.2
+    %t5 = add i32 %t3, %t4
+    ret i32 %t5
+L6:
+    ret i32 0
+}
+
+define i32 @bp_describe(ptr %arg0, i32 %arg1) {
+entry:
+    %bp_what.7 = alloca ptr
+    %bp_n.8 = alloca i32
+    store ptr %arg0, ptr %bp_what.7
+    store i32 %arg1, ptr %bp_n.8
+    %t9 = load i32, ptr %bp_n.8
+    %t10 = call ptr @bprt_int_str(i32 %t9)
+    %t11 = call ptr @bprt_concat(ptr %t10, ptr @.str.0)
+    %t12 = load ptr, ptr %bp_what.7
+    %t13 = call ptr @bprt_concat(ptr %t11, ptr %t12)
+    call i32 (ptr, ...) @printf(ptr @.str.1, ptr %t13)
+    %t14 = load ptr, ptr %bp_what.7
+    %t15 = call i64 @strlen(ptr %t14)
+    %t16 = trunc i64 %t15 to i32
+    ret i32 %t16
+L17:
+    ret i32 0
+}
+
+define i32 @bp_nothing() {
+entry:
+    ret i32 0
+}
+
+define i32 @bp_gcd(i32 %arg0, i32 %arg1) {
+entry:
+    %bp_a.18 = alloca i32
+    %bp_b.19 = alloca i32
+    store i32 %arg0, ptr %bp_a.18
+    store i32 %arg1, ptr %bp_b.19
+    %t20 = load i32, ptr %bp_b.19
+    %t21 = icmp eq i32 %t20, 0
+    br i1 %t21, label %L22, label %L23
+L22:
+    %t24 = load i32, ptr %bp_a.18
+    ret i32 %t24
+L25:
+    br label %L23
+L23:
+    %t26 = load i32, ptr %bp_b.19
+    %t27 = load i32, ptr %bp_a.18
+    %t28 = load i32, ptr %bp_b.19
+    %t29 = call i32 @bprt_wrapping_mod(i32 %t27, i32 %t28)
+    %t30 = call i32 @bp_gcd(i32 %t26, i32 %t29)
+    ret i32 %t30
+L31:
+    ret i32 0
+}
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %t32 = call i32 @bp_add(i32 2, i32 3)
+    call i32 (ptr, ...) @printf(ptr @.str.2, i32 %t32)
+    %t33 = call i32 @bp_describe(ptr @.str.3, i32 3)
+    call i32 (ptr, ...) @printf(ptr @.str.2, i32 %t33)
+    %t34 = call i32 @bp_nothing()
+    call i32 (ptr, ...) @printf(ptr @.str.2, i32 %t34)
+    %t35 = call i32 @bp_gcd(i32 84, i32 36)
+    call i32 (ptr, ...) @printf(ptr @.str.2, i32 %t35)
+    %t36 = call i32 @bp_add(i32 1, i32 2)
+    %t37 = call i32 @bp_add(i32 3, i32 4)
+    %t38 = call i32 @bp_add(i32 %t36, i32 %t37)
+    %t39 = mul i32 %t38, 2
+    call i32 (ptr, ...) @printf(ptr @.str.2, i32 %t39)
+    ret i32 0
+}
+
+@bprt_out_of_memory = private unnamed_addr constant [22 x i8] c"error: out of memory\0A\00"
+
+define private ptr @bprt_alloc(i64 %size) {
+entry:
+    %result = call ptr @malloc(i64 %size)
+    %failed = icmp eq ptr %result, null
+    br i1 %failed, label %fail, label %done
+fail:
+    call i32 @fflush(ptr null)
+    call i64 @write(i32 2, ptr @bprt_out_of_memory, i64 21)
+    call void @exit(i32 1)
+    unreachable
+done:
+    ret ptr %result
+}
+
+@bprt_int_format = private unnamed_addr constant [3 x i8] c"%d\00"
+
+define private ptr @bprt_int_str(i32 %value) {
+entry:
+    %result = call ptr @bprt_alloc(i64 12)
+    call i32 (ptr, i64, ptr, ...) @snprintf(ptr %result, i64 12, ptr @bprt_int_format, i32 %value)
+    ret ptr %result
+}
+
+define private ptr @bprt_concat(ptr %a, ptr %b) {
+entry:
+    %a_len = call i64 @strlen(ptr %a)
+    %b_len = call i64 @strlen(ptr %b)
+    %b_size = add i64 %b_len, 1
+    %size = add i64 %a_len, %b_size
+    %result = call ptr @bprt_alloc(i64 %size)
+    call ptr @memcpy(ptr %result, ptr %a, i64 %a_len)
+    %tail = getelementptr i8, ptr %result, i64 %a_len
+    call ptr @memcpy(ptr %tail, ptr %b, i64 %b_size)
+    ret ptr %result
+}
+
+define private i32 @bprt_wrapping_div(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %negate, label %divide
+negate:
+    %negated = sub i32 0, %a
+    ret i32 %negated
+divide:
+    %quotient = sdiv i32 %a, %b
+    ret i32 %quotient
+}
+
+define private i32 @bprt_wrapping_mod(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %zero, label %divide
+zero:
+    ret i32 0
+divide:
+    %remainder = srem i32 %a, %b
+    ret i32 %remainder
+}
+
+declare i32 @fflush(ptr)
+declare i32 @printf(ptr, ...)
+declare i32 @snprintf(ptr, i64, ptr, ...)
+declare i64 @strlen(ptr)
+declare i64 @write(i32, ptr, i64)
+declare ptr @malloc(i64)
+declare ptr @memcpy(ptr, ptr, i64)
+declare void @exit(i32)
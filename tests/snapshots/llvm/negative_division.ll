@@ -0,0 +1,60 @@
+@.str.0 = private unnamed_addr constant [7 x i8] c"%d %d\0A\00"
+@.str.1 = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %bp_a.1 = alloca i32
+    %bp_b.2 = alloca i32
+    store i32 -7, ptr %bp_a.1
+    store i32 2, ptr %bp_b.2
+    %t3 = load i32, ptr %bp_a.1
+    %t4 = load i32, ptr %bp_b.2
+    %t5 = call i32 @bprt_wrapping_div(i32 %t3, i32 %t4)
+    %t6 = load i32, ptr %bp_a.1
+    %t7 = load i32, ptr %bp_b.2
+    %t8 = call i32 @bprt_wrapping_mod(i32 %t6, i32 %t7)
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t5, i32 %t8)
+    %t9 = load i32, ptr %bp_b.2
+    %t10 = sub i32 0, %t9
+    %t11 = call i32 @bprt_wrapping_div(i32 7, i32 %t10)
+    %t12 = load i32, ptr %bp_b.2
+    %t13 = sub i32 0, %t12
+    %t14 = call i32 @bprt_wrapping_mod(i32 7, i32 %t13)
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t11, i32 %t14)
+    %t15 = load i32, ptr %bp_a.1
+    %t16 = load i32, ptr %bp_b.2
+    %t17 = sub i32 0, %t16
+    %t18 = call i32 @bprt_wrapping_div(i32 %t15, i32 %t17)
+    %t19 = load i32, ptr %bp_a.1
+    %t20 = load i32, ptr %bp_b.2
+    %t21 = sub i32 0, %t20
+    %t22 = call i32 @bprt_wrapping_mod(i32 %t19, i32 %t21)
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t18, i32 %t22)
+    call i32 (ptr, ...) @printf(ptr @.str.1, i32 -2147483648)
+    ret i32 0
+}
+
+define private i32 @bprt_wrapping_div(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %negate, label %divide
+negate:
+    %negated = sub i32 0, %a
+    ret i32 %negated
+divide:
+    %quotient = sdiv i32 %a, %b
+    ret i32 %quotient
+}
+
+define private i32 @bprt_wrapping_mod(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %zero, label %divide
+zero:
+    ret i32 0
+divide:
+    %remainder = srem i32 %a, %b
+    ret i32 %remainder
+}
+
+declare i32 @printf(ptr, ...)
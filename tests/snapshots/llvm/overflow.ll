@@ -0,0 +1,54 @@
+@.str.0 = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %bp_biggest.1 = alloca i32
+    %bp_smallest.2 = alloca i32
+    %bp_minus_one.3 = alloca i32
+    store i32 2147483647, ptr %bp_biggest.1
+    store i32 -2147483648, ptr %bp_smallest.2
+    store i32 -1, ptr %bp_minus_one.3
+    %t4 = load i32, ptr %bp_biggest.1
+    %t5 = add i32 %t4, 1
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t5)
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 -2147483648)
+    %t6 = load i32, ptr %bp_smallest.2
+    %t7 = sub i32 %t6, 1
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t7)
+    %t8 = load i32, ptr %bp_smallest.2
+    %t9 = sub i32 0, %t8
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t9)
+    %t10 = load i32, ptr %bp_smallest.2
+    %t11 = load i32, ptr %bp_minus_one.3
+    %t12 = call i32 @bprt_wrapping_div(i32 %t10, i32 %t11)
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t12)
+    %t13 = load i32, ptr %bp_biggest.1
+    %t14 = mul i32 %t13, 2
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t14)
+    ret i32 0
+}
+
+define private i32 @bprt_wrapping_div(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %negate, label %divide
+negate:
+    %negated = sub i32 0, %a
+    ret i32 %negated
+divide:
+    %quotient = sdiv i32 %a, %b
+    ret i32 %quotient
+}
+
+define private i32 @bprt_wrapping_mod(i32 %a, i32 %b) {
+entry:
+    %minus_one = icmp eq i32 %b, -1
+    br i1 %minus_one, label %zero, label %divide
+zero:
+    ret i32 0
+divide:
+    %remainder = srem i32 %a, %b
+    ret i32 %remainder
+}
+
+declare i32 @printf(ptr, ...)
@@ -0,0 +1,60 @@
+@.str.0 = private unnamed_addr constant [20 x i8] c"factorial(10) = %d\0A\00"
+@.str.1 = private unnamed_addr constant [14 x i8] c"fib(10) = %d\0A\00"
+
+define i32 @bp_factorial(i32 %arg0) {
+entry:
+    %bp_n.1 = alloca i32
+    store i32 %arg0, ptr %bp_n.1
+    %t2 = load i32, ptr %bp_n.1
+    %t3 = icmp sle i32 %t2, 1
+    br i1 %t3, label %L4, label %L5
+L4:
+    ret i32 1
+L6:
+    br label %L5
+L5:
+    %t7 = load i32, ptr %bp_n.1
+    %t8 = load i32, ptr %bp_n.1
+    %t9 = sub i32 %t8, 1
+    %t10 = call i32 @bp_factorial(i32 %t9)
+    %t11 = mul i32 %t7, %t10
+    ret i32 %t11
+L12:
+    ret i32 0
+}
+
+define i32 @bp_fib(i32 %arg0) {
+entry:
+    %bp_n.13 = alloca i32
+    store i32 %arg0, ptr %bp_n.13
+    %t14 = load i32, ptr %bp_n.13
+    %t15 = icmp slt i32 %t14, 2
+    br i1 %t15, label %L16, label %L17
+L16:
+    %t18 = load i32, ptr %bp_n.13
+    ret i32 %t18
+L19:
+    br label %L17
+L17:
+    %t20 = load i32, ptr %bp_n.13
+    %t21 = sub i32 %t20, 1
+    %t22 = call i32 @bp_fib(i32 %t21)
+    %t23 = load i32, ptr %bp_n.13
+    %t24 = sub i32 %t23, 2
+    %t25 = call i32 @bp_fib(i32 %t24)
+    %t26 = add i32 %t22, %t25
+    ret i32 %t26
+L27:
+    ret i32 0
+}
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %t28 = call i32 @bp_factorial(i32 10)
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t28)
+    %t29 = call i32 @bp_fib(i32 10)
+    call i32 (ptr, ...) @printf(ptr @.str.1, i32 %t29)
+    ret i32 0
+}
+
+declare i32 @printf(ptr, ...)
@@ -0,0 +1,44 @@
+@.str.0 = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.str.1 = private unnamed_addr constant [1 x i8] c"\00"
+@.str.2 = private unnamed_addr constant [5 x i8] c"high\00"
+@.str.3 = private unnamed_addr constant [4 x i8] c"low\00"
+@.str.4 = private unnamed_addr constant [4 x i8] c"%s\0A\00"
+@.str.5 = private unnamed_addr constant [4 x i8] c"%g\0A\00"
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %bp_total.1 = alloca i32
+    %bp_step.2 = alloca i32
+    %bp_limit.7 = alloca i32
+    %bp_label.8 = alloca ptr
+    %bp_ratio.15 = alloca double
+    store i32 1, ptr %bp_total.1
+    store i32 2, ptr %bp_step.2
+    %t3 = load i32, ptr %bp_total.1
+    %t4 = load i32, ptr %bp_step.2
+    %t5 = add i32 %t3, %t4
+    store i32 %t5, ptr %bp_total.1
+    %t6 = load i32, ptr %bp_total.1
+    call i32 (ptr, ...) @printf(ptr @.str.0, i32 %t6)
+    store i32 3, ptr %bp_limit.7
+    store ptr @.str.1, ptr %bp_label.8
+    %t9 = load i32, ptr %bp_limit.7
+    %t10 = icmp sgt i32 %t9, 2
+    br i1 %t10, label %L11, label %L13
+L11:
+    store ptr @.str.2, ptr %bp_label.8
+    br label %L12
+L13:
+    store ptr @.str.3, ptr %bp_label.8
+    br label %L12
+L12:
+    %t14 = load ptr, ptr %bp_label.8
+    call i32 (ptr, ...) @printf(ptr @.str.4, ptr %t14)
+    store double 0x3FF0000000000000, ptr %bp_ratio.15
+    %t16 = load double, ptr %bp_ratio.15
+    %t17 = fdiv double %t16, 0x4010000000000000
+    call i32 (ptr, ...) @printf(ptr @.str.5, double %t17)
+    ret i32 0
+}
+
+declare i32 @printf(ptr, ...)
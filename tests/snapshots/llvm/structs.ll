@@ -0,0 +1,94 @@
+%bpt_Point = type { i32, i32, ptr }
+
+@.str.0 = private unnamed_addr constant [6 x i8] c"start\00"
+@.str.1 = private unnamed_addr constant [13 x i8] c"%s at %d,%d\0A\00"
+@.str.2 = private unnamed_addr constant [8 x i8] c"%s: %d\0A\00"
+@.str.3 = private unnamed_addr constant [5 x i8] c"true\00"
+@.str.4 = private unnamed_addr constant [6 x i8] c"false\00"
+@.str.5 = private unnamed_addr constant [10 x i8] c"%s %s %s\0A\00"
+@.str.6 = private unnamed_addr constant [6 x i8] c"stop\0A\00"
+@.str.7 = private unnamed_addr constant [4 x i8] c"go\0A\00"
+@.str.8 = private unnamed_addr constant [6 x i8] c"wait\0A\00"
+@.str.9 = private unnamed_addr constant [4 x i8] c"Red\00"
+@.str.10 = private unnamed_addr constant [6 x i8] c"Green\00"
+@.str.11 = private unnamed_addr constant [5 x i8] c"Blue\00"
+@bprt_names_Color = private unnamed_addr constant [3 x ptr] [ptr @.str.9, ptr @.str.10, ptr @.str.11]
+
+define i32 @main(i32 %argc, ptr %argv) {
+entry:
+    %bp_p.1 = alloca %bpt_Point
+    %bp_color.19 = alloca i32
+    %bp_i.29 = alloca i32
+    %t2 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 1
+    store i32 2, ptr %t2
+    %t3 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 2
+    store ptr @.str.0, ptr %t3
+    %t4 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 0
+    store i32 1, ptr %t4
+    %t5 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 0
+    %t6 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 0
+    %t7 = load i32, ptr %t6
+    %t8 = add i32 %t7, 10
+    store i32 %t8, ptr %t5
+    %t9 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 2
+    %t10 = load ptr, ptr %t9
+    %t11 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 0
+    %t12 = load i32, ptr %t11
+    %t13 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 1
+    %t14 = load i32, ptr %t13
+    call i32 (ptr, ...) @printf(ptr @.str.1, ptr %t10, i32 %t12, i32 %t14)
+    %t15 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 2
+    %t16 = load ptr, ptr %t15
+    %t17 = getelementptr %bpt_Point, ptr %bp_p.1, i32 0, i32 0
+    %t18 = load i32, ptr %t17
+    call i32 (ptr, ...) @printf(ptr @.str.2, ptr %t16, i32 %t18)
+    store i32 1, ptr %bp_color.19
+    %t20 = load i32, ptr %bp_color.19
+    %t21 = getelementptr [3 x ptr], ptr @bprt_names_Color, i32 0, i32 %t20
+    %t22 = load ptr, ptr %t21
+    %t23 = load i32, ptr %bp_color.19
+    %t24 = icmp eq i32 %t23, 0
+    %t25 = select i1 %t24, ptr @.str.3, ptr @.str.4
+    %t26 = load i32, ptr %bp_color.19
+    %t27 = icmp ne i32 %t26, 2
+    %t28 = select i1 %t27, ptr @.str.3, ptr @.str.4
+    call i32 (ptr, ...) @printf(ptr @.str.5, ptr %t22, ptr %t25, ptr %t28)
+    store i32 1, ptr %bp_i.29
+    br label %L30
+L30:
+    %t34 = load i32, ptr %bp_i.29
+    %t35 = icmp sle i32 %t34, 3
+    br i1 %t35, label %L31, label %L33
+L31:
+    %t36 = load i32, ptr %bp_color.19
+    switch i32 %t36, label %L38 [
+      i32 0, label %L39
+      i32 1, label %L40
+    ]
+L39:
+    call i32 (ptr, ...) @printf(ptr @.str.6)
+    store i32 1, ptr %bp_color.19
+    br label %L37
+L40:
+    call i32 (ptr, ...) @printf(ptr @.str.7)
+    store i32 2, ptr %bp_color.19
+    br label %L37
+L38:
+    call i32 (ptr, ...) @printf(ptr @.str.8)
+    store i32 0, ptr %bp_color.19
+    br label %L37
+L37:
+    br label %L32
+L32:
+    %t41 = load i32, ptr %bp_i.29
+    %t42 = icmp eq i32 %t41, 2147483647
+    br i1 %t42, label %L33, label %L43
+L43:
+    %t44 = add i32 %t41, 1
+    store i32 %t44, ptr %bp_i.29
+    br label %L30
+L33:
+    ret i32 0
+}
+
+declare i32 @printf(ptr, ...)
@@ -0,0 +1,95 @@
+//! The token stream of every fixture: the same whether the lexer is walked
+//! one token at a time or collected by `tokenize`, and the same as the
+//! snapshots in `tests/snapshots/tokens`. Those snapshots match what the
+//! lexer printed before tokens borrowed their text from the source, for
+//! every fixture that lexer could read. Run with `BPLANG_BLESS=1` to write
+//! the snapshots instead.
+
+mod common;
+
+use std::env;
+use std::fs;
+
+use bplang::ast::ast_tree;
+use bplang::lexer::{tokenize, FileId, Lexer};
+use bplang::parser::{parse, parse_source};
+
+use common::{fixtures, repo, stderr, stdout, Scratch};
+
+#[test]
+fn lexer_and_tokenize_agree_on_every_fixture() {
+    for (name, source) in &fixtures() {
+        let mut streamed = Vec::new();
+        for token in Lexer::new(source) {
+            streamed.push(token.unwrap_or_else(|err| panic!("{}: {}", name, err)));
+        }
+        let collected = tokenize(source).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        assert_eq!(streamed, collected, "{}", name);
+    }
+}
+
+#[test]
+fn lexer_stops_at_the_error_tokenize_reports() {
+    let source = "m x = 1;\nshow \"open;\n";
+    let streamed: Vec<_> = Lexer::new(source).collect();
+    let err = tokenize(source).expect_err("the string is unterminated");
+    assert_eq!(streamed.len(), 7);
+    assert_eq!(streamed.last(), Some(&Err(err)));
+}
+
+#[test]
+fn parsing_as_the_source_is_read_matches_parsing_its_tokens() {
+    for (name, source) in &fixtures() {
+        let tokens = tokenize(source).expect("the fixtures tokenize");
+        let streamed = parse_source(source, FileId::MAIN);
+        match (streamed, parse(&tokens)) {
+            (Ok(streamed), Ok(collected)) => {
+                assert_eq!(ast_tree(&streamed), ast_tree(&collected), "{}", name)
+            }
+            (Err(_), Err(_)) => {}
+            (streamed, collected) => panic!(
+                "{}: parsing the source gave {}, parsing its tokens {}",
+                name,
+                if streamed.is_ok() { "an AST" } else { "errors" },
+                if collected.is_ok() {
+                    "an AST"
+                } else {
+                    "errors"
+                }
+            ),
+        }
+    }
+}
+
+#[test]
+fn tokens_match_the_snapshots() {
+    let bless = env::var_os("BPLANG_BLESS").is_some();
+    let snapshots = repo().join("tests/snapshots/tokens");
+    let scratch = Scratch::new("token-snapshots");
+    for (name, source) in &fixtures() {
+        scratch.write(name, source);
+        let output = scratch.run(&["build", "--emit-tokens", name]);
+        assert!(output.status.success(), "{}: {}", name, stderr(&output));
+        let tokens = stdout(&output);
+        let snapshot = snapshots.join(name.replace(".bp", ".tokens"));
+        if bless {
+            fs::create_dir_all(&snapshots).expect("create the snapshot directory");
+            fs::write(&snapshot, &tokens).expect("write the snapshot");
+        } else {
+            let expected = fs::read_to_string(&snapshot).unwrap_or_else(|_| {
+                panic!(
+                    "no snapshot {}; run with BPLANG_BLESS=1",
+                    snapshot.display()
+                )
+            });
+            assert!(
+                tokens == expected,
+                "{} differs from {}; run with BPLANG_BLESS=1 if the change is \
+                 intended\n{}",
+                name,
+                snapshot.display(),
+                tokens
+            );
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! `bplang watch`: a run at the start and one after every change, until
+//! Ctrl-C stops it, even while an engine runs the program in-process
+
+#![cfg(unix)]
+
+mod common;
+
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::Scratch;
+
+// Long enough for a build, short enough to fail a stuck test
+const LIMIT: Duration = Duration::from_secs(20);
+
+// A running `bplang watch` and what it has printed so far
+struct Watch {
+    child: Child,
+    output: Arc<Mutex<String>>,
+}
+
+impl Watch {
+    fn start(scratch: &Scratch, args: &[&str]) -> Watch {
+        let mut child = scratch
+            .bplang()
+            .arg("watch")
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            // A group of its own, as a shell gives a foreground command
+            .process_group(0)
+            .spawn()
+            .expect("run bplang watch");
+        let output = Arc::new(Mutex::new(String::new()));
+        let mut stdout = child.stdout.take().expect("piped");
+        let shared = Arc::clone(&output);
+        thread::spawn(move || {
+            let mut buf = [0; 1024];
+            while let Ok(read) = stdout.read(&mut buf) {
+                if read == 0 {
+                    break;
+                }
+                let text = String::from_utf8_lossy(&buf[..read]);
+                shared.lock().unwrap().push_str(&text);
+            }
+        });
+        Watch { child, output }
+    }
+
+    fn output(&self) -> String {
+        self.output.lock().unwrap().clone()
+    }
+
+    // Wait until `text` has been printed `count` times
+    fn wait_for(&self, text: &str, count: usize) {
+        let started = Instant::now();
+        while self.output().matches(text).count() < count {
+            assert!(
+                started.elapsed() < LIMIT,
+                "{:?} not printed {} times:\n{}",
+                text,
+                count,
+                self.output()
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    // Send Ctrl-C's signal to the watch's process group, as a terminal
+    // does, and wait for the watch to end
+    fn interrupt(&mut self) -> ExitStatus {
+        let sent = Command::new("kill")
+            .args(["-INT", "--", &format!("-{}", self.child.id())])
+            .status()
+            .expect("run kill");
+        assert!(sent.success());
+        let started = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait().expect("wait for bplang") {
+                return status;
+            }
+            assert!(
+                started.elapsed() < LIMIT,
+                "watch still running after Ctrl-C:\n{}",
+                self.output()
+            );
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+const WAITING: &str = "Watching prog.bp for changes";
+
+#[test]
+fn every_change_runs_the_program_again() {
+    let scratch = Scratch::new("watch-changes");
+    scratch.write("prog.bp", "show 1;\n");
+    let mut watch = Watch::start(&scratch, &["prog.bp"]);
+    watch.wait_for(WAITING, 1);
+    // Each version a different size, in case the clock is coarse
+    scratch.write("prog.bp", "show 22;\n");
+    watch.wait_for(WAITING, 2);
+    scratch.write("prog.bp", "show 333;\n");
+    watch.wait_for(WAITING, 3);
+    assert!(watch.interrupt().success());
+
+    let output = watch.output();
+    assert_eq!(output.matches("---- running prog.bp ----").count(), 3);
+    assert_eq!(output.matches("---- exited with code 0 ----").count(), 3);
+    for shown in ["1\n", "22\n", "333\n"] {
+        assert!(output.contains(shown), "{}", output);
+    }
+}
+
+// Counts far longer than any test waits
+const ENDLESS: &str = "\
+m total = 0;
+for i = 1 to 100000 {
+    for j = 1 to 100000 {
+        total = total + 1;
+    }
+}
+show total;
+";
+
+fn interrupted_while_running(engine: &str) {
+    let scratch = Scratch::new("watch-interrupt");
+    scratch.write("prog.bp", ENDLESS);
+    let mut watch = Watch::start(&scratch, &["--engine", engine, "prog.bp"]);
+    watch.wait_for("---- running prog.bp ----", 1);
+    // Let the program get going
+    thread::sleep(Duration::from_millis(300));
+    watch.interrupt();
+    assert!(!watch.output().contains("exited with code"));
+}
+
+#[test]
+fn ctrl_c_stops_the_tree_engine() {
+    interrupted_while_running("tree");
+}
+
+#[test]
+fn ctrl_c_stops_the_vm() {
+    interrupted_while_running("vm");
+}
+
+#[test]
+fn ctrl_c_stops_a_built_program() {
+    interrupted_while_running("compile");
+}